@@ -0,0 +1,169 @@
+use crate::upsert::{
+    image_key_prefix, image_list_records, process_images, store_records, write_image_manifest,
+};
+use anyhow::{anyhow, Error};
+use database::{ContentStore, ContentType, ImageInfo};
+use log::*;
+use std::collections::HashMap;
+use std::path::Path;
+use storage::{content_type_for, md5_hex, ObjectStore, StorageConfig};
+
+/// Print every object under `prefix`, one per line: key, size, md5
+pub async fn list(store: &dyn ObjectStore, prefix: &str) -> Result<(), Error> {
+    let objects = store.list(prefix).await?;
+    for object in objects.iter() {
+        println!(
+            "{:<60}  {:>10}  {}",
+            object.key,
+            object.size,
+            object.md5.as_deref().unwrap_or("-")
+        );
+    }
+    info!(
+        "{} object(s) under {}/{}",
+        objects.len(),
+        store.location(),
+        prefix
+    );
+    Ok(())
+}
+
+/// Upload a file, to `key` or to its file name at the root of the store
+pub async fn put(store: &dyn ObjectStore, file: &Path, key: Option<&str>) -> Result<(), Error> {
+    let key = match key {
+        Some(key) => key.to_string(),
+        None => file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("{} is not a file", file.display()))?,
+    };
+    let data =
+        std::fs::read(file).map_err(|e| anyhow!("Failed to read {}: {}", file.display(), e))?;
+    let object = store.put(&key, data, &content_type_for(&key)).await?;
+    info!(
+        "Uploaded {} ({} bytes) to {}",
+        file.display(),
+        object.size,
+        store.public_url(&object.key)
+    );
+    Ok(())
+}
+
+/// Download an object, to `out` or to its file name in the current directory
+pub async fn get(store: &dyn ObjectStore, key: &str, out: Option<&Path>) -> Result<(), Error> {
+    let data = store.get(key).await?;
+    let out = match out {
+        Some(out) => out.to_path_buf(),
+        None => Path::new(key.rsplit('/').next().unwrap_or(key)).to_path_buf(),
+    };
+    std::fs::write(&out, &data).map_err(|e| anyhow!("Failed to write {}: {}", out.display(), e))?;
+    info!(
+        "Downloaded {} ({} bytes) to {}",
+        key,
+        data.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Delete objects. Every key must exist, nothing is deleted otherwise.
+pub async fn delete(store: &dyn ObjectStore, keys: &[String], dry_run: bool) -> Result<(), Error> {
+    for key in keys.iter() {
+        if store.head(key).await?.is_none() {
+            return Err(anyhow!("No object named {} in {}", key, store.location()));
+        }
+    }
+    for key in keys.iter() {
+        if dry_run {
+            info!("[dry run] delete {}", key);
+            continue;
+        }
+        store.delete(key).await?;
+        info!("Deleted {}", key);
+    }
+    Ok(())
+}
+
+/// Process a directory of images, upload the originals and their variants to
+/// `images/<content type>/`, then record the images in their manifest and cache.
+///
+/// Files already stored with the same md5 are not uploaded again. The manifest and cache are
+/// only written once every upload succeeded.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_images(
+    objects: &dyn ObjectStore,
+    storage: &StorageConfig,
+    store: &ContentStore,
+    content_type: ContentType,
+    dir: &Path,
+    images_out: &Path,
+    replace: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    if !content_type.is_image_list() {
+        return Err(anyhow!("{} is not an image list", content_type));
+    }
+    let images = process_images(content_type, dir, images_out, storage)?;
+
+    let prefix = image_key_prefix(content_type);
+    let stored = objects
+        .list(&prefix)
+        .await?
+        .into_iter()
+        .map(|object| (object.key, object.md5))
+        .collect::<HashMap<String, Option<String>>>();
+
+    let out_dir = images_out.join(content_type.name());
+    let url_prefix = storage.public_url(&prefix);
+    let (mut uploaded, mut unchanged) = (0, 0);
+    for file_name in image_files(&images, &url_prefix)? {
+        let key = format!("{}{}", prefix, file_name);
+        let path = out_dir.join(&file_name);
+        let data = std::fs::read(&path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let md5 = md5_hex(&data);
+        if stored.get(&key).and_then(|md5| md5.as_deref()) == Some(md5.as_str()) {
+            debug!("Unchanged: {}", key);
+            unchanged += 1;
+            continue;
+        }
+        if dry_run {
+            info!("[dry run] upload {} ({} bytes)", key, data.len());
+        } else {
+            objects.put(&key, data, &content_type_for(&key)).await?;
+            info!("Uploaded {}", key);
+        }
+        uploaded += 1;
+    }
+    info!(
+        "{}{} {} file(s) to {}, {} unchanged",
+        if dry_run { "[dry run] " } else { "" },
+        if dry_run { "Would upload" } else { "Uploaded" },
+        uploaded,
+        objects.location(),
+        unchanged
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    write_image_manifest(content_type, &images);
+    store_records(store, content_type, image_list_records(&images)?, replace)
+}
+
+/// File names of every original, variant and thumbnail of the images, relative to `url_prefix`
+fn image_files(images: &[ImageInfo], url_prefix: &str) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+    for image in images.iter() {
+        let urls = std::iter::once(&image.url)
+            .chain(image.thumbnail.iter().map(|thumbnail| &thumbnail.url))
+            .chain(image.variants.iter().map(|variant| &variant.url));
+        for url in urls {
+            let file_name = url
+                .strip_prefix(url_prefix)
+                .ok_or_else(|| anyhow!("{} is not under {}", url, url_prefix))?;
+            files.push(file_name.to_string());
+        }
+    }
+    Ok(files)
+}
@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Error};
+use database::postgres::DEFAULT_POOL_SIZE;
+use database::{ContentStore, StoreConfig};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use storage::{StorageConfig, DEFAULT_BUCKET, DEFAULT_LOCAL_DIR};
+
+/// Read from the working directory when `CONFIG_FILE` isn't set, if it exists, as the server does
+const DEFAULT_CONFIG_FILE: &str = "server.toml";
+
+/// The `[content]` and `[storage]` settings of the server's config file, so admin commands work
+/// on the content and assets the server serves. Env vars override the file as they do for the
+/// server, e.g. `CONTENT_STORE` for `content.store`.
+pub struct ServerSettings {
+    /// `section.key` of every setting in the file
+    values: BTreeMap<String, String>,
+}
+
+impl ServerSettings {
+    /// Read `CONFIG_FILE`, or `server.toml` if it exists
+    pub fn load() -> Result<Self, Error> {
+        let mut settings = ServerSettings {
+            values: BTreeMap::new(),
+        };
+        let (path, required) = match env("CONFIG_FILE") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(settings),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        let table = contents
+            .parse::<toml::Value>()
+            .map_err(|e| anyhow!("Invalid TOML in {}: {}", path.display(), e))?;
+        for section in ["content", "storage"] {
+            let keys = match table.get(section) {
+                Some(toml::Value::Table(keys)) => keys,
+                Some(_) => {
+                    return Err(anyhow!("{} in {} must be a table", section, path.display()))
+                }
+                None => continue,
+            };
+            for (key, value) in keys {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                settings
+                    .values
+                    .insert(format!("{}.{}", section, key), value);
+            }
+        }
+        Ok(settings)
+    }
+
+    /// `env_var` if set, otherwise `key` of the file
+    fn get(&self, key: &str, env_var: &str) -> Option<String> {
+        env(env_var).or_else(|| {
+            self.values
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .cloned()
+        })
+    }
+
+    /// The content store of `content.store`, the cache files in `cache` by default
+    pub fn open_store(&self, cache: PathBuf) -> Result<ContentStore, Error> {
+        let config = match self.get("content.store", "CONTENT_STORE").as_deref() {
+            None | Some("file") => StoreConfig::File { dir: cache },
+            Some("postgres") => StoreConfig::Postgres {
+                url: self
+                    .get("content.database_url", "DATABASE_URL")
+                    .ok_or_else(|| anyhow!("content.database_url (DATABASE_URL): is required for postgres"))?,
+                pool_size: match self.get("content.pool_size", "DATABASE_POOL_SIZE") {
+                    Some(size) => size
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|size| *size > 0)
+                        .ok_or_else(|| anyhow!("content.pool_size (DATABASE_POOL_SIZE): {} is not a number of connections", size))?,
+                    None => DEFAULT_POOL_SIZE,
+                },
+            },
+            Some(store) => {
+                return Err(anyhow!(
+                    "content.store (CONTENT_STORE): {} is not file or postgres",
+                    store
+                ))
+            }
+        };
+        Ok(config.open()?)
+    }
+
+    /// The object storage of `storage.backend`, the GCS bucket by default
+    pub fn storage(&self) -> Result<StorageConfig, Error> {
+        let public_url = self.get("storage.public_url", "STORAGE_PUBLIC_URL");
+        match self.get("storage.backend", "STORAGE_BACKEND").as_deref() {
+            None | Some("gcs") => Ok(StorageConfig::gcs(
+                self.get("storage.bucket", "STORAGE_BUCKET")
+                    .unwrap_or_else(|| DEFAULT_BUCKET.to_string()),
+                self.get("storage.emulator_host", "STORAGE_EMULATOR_HOST"),
+                public_url,
+            )),
+            Some("local") => Ok(StorageConfig::local(
+                self.get("storage.dir", "STORAGE_DIR")
+                    .unwrap_or_else(|| DEFAULT_LOCAL_DIR.to_string()),
+                public_url,
+            )?),
+            Some(backend) => Err(anyhow!(
+                "storage.backend (STORAGE_BACKEND): {} is not gcs or local",
+                backend
+            )),
+        }
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
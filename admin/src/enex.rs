@@ -0,0 +1,679 @@
+use crate::front_matter::{article_sources, render, split, ArticleMeta, MANIFEST_FILE};
+use crate::{slug, ArticleRaw};
+use anyhow::{anyhow, Error};
+use base64::Engine;
+use log::*;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use storage::StorageConfig;
+
+/// Attachments are written to `<out_dir>/images/<article slug>/`
+const IMAGES_DIR: &str = "images";
+
+/// One `<note>` of an Evernote export
+#[derive(Debug, Default)]
+struct Note {
+    title: String,
+    /// ENML, the XHTML subset Evernote stores note bodies in
+    content: String,
+    tags: Vec<String>,
+    resources: Vec<Resource>,
+}
+
+/// An attachment, referenced from ENML by the md5 of its data: `<en-media hash="..."/>`
+#[derive(Debug, Default)]
+struct Resource {
+    data: Vec<u8>,
+    mime: String,
+    file_name: Option<String>,
+}
+
+/// Convert every note of an `.enex` export into an article markdown file with front matter,
+/// write its attachments as image files and list the new files in `<out_dir>/articles.json`.
+///
+/// Notes get `tags` in addition to their own Evernote tags and are numbered after the
+/// highest index already in `out_dir`. Existing markdown files are only replaced with
+/// `overwrite`. Returns the number of articles written.
+pub fn import_enex(
+    enex_path: &Path,
+    out_dir: &Path,
+    tags: &[String],
+    premium: bool,
+    overwrite: bool,
+    storage: &StorageConfig,
+) -> Result<usize, Error> {
+    let enex = std::fs::read_to_string(enex_path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", enex_path.display(), e))?;
+    let notes =
+        parse_enex(&enex).map_err(|e| anyhow!("Failed to parse {}: {}", enex_path.display(), e))?;
+    info!("Found {} note(s) in {}", notes.len(), enex_path.display());
+
+    std::fs::create_dir_all(out_dir)?;
+    let mut next_index = next_article_index(out_dir)?;
+    let mut imported = Vec::new();
+    for note in notes.into_iter() {
+        let base = match slug(&note.title) {
+            base if base.is_empty() => "Untitled".to_string(),
+            base => base,
+        };
+        let file_name = format!("{}.md", base);
+        let path = out_dir.join(&file_name);
+        if path.exists() && !overwrite {
+            warn!(
+                "Skipping \"{}\": {} already exists, pass --overwrite to replace it",
+                note.title,
+                path.display()
+            );
+            continue;
+        }
+
+        let attachments = write_resources(&note, out_dir, &base)?;
+        let body = enml_to_markdown(&note.content, &attachments)?;
+
+        let mut note_tags = note.tags.clone();
+        for tag in tags.iter() {
+            if !note_tags.contains(tag) {
+                note_tags.push(tag.clone());
+            }
+        }
+        // the first image in the note becomes the article image
+        let image_url = note
+            .resources
+            .iter()
+            .filter_map(|resource| attachments.get(&format!("{:x}", md5::compute(&resource.data))))
+            .find(|attachment| attachment.is_image)
+            .map(|attachment| attachment.path.clone())
+            .unwrap_or_else(|| storage.public_url(&format!("images/articles/{}.png", base)));
+        let meta = ArticleMeta {
+            title: Some(note.title.trim().to_string()),
+            tags: Some(note_tags),
+            image_url: Some(image_url),
+            index: Some(next_index),
+            premium: Some(premium),
+        };
+        std::fs::write(&path, render(&meta, &body)?)?;
+        info!("Imported \"{}\" to {}", note.title, path.display());
+        next_index += 1;
+        imported.push(file_name);
+    }
+
+    add_manifest_entries(&out_dir.join(MANIFEST_FILE), &imported)?;
+    Ok(imported.len())
+}
+
+/// One past the highest article index in the manifest or front matter under `dir`
+fn next_article_index(dir: &Path) -> Result<u32, Error> {
+    let mut next = 0;
+    for source in article_sources(dir)?.into_iter() {
+        let manifest_index = source.manifest.and_then(|entry| entry.meta.index);
+        let front_matter_index = std::fs::read_to_string(&source.path)
+            .ok()
+            .and_then(|markdown| split(&markdown).ok().and_then(|(meta, _)| meta))
+            .and_then(|meta| meta.index);
+        if let Some(index) = front_matter_index.or(manifest_index) {
+            next = next.max(index + 1);
+        }
+    }
+    Ok(next)
+}
+
+/// Append `file_names` the manifest doesn't list yet, creating it if needed
+fn add_manifest_entries(manifest: &Path, file_names: &[String]) -> Result<(), Error> {
+    let mut entries = match std::fs::read_to_string(manifest) {
+        Ok(json) => serde_json::from_str::<Vec<Value>>(&json)
+            .map_err(|e| anyhow!("Failed to parse {}: {}", manifest.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", manifest.display(), e)),
+    };
+    let listed = entries
+        .iter()
+        .filter_map(|entry| entry.get("file_name").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect::<HashSet<String>>();
+
+    let mut added = 0;
+    for file_name in file_names.iter().filter(|name| !listed.contains(*name)) {
+        // metadata lives in the front matter
+        entries.push(serde_json::to_value(ArticleRaw {
+            file_name: file_name.clone(),
+            meta: ArticleMeta::default(),
+        })?);
+        added += 1;
+    }
+    if added > 0 {
+        std::fs::write(manifest, serde_json::to_string_pretty(&entries)?)?;
+        info!("Added {} entries to {}", added, manifest.display());
+    }
+    Ok(())
+}
+
+/// Where an attachment was written, relative to the articles directory
+#[derive(Debug, Clone)]
+struct Attachment {
+    path: String,
+    name: String,
+    is_image: bool,
+}
+
+/// Write a note's resources to `images/<slug>/`, keyed by the md5 hash ENML refers to them by
+fn write_resources(
+    note: &Note,
+    out_dir: &Path,
+    base: &str,
+) -> Result<HashMap<String, Attachment>, Error> {
+    let mut attachments = HashMap::new();
+    if note.resources.is_empty() {
+        return Ok(attachments);
+    }
+    let relative_dir = format!("{}/{}", IMAGES_DIR, base);
+    let dir = out_dir.join(&relative_dir);
+    std::fs::create_dir_all(&dir)?;
+
+    let mut taken = HashSet::new();
+    for resource in note.resources.iter() {
+        let hash = format!("{:x}", md5::compute(&resource.data));
+        let name = match resource.file_name.as_deref().map(resource_file_name) {
+            Some(name) if !name.is_empty() && !taken.contains(&name) => name,
+            _ => format!("{}.{}", hash, extension(&resource.mime)),
+        };
+        taken.insert(name.clone());
+        std::fs::write(dir.join(&name), &resource.data)?;
+        debug!("Wrote attachment {}/{}", dir.display(), name);
+        attachments.insert(
+            hash,
+            Attachment {
+                path: format!("{}/{}", relative_dir, name),
+                name,
+                is_image: resource.mime.starts_with("image/"),
+            },
+        );
+    }
+    Ok(attachments)
+}
+
+/// Attachment names keep only their final path component, with spaces as `-` like image uploads
+fn resource_file_name(name: &str) -> String {
+    name.rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .replace(' ', "-")
+}
+
+fn extension(mime: &str) -> &str {
+    match mime {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        "application/pdf" => "pdf",
+        mime => mime
+            .rsplit('/')
+            .next()
+            .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or("bin"),
+    }
+}
+
+fn element_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase()
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|attribute| {
+            attribute
+                .unescape_value_with(html_entity)
+                .ok()
+                .map(|value| value.to_string())
+        })
+}
+
+/// HTML entities ENML declares through its DTD, beyond the five XML ones
+fn html_entity(entity: &str) -> Option<&'static str> {
+    match entity {
+        "nbsp" => Some("\u{a0}"),
+        "ndash" => Some("–"),
+        "mdash" => Some("—"),
+        "lsquo" => Some("‘"),
+        "rsquo" => Some("’"),
+        "ldquo" => Some("“"),
+        "rdquo" => Some("”"),
+        "hellip" => Some("…"),
+        "bull" => Some("•"),
+        "middot" => Some("·"),
+        "copy" => Some("©"),
+        "reg" => Some("®"),
+        "trade" => Some("™"),
+        "deg" => Some("°"),
+        "times" => Some("×"),
+        _ => None,
+    }
+}
+
+fn parse_enex(enex: &str) -> Result<Vec<Note>, Error> {
+    let mut reader = Reader::from_str(enex);
+    reader.check_end_names(false);
+
+    let mut notes = Vec::new();
+    let mut note: Option<Note> = None;
+    let mut resource: Option<Resource> = None;
+    let mut path = Vec::<String>::new();
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = element_name(&element);
+                match name.as_str() {
+                    "note" => note = Some(Note::default()),
+                    "resource" => resource = Some(Resource::default()),
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Text(t) => text.push_str(&t.unescape_with(html_entity)?),
+            Event::CData(data) => text.push_str(&String::from_utf8_lossy(&data.into_inner())),
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                match (parent, name.as_str(), note.as_mut(), resource.as_mut()) {
+                    ("note", "title", Some(note), _) => note.title = text.trim().to_string(),
+                    ("note", "content", Some(note), _) => note.content = text.clone(),
+                    ("note", "tag", Some(note), _) => note.tags.push(text.trim().to_string()),
+                    ("resource", "data", _, Some(resource)) => {
+                        let encoded = text
+                            .chars()
+                            .filter(|c| !c.is_whitespace())
+                            .collect::<String>();
+                        resource.data = base64::engine::general_purpose::STANDARD
+                            .decode(encoded)
+                            .map_err(|e| anyhow!("invalid attachment data: {}", e))?;
+                    }
+                    ("resource", "mime", _, Some(resource)) => {
+                        resource.mime = text.trim().to_string()
+                    }
+                    ("resource-attributes", "file-name", _, Some(resource)) => {
+                        resource.file_name = Some(text.trim().to_string())
+                    }
+                    (_, "resource", Some(note), _) => {
+                        if let Some(resource) = resource.take() {
+                            note.resources.push(resource);
+                        }
+                    }
+                    (_, "note", _, _) => {
+                        if let Some(note) = note.take() {
+                            notes.push(note);
+                        }
+                    }
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(notes)
+}
+
+/// What an open ENML element does once it closes
+#[derive(Debug)]
+enum Open {
+    Block,
+    Heading,
+    Inline(&'static str),
+    Link(String),
+    List,
+    ListItem,
+    Quote,
+    Code,
+    /// A line of an Evernote code block
+    CodeLine,
+    Cell,
+    Row,
+    Skip,
+    Nothing,
+}
+
+/// Streams ENML elements into markdown
+struct MarkdownWriter<'a> {
+    out: String,
+    attachments: &'a HashMap<String, Attachment>,
+    open: Vec<Open>,
+    /// One entry per open list, the next number of ordered lists
+    lists: Vec<Option<u32>>,
+    quote: usize,
+    code: usize,
+    skip: usize,
+}
+
+impl<'a> MarkdownWriter<'a> {
+    fn new(attachments: &'a HashMap<String, Attachment>) -> Self {
+        Self {
+            out: String::new(),
+            attachments,
+            open: Vec::new(),
+            lists: Vec::new(),
+            quote: 0,
+            code: 0,
+            skip: 0,
+        }
+    }
+
+    fn prefix(&self) -> String {
+        "> ".repeat(self.quote)
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n') || self.out.ends_with("> ")
+    }
+
+    /// End the current line
+    fn newline(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+        self.out.push_str(&self.prefix());
+    }
+
+    /// Start a new paragraph. Inside list items lines only break, so the list stays together.
+    fn block(&mut self) {
+        if self.out.trim().is_empty() {
+            self.out.clear();
+            self.out.push_str(&self.prefix());
+            return;
+        }
+        let trimmed = self.out.trim_end().len();
+        self.out.truncate(trimmed);
+        if self.lists.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push('\n');
+        self.out.push_str(&self.prefix());
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip > 0 {
+            return;
+        }
+        if self.code > 0 {
+            self.out.push_str(text);
+            return;
+        }
+        let text = text.replace('\u{a0}', " ");
+        let mut collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if collapsed.is_empty() {
+            if !self.at_line_start() && !text.is_empty() && !self.out.ends_with(' ') {
+                self.out.push(' ');
+            }
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && !self.at_line_start() {
+            collapsed.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+        if self.at_line_start() {
+            collapsed = collapsed.trim_start().to_string();
+        }
+        self.out.push_str(&collapsed);
+    }
+
+    fn start(&mut self, element: &BytesStart, empty: bool) {
+        let name = element_name(element);
+        if self.skip > 0 {
+            if !empty {
+                self.skip += 1;
+                self.open.push(Open::Skip);
+            }
+            return;
+        }
+
+        let open = match name.as_str() {
+            "p" | "div" | "en-note" => {
+                let style = attribute(element, "style").unwrap_or_default();
+                if self.code > 0 {
+                    Open::CodeLine
+                } else if style.contains("-en-codeblock") {
+                    self.block();
+                    self.out.push_str("```");
+                    self.newline();
+                    self.code += 1;
+                    Open::Code
+                } else if self.lists.is_empty() {
+                    self.block();
+                    Open::Block
+                } else {
+                    Open::Nothing
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block();
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                self.out.push_str(&format!("{} ", "#".repeat(level)));
+                Open::Heading
+            }
+            "b" | "strong" => {
+                self.out.push_str("**");
+                Open::Inline("**")
+            }
+            "i" | "em" => {
+                self.out.push('_');
+                Open::Inline("_")
+            }
+            "s" | "strike" | "del" => {
+                self.out.push_str("~~");
+                Open::Inline("~~")
+            }
+            "code" if self.code == 0 => {
+                self.out.push('`');
+                Open::Inline("`")
+            }
+            "a" => match attribute(element, "href") {
+                Some(href) => {
+                    self.out.push('[');
+                    Open::Link(href)
+                }
+                None => Open::Nothing,
+            },
+            "ul" | "ol" => {
+                if self.lists.is_empty() {
+                    self.block();
+                }
+                self.lists.push((name == "ol").then_some(1));
+                Open::List
+            }
+            "li" => {
+                if !self.at_line_start() {
+                    self.newline();
+                }
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "-".to_string(),
+                };
+                self.out
+                    .push_str(&format!("{}{} ", "   ".repeat(depth), marker));
+                Open::ListItem
+            }
+            "blockquote" => {
+                self.quote += 1;
+                self.block();
+                Open::Quote
+            }
+            "pre" => {
+                self.block();
+                self.out.push_str("```");
+                self.newline();
+                self.code += 1;
+                Open::Code
+            }
+            "br" => {
+                if self.code > 0 {
+                    self.out.push('\n');
+                } else if !self.at_line_start() {
+                    self.newline();
+                }
+                Open::Nothing
+            }
+            "hr" => {
+                self.block();
+                self.out.push_str("---");
+                self.block();
+                Open::Nothing
+            }
+            "tr" => {
+                if !self.at_line_start() {
+                    self.newline();
+                }
+                self.out.push_str("| ");
+                Open::Row
+            }
+            "td" | "th" => Open::Cell,
+            "en-todo" => {
+                let checked = attribute(element, "checked").map_or(false, |c| c == "true");
+                if self.at_line_start() && self.lists.is_empty() {
+                    self.out.push_str("- ");
+                }
+                self.out.push_str(if checked { "[x] " } else { "[ ] " });
+                Open::Nothing
+            }
+            "en-media" => {
+                let hash = attribute(element, "hash").unwrap_or_default();
+                match self.attachments.get(&hash) {
+                    Some(attachment) if attachment.is_image => {
+                        self.out.push_str(&format!("![]({})", attachment.path))
+                    }
+                    Some(attachment) => self
+                        .out
+                        .push_str(&format!("[{}]({})", attachment.name, attachment.path)),
+                    None => warn!("Note references missing attachment {}", hash),
+                }
+                Open::Nothing
+            }
+            "img" => {
+                if let Some(src) = attribute(element, "src") {
+                    let alt = attribute(element, "alt").unwrap_or_default();
+                    self.out.push_str(&format!("![{}]({})", alt, src));
+                }
+                Open::Nothing
+            }
+            "en-crypt" | "script" | "style" | "head" | "title" => {
+                self.skip += 1;
+                Open::Skip
+            }
+            _ => Open::Nothing,
+        };
+
+        if empty {
+            self.end(open);
+        } else {
+            self.open.push(open);
+        }
+    }
+
+    fn end(&mut self, open: Open) {
+        match open {
+            Open::Block | Open::Heading => self.block(),
+            Open::Inline(marker) => {
+                // markdown emphasis can't end on whitespace
+                let trailing = self.out.len() - self.out.trim_end().len();
+                let trimmed = self.out.trim_end().len();
+                self.out.truncate(trimmed);
+                self.out.push_str(marker);
+                self.out.push_str(&" ".repeat(trailing.min(1)));
+            }
+            Open::Link(href) => self.out.push_str(&format!("]({})", href)),
+            Open::List => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block();
+                }
+            }
+            Open::ListItem => {
+                if !self.at_line_start() {
+                    self.newline();
+                }
+            }
+            Open::Quote => {
+                self.quote = self.quote.saturating_sub(1);
+                self.block();
+            }
+            Open::Code => {
+                self.code = self.code.saturating_sub(1);
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("```");
+                self.block();
+            }
+            Open::CodeLine => {
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+            }
+            Open::Cell => self.out.push_str(" | "),
+            Open::Row => {
+                let trimmed = self.out.trim_end().len();
+                self.out.truncate(trimmed);
+                self.newline();
+            }
+            Open::Skip => self.skip = self.skip.saturating_sub(1),
+            Open::Nothing => {}
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut markdown = String::new();
+        let mut blank_lines = 0;
+        for line in self.out.trim().lines() {
+            let line = line.trim_end();
+            if line.trim_start_matches(['>', ' ']).is_empty() {
+                blank_lines += 1;
+                if blank_lines > 1 {
+                    continue;
+                }
+            } else {
+                blank_lines = 0;
+            }
+            markdown.push_str(line);
+            markdown.push('\n');
+        }
+        markdown
+    }
+}
+
+/// Convert an ENML note body to markdown, linking `<en-media>` to the written attachments
+fn enml_to_markdown(
+    enml: &str,
+    attachments: &HashMap<String, Attachment>,
+) -> Result<String, Error> {
+    let mut reader = Reader::from_str(enml);
+    reader.check_end_names(false);
+    let mut writer = MarkdownWriter::new(attachments);
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => writer.start(&element, false),
+            Event::Empty(element) => writer.start(&element, true),
+            Event::End(_) => {
+                if let Some(open) = writer.open.pop() {
+                    writer.end(open);
+                }
+            }
+            Event::Text(t) => writer.text(&t.unescape_with(html_entity)?),
+            Event::CData(data) => writer.text(&String::from_utf8_lossy(&data.into_inner())),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(writer.finish())
+}
@@ -0,0 +1,153 @@
+use crate::front_matter::{render, ArticleMeta};
+use crate::images::ImageEntry;
+use crate::ArticleRaw;
+use anyhow::Error;
+use database::{Article, Calibration, ContentStore, ContentType, ImageInfo, Testimonial};
+use log::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+/// Calibrations are split into one manifest per category tag, "All" is shared by every record
+const SHARED_CALIBRATION_TAG: &str = "All";
+
+/// Write a cache back out in the same layout `admin upsert` ingests from `data/`,
+/// e.g. `<out_dir>/articles/articles.json` plus one markdown file per article,
+/// with the article's metadata in its front matter.
+///
+/// Records are sorted so repeated exports of the same cache produce identical files.
+/// Returns the number of records exported.
+pub fn export(
+    store: &ContentStore,
+    content_type: ContentType,
+    out_dir: &Path,
+) -> Result<usize, Error> {
+    let dir = out_dir.join(content_type.name());
+    std::fs::create_dir_all(&dir)?;
+
+    let count = match content_type {
+        ContentType::Articles => {
+            let mut articles = store
+                .load::<Article>(content_type)?
+                .into_iter()
+                .map(|(_, article)| article)
+                .collect::<Vec<Article>>();
+            articles.sort_by(|a, b| a.index.cmp(&b.index).then(a.title.cmp(&b.title)));
+
+            let mut file_names = HashSet::new();
+            let mut manifest = Vec::new();
+            for article in articles.into_iter() {
+                // front matter carries the metadata, the manifest only lists the files
+                let file_name = unique_file_name(&article.title, &mut file_names);
+                let markdown = render(&ArticleMeta::from_article(&article), &article.data)?;
+                std::fs::write(dir.join(&file_name), markdown)?;
+                manifest.push(ArticleRaw {
+                    file_name,
+                    meta: ArticleMeta::default(),
+                });
+            }
+            write_manifest(&dir.join("articles.json"), &manifest)?;
+            manifest.len()
+        }
+        ContentType::Calibrations => {
+            let mut groups = BTreeMap::<String, Vec<Calibration>>::new();
+            for (_, calibration) in store.load::<Calibration>(content_type)? {
+                groups
+                    .entry(calibration_group(&calibration))
+                    .or_default()
+                    .push(calibration);
+            }
+            let mut count = 0;
+            for (group, mut calibrations) in groups.into_iter() {
+                calibrations.sort_by(|a, b| {
+                    b.calibration
+                        .cmp(&a.calibration)
+                        .then(a.title.cmp(&b.title))
+                });
+                write_manifest(&dir.join(format!("{}.json", group)), &calibrations)?;
+                count += calibrations.len();
+            }
+            count
+        }
+        ContentType::Testimonials => {
+            let mut testimonials = store
+                .load::<Testimonial>(content_type)?
+                .into_iter()
+                .map(|(_, testimonial)| testimonial)
+                .collect::<Vec<Testimonial>>();
+            testimonials.sort_by(|a, b| {
+                a.image_url
+                    .cmp(&b.image_url)
+                    .then(a.testimonial.cmp(&b.testimonial))
+            });
+            write_manifest(&dir.join("testimonials.json"), &testimonials)?;
+            testimonials.len()
+        }
+        ContentType::TestimonialImages
+        | ContentType::CategoryImages
+        | ContentType::ContentTypeImages => {
+            let mut images = store
+                .read(content_type)?
+                .values()
+                .map(|image| ImageInfo::de(image))
+                .collect::<Result<Vec<ImageInfo>, Error>>()?;
+            images.sort_by(|a, b| a.url.cmp(&b.url));
+            let entries = images
+                .into_iter()
+                .map(ImageEntry::from)
+                .collect::<Vec<ImageEntry>>();
+            write_manifest(&dir.join(format!("{}.json", content_type.name())), &entries)?;
+            entries.len()
+        }
+    };
+
+    info!("Exported {} {} to {}", count, content_type, dir.display());
+    Ok(count)
+}
+
+fn write_manifest<T: Serialize>(path: &Path, records: &[T]) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(records)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Manifest a calibration belongs in, named after its first category tag, e.g. `books`
+fn calibration_group(calibration: &Calibration) -> String {
+    calibration
+        .tags
+        .iter()
+        .find(|tag| tag.as_str() != SHARED_CALIBRATION_TAG)
+        .map(|tag| slug(tag).to_lowercase())
+        .filter(|group| !group.is_empty())
+        .unwrap_or_else(|| ContentType::Calibrations.name().to_string())
+}
+
+/// Markdown file name for an article title, following the existing `data/articles` naming:
+/// "What Does a Solid Self-Concept Look Like?" -> "What_Does_a_Solid_Self-Concept_Look_Like.md"
+fn unique_file_name(title: &str, taken: &mut HashSet<String>) -> String {
+    let base = match slug(title) {
+        slug if slug.is_empty() => "article".to_string(),
+        slug => slug,
+    };
+    let mut file_name = format!("{}.md", base);
+    let mut suffix = 2;
+    while !taken.insert(file_name.to_lowercase()) {
+        file_name = format!("{}_{}.md", base, suffix);
+        suffix += 1;
+    }
+    file_name
+}
+
+/// Keep letters, digits, `-` and `'`, join words with `_`
+pub fn slug(title: &str) -> String {
+    title
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '\'')
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<String>>()
+        .join("_")
+}
@@ -0,0 +1,241 @@
+use crate::ArticleRaw;
+use anyhow::{anyhow, Error};
+use database::Article;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+const DELIMITER: &str = "---";
+pub const MANIFEST_FILE: &str = "articles.json";
+
+/// Article metadata, either from the YAML front matter at the top of a markdown file
+///
+/// ```markdown
+/// ---
+/// title: Relationship Hack #1
+/// tags: [Relationships, All]
+/// image_url: https://storage.googleapis.com/consciousness-archive/images/articles/hack.png
+/// index: 11
+/// premium: false
+/// ---
+/// # Relationship Hack #1
+/// ```
+///
+/// or from an `articles.json` entry. Front matter wins where both are set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArticleMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub premium: Option<bool>,
+}
+
+impl ArticleMeta {
+    pub fn from_article(article: &Article) -> Self {
+        Self {
+            title: Some(article.title.clone()),
+            tags: Some(article.tags.clone()),
+            image_url: Some(article.image_url.clone()),
+            index: Some(article.index),
+            premium: Some(article.premium),
+        }
+    }
+}
+
+/// Split a markdown file into its front matter, if it has any, and the article body.
+/// The body has leading blank lines removed, as `admin upsert` always has.
+pub fn split(markdown: &str) -> Result<(Option<ArticleMeta>, &str), Error> {
+    let body = markdown.trim_start_matches('\u{feff}');
+    let rest = match body.strip_prefix(DELIMITER).and_then(|rest| {
+        rest.strip_prefix('\n')
+            .or_else(|| rest.strip_prefix("\r\n"))
+    }) {
+        Some(rest) => rest,
+        None => return Ok((None, markdown.trim_start_matches('\n'))),
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            let yaml = &rest[..offset];
+            let meta = if yaml.trim().is_empty() {
+                ArticleMeta::default()
+            } else {
+                serde_yaml::from_str::<ArticleMeta>(yaml)
+                    .map_err(|e| anyhow!("invalid front matter: {}", e))?
+            };
+            let body = &rest[offset + line.len()..];
+            return Ok((Some(meta), body.trim_start_matches('\n')));
+        }
+        offset += line.len();
+    }
+    Err(anyhow!(
+        "front matter is missing its closing `{}`",
+        DELIMITER
+    ))
+}
+
+/// Prefix an article body with its metadata as front matter
+pub fn render(meta: &ArticleMeta, body: &str) -> Result<String, Error> {
+    let yaml = serde_yaml::to_string(meta)?;
+    // serde_yaml starts documents with their own `---`
+    let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml).trim_end();
+    Ok(format!(
+        "{}\n{}\n{}\n\n{}",
+        DELIMITER, yaml, DELIMITER, body
+    ))
+}
+
+/// An article with front matter and manifest merged
+#[derive(Debug)]
+pub struct Resolved {
+    pub article: Article,
+    /// Fields set differently in front matter and manifest, the front matter value was used
+    pub conflicts: Vec<String>,
+}
+
+/// Merge front matter over a manifest entry. Errors name every field neither one sets.
+pub fn resolve(
+    front_matter: Option<&ArticleMeta>,
+    manifest: Option<&ArticleMeta>,
+    body: &str,
+) -> Result<Resolved, Error> {
+    let empty = ArticleMeta::default();
+    let front_matter = front_matter.unwrap_or(&empty);
+    let manifest = manifest.unwrap_or(&empty);
+    let mut conflicts = Vec::new();
+    let mut missing = Vec::new();
+
+    fn pick<T: Clone + PartialEq + Debug>(
+        field: &str,
+        front_matter: &Option<T>,
+        manifest: &Option<T>,
+        conflicts: &mut Vec<String>,
+        missing: &mut Vec<String>,
+    ) -> Option<T> {
+        match (front_matter, manifest) {
+            (Some(ours), Some(theirs)) if ours != theirs => conflicts.push(format!(
+                "{} is {:?} in front matter but {:?} in {}",
+                field, ours, theirs, MANIFEST_FILE
+            )),
+            (None, None) => missing.push(field.to_string()),
+            _ => {}
+        }
+        front_matter.clone().or_else(|| manifest.clone())
+    }
+
+    let title = pick(
+        "title",
+        &front_matter.title,
+        &manifest.title,
+        &mut conflicts,
+        &mut missing,
+    );
+    let tags = pick(
+        "tags",
+        &front_matter.tags,
+        &manifest.tags,
+        &mut conflicts,
+        &mut missing,
+    );
+    let image_url = pick(
+        "image_url",
+        &front_matter.image_url,
+        &manifest.image_url,
+        &mut conflicts,
+        &mut missing,
+    );
+    let index = pick(
+        "index",
+        &front_matter.index,
+        &manifest.index,
+        &mut conflicts,
+        &mut missing,
+    );
+    let premium = pick(
+        "premium",
+        &front_matter.premium,
+        &manifest.premium,
+        &mut conflicts,
+        &mut missing,
+    );
+
+    match (title, tags, image_url, index, premium) {
+        (Some(title), Some(tags), Some(image_url), Some(index), Some(premium)) => Ok(Resolved {
+            article: Article {
+                title,
+                tags,
+                data: body.to_string(),
+                image_url,
+                index,
+                premium,
+            },
+            conflicts,
+        }),
+        _ => Err(anyhow!(
+            "missing {} in both front matter and {}",
+            missing.join(", "),
+            MANIFEST_FILE
+        )),
+    }
+}
+
+/// A markdown file to ingest, with its manifest entry if it has one
+#[derive(Debug)]
+pub struct ArticleSource {
+    pub path: PathBuf,
+    pub manifest: Option<ArticleRaw>,
+}
+
+/// Every article under `path`, which is either an `articles.json` manifest or a directory.
+///
+/// Manifest entries come first, in manifest order, followed by the markdown files next to
+/// it that the manifest doesn't list, which must then be fully described by front matter.
+pub fn article_sources(path: &Path) -> Result<Vec<ArticleSource>, Error> {
+    let (dir, manifest_path) = if path.is_dir() {
+        (path.to_path_buf(), path.join(MANIFEST_FILE))
+    } else {
+        (
+            path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            path.to_path_buf(),
+        )
+    };
+
+    let mut sources = Vec::new();
+    if path.is_file() || manifest_path.exists() {
+        let manifest = std::fs::read_to_string(&manifest_path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", manifest_path.display(), e))?;
+        let entries = serde_json::from_str::<Vec<ArticleRaw>>(&manifest)
+            .map_err(|e| anyhow!("Failed to parse {}: {}", manifest_path.display(), e))?;
+        for entry in entries.into_iter() {
+            sources.push(ArticleSource {
+                path: dir.join(&entry.file_name),
+                manifest: Some(entry),
+            });
+        }
+    }
+
+    let listed = sources
+        .iter()
+        .map(|source| source.path.clone())
+        .collect::<HashSet<PathBuf>>();
+    let mut unlisted = std::fs::read_dir(&dir)
+        .map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "md"))
+        .filter(|path| !listed.contains(path))
+        .collect::<Vec<PathBuf>>();
+    unlisted.sort();
+    sources.extend(unlisted.into_iter().map(|path| ArticleSource {
+        path,
+        manifest: None,
+    }));
+    Ok(sources)
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Error};
+use database::{ImageInfo, ImageVariant};
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, GenericImageView};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Widths of the responsive variants, only those narrower than the source are generated
+pub const VARIANT_WIDTHS: [u32; 3] = [320, 640, 1280];
+/// Thumbnails are square crops of this size
+pub const THUMBNAIL_SIZE: u32 = 160;
+const WEBP_QUALITY: u8 = 80;
+/// Blurhash components along the longer side, 3 along the shorter
+const BLURHASH_COMPONENTS: u32 = 4;
+/// Images are shrunk to this width before computing the blurhash, the result is the same
+const BLURHASH_SAMPLE_WIDTH: u32 = 64;
+
+/// Entry of an image list manifest, e.g. `data/testimonial_images/testimonial_images.json`.
+/// Images that were never processed are listed as bare URLs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ImageEntry {
+    Url(String),
+    Info(ImageInfo),
+}
+
+impl ImageEntry {
+    pub fn into_info(self) -> ImageInfo {
+        match self {
+            ImageEntry::Url(url) => ImageInfo::from_url(url),
+            ImageEntry::Info(info) => info,
+        }
+    }
+}
+
+impl From<ImageInfo> for ImageEntry {
+    fn from(info: ImageInfo) -> Self {
+        if info == ImageInfo::from_url(info.url.clone()) {
+            ImageEntry::Url(info.url)
+        } else {
+            ImageEntry::Info(info)
+        }
+    }
+}
+
+/// Image file names as they're uploaded: spaces become `-`
+pub fn image_file_name(file_name: &str) -> String {
+    file_name.replace(' ', "-")
+}
+
+/// Process one source image into `out_dir`: a copy of the original, a full size WebP,
+/// WebP variants for every [`VARIANT_WIDTHS`] narrower than the source and a square
+/// thumbnail. `url_prefix` is the URL `out_dir` is served from, e.g.
+/// `https://storage.googleapis.com/consciousness-archive/images/testimonial_images/`.
+///
+/// Files the `image` crate can't decode (e.g. SVG) are copied as is, with only a URL.
+pub fn process_image(source: &Path, out_dir: &Path, url_prefix: &str) -> Result<ImageInfo, Error> {
+    let file_name = source
+        .file_name()
+        .map(|name| image_file_name(&name.to_string_lossy()))
+        .ok_or_else(|| anyhow!("{} is not a file", source.display()))?;
+    let stem = Path::new(&file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| file_name.clone());
+    std::fs::create_dir_all(out_dir)?;
+    std::fs::copy(source, out_dir.join(&file_name))?;
+    let url = format!("{}{}", url_prefix, file_name);
+
+    let image = match image::open(source) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!(
+                "Not processing {}, it can't be decoded: {}",
+                source.display(),
+                e
+            );
+            return Ok(ImageInfo::from_url(url));
+        }
+    };
+    let (width, height) = image.dimensions();
+
+    let mut variants = Vec::new();
+    for variant_width in VARIANT_WIDTHS.into_iter().filter(|w| *w < width) {
+        let resized = image.resize(variant_width, u32::MAX, FilterType::Lanczos3);
+        let variant_name = format!("{}-{}w.webp", stem, variant_width);
+        variants.push(write_webp(&resized, out_dir, &variant_name, url_prefix)?);
+    }
+    variants.push(write_webp(
+        &image,
+        out_dir,
+        &format!("{}.webp", stem),
+        url_prefix,
+    )?);
+
+    let thumbnail = image.resize_to_fill(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle);
+    let thumbnail = write_webp(
+        &thumbnail,
+        out_dir,
+        &format!("{}-thumb.webp", stem),
+        url_prefix,
+    )?;
+
+    debug!(
+        "Processed {} ({}x{}) into {} variants",
+        source.display(),
+        width,
+        height,
+        variants.len()
+    );
+    Ok(ImageInfo {
+        url,
+        width: Some(width),
+        height: Some(height),
+        blurhash: Some(blurhash(&image)),
+        thumbnail: Some(thumbnail),
+        variants,
+    })
+}
+
+fn write_webp(
+    image: &DynamicImage,
+    out_dir: &Path,
+    file_name: &str,
+    url_prefix: &str,
+) -> Result<ImageVariant, Error> {
+    // libwebp only takes 8 bit RGB(A)
+    let (data, color) = if image.color().has_alpha() {
+        (image.to_rgba8().into_raw(), ColorType::Rgba8)
+    } else {
+        (image.to_rgb8().into_raw(), ColorType::Rgb8)
+    };
+    let mut webp = Vec::new();
+    WebPEncoder::new_with_quality(&mut webp, WebPQuality::lossy(WEBP_QUALITY)).encode(
+        &data,
+        image.width(),
+        image.height(),
+        color,
+    )?;
+    std::fs::write(out_dir.join(file_name), webp)?;
+
+    Ok(ImageVariant {
+        url: format!("{}{}", url_prefix, file_name),
+        width: image.width(),
+        height: image.height(),
+        format: "webp".to_string(),
+    })
+}
+
+fn blurhash(image: &DynamicImage) -> String {
+    let sample = image.resize(BLURHASH_SAMPLE_WIDTH, u32::MAX, FilterType::Triangle);
+    let (width, height) = sample.dimensions();
+    let (components_x, components_y) = if width >= height {
+        (BLURHASH_COMPONENTS, 3)
+    } else {
+        (3, BLURHASH_COMPONENTS)
+    };
+    blurhash::encode(
+        components_x,
+        components_y,
+        width,
+        height,
+        &sample.to_rgba8().into_raw(),
+    )
+}
@@ -0,0 +1,45 @@
+use anyhow::{anyhow, Error};
+use database::{AdminKeyStore, AdminRole};
+use log::*;
+
+/// Issue a key and print it, it is only stored hashed and can't be shown again
+pub fn create(store: &AdminKeyStore, name: &str, role: AdminRole) -> Result<(), Error> {
+    let (key, token) = store.create(name, role)?;
+    info!(
+        "Created {} key {} for {} in {}, it can't be shown again",
+        key.role,
+        key.id,
+        key.name,
+        store.path().display()
+    );
+    println!("{}", token);
+    Ok(())
+}
+
+pub fn list(store: &AdminKeyStore) -> Result<(), Error> {
+    let keys = store.list()?;
+    for key in keys.iter() {
+        let revoked = match key.revoked_at {
+            Some(revoked_at) => format!("revoked {}", revoked_at.to_rfc3339()),
+            None => "active".to_string(),
+        };
+        println!(
+            "{:<12}  {:<14}  {}  {:<34}  {}",
+            key.id,
+            key.role,
+            key.created_at.to_rfc3339(),
+            revoked,
+            key.name
+        );
+    }
+    info!("{} key(s) in {}", keys.len(), store.path().display());
+    Ok(())
+}
+
+pub fn revoke(store: &AdminKeyStore, id: &str) -> Result<(), Error> {
+    let key = store
+        .revoke(id)?
+        .ok_or_else(|| anyhow!("No admin key {} in {}", id, store.path().display()))?;
+    info!("Revoked {} key {} of {}", key.role, key.id, key.name);
+    Ok(())
+}
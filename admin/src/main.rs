@@ -1,17 +1,30 @@
-use anyhow::{anyhow, Error};
-use clap::{ArgEnum, Parser};
-use database::{Article, Calibration, MessageHasher, MessageHasherTrait, Testimonial};
+mod assets;
+mod config;
+mod enex;
+mod export;
+mod front_matter;
+mod images;
+mod keys;
+mod records;
+mod snapshot;
+mod upsert;
+mod validate;
+
+use anyhow::Error;
+use clap::{Parser, Subcommand};
+use config::ServerSettings;
+use database::{AdminKeyStore, AdminRole, ContentType};
 use dotenv::dotenv;
+use enex::*;
+use export::*;
+use front_matter::ArticleMeta;
 use log::*;
-use serde::Deserialize;
+use records::*;
+use serde::{Deserialize, Serialize};
 use simplelog::{ColorChoice, Config as SimpleLogConfig, TermLogger, TerminalMode};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
 use std::path::PathBuf;
-use std::str::FromStr;
-
-pub const GCLOUD_STORAGE_PREFIX: &str = "https://storage.googleapis.com/consciousness-archive/";
+use upsert::*;
+use validate::*;
 
 fn init_logger() {
     TermLogger::init(
@@ -23,51 +36,301 @@ fn init_logger() {
     .expect("Failed to initialize logger");
 }
 
-#[derive(ArgEnum, Debug, Clone)]
-enum FileType {
-    Articles,
-    Calibrations,
-    Testimonials,
-    TestimonialImages,
-    CategoryImages,
-    ContentTypeImages,
+/// Entry of `data/articles/articles.json`.
+/// Metadata set in the markdown file's front matter takes precedence over the entry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArticleRaw {
+    file_name: String,
+    #[serde(flatten)]
+    meta: ArticleMeta,
 }
 
-impl FromStr for FileType {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "articles" => Ok(FileType::Articles),
-            "calibrations" => Ok(FileType::Calibrations),
-            "testimonials" => Ok(FileType::Testimonials),
-            "testimonial_images" => Ok(FileType::TestimonialImages),
-            "content_type_images" => Ok(FileType::ContentTypeImages),
-            "category_images" => Ok(FileType::CategoryImages),
-            _ => Err(format!("{} is not a valid file type", s)),
-        }
-    }
+#[derive(Parser, Debug)]
+struct Args {
+    #[clap(subcommand)]
+    command: Command,
 }
 
-#[derive(Deserialize, Debug)]
-struct ArticleRaw {
-    title: String,
-    tags: Vec<String>,
-    file_name: String,
-    image_url: String,
-    index: u32,
-    premium: bool,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Upsert manifest files (or directories of images) into their cache
+    Upsert {
+        /// File type (articles, calibrations, testimonials, etc)
+        #[clap(short)]
+        t: ContentType,
+
+        /// Path to file/folder, may be repeated
+        #[clap(short, required = true)]
+        f: Vec<String>,
+
+        /// Replace the whole cache with these records instead of merging into it
+        #[clap(long)]
+        replace: bool,
+
+        /// Where processed images and their variants are written, laid out like the bucket
+        #[clap(long, default_value = "processed_images")]
+        images_out: PathBuf,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Check every data/ manifest and cache for problems.
+    /// Exits 1 if any errors are found, 2 if validation could not run.
+    Validate {
+        /// Data directory holding the content manifests
+        #[clap(long, default_value = "data")]
+        data: PathBuf,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+
+        /// Fail on warnings as well as errors
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Write the cache back out in the data/ layout that `upsert` ingests
+    Export {
+        /// File type to export, every type if omitted
+        #[clap(short)]
+        t: Option<ContentType>,
+
+        /// Directory to write into, laid out like data/
+        #[clap(short, long, default_value = "export")]
+        out: PathBuf,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// List the id, slug and title of every record of a content type
+    List {
+        /// File type (articles, calibrations, testimonials, etc)
+        #[clap(short)]
+        t: ContentType,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Print a record as JSON
+    Show {
+        /// File type (articles, calibrations, testimonials, etc)
+        #[clap(short)]
+        t: ContentType,
+
+        /// Record id, slug or title
+        selector: String,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Edit a record in place, e.g. `--set premium=true` or `--set data=@article.md`
+    Update {
+        /// File type (articles, calibrations, testimonials, etc)
+        #[clap(short)]
+        t: ContentType,
+
+        /// Record id, slug or title
+        selector: String,
+
+        /// JSON file holding the replacement record
+        #[clap(short)]
+        f: Option<PathBuf>,
+
+        /// Field to change, string fields take the value as is, others parse it as JSON
+        #[clap(long, value_name = "FIELD=VALUE")]
+        set: Vec<String>,
+
+        /// Print what would change without writing the cache
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Remove a record from its cache
+    Delete {
+        /// File type (articles, calibrations, testimonials, etc)
+        #[clap(short)]
+        t: ContentType,
+
+        /// Record id, slug or title
+        selector: String,
+
+        /// Print what would be deleted without writing the cache
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Convert an Evernote .enex export into article markdown, images and manifest entries
+    ImportEnex {
+        /// Path to the .enex file
+        #[clap(short)]
+        f: PathBuf,
+
+        /// Articles directory to write into
+        #[clap(short, long, default_value = "data/articles")]
+        out: PathBuf,
+
+        /// Tag added to every imported article, may be repeated
+        #[clap(long)]
+        tag: Vec<String>,
+
+        /// Mark imported articles as premium
+        #[clap(long)]
+        premium: bool,
+
+        /// Replace markdown files that already exist
+        #[clap(long)]
+        overwrite: bool,
+    },
+    /// Create, list, restore and prune snapshots of every cache
+    Snapshot {
+        #[clap(subcommand)]
+        command: SnapshotCommand,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// List, upload, download and delete objects in the storage backend set by STORAGE_* env vars
+    Assets {
+        #[clap(subcommand)]
+        command: AssetsCommand,
+    },
+    /// Issue, list and revoke API keys for the server's /admin routes
+    Keys {
+        #[clap(subcommand)]
+        command: KeysCommand,
+
+        /// Cache directory holding admin_keys.json, unless ADMIN_KEYS_FILE is set
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
 }
 
-#[derive(Parser, Debug)]
-struct Args {
-    /// File type (articles, calibrations, testimonials, etc)
-    #[clap(short)]
-    t: FileType,
+#[derive(Subcommand, Debug)]
+enum KeysCommand {
+    /// Issue a key and print it, it can't be shown again
+    Create {
+        /// Who or what the key is for, e.g. "support laptop"
+        #[clap(long)]
+        name: String,
+
+        /// support, billing, content_editor or owner
+        #[clap(long)]
+        role: AdminRole,
+    },
+    /// List every key ever issued, revoked ones included
+    List,
+    /// Revoke a key, the server rejects it from the next request on
+    Revoke {
+        /// Key id, as printed by `list`
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AssetsCommand {
+    /// List objects, with their size and md5
+    List {
+        /// Only list keys starting with this, e.g. images/testimonial_images/
+        #[clap(default_value = "")]
+        prefix: String,
+    },
+    /// Upload a file
+    Put {
+        file: PathBuf,
+
+        /// Key to upload to, defaults to the file name
+        #[clap(short, long)]
+        key: Option<String>,
+    },
+    /// Download an object
+    Get {
+        key: String,
+
+        /// File to write, defaults to the key's file name
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Process a directory of images, upload new or changed files and update the image
+    /// list's manifest and cache
+    Upload {
+        /// Image list to upload to, e.g. testimonial_images
+        #[clap(short)]
+        t: ContentType,
+
+        /// Directory of source images
+        dir: PathBuf,
+
+        /// Where processed images and their variants are written before uploading
+        #[clap(long, default_value = "processed_images")]
+        images_out: PathBuf,
+
+        /// Replace the whole cache with these images instead of merging into it
+        #[clap(long)]
+        replace: bool,
+
+        /// Print what would be uploaded without uploading or writing the manifest and cache
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Cache directory holding the *.bin content caches
+        #[clap(long, default_value = "cache")]
+        cache: PathBuf,
+    },
+    /// Delete objects
+    Delete {
+        #[clap(required = true)]
+        keys: Vec<String>,
+
+        /// Print what would be deleted without deleting
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
 
-    /// Path to file/folder
-    #[clap(short)]
-    f: String,
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Copy every cache into a new timestamped snapshot
+    Create {
+        /// Label added to the timestamp, e.g. pre-reset
+        #[clap(short, long, default_value = "snapshot")]
+        name: String,
+
+        /// Number of snapshots to keep after creating this one, 0 keeps all
+        #[clap(long, default_value = "20")]
+        keep: usize,
+    },
+    /// List snapshots, oldest first
+    List,
+    /// Verify a snapshot's checksums and swap it in for the current caches
+    Restore {
+        /// Snapshot id, name or "latest"
+        snapshot: String,
+
+        /// Print what would be restored without writing the caches
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Delete all but the newest snapshots
+    Prune {
+        /// Number of snapshots to keep
+        #[clap(long)]
+        keep: usize,
+
+        /// Print what would be deleted without deleting
+        #[clap(long)]
+        dry_run: bool,
+    },
 }
 
 #[tokio::main]
@@ -76,451 +339,153 @@ async fn main() -> Result<(), Error> {
     init_logger();
 
     let args = Args::parse();
-    let file_type = args.t;
-    let path = args.f;
-
-    match file_type {
-        FileType::Articles => {
-            // existing articles cache
-            let articles_cache = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/articles.bin";
-            let mut file = File::open(&articles_cache).expect("Failed to open articles cache");
-            let mut articles_buf = Vec::new();
-            file.read_to_end(&mut articles_buf)
-                .expect("Failed to read articles cache");
-
-            let mut new_file = File::open(path).expect("Failed to open new articles file");
-            let mut new_buf = String::new();
-            new_file
-                .read_to_string(&mut new_buf)
-                .expect("Failed to read new articles file");
-            let new_articles_raw = serde_json::from_str::<Vec<ArticleRaw>>(&new_buf)
-                .expect("Failed to deserialize new articles");
-
-            let mut new_articles = Vec::new();
-            for article in new_articles_raw.into_iter() {
-                let file_path = std::env::current_dir()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-                    + "/data/articles/"
-                    + &article.file_name;
-                info!("Article file path: {}", &file_path);
-
-                let markdown = std::fs::read_to_string(file_path)?
-                    .trim_start_matches('\n')
-                    .to_string();
-                new_articles.push(Article {
-                    title: article.title,
-                    tags: article.tags,
-                    data: markdown,
-                    image_url: article.image_url,
-                    index: article.index,
-                    premium: article.premium,
-                });
-            }
-
-            let mut db_articles = match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&articles_buf)
-            {
-                Ok(db_articles) => db_articles,
+    let settings = ServerSettings::load()?;
+
+    match args.command {
+        Command::Upsert {
+            t,
+            f,
+            replace,
+            images_out,
+            cache,
+        } => upsert(
+            &settings.open_store(cache)?,
+            t,
+            &f,
+            replace,
+            &images_out,
+            &settings.storage()?,
+        ),
+        Command::Validate {
+            data,
+            cache,
+            strict,
+        } => {
+            let report = match settings.open_store(cache).and_then(|store| validate(&data, &store)) {
+                Ok(report) => report,
                 Err(e) => {
-                    // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                    if e.to_string().contains("unexpected end of file") {
-                        HashMap::new()
-                    } else {
-                        return Err(anyhow!("Failed to deserialize articles cache: {}", e));
-                    }
+                    error!("Validation could not run: {}", e);
+                    std::process::exit(2);
                 }
             };
-
-            for new_article in new_articles.into_iter() {
-                let bytes = new_article.ser()?;
-                db_articles.insert(bytes.key, bytes.value);
+            report.log();
+            if report.failed(strict) {
+                std::process::exit(1);
             }
-            let ser_articles = bincode::serialize(&db_articles)?;
-            std::fs::write(articles_cache, ser_articles)?;
-            info!("Wrote articles to existing cache");
+            Ok(())
         }
-        FileType::Calibrations => {
-            // Read the contents of the calibrations cache into a Vec<u8>
-            let cache_path = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/calibrations.bin";
-            let mut cache_file =
-                File::open(&cache_path).expect("Failed to open calibrations cache");
-            let mut cache_buf = Vec::new();
-            cache_file
-                .read_to_end(&mut cache_buf)
-                .expect("Failed to read calibrations cache");
-
-            // Read the contents of the new calibrations file into a Vec<u8>
-            let mut new_file = File::open(path).expect("Failed to open new calibrations file");
-            let mut new_buf = String::new();
-            new_file
-                .read_to_string(&mut new_buf)
-                .expect("Failed to read new calibrations file");
-            let new_calibrations = serde_json::from_str::<Vec<Calibration>>(&new_buf)
-                .expect("Failed to deserialize new calibrations");
-
-            let mut db_calibrations =
-                match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf) {
-                    Ok(db_calibrations) => db_calibrations,
-                    Err(e) => {
-                        // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                        if e.to_string().contains("unexpected end of file") {
-                            HashMap::new()
-                        } else {
-                            return Err(anyhow!("Failed to deserialize calibrations cache: {}", e));
-                        }
-                    }
-                };
-            // append new calibration to cache
-            for new_calibration in new_calibrations.into_iter() {
-                let bytes = new_calibration.ser()?;
-                db_calibrations.insert(bytes.key, bytes.value);
+        Command::Export { t, out, cache } => {
+            let store = settings.open_store(cache)?;
+            let content_types = match t {
+                Some(content_type) => vec![content_type],
+                None => ContentType::ALL.to_vec(),
+            };
+            for content_type in content_types {
+                export(&store, content_type, &out)?;
             }
-
-            let ser_calibrations = bincode::serialize(&db_calibrations)?;
-            std::fs::write(cache_path, ser_calibrations)?;
-            info!("Wrote calibrations to existing cache");
+            Ok(())
         }
-        FileType::Testimonials => {
-            // Read the contents of the testimonials cache into a Vec<u8>
-            let cache_path = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/testimonials.bin";
-            let mut cache_file =
-                File::open(&cache_path).expect("Failed to open testimonials cache");
-            let mut cache_buf = Vec::new();
-            cache_file
-                .read_to_end(&mut cache_buf)
-                .expect("Failed to read testimonials cache");
-
-            // Read the contents of the new testimonials file into a Vec<u8>
-            let mut new_file = File::open(path).expect("Failed to open new testimonials file");
-            let mut new_buf = String::new();
-            new_file
-                .read_to_string(&mut new_buf)
-                .expect("Failed to read new testimonials file");
-            let new_testimonials = serde_json::from_str::<Vec<Testimonial>>(&new_buf)
-                .expect("Failed to deserialize new testimonials");
-
-            let mut db_testimonials =
-                match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf) {
-                    Ok(db_testimonials) => db_testimonials,
-                    Err(e) => {
-                        // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                        if e.to_string().contains("unexpected end of file") {
-                            HashMap::new()
-                        } else {
-                            return Err(anyhow!("Failed to deserialize testimonials cache: {}", e));
-                        }
-                    }
-                };
-            for new_testimonial in new_testimonials.into_iter() {
-                let bytes = new_testimonial.ser()?;
-                db_testimonials.insert(bytes.key, bytes.value);
+        Command::List { t, cache } => list(&settings.open_store(cache)?, t),
+        Command::Show { t, selector, cache } => show(&settings.open_store(cache)?, t, &selector),
+        Command::Update {
+            t,
+            selector,
+            f,
+            set,
+            dry_run,
+            cache,
+        } => {
+            if f.is_none() && set.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Nothing to update, pass -f <file> or --set"
+                ));
             }
-            let ser_testimonials = bincode::serialize(&db_testimonials)?;
-            std::fs::write(cache_path, ser_testimonials)?;
-            info!("Wrote testimonials to new cache");
+            update(
+                &settings.open_store(cache)?,
+                t,
+                &selector,
+                f.as_deref(),
+                &set,
+                dry_run,
+            )
         }
-        FileType::TestimonialImages => {
-            // read all files from directory
-            let dir = std::fs::read_dir(PathBuf::from(&path))
-                .expect("Failed to read testimonial images directory");
-
-            // read directory after renaming and create JSON for
-            // data/testimonial_images/testimonial_images.json
-            let mut testimonial_images = Vec::<String>::new();
-
-            for file in dir {
-                let file = file.expect("Failed to read testimonial image DirEntry");
-                let file_name_os = file.file_name();
-
-                let file_name = file_name_os.to_str().unwrap().to_string();
-
-                if file_name == ".DS_Store" {
-                    continue;
-                };
-
-                // check if name contains a space, if so concat with -
-                let file_name = if file_name.contains(' ') {
-                    file_name.replace(' ', "-")
-                } else {
-                    file_name
-                };
-
-                debug!("File name: {:?}", file_name);
-                let gcloud_url = format!(
-                    "{}images/testimonial_images/{}",
-                    GCLOUD_STORAGE_PREFIX, file_name
-                );
-                info!("Testimonial image: {}", gcloud_url);
-                testimonial_images.push(gcloud_url)
-            }
-
-            // write testimonial_images.json to data/testimonial_images/testimonial_images.json
-            let testimonial_images_json = serde_json::to_string(&testimonial_images)?;
-
-            let database = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/data/testimonial_images";
-
-            let testimonial_images_path = format!("{}/testimonial_images.json", database);
-            match std::fs::write(testimonial_images_path, testimonial_images_json) {
-                Ok(_) => {
-                    info!("Successfully wrote testimonial_images.json");
+        Command::Delete {
+            t,
+            selector,
+            dry_run,
+            cache,
+        } => delete(&settings.open_store(cache)?, t, &selector, dry_run),
+        Command::ImportEnex {
+            f,
+            out,
+            tag,
+            premium,
+            overwrite,
+        } => import_enex(
+            &f,
+            &out,
+            &tag,
+            premium,
+            overwrite,
+            &settings.storage()?,
+        )
+        .map(|_| ()),
+        Command::Snapshot { command, cache } => {
+            let root = snapshot::snapshots_dir(&cache);
+            match command {
+                SnapshotCommand::Create { name, keep } => {
+                    snapshot::create(&settings.open_store(cache)?, &root, &name, keep).map(|_| ())
                 }
-                Err(e) => {
-                    error!("Failed to write testimonial_images.json: {}", e);
+                SnapshotCommand::List => snapshot::list(&root),
+                SnapshotCommand::Restore { snapshot, dry_run } => {
+                    snapshot::restore(&settings.open_store(cache)?, &root, &snapshot, dry_run)
                 }
+                SnapshotCommand::Prune { keep, dry_run } => snapshot::prune(&root, keep, dry_run),
             }
-
-            // Read the contents of the testimonials cache into a Vec<u8>
-            let cache_path = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/testimonial_images.bin";
-            let mut cache_file =
-                File::open(&cache_path).expect("Failed to open testimonials cache");
-            let mut cache_buf = Vec::new();
-            cache_file
-                .read_to_end(&mut cache_buf)
-                .expect("Failed to read testimonials cache");
-
-            let mut db_testimonial_images =
-                match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf) {
-                    Ok(db_testimonial_images) => db_testimonial_images,
-                    Err(e) => {
-                        // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                        if e.to_string().contains("unexpected end of file") {
-                            HashMap::new()
-                        } else {
-                            return Err(anyhow!("Failed to deserialize testimonials cache: {}", e));
-                        }
-                    }
-                };
-            // append new calibration to cache
-            for image in testimonial_images.into_iter() {
-                let key = MessageHasher::new().hash_string(&image);
-                let value =
-                    bincode::serialize(&image).expect("Failed to serialize testimonial image");
-                db_testimonial_images.insert(key, value);
-            }
-
-            let ser_testimonial_images = bincode::serialize(&db_testimonial_images)?;
-            std::fs::write(cache_path, ser_testimonial_images)?;
-            info!("Wrote testimonial images to existing cache");
         }
-        FileType::ContentTypeImages => {
-            // read all files from directory
-            let dir = std::fs::read_dir(PathBuf::from(&path))
-                .expect("Failed to read content type images directory");
-
-            // read directory after renaming and create JSON for
-            // data/testimonial_images/testimonial_images.json
-            let mut content_type_images = Vec::<String>::new();
-
-            for file in dir {
-                let file = file.expect("Failed to read content type image DirEntry");
-                let file_name_os = file.file_name();
-
-                let file_name = file_name_os.to_str().unwrap().to_string();
-
-                if file_name == ".DS_Store" {
-                    continue;
-                };
-
-                // check if name contains a space, if so concat with -
-                let file_name = if file_name.contains(' ') {
-                    file_name.replace(' ', "-")
-                } else {
-                    file_name
-                };
-
-                debug!("File name: {:?}", file_name);
-                let gcloud_url = format!(
-                    "{}images/content_type_images/{}",
-                    GCLOUD_STORAGE_PREFIX, file_name
-                );
-                info!("Content type image: {}", gcloud_url);
-                content_type_images.push(gcloud_url)
-            }
-
-            let content_type_images_json = serde_json::to_string(&content_type_images)?;
-
-            let database = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/data/content_type_images";
-
-            let content_type_images_path = format!("{}/content_type_images.json", database);
-            match std::fs::write(content_type_images_path, content_type_images_json) {
-                Ok(_) => {
-                    info!("Successfully wrote content_type_images.json");
+        Command::Assets { command } => {
+            let config = settings.storage()?;
+            let store = config.open().await?;
+            match command {
+                AssetsCommand::List { prefix } => assets::list(store.as_ref(), &prefix).await,
+                AssetsCommand::Put { file, key } => {
+                    assets::put(store.as_ref(), &file, key.as_deref()).await
                 }
-                Err(e) => {
-                    error!("Failed to write content_type_images.json: {}", e);
+                AssetsCommand::Get { key, out } => {
+                    assets::get(store.as_ref(), &key, out.as_deref()).await
                 }
-            }
-
-            // Read the contents of the testimonials cache into a Vec<u8>
-            let cache_path = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/content_type_images.bin";
-            let mut cache_file =
-                File::open(&cache_path).expect("Failed to open content type images cache");
-            let mut cache_buf = Vec::new();
-            cache_file
-                .read_to_end(&mut cache_buf)
-                .expect("Failed to read content type images cache");
-
-            let mut db_content_type_images =
-                match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf) {
-                    Ok(db_content_type_images) => db_content_type_images,
-                    Err(e) => {
-                        // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                        if e.to_string().contains("unexpected end of file") {
-                            HashMap::new()
-                        } else {
-                            return Err(anyhow!(
-                                "Failed to deserialize content type images cache: \
-                            {}",
-                                e
-                            ));
-                        }
-                    }
-                };
-            // append new calibration to cache
-            for image in content_type_images.into_iter() {
-                let key = MessageHasher::new().hash_string(&image);
-                let value =
-                    bincode::serialize(&image).expect("Failed to serialize testimonial image");
-                db_content_type_images.insert(key, value);
-            }
-
-            let ser_content_type_images = bincode::serialize(&db_content_type_images)?;
-            std::fs::write(cache_path, ser_content_type_images)?;
-            info!("Wrote content type images to existing cache");
-        }
-        FileType::CategoryImages => {
-            // read all files from directory
-            let dir = std::fs::read_dir(PathBuf::from(&path))
-                .expect("Failed to read category images directory");
-
-            // read directory after renaming and create JSON for
-            // data/testimonial_images/testimonial_images.json
-            let mut category_images = Vec::<String>::new();
-
-            for file in dir {
-                let file = file.expect("Failed to read category image DirEntry");
-                let file_name_os = file.file_name();
-
-                let file_name = file_name_os.to_str().unwrap().to_string();
-
-                if file_name == ".DS_Store" {
-                    continue;
-                };
-
-                // check if name contains a space, if so concat with -
-                let file_name = if file_name.contains(' ') {
-                    file_name.replace(' ', "-")
-                } else {
-                    file_name
-                };
-
-                debug!("File name: {:?}", file_name);
-                let gcloud_url = format!(
-                    "{}images/category_images/{}",
-                    GCLOUD_STORAGE_PREFIX, file_name
-                );
-                info!("Category image: {}", gcloud_url);
-                category_images.push(gcloud_url)
-            }
-
-            let category_images_json = serde_json::to_string(&category_images)?;
-
-            let database = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/data/category_images";
-
-            let category_images_path = format!("{}/category_images.json", database);
-            match std::fs::write(category_images_path, category_images_json) {
-                Ok(_) => {
-                    info!("Successfully wrote content_type_images.json");
+                AssetsCommand::Upload {
+                    t,
+                    dir,
+                    images_out,
+                    replace,
+                    dry_run,
+                    cache,
+                } => {
+                    assets::upload_images(
+                        store.as_ref(),
+                        &config,
+                        &settings.open_store(cache)?,
+                        t,
+                        &dir,
+                        &images_out,
+                        replace,
+                        dry_run,
+                    )
+                    .await
                 }
-                Err(e) => {
-                    error!("Failed to write content_type_images.json: {}", e);
+                AssetsCommand::Delete { keys, dry_run } => {
+                    assets::delete(store.as_ref(), &keys, dry_run).await
                 }
             }
-
-            // Read the contents of the category images cache into a Vec<u8>
-            let cache_path = std::env::current_dir()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string()
-                + "/cache/category_images.bin";
-            let mut cache_file =
-                File::open(&cache_path).expect("Failed to open category images cache");
-            let mut cache_buf = Vec::new();
-            cache_file
-                .read_to_end(&mut cache_buf)
-                .expect("Failed to read category images cache");
-
-            let mut db_category_images =
-                match bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf) {
-                    Ok(db_category_images) => db_category_images,
-                    Err(e) => {
-                        // if error is Io(Kind(UnexpectedEof)), then write to file as new hashmap
-                        if e.to_string().contains("unexpected end of file") {
-                            HashMap::new()
-                        } else {
-                            return Err(anyhow!(
-                                "Failed to deserialize category images cache: \
-                            {}",
-                                e
-                            ));
-                        }
-                    }
-                };
-            // append new category image to cache
-            for image in category_images.into_iter() {
-                let key = MessageHasher::new().hash_string(&image);
-                let value = bincode::serialize(&image).expect("Failed to serialize category image");
-                db_category_images.insert(key, value);
+        }
+        Command::Keys { command, cache } => {
+            let store = AdminKeyStore::from_env(cache);
+            match command {
+                KeysCommand::Create { name, role } => keys::create(&store, &name, role),
+                KeysCommand::List => keys::list(&store),
+                KeysCommand::Revoke { id } => keys::revoke(&store, &id),
             }
-
-            let ser_category_images = bincode::serialize(&db_category_images)?;
-            std::fs::write(cache_path, ser_category_images)?;
-            info!("Wrote category images to existing cache");
         }
     }
-
-    Ok(())
 }
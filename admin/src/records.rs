@@ -0,0 +1,314 @@
+use crate::images::ImageEntry;
+use crate::slug;
+use anyhow::{anyhow, Error};
+use database::{Article, Calibration, ContentStore, ContentType, ImageInfo, Testimonial};
+use log::*;
+use serde_json::Value;
+use std::path::Path;
+
+/// A cache record decoded to JSON, so every content type can be listed and edited the same way
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Content hash key the record is stored under
+    pub id: u64,
+    pub record: Value,
+}
+
+impl Entry {
+    /// Human readable name: the title of articles and calibrations, otherwise the image file name
+    pub fn title(&self) -> String {
+        match self.record.get("title").and_then(Value::as_str) {
+            Some(title) => title.to_string(),
+            None => ["url", "image_url"]
+                .iter()
+                .find_map(|field| self.record.get(field).and_then(Value::as_str))
+                .map(file_name)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn slug(&self) -> String {
+        slug(&self.title().replace(['_', '.'], " ")).to_lowercase()
+    }
+
+    /// Whether `selector` is this record's id, slug or (case insensitive) title
+    fn matches(&self, selector: &str) -> bool {
+        let selector = selector.trim();
+        selector.parse::<u64>().ok() == Some(self.id)
+            || self.slug() == slug(&selector.replace(['_', '.'], " ")).to_lowercase()
+            || self.title().eq_ignore_ascii_case(selector)
+    }
+}
+
+fn file_name(url: &str) -> String {
+    url.rsplit('/').next().unwrap_or(url).to_string()
+}
+
+/// Every record of a cache, sorted by title
+pub fn entries(store: &ContentStore, content_type: ContentType) -> Result<Vec<Entry>, Error> {
+    let mut entries = store
+        .read(content_type)?
+        .into_iter()
+        .map(|(id, value)| {
+            decode(content_type, &value)
+                .map(|record| Entry { id, record })
+                .map_err(|e| anyhow!("{} record {} failed to decode: {}", content_type, id, e))
+        })
+        .collect::<Result<Vec<Entry>, Error>>()?;
+    entries.sort_by(|a, b| a.title().cmp(&b.title()).then(a.id.cmp(&b.id)));
+    Ok(entries)
+}
+
+/// The single record matching `selector` (id, slug or title)
+pub fn find(
+    store: &ContentStore,
+    content_type: ContentType,
+    selector: &str,
+) -> Result<Entry, Error> {
+    let mut matches = entries(store, content_type)?
+        .into_iter()
+        .filter(|entry| entry.matches(selector))
+        .collect::<Vec<Entry>>();
+    match matches.len() {
+        0 => Err(anyhow!(
+            "No {} record matches \"{}\"",
+            content_type,
+            selector
+        )),
+        1 => Ok(matches.remove(0)),
+        _ => Err(anyhow!(
+            "\"{}\" matches {} {} records, select one by id: {}",
+            selector,
+            matches.len(),
+            content_type,
+            matches
+                .iter()
+                .map(|entry| entry.id.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )),
+    }
+}
+
+pub fn list(store: &ContentStore, content_type: ContentType) -> Result<(), Error> {
+    let entries = entries(store, content_type)?;
+    for entry in entries.iter() {
+        println!("{:<20}  {:<48}  {}", entry.id, entry.slug(), entry.title());
+    }
+    info!("{} {} record(s)", entries.len(), content_type);
+    Ok(())
+}
+
+pub fn show(store: &ContentStore, content_type: ContentType, selector: &str) -> Result<(), Error> {
+    let entry = find(store, content_type, selector)?;
+    info!("{} record {}", content_type, entry.id);
+    println!("{}", serde_json::to_string_pretty(&entry.record)?);
+    Ok(())
+}
+
+/// Change one record, either replaced by the JSON record in `file` or by `FIELD=VALUE` edits.
+///
+/// The record is re-keyed by its new content hash. With `dry_run` the changes are only printed.
+pub fn update(
+    store: &ContentStore,
+    content_type: ContentType,
+    selector: &str,
+    file: Option<&Path>,
+    set: &[String],
+    dry_run: bool,
+) -> Result<(), Error> {
+    let entry = find(store, content_type, selector)?;
+
+    let mut record = match file {
+        Some(file) => serde_json::from_str::<Value>(&std::fs::read_to_string(file)?)?,
+        None => entry.record.clone(),
+    };
+    for assignment in set.iter() {
+        let (field, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected FIELD=VALUE, got \"{}\"", assignment))?;
+        set_field(&mut record, field.trim(), value)?;
+    }
+
+    // round trip through the typed record so the update can't write a malformed cache entry
+    let (id, bytes) = encode(content_type, &record)?;
+    let record = decode(content_type, &bytes)?;
+
+    let changes = diff(&entry.record, &record);
+    if changes.is_empty() {
+        info!("{} record {} is unchanged", content_type, entry.id);
+        return Ok(());
+    }
+
+    if id != entry.id && store.read(content_type)?.contains_key(&id) {
+        return Err(anyhow!(
+            "Updated record would replace existing {} record {}",
+            content_type,
+            id
+        ));
+    }
+
+    let prefix = if dry_run { "[dry run] " } else { "" };
+    println!("{}update {} record {}", prefix, content_type, entry.id);
+    for change in changes.iter() {
+        println!("{}  {}", prefix, change);
+    }
+    if id != entry.id {
+        println!("{}  id: {} -> {}", prefix, entry.id, id);
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let version = store.update(content_type, |records| {
+        if records.remove(&entry.id).is_none() {
+            return Err(anyhow!(
+                "{} record {} was removed while updating",
+                content_type,
+                entry.id
+            ));
+        }
+        records.insert(id, bytes);
+        Ok(())
+    })?;
+    info!(
+        "Updated {} record {}, cache version {}",
+        content_type, id, version
+    );
+    Ok(())
+}
+
+pub fn delete(
+    store: &ContentStore,
+    content_type: ContentType,
+    selector: &str,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let entry = find(store, content_type, selector)?;
+
+    let prefix = if dry_run { "[dry run] " } else { "" };
+    println!(
+        "{}delete {} record {} ({})",
+        prefix,
+        content_type,
+        entry.id,
+        entry.title()
+    );
+    if dry_run {
+        return Ok(());
+    }
+
+    let version = store.update(content_type, |records| {
+        records.remove(&entry.id);
+        Ok::<(), Error>(())
+    })?;
+    info!(
+        "Deleted {} record {}, cache version {}",
+        content_type, entry.id, version
+    );
+    Ok(())
+}
+
+fn decode(content_type: ContentType, bytes: &[u8]) -> Result<Value, Error> {
+    let record = match content_type {
+        ContentType::Articles => serde_json::to_value(Article::de(bytes)?)?,
+        ContentType::Calibrations => serde_json::to_value(Calibration::de(bytes)?)?,
+        ContentType::Testimonials => serde_json::to_value(Testimonial::de(bytes)?)?,
+        ContentType::TestimonialImages
+        | ContentType::CategoryImages
+        | ContentType::ContentTypeImages => serde_json::to_value(ImageInfo::de(bytes)?)?,
+    };
+    Ok(record)
+}
+
+/// Key and cache bytes of a record, keyed the same way `admin upsert` keys it
+fn encode(content_type: ContentType, record: &Value) -> Result<(u64, Vec<u8>), Error> {
+    let encoded = match content_type {
+        ContentType::Articles => {
+            let article = serde_json::from_value::<Article>(record.clone())?.ser()?;
+            (article.key, article.value)
+        }
+        ContentType::Calibrations => {
+            let calibration = serde_json::from_value::<Calibration>(record.clone())?.ser()?;
+            (calibration.key, calibration.value)
+        }
+        ContentType::Testimonials => {
+            let testimonial = serde_json::from_value::<Testimonial>(record.clone())?.ser()?;
+            (testimonial.key, testimonial.value)
+        }
+        ContentType::TestimonialImages
+        | ContentType::CategoryImages
+        | ContentType::ContentTypeImages => {
+            let image = serde_json::from_value::<ImageEntry>(record.clone())?
+                .into_info()
+                .ser()?;
+            (image.key, image.value)
+        }
+    };
+    Ok(encoded)
+}
+
+/// Apply one `FIELD=VALUE` edit. String fields take the value verbatim, other fields parse it
+/// as JSON (e.g. `tags=["Love","All"]`), unset fields fall back to a string.
+/// `@path` reads a string value from a file.
+fn set_field(record: &mut Value, field: &str, value: &str) -> Result<(), Error> {
+    let target = match record {
+        Value::Object(fields) => {
+            let known = fields.keys().cloned().collect::<Vec<String>>();
+            fields.get_mut(field).ok_or_else(|| {
+                anyhow!(
+                    "Unknown field \"{}\", expected one of: {}",
+                    field,
+                    known.join(", ")
+                )
+            })?
+        }
+        _ => return Err(anyhow!("Record has no fields to set")),
+    };
+
+    *target = match (&target, value.strip_prefix('@')) {
+        (Value::String(_), Some(path)) => Value::String(
+            std::fs::read_to_string(path)?
+                .trim_start_matches('\n')
+                .to_string(),
+        ),
+        (Value::String(_), None) => Value::String(value.to_string()),
+        (Value::Null, _) => serde_json::from_str::<Value>(value)
+            .unwrap_or_else(|_| Value::String(value.to_string())),
+        _ => serde_json::from_str::<Value>(value)
+            .map_err(|e| anyhow!("Invalid JSON for \"{}\": {}", field, e))?,
+    };
+    Ok(())
+}
+
+/// One `field: old -> new` line per changed field
+fn diff(old: &Value, new: &Value) -> Vec<String> {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => new
+            .iter()
+            .filter(|(field, value)| old.get(*field) != Some(*value))
+            .map(|(field, value)| {
+                format!(
+                    "{}: {} -> {}",
+                    field,
+                    summarize(old.get(field).unwrap_or(&Value::Null)),
+                    summarize(value)
+                )
+            })
+            .collect(),
+        (old, new) if old != new => vec![format!("url: {} -> {}", summarize(old), summarize(new))],
+        _ => Vec::new(),
+    }
+}
+
+/// Keep long values (article markdown) readable in change listings
+fn summarize(value: &Value) -> String {
+    const MAX_CHARS: usize = 80;
+    let text = value.to_string();
+    if text.chars().count() <= MAX_CHARS {
+        return text;
+    }
+    let mut short = text.chars().take(MAX_CHARS).collect::<String>();
+    short.push_str(&format!("... ({} chars)", text.chars().count()));
+    short
+}
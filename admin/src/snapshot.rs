@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use database::{ContentStore, ContentType, Records};
+use log::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Snapshots live in `<cache>/snapshots/<timestamp>_<name>/`
+const SNAPSHOTS_DIR: &str = "snapshots";
+const MANIFEST_FILE: &str = "manifest.json";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// `manifest.json` of a snapshot directory
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotManifest {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotFile {
+    pub content_type: String,
+    pub file: String,
+    /// Hex sha256 of the file contents
+    pub sha256: String,
+    pub bytes: u64,
+    pub records: usize,
+    /// Store version of the cache when the snapshot was taken
+    pub version: u64,
+}
+
+/// Snapshots are kept in the cache directory whichever store holds the content
+pub fn snapshots_dir(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(SNAPSHOTS_DIR)
+}
+
+/// Snapshot names are slugs so they're safe in a directory name, e.g. "Pre Reset" -> "pre_reset"
+fn snapshot_name(name: &str) -> String {
+    match crate::slug(&name.replace(['-', '_'], " ")).to_lowercase() {
+        name if name.is_empty() => "snapshot".to_string(),
+        name => name,
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Copy every cache into a new snapshot under `root`, then prune all but the newest `keep`
+/// snapshots (`0` keeps everything). Returns the snapshot id.
+pub fn create(store: &ContentStore, root: &Path, name: &str, keep: usize) -> Result<String, Error> {
+    let created_at = Utc::now();
+    let name = snapshot_name(name);
+    let id = format!("{}_{}", created_at.format(TIMESTAMP_FORMAT), name);
+
+    let dir = root.join(&id);
+    if dir.exists() {
+        return Err(anyhow!("Snapshot {} already exists", id));
+    }
+    // written under a hidden name and renamed into place, so a listed snapshot is always complete
+    let tmp_dir = root.join(format!(".{}.tmp", id));
+    std::fs::create_dir_all(&tmp_dir)?;
+
+    let mut files = Vec::new();
+    for content_type in ContentType::ALL.into_iter() {
+        // read under the write lock so the records and their version stamp match
+        let (bytes, version) = match store.dump(content_type)? {
+            Some(dump) => dump,
+            None => {
+                warn!(
+                    "{} does not exist, not included in snapshot",
+                    store.location(content_type)
+                );
+                continue;
+            }
+        };
+        let records = match decode(&bytes) {
+            Ok(records) => records.len(),
+            Err(e) => {
+                warn!(
+                    "Snapshotting unreadable cache {}: {}",
+                    store.location(content_type),
+                    e
+                );
+                0
+            }
+        };
+        std::fs::write(tmp_dir.join(content_type.cache_file()), &bytes)?;
+        files.push(SnapshotFile {
+            content_type: content_type.to_string(),
+            file: content_type.cache_file(),
+            sha256: sha256_hex(&bytes),
+            bytes: bytes.len() as u64,
+            records,
+            version,
+        });
+    }
+
+    let manifest = SnapshotManifest {
+        id: id.clone(),
+        name,
+        created_at,
+        files,
+    };
+    std::fs::write(
+        tmp_dir.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    std::fs::rename(&tmp_dir, &dir)?;
+    info!("Created snapshot {} in {}", id, dir.display());
+
+    if keep > 0 {
+        prune(root, keep, false)?;
+    }
+    Ok(id)
+}
+
+/// Every complete snapshot, oldest first
+pub fn snapshots(root: &Path) -> Result<Vec<SnapshotManifest>, Error> {
+    let entries = match std::fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(anyhow!("Failed to read {}: {}", root.display(), e)),
+    };
+
+    let mut manifests = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let manifest_path = entry.path().join(MANIFEST_FILE);
+        match std::fs::read_to_string(&manifest_path)
+            .map_err(Error::from)
+            .and_then(|json| Ok(serde_json::from_str::<SnapshotManifest>(&json)?))
+        {
+            Ok(manifest) => manifests.push(manifest),
+            Err(e) => warn!("Skipping {}: {}", manifest_path.display(), e),
+        }
+    }
+    manifests.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+    Ok(manifests)
+}
+
+pub fn list(root: &Path) -> Result<(), Error> {
+    let snapshots = snapshots(root)?;
+    for snapshot in snapshots.iter() {
+        println!(
+            "{:<40}  {}  {} file(s), {} record(s)",
+            snapshot.id,
+            snapshot.created_at.to_rfc3339(),
+            snapshot.files.len(),
+            snapshot
+                .files
+                .iter()
+                .map(|file| file.records)
+                .sum::<usize>()
+        );
+    }
+    info!("{} snapshot(s) in {}", snapshots.len(), root.display());
+    Ok(())
+}
+
+/// Snapshot matching an id, a name (newest with that name wins) or `latest`
+fn find(root: &Path, selector: &str) -> Result<SnapshotManifest, Error> {
+    snapshots(root)?
+        .into_iter()
+        .rev()
+        .find(|snapshot| {
+            selector == "latest"
+                || snapshot.id == selector
+                || snapshot.name == snapshot_name(selector)
+        })
+        .ok_or_else(|| anyhow!("No snapshot matches \"{}\"", selector))
+}
+
+/// Replace the content store with a snapshot.
+///
+/// Every file is checksummed and decoded before anything is written, and the current state is
+/// snapshotted first so a restore can itself be undone. Content types the snapshot doesn't
+/// have are cleared. Each cache is swapped in with an atomic store write, so a running server
+/// picks up the restored content on its next read, and if any write fails the ones already
+/// made are rolled back to the content read before the restore.
+pub fn restore(
+    store: &ContentStore,
+    root: &Path,
+    selector: &str,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let snapshot = find(root, selector)?;
+    let dir = root.join(&snapshot.id);
+
+    let mut restored = Vec::<(ContentType, Records)>::new();
+    for file in snapshot.files.iter() {
+        let content_type = file
+            .content_type
+            .parse::<ContentType>()
+            .map_err(|e| anyhow!("Snapshot {}: {}", snapshot.id, e))?;
+        let path = dir.join(&file.file);
+        let bytes = std::fs::read(&path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let checksum = sha256_hex(&bytes);
+        if checksum != file.sha256 {
+            return Err(anyhow!(
+                "{} checksum mismatch, expected {} found {}",
+                path.display(),
+                file.sha256,
+                checksum
+            ));
+        }
+        let records =
+            decode(&bytes).map_err(|e| anyhow!("{} failed to decode: {}", path.display(), e))?;
+        restored.push((content_type, records));
+    }
+
+    let prefix = if dry_run { "[dry run] " } else { "" };
+    // `None` for a missing or unreadable cache
+    let mut previous = Vec::<(ContentType, Option<Records>)>::new();
+    for content_type in ContentType::ALL.into_iter() {
+        let current = store.read(content_type).ok();
+        let in_snapshot = restored
+            .iter()
+            .any(|(restored, _)| *restored == content_type);
+        if !in_snapshot && current.is_some() {
+            restored.push((content_type, Records::new()));
+        }
+        match restored
+            .iter()
+            .find(|(restored, _)| *restored == content_type)
+        {
+            Some((_, records)) => println!(
+                "{}restore {}: {} -> {} record(s){}",
+                prefix,
+                content_type,
+                current
+                    .as_ref()
+                    .map_or("missing".to_string(), |current| current.len().to_string()),
+                records.len(),
+                if in_snapshot { "" } else { ", not in snapshot" }
+            ),
+            None => continue,
+        }
+        previous.push((content_type, current));
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let backup = create(store, root, &format!("pre-restore {}", snapshot.name), 0)?;
+    info!("Saved current content as snapshot {}", backup);
+    for (i, (content_type, records)) in restored.iter().enumerate() {
+        match store.write(*content_type, records) {
+            Ok(version) => info!("Restored {}, cache version {}", content_type, version),
+            Err(e) => {
+                error!("Failed to restore {}, rolling back: {}", content_type, e);
+                rollback(store, &restored[..i], &previous);
+                return Err(anyhow!(
+                    "Restoring snapshot {} failed at {}: {}. The content before it is snapshot {}",
+                    snapshot.id,
+                    content_type,
+                    e,
+                    backup
+                ));
+            }
+        }
+    }
+    info!("Restored snapshot {}", snapshot.id);
+    Ok(())
+}
+
+/// Write back the content read before a restore for the content types it already wrote
+fn rollback(
+    store: &ContentStore,
+    written: &[(ContentType, Records)],
+    previous: &[(ContentType, Option<Records>)],
+) {
+    for (content_type, _) in written.iter() {
+        let records = previous
+            .iter()
+            .find(|(previous, _)| previous == content_type)
+            .and_then(|(_, records)| records.clone())
+            .unwrap_or_default();
+        match store.write(*content_type, &records) {
+            Ok(_) => info!("Rolled back {}", content_type),
+            Err(e) => error!("Failed to roll back {}: {}", content_type, e),
+        }
+    }
+}
+
+/// Delete all but the newest `keep` snapshots
+pub fn prune(root: &Path, keep: usize, dry_run: bool) -> Result<(), Error> {
+    let snapshots = snapshots(root)?;
+    let expired = snapshots.len().saturating_sub(keep);
+    for snapshot in snapshots.into_iter().take(expired) {
+        let dir = root.join(&snapshot.id);
+        if dry_run {
+            println!("[dry run] delete snapshot {}", snapshot.id);
+            continue;
+        }
+        remove_snapshot_dir(&dir)?;
+        info!("Deleted snapshot {}", snapshot.id);
+    }
+    Ok(())
+}
+
+/// Snapshot files have the cache file encoding, an empty file is an empty cache
+fn decode(bytes: &[u8]) -> Result<Records, bincode::Error> {
+    if bytes.is_empty() {
+        Ok(Records::new())
+    } else {
+        bincode::deserialize::<Records>(bytes)
+    }
+}
+
+fn remove_snapshot_dir(dir: &Path) -> Result<(), Error> {
+    // renamed away first so a half deleted snapshot is never listed
+    let hidden = dir.with_file_name(format!(
+        ".{}.deleting",
+        dir.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    ));
+    std::fs::rename(dir, &hidden)?;
+    std::fs::remove_dir_all(&hidden)?;
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use crate::front_matter::{self, article_sources, MANIFEST_FILE};
+use crate::images::{process_image, ImageEntry};
+use anyhow::{anyhow, Error};
+use database::{Calibration, ContentStore, ContentType, ImageInfo, Testimonial};
+use log::*;
+use serde::de::DeserializeOwned;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use storage::StorageConfig;
+
+/// Upsert the records of manifest files (or directories of images) into their cache.
+///
+/// Every file is read before the cache is touched, then the cache is rewritten in one atomic
+/// write. With `replace` the cache is cleared first instead of merged into.
+/// Images are processed into responsive variants under `images_out`, with the URLs they'll
+/// have once uploaded to `storage`.
+pub fn upsert(
+    store: &ContentStore,
+    file_type: ContentType,
+    paths: &[String],
+    replace: bool,
+    images_out: &Path,
+    storage: &StorageConfig,
+) -> Result<(), Error> {
+    let mut new_records = Vec::new();
+    for path in paths.iter() {
+        let records = match file_type {
+            ContentType::Articles => article_records(path)?,
+            ContentType::Calibrations => calibration_records(path)?,
+            ContentType::Testimonials => testimonial_records(path)?,
+            ContentType::TestimonialImages
+            | ContentType::ContentTypeImages
+            | ContentType::CategoryImages => image_records(file_type, path, images_out, storage)?,
+        };
+        info!("Read {} {} from {}", records.len(), file_type, path);
+        new_records.extend(records);
+    }
+
+    store_records(store, file_type, new_records, replace)
+}
+
+/// Merge records into their cache, or replace the cache with them, in one atomic write
+pub fn store_records(
+    store: &ContentStore,
+    file_type: ContentType,
+    new_records: Vec<(u64, Vec<u8>)>,
+    replace: bool,
+) -> Result<(), Error> {
+    let count = new_records.len();
+    let version = store.update(file_type, |records| {
+        if replace {
+            records.clear();
+        }
+        records.extend(new_records);
+        Ok::<(), Error>(())
+    })?;
+    info!(
+        "Wrote {} {} to {} cache, version {}",
+        count,
+        file_type,
+        if replace { "new" } else { "existing" },
+        version
+    );
+
+    Ok(())
+}
+
+fn article_records(path: &str) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+    let mut new_articles = Vec::new();
+    for source in article_sources(Path::new(path))?.into_iter() {
+        info!("Article file path: {}", source.path.display());
+        let markdown = std::fs::read_to_string(&source.path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", source.path.display(), e))?;
+        let (front_matter, body) = front_matter::split(&markdown)
+            .map_err(|e| anyhow!("{}: {}", source.path.display(), e))?;
+
+        let manifest = source.manifest.as_ref().map(|entry| &entry.meta);
+        if front_matter.is_none() && manifest.is_none() {
+            warn!(
+                "Skipping {}: no front matter and not listed in {}",
+                source.path.display(),
+                MANIFEST_FILE
+            );
+            continue;
+        }
+        let resolved = front_matter::resolve(front_matter.as_ref(), manifest, body)
+            .map_err(|e| anyhow!("{}: {}", source.path.display(), e))?;
+        for conflict in resolved.conflicts.iter() {
+            warn!("{}: {}", source.path.display(), conflict);
+        }
+
+        let bytes = resolved.article.ser()?;
+        new_articles.push((bytes.key, bytes.value));
+    }
+    Ok(new_articles)
+}
+
+/// The records of a JSON file, named by `what` in errors
+fn read_json_file<T: DeserializeOwned>(path: &str, what: &str) -> Result<T, Error> {
+    let mut new_file =
+        File::open(path).map_err(|e| anyhow!("Failed to open {} file {}: {}", what, path, e))?;
+    let mut new_buf = String::new();
+    new_file
+        .read_to_string(&mut new_buf)
+        .map_err(|e| anyhow!("Failed to read {} file {}: {}", what, path, e))?;
+    serde_json::from_str::<T>(&new_buf)
+        .map_err(|e| anyhow!("Failed to deserialize {} in {}: {}", what, path, e))
+}
+
+fn calibration_records(path: &str) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+    let new_calibrations = read_json_file::<Vec<Calibration>>(path, "calibrations")?;
+
+    new_calibrations
+        .iter()
+        .map(|calibration| {
+            let bytes = calibration.ser()?;
+            Ok((bytes.key, bytes.value))
+        })
+        .collect()
+}
+
+fn testimonial_records(path: &str) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+    let new_testimonials = read_json_file::<Vec<Testimonial>>(path, "testimonials")?;
+
+    new_testimonials
+        .iter()
+        .map(|testimonial| {
+            let bytes = testimonial.ser()?;
+            Ok((bytes.key, bytes.value))
+        })
+        .collect()
+}
+
+/// Process a directory of images and record them in `data/<content type>/<content type>.json`
+fn image_records(
+    content_type: ContentType,
+    path: &str,
+    images_out: &Path,
+    storage: &StorageConfig,
+) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+    let images = process_images(content_type, Path::new(path), images_out, storage)?;
+    write_image_manifest(content_type, &images);
+    image_list_records(&images)
+}
+
+/// Process a directory of images into `<images_out>/<content type>/`, with the URLs they'll
+/// have once uploaded to `images/<content type>/` in `storage`
+pub fn process_images(
+    content_type: ContentType,
+    dir: &Path,
+    images_out: &Path,
+    storage: &StorageConfig,
+) -> Result<Vec<ImageInfo>, Error> {
+    // read all files from directory
+    let mut files = std::fs::read_dir(dir)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to read {} directory {}: {}",
+                content_type,
+                dir.display(),
+                e
+            )
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            !path
+                .file_name()
+                .map_or(true, |name| name.to_string_lossy().starts_with('.'))
+        })
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+
+    let out_dir = images_out.join(content_type.name());
+    let url_prefix = storage.public_url(&image_key_prefix(content_type));
+    let mut images = Vec::<ImageInfo>::new();
+    for file in files.iter() {
+        let image = process_image(file, &out_dir, &url_prefix)?;
+        info!("{} image: {}", content_type, image.url);
+        images.push(image);
+    }
+    Ok(images)
+}
+
+/// Where an image list is uploaded in the bucket, e.g. `images/testimonial_images/`
+pub fn image_key_prefix(content_type: ContentType) -> String {
+    format!("images/{}/", content_type)
+}
+
+/// Write e.g. `data/testimonial_images/testimonial_images.json`
+pub fn write_image_manifest(content_type: ContentType, images: &[ImageInfo]) {
+    let entries = images
+        .iter()
+        .cloned()
+        .map(ImageEntry::from)
+        .collect::<Vec<ImageEntry>>();
+    let images_path = std::env::current_dir()
+        .unwrap_or_default()
+        .join("data")
+        .join(content_type.name())
+        .join(format!("{}.json", content_type));
+    let written = serde_json::to_string_pretty(&entries)
+        .map_err(Error::from)
+        .and_then(|json| std::fs::write(&images_path, json).map_err(Error::from));
+    match written {
+        Ok(_) => {
+            info!("Successfully wrote {}", images_path.display());
+        }
+        Err(e) => {
+            error!("Failed to write {}: {}", images_path.display(), e);
+        }
+    }
+}
+
+pub fn image_list_records(images: &[ImageInfo]) -> Result<Vec<(u64, Vec<u8>)>, Error> {
+    images
+        .iter()
+        .map(|image| {
+            let bytes = image.ser()?;
+            Ok((bytes.key, bytes.value))
+        })
+        .collect()
+}
@@ -0,0 +1,695 @@
+use crate::front_matter::{self, ArticleMeta, MANIFEST_FILE};
+use crate::images::ImageEntry;
+use crate::ArticleRaw;
+use anyhow::{anyhow, Error};
+use database::{
+    Article, Calibration, ContentStore, ContentType, ImageInfo, MessageHasher, MessageHasherTrait,
+    StoreError, Testimonial,
+};
+use log::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+const ARTICLE_FIELDS: &[&str] = &[
+    "title",
+    "tags",
+    "file_name",
+    "image_url",
+    "index",
+    "premium",
+];
+const CALIBRATION_FIELDS: &[&str] = &["title", "calibration", "tags", "image_url", "description"];
+const TESTIMONIAL_FIELDS: &[&str] = &["image_url", "testimonial"];
+const IMAGE_FIELDS: &[&str] = &[
+    "url",
+    "width",
+    "height",
+    "blurhash",
+    "thumbnail",
+    "variants",
+];
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "gif", "svg", "avif"];
+/// Consciousness calibrations are on a logarithmic scale of 0 to 1000
+const MAX_CALIBRATION: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    /// File, and the record within it when known
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    fn error(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            severity: Severity::Error,
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warn(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            severity: Severity::Warning,
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn errors(&self) -> usize {
+        self.problems
+            .iter()
+            .filter(|p| p.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.problems
+            .iter()
+            .filter(|p| p.severity == Severity::Warning)
+            .count()
+    }
+
+    pub fn failed(&self, strict: bool) -> bool {
+        self.errors() > 0 || (strict && self.warnings() > 0)
+    }
+
+    pub fn log(&self) {
+        for problem in self.problems.iter() {
+            match problem.severity {
+                Severity::Error => error!("{}: {}", problem.location, problem.message),
+                Severity::Warning => warn!("{}: {}", problem.location, problem.message),
+            }
+        }
+        info!(
+            "Validation finished with {} error(s) and {} warning(s)",
+            self.errors(),
+            self.warnings()
+        );
+    }
+}
+
+/// Check every manifest under `data_dir` and every cache in `store`.
+///
+/// Problems are collected rather than returned early so a single run reports all of them.
+/// Only a missing data directory prevents validation from running at all.
+pub fn validate(data_dir: &Path, store: &ContentStore) -> Result<Report, Error> {
+    if !data_dir.is_dir() {
+        return Err(anyhow!(
+            "Data directory {} does not exist",
+            data_dir.display()
+        ));
+    }
+    let mut report = Report::default();
+
+    let mut expected = HashMap::<ContentType, HashSet<u64>>::new();
+    expected.insert(
+        ContentType::Articles,
+        validate_articles(data_dir, &mut report),
+    );
+    expected.insert(
+        ContentType::Calibrations,
+        validate_calibrations(data_dir, &mut report),
+    );
+    expected.insert(
+        ContentType::Testimonials,
+        validate_testimonials(data_dir, &mut report),
+    );
+    for content_type in ContentType::ALL.into_iter().filter(|c| c.is_image_list()) {
+        let keys = validate_image_list(data_dir, content_type, &mut report);
+        expected.insert(content_type, keys);
+    }
+
+    for content_type in ContentType::ALL {
+        let keys = expected.remove(&content_type).unwrap_or_default();
+        validate_cache(store, content_type, &keys, &mut report);
+    }
+
+    Ok(report)
+}
+
+/// Returns the cache keys the manifest and front matter should produce
+fn validate_articles(data_dir: &Path, report: &mut Report) -> HashSet<u64> {
+    let articles_dir = data_dir.join("articles");
+    let manifest = articles_dir.join(MANIFEST_FILE);
+    let mut keys = HashSet::new();
+
+    // (location, markdown path, manifest entry), manifest entries first
+    let mut sources = Vec::<(String, PathBuf, Option<ArticleMeta>)>::new();
+    if manifest.exists() || !articles_dir.is_dir() {
+        for (location, entry) in parse_manifest::<ArticleRaw>(&manifest, ARTICLE_FIELDS, report) {
+            let file_name = Path::new(&entry.file_name);
+            if file_name.components().count() != 1 {
+                report.error(
+                    &location,
+                    format!(
+                        "file_name {} must name a file directly inside {}",
+                        entry.file_name,
+                        articles_dir.display()
+                    ),
+                );
+                continue;
+            }
+            sources.push((location, articles_dir.join(file_name), Some(entry.meta)));
+        }
+    }
+    let referenced = sources
+        .iter()
+        .map(|(_, path, _)| path.clone())
+        .collect::<HashSet<PathBuf>>();
+    if let Ok(dir) = std::fs::read_dir(&articles_dir) {
+        let mut unreferenced = dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "md"))
+            .filter(|path| !referenced.contains(path))
+            .collect::<Vec<PathBuf>>();
+        unreferenced.sort();
+        for path in unreferenced {
+            sources.push((path.display().to_string(), path, None));
+        }
+    }
+
+    let mut indexes = HashMap::<u32, String>::new();
+    let mut titles = HashMap::<String, String>::new();
+    for (location, file_path, manifest_meta) in sources.into_iter() {
+        let file_location = file_path.display().to_string();
+        let markdown = match std::fs::read_to_string(&file_path) {
+            Ok(markdown) => markdown,
+            Err(e) => {
+                report.error(
+                    &location,
+                    format!("cannot read markdown {}: {}", file_location, e),
+                );
+                continue;
+            }
+        };
+        let (front_matter, body) = match front_matter::split(&markdown) {
+            Ok(split) => split,
+            Err(e) => {
+                report.error(&file_location, e.to_string());
+                continue;
+            }
+        };
+        if front_matter.is_none() && manifest_meta.is_none() {
+            // markdown files nobody describes are never ingested
+            report.warn(
+                &file_location,
+                "markdown file has no front matter and is not referenced by articles.json",
+            );
+            continue;
+        }
+
+        let resolved =
+            match front_matter::resolve(front_matter.as_ref(), manifest_meta.as_ref(), body) {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    report.error(&location, e.to_string());
+                    continue;
+                }
+            };
+        for conflict in resolved.conflicts.iter() {
+            report.warn(&file_location, conflict);
+        }
+
+        let article = resolved.article;
+        check_not_blank(&location, "title", &article.title, report);
+        check_tags(&location, &article.tags, report);
+        check_image_url(&location, &article.image_url, &articles_dir, report);
+        check_duplicate(&mut indexes, article.index, &location, "index", report);
+        check_duplicate(
+            &mut titles,
+            article.title.trim().to_lowercase(),
+            &location,
+            "title",
+            report,
+        );
+        check_markdown(&file_path, &markdown, &articles_dir, report);
+        // same body as `admin upsert` ingests so keys line up with the cache
+        keys.insert(MessageHasher::new().hash_article(&article.data));
+    }
+    keys
+}
+
+fn validate_calibrations(data_dir: &Path, report: &mut Report) -> HashSet<u64> {
+    let calibrations_dir = data_dir.join("calibrations");
+    let mut keys = HashSet::new();
+
+    let mut manifests = match std::fs::read_dir(&calibrations_dir) {
+        Ok(dir) => dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .collect::<Vec<PathBuf>>(),
+        Err(e) => {
+            report.error(calibrations_dir.display().to_string(), e.to_string());
+            return keys;
+        }
+    };
+    manifests.sort();
+
+    let mut titles = HashMap::<String, String>::new();
+    for manifest in manifests.iter() {
+        let entries = parse_manifest::<Calibration>(manifest, CALIBRATION_FIELDS, report);
+        for (location, calibration) in entries.into_iter() {
+            check_not_blank(&location, "title", &calibration.title, report);
+            check_tags(&location, &calibration.tags, report);
+            check_image_url(&location, &calibration.image_url, &calibrations_dir, report);
+            if calibration.description.trim().is_empty() {
+                report.warn(&location, "description is empty");
+            }
+            if calibration.calibration > MAX_CALIBRATION {
+                report.error(
+                    &location,
+                    format!(
+                        "calibration {} is above the maximum of {}",
+                        calibration.calibration, MAX_CALIBRATION
+                    ),
+                );
+            }
+            check_duplicate(
+                &mut titles,
+                calibration.title.trim().to_lowercase(),
+                &location,
+                "title",
+                report,
+            );
+            keys.insert(
+                MessageHasher::new().hash_calibration(&calibration.title, calibration.calibration),
+            );
+        }
+    }
+    keys
+}
+
+fn validate_testimonials(data_dir: &Path, report: &mut Report) -> HashSet<u64> {
+    let testimonials_dir = data_dir.join("testimonials");
+    let manifest = testimonials_dir.join("testimonials.json");
+    let mut keys = HashSet::new();
+
+    let mut texts = HashMap::<String, String>::new();
+    let entries = parse_manifest::<Testimonial>(&manifest, TESTIMONIAL_FIELDS, report);
+    for (location, testimonial) in entries.into_iter() {
+        check_not_blank(&location, "testimonial", &testimonial.testimonial, report);
+        check_image_url(&location, &testimonial.image_url, &testimonials_dir, report);
+        check_duplicate(
+            &mut texts,
+            testimonial.testimonial.trim().to_string(),
+            &location,
+            "testimonial",
+            report,
+        );
+        keys.insert(
+            MessageHasher::new().hash_testimonial(&testimonial.image_url, &testimonial.testimonial),
+        );
+    }
+    keys
+}
+
+fn validate_image_list(
+    data_dir: &Path,
+    content_type: ContentType,
+    report: &mut Report,
+) -> HashSet<u64> {
+    let images_dir = data_dir.join(content_type.name());
+    let manifest = images_dir.join(format!("{}.json", content_type.name()));
+    let mut keys = HashSet::new();
+
+    let mut urls = HashMap::<String, String>::new();
+    for (location, entry) in parse_manifest::<ImageEntry>(&manifest, IMAGE_FIELDS, report) {
+        let image = entry.into_info();
+        check_image_url(&location, &image.url, &images_dir, report);
+        for variant in image.thumbnail.iter().chain(image.variants.iter()) {
+            check_image_url(&location, &variant.url, &images_dir, report);
+            if variant.width == 0 || variant.height == 0 {
+                report.error(&location, format!("variant {} has no size", variant.url));
+            }
+        }
+        check_duplicate(&mut urls, image.url.clone(), &location, "image", report);
+        keys.insert(MessageHasher::new().hash_string(&image.url));
+    }
+    keys
+}
+
+/// Decode every record of a cache and compare its keys with what the manifests produce
+fn validate_cache(
+    store: &ContentStore,
+    content_type: ContentType,
+    expected: &HashSet<u64>,
+    report: &mut Report,
+) {
+    let location = store.location(content_type);
+    let records = match store.read(content_type) {
+        Ok(records) => records,
+        Err(StoreError::Io(_, e)) => {
+            report.error(&location, format!("cannot read cache: {}", e));
+            return;
+        }
+        Err(StoreError::Decode(_, e)) | Err(StoreError::Encode(_, e)) => {
+            report.error(&location, format!("cache failed to decode: {}", e));
+            return;
+        }
+        Err(StoreError::Database(e)) => {
+            report.error(&location, format!("cannot read records: {}", e));
+            return;
+        }
+    };
+    if records.is_empty() {
+        if !expected.is_empty() {
+            report.warn(&location, "cache is empty");
+        }
+        return;
+    }
+
+    let mut hasher = MessageHasher::new();
+    let mut keys = records.keys().copied().collect::<Vec<u64>>();
+    keys.sort();
+    for key in keys.iter() {
+        let value = &records[key];
+        let computed = match content_type {
+            ContentType::Articles => bincode::deserialize::<Article>(value)
+                .map(|article| hasher.hash_article(&article.data)),
+            ContentType::Calibrations => {
+                bincode::deserialize::<Calibration>(value).map(|calibration| {
+                    hasher.hash_calibration(&calibration.title, calibration.calibration)
+                })
+            }
+            ContentType::Testimonials => {
+                bincode::deserialize::<Testimonial>(value).map(|testimonial| {
+                    hasher.hash_testimonial(&testimonial.image_url, &testimonial.testimonial)
+                })
+            }
+            ContentType::TestimonialImages
+            | ContentType::CategoryImages
+            | ContentType::ContentTypeImages => bincode::deserialize::<ImageInfo>(value)
+                .or_else(|_| bincode::deserialize::<String>(value).map(ImageInfo::from_url))
+                .map(|image| hasher.hash_string(&image.url)),
+        };
+        let record_location = format!("{}[{}]", location, key);
+        match computed {
+            Err(e) => report.error(record_location, format!("record failed to decode: {}", e)),
+            Ok(computed) if computed != *key => report.warn(
+                record_location,
+                "key does not match the content hash of the record",
+            ),
+            Ok(_) => {}
+        }
+    }
+
+    let missing = expected
+        .iter()
+        .filter(|key| !records.contains_key(key))
+        .count();
+    if missing > 0 {
+        report.warn(
+            &location,
+            format!(
+                "{} manifest record(s) are not in the cache, run `admin upsert -t {}`",
+                missing, content_type
+            ),
+        );
+    }
+    let stale = records.keys().filter(|key| !expected.contains(key)).count();
+    if stale > 0 {
+        report.warn(
+            &location,
+            format!("{} cached record(s) are no longer in any manifest", stale),
+        );
+    }
+}
+
+/// Parse a JSON array manifest entry by entry, so one bad record doesn't hide the rest.
+/// Returns each valid record with its location, e.g. `data/articles/articles.json[3]`.
+fn parse_manifest<T: DeserializeOwned>(
+    path: &Path,
+    fields: &[&str],
+    report: &mut Report,
+) -> Vec<(String, T)> {
+    let file_location = path.display().to_string();
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            report.error(file_location, format!("cannot read manifest: {}", e));
+            return Vec::new();
+        }
+    };
+    let entries = match serde_json::from_str::<Vec<Value>>(&contents) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report.error(file_location, format!("invalid JSON array: {}", e));
+            return Vec::new();
+        }
+    };
+
+    let mut records = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let location = format!("{}[{}]", file_location, i);
+        if let Value::Object(object) = &entry {
+            for key in object.keys().filter(|key| !fields.contains(&key.as_str())) {
+                report.warn(&location, format!("unknown field `{}`", key));
+            }
+        }
+        match serde_json::from_value::<T>(entry) {
+            Ok(record) => records.push((location, record)),
+            Err(e) => report.error(location, format!("schema error: {}", e)),
+        }
+    }
+    records
+}
+
+fn check_not_blank(location: &str, field: &str, value: &str, report: &mut Report) {
+    if value.trim().is_empty() {
+        report.error(location, format!("{} is empty", field));
+    }
+}
+
+fn check_tags(location: &str, tags: &[String], report: &mut Report) {
+    if tags.is_empty() {
+        report.error(location, "tags are empty");
+    }
+    let mut seen = HashSet::new();
+    for tag in tags.iter() {
+        if tag.trim().is_empty() {
+            report.error(location, "contains an empty tag");
+        } else if !seen.insert(tag.trim()) {
+            report.warn(location, format!("tag `{}` is listed twice", tag));
+        }
+    }
+}
+
+/// Remote URLs must be well formed and point at an image,
+/// anything else is a local path that must exist relative to `base_dir`
+fn check_image_url(location: &str, image_url: &str, base_dir: &Path, report: &mut Report) {
+    if image_url.trim().is_empty() {
+        report.error(location, "image_url is empty");
+        return;
+    }
+    if image_url.starts_with("http://") || image_url.starts_with("https://") {
+        match Url::parse(image_url) {
+            Err(e) => report.error(
+                location,
+                format!("image_url {} is malformed: {}", image_url, e),
+            ),
+            Ok(url) => {
+                if url.host_str().is_none() {
+                    report.error(location, format!("image_url {} has no host", image_url));
+                }
+                if url.scheme() == "http" {
+                    report.warn(location, format!("image_url {} is not https", image_url));
+                }
+                if !has_image_extension(url.path()) {
+                    report.warn(
+                        location,
+                        format!("image_url {} does not look like an image", image_url),
+                    );
+                }
+                if image_url.contains(' ') {
+                    report.error(
+                        location,
+                        format!("image_url {} contains a space", image_url),
+                    );
+                }
+            }
+        }
+    } else if !base_dir.join(image_url).is_file() {
+        report.error(
+            location,
+            format!(
+                "local image {} not found in {}",
+                image_url,
+                base_dir.display()
+            ),
+        );
+    } else if !has_image_extension(image_url) {
+        report.warn(
+            location,
+            format!("local image {} does not look like an image", image_url),
+        );
+    }
+}
+
+fn has_image_extension(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        })
+}
+
+fn check_duplicate<K: std::hash::Hash + Eq + std::fmt::Display>(
+    seen: &mut HashMap<K, String>,
+    key: K,
+    location: &str,
+    field: &str,
+    report: &mut Report,
+) {
+    if let Some(first) = seen.get(&key) {
+        report.error(
+            location,
+            format!("duplicate {} `{}`, first used at {}", field, key, first),
+        );
+    } else {
+        seen.insert(key, location.to_string());
+    }
+}
+
+/// Structural markdown checks: unclosed code fences, unterminated links,
+/// and image references to local files that don't exist
+fn check_markdown(path: &Path, markdown: &str, base_dir: &Path, report: &mut Report) {
+    let location = path.display().to_string();
+    if markdown.trim().is_empty() {
+        report.error(location, "markdown is empty");
+        return;
+    }
+
+    let mut open_fence: Option<usize> = None;
+    for (i, line) in markdown.lines().enumerate() {
+        let line_number = i + 1;
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            open_fence = match open_fence {
+                Some(_) => None,
+                None => Some(line_number),
+            };
+            continue;
+        }
+        if open_fence.is_some() {
+            continue;
+        }
+
+        // text between backticks is inline code, only scan what is outside of it
+        for (segment_index, segment) in line.split('`').enumerate() {
+            if segment_index % 2 == 1 {
+                continue;
+            }
+            for link in markdown_links(segment) {
+                let line_location = format!("{}:{}", location, line_number);
+                match link {
+                    MarkdownLink::Unterminated => {
+                        report.error(line_location, "unterminated link target `](`")
+                    }
+                    MarkdownLink::Image(target) => {
+                        check_markdown_image(&line_location, &target, base_dir, report)
+                    }
+                    MarkdownLink::Link(_) => {}
+                }
+            }
+        }
+    }
+    if let Some(line_number) = open_fence {
+        report.error(
+            format!("{}:{}", location, line_number),
+            "code fence is never closed",
+        );
+    }
+}
+
+fn check_markdown_image(location: &str, target: &str, base_dir: &Path, report: &mut Report) {
+    // `![alt](path "title")` -> path
+    let target = target.split_whitespace().next().unwrap_or_default();
+    if target.is_empty() {
+        report.error(location, "image reference has no target");
+        return;
+    }
+    let is_remote = ["http://", "https://", "data:"]
+        .iter()
+        .any(|scheme| target.starts_with(scheme));
+    if !is_remote && !base_dir.join(target).is_file() {
+        report.error(
+            location,
+            format!("image {} not found in {}", target, base_dir.display()),
+        );
+    }
+}
+
+enum MarkdownLink {
+    Link(String),
+    Image(String),
+    Unterminated,
+}
+
+/// Find inline `[text](target)` and `![alt](target)` references in a line
+fn markdown_links(line: &str) -> Vec<MarkdownLink> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut links = Vec::new();
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        let is_escaped = i > 0 && chars[i - 1] == '\\';
+        if chars[i] != ']' || chars[i + 1] != '(' || is_escaped {
+            i += 1;
+            continue;
+        }
+        // the `[` opening this link decides whether it is an image
+        let open = chars[..i]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(j, c)| **c == '[' && (*j == 0 || chars[j - 1] != '\\'))
+            .map(|(j, _)| j);
+        let is_image = matches!(open, Some(j) if j > 0 && chars[j - 1] == '!');
+
+        let mut depth = 0;
+        let mut close = None;
+        for (j, c) in chars.iter().enumerate().skip(i + 2) {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    close = Some(j);
+                    break;
+                }
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+        match close {
+            None => {
+                links.push(MarkdownLink::Unterminated);
+                break;
+            }
+            Some(close) => {
+                let target = chars[i + 2..close].iter().collect::<String>();
+                if is_image {
+                    links.push(MarkdownLink::Image(target));
+                } else {
+                    links.push(MarkdownLink::Link(target));
+                }
+                i = close + 1;
+            }
+        }
+    }
+    links
+}
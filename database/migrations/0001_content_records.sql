@@ -0,0 +1,15 @@
+-- One row per record of a content type, the same key and bincode value as the cache files
+CREATE TABLE content_records (
+    content_type TEXT NOT NULL,
+    -- u64 content hash, stored with the same bits as an i64
+    key BIGINT NOT NULL,
+    value BYTEA NOT NULL,
+    PRIMARY KEY (content_type, key)
+);
+
+-- Bumped on every write of a content type, like cache/<name>.version
+CREATE TABLE content_versions (
+    content_type TEXT PRIMARY KEY,
+    version BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
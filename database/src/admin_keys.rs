@@ -0,0 +1,224 @@
+use crate::store::{FileStore, StoreError, StoreLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+/// Every admin API key starts with this, which tells them apart from JWTs
+pub const KEY_PREFIX: &str = "ca_admin_";
+
+/// What an admin may do. Each role grants a fixed set of scopes, see the server's `admin_auth`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AdminRole {
+    /// Read-only access to customers, subscriptions, orders and assets
+    Support,
+    /// Read-only access to customers, subscriptions, orders and invoices, plus changing the
+    /// subscription catalog. No access to assets.
+    Billing,
+    /// Upload and delete assets
+    ContentEditor,
+    /// Everything, including managing admin keys
+    Owner,
+}
+
+impl AdminRole {
+    pub const ALL: [AdminRole; 4] = [
+        AdminRole::Support,
+        AdminRole::Billing,
+        AdminRole::ContentEditor,
+        AdminRole::Owner,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminRole::Support => "support",
+            AdminRole::Billing => "billing",
+            AdminRole::ContentEditor => "content_editor",
+            AdminRole::Owner => "owner",
+        }
+    }
+}
+
+impl Display for AdminRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for AdminRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AdminRole::ALL
+            .into_iter()
+            .find(|role| role.name() == s)
+            .ok_or_else(|| format!("{} is not a valid admin role", s))
+    }
+}
+
+/// An admin API key, without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AdminKey {
+    pub id: String,
+    /// Who or what the key was issued to
+    pub name: String,
+    pub role: AdminRole,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl AdminKey {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// Only the sha256 of a key is stored, the key itself is shown once when it is created
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredKey {
+    #[serde(flatten)]
+    key: AdminKey,
+    sha256: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyFile {
+    keys: Vec<StoredKey>,
+}
+
+/// Admin API keys kept in a JSON file, `cache/admin_keys.json` by default.
+///
+/// The file is written like the caches, under a lock file and by atomic rename. It is read on
+/// every check, so a revoked key stops working on the next request, even in a running server.
+#[derive(Debug, Clone)]
+pub struct AdminKeyStore {
+    path: PathBuf,
+}
+
+impl AdminKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `ADMIN_KEYS_FILE`, or `admin_keys.json` in `cache_dir`
+    pub fn from_env(cache_dir: impl AsRef<Path>) -> Self {
+        match std::env::var("ADMIN_KEYS_FILE") {
+            Ok(path) if !path.is_empty() => Self::new(path),
+            _ => Self::new(cache_dir.as_ref().join("admin_keys.json")),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every key ever issued, revoked ones included, oldest first
+    pub fn list(&self) -> Result<Vec<AdminKey>, StoreError> {
+        Ok(self
+            .read()?
+            .keys
+            .into_iter()
+            .map(|stored| stored.key)
+            .collect())
+    }
+
+    /// Issue a key. Returns it along with the only copy of the key itself.
+    pub fn create(&self, name: &str, role: AdminRole) -> Result<(AdminKey, String), StoreError> {
+        let id = Uuid::new_v4().simple().to_string()[..12].to_string();
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let token = format!("{}{}_{}", KEY_PREFIX, id, secret);
+        let key = AdminKey {
+            id,
+            name: name.to_string(),
+            role,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        let stored = StoredKey {
+            key: key.clone(),
+            sha256: sha256(&token),
+        };
+        self.update(|file| file.keys.push(stored))?;
+        Ok((key, token))
+    }
+
+    /// Revoke the key with `id`, `None` if there is none. Revoking twice keeps the first time.
+    pub fn revoke(&self, id: &str) -> Result<Option<AdminKey>, StoreError> {
+        self.update(|file| {
+            let stored = file.keys.iter_mut().find(|stored| stored.key.id == id)?;
+            if stored.key.revoked_at.is_none() {
+                stored.key.revoked_at = Some(Utc::now());
+            }
+            Some(stored.key.clone())
+        })
+    }
+
+    /// The key `token` belongs to, `None` if it is unknown, revoked or the secret is wrong
+    pub fn verify(&self, token: &str) -> Result<Option<AdminKey>, StoreError> {
+        let id = match token
+            .strip_prefix(KEY_PREFIX)
+            .and_then(|rest| rest.split_once('_'))
+        {
+            Some((id, _)) => id,
+            None => return Ok(None),
+        };
+        let hash = sha256(token);
+        Ok(self
+            .read()?
+            .keys
+            .into_iter()
+            .find(|stored| stored.key.id == id)
+            // constant time, so response times don't reveal how much of the hash matched
+            .filter(|stored| {
+                !stored.key.is_revoked()
+                    && bool::from(stored.sha256.as_bytes().ct_eq(hash.as_bytes()))
+            })
+            .map(|stored| stored.key))
+    }
+
+    fn read(&self) -> Result<KeyFile, StoreError> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                StoreError::Io(
+                    self.path.clone(),
+                    std::io::Error::new(ErrorKind::InvalidData, e),
+                )
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(KeyFile::default()),
+            Err(e) => Err(StoreError::Io(self.path.clone(), e)),
+        }
+    }
+
+    /// Read, modify and write back the key file under its lock
+    fn update<T>(&self, modify: impl FnOnce(&mut KeyFile) -> T) -> Result<T, StoreError> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        std::fs::create_dir_all(&dir).map_err(|e| StoreError::Io(dir.clone(), e))?;
+        let _lock = StoreLock::acquire(self.path.with_extension("lock"))?;
+        let mut file = self.read()?;
+        let result = modify(&mut file);
+        let contents = serde_json::to_vec_pretty(&file).map_err(|e| {
+            StoreError::Io(
+                self.path.clone(),
+                std::io::Error::new(ErrorKind::InvalidData, e),
+            )
+        })?;
+        FileStore::new(dir).replace(&self.path, &contents)?;
+        Ok(result)
+    }
+}
+
+fn sha256(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,89 @@
+use crate::postgres::{self, PgStore};
+use crate::store::{ContentType, FileStore, Records, StoreError};
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+
+/// Which content store to use: the `cache/*.bin` files or Postgres with at most `pool_size`
+/// connections, [`postgres::DEFAULT_POOL_SIZE`] unless configured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreConfig {
+    File { dir: PathBuf },
+    Postgres { url: String, pool_size: usize },
+}
+
+impl StoreConfig {
+    /// Open the store, connecting to and migrating the database for `postgres`
+    pub fn open(&self) -> Result<ContentStore, StoreError> {
+        match self {
+            StoreConfig::File { dir } => Ok(ContentStore::File(FileStore::new(dir))),
+            StoreConfig::Postgres { url, pool_size } => {
+                Ok(ContentStore::Postgres(PgStore::connect(url, *pool_size)?))
+            }
+        }
+    }
+}
+
+/// The content store selected by [`StoreConfig`], with the API both stores share
+pub enum ContentStore {
+    File(FileStore),
+    Postgres(PgStore),
+}
+
+impl ContentStore {
+    /// Where a content type's records live, e.g. `cache/articles.bin`
+    pub fn location(&self, content_type: ContentType) -> String {
+        match self {
+            ContentStore::File(store) => store.path(content_type).display().to_string(),
+            ContentStore::Postgres(_) => postgres::location(content_type),
+        }
+    }
+
+    pub fn read(&self, content_type: ContentType) -> Result<Records, StoreError> {
+        match self {
+            ContentStore::File(store) => store.read(content_type),
+            ContentStore::Postgres(store) => store.read(content_type),
+        }
+    }
+
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        content_type: ContentType,
+    ) -> Result<Vec<(u64, T)>, StoreError> {
+        match self {
+            ContentStore::File(store) => store.load(content_type),
+            ContentStore::Postgres(store) => store.load(content_type),
+        }
+    }
+
+    pub fn version(&self, content_type: ContentType) -> Result<u64, StoreError> {
+        match self {
+            ContentStore::File(store) => store.version(content_type),
+            ContentStore::Postgres(store) => store.version(content_type),
+        }
+    }
+
+    pub fn write(&self, content_type: ContentType, records: &Records) -> Result<u64, StoreError> {
+        match self {
+            ContentStore::File(store) => store.write(content_type, records),
+            ContentStore::Postgres(store) => store.write(content_type, records),
+        }
+    }
+
+    pub fn update<E, F>(&self, content_type: ContentType, modify: F) -> Result<u64, E>
+    where
+        E: From<StoreError>,
+        F: FnOnce(&mut Records) -> Result<(), E>,
+    {
+        match self {
+            ContentStore::File(store) => store.update(content_type, modify),
+            ContentStore::Postgres(store) => store.update(content_type, modify),
+        }
+    }
+
+    pub fn dump(&self, content_type: ContentType) -> Result<Option<(Vec<u8>, u64)>, StoreError> {
+        match self {
+            ContentStore::File(store) => store.dump(content_type),
+            ContentStore::Postgres(store) => store.dump(content_type),
+        }
+    }
+}
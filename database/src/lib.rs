@@ -1,5 +1,13 @@
 pub mod types;
 pub mod hash;
+pub mod store;
+pub mod postgres;
+pub mod content_store;
+pub mod admin_keys;
 
 pub use types::*;
 pub use hash::*;
+pub use store::*;
+pub use postgres::PgStore;
+pub use content_store::*;
+pub use admin_keys::*;
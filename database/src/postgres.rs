@@ -0,0 +1,409 @@
+use crate::store::{ContentType, Records, StoreError};
+use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod};
+use log::*;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::sync::mpsc;
+use tokio::runtime::Runtime;
+use tokio_postgres::{Client, NoTls};
+
+/// SQL migrations, applied in order and recorded in `schema_migrations`
+const MIGRATIONS: [(i32, &str, &str); 1] = [(
+    1,
+    "content_records",
+    include_str!("../migrations/0001_content_records.sql"),
+)];
+
+/// Serializes migrations of concurrently starting servers and admin runs
+const MIGRATION_LOCK: i64 = 0x6361_6d69_6772_6174;
+
+/// Connections kept open by default
+pub const DEFAULT_POOL_SIZE: usize = 8;
+
+/// Content store backed by Postgres, with the same API as [`crate::FileStore`].
+///
+/// Records are rows of `content_records` keyed by content type and content hash, holding the
+/// same bincode values as the cache files, and `content_versions` has the version stamp of
+/// each content type. Writes replace every row of a content type and bump its version in one
+/// transaction under an advisory lock, so readers see either the old or the new records.
+///
+/// Queries run on a small runtime owned by the store, so its blocking methods can be called
+/// from sync code and from inside other async runtimes alike.
+pub struct PgStore {
+    runtime: StoreRuntime,
+    pool: Pool,
+}
+
+impl PgStore {
+    /// Connect to `url`, e.g. `postgres://postgres@localhost/consciousness_archive`, with at
+    /// most `pool_size` connections, and apply any pending migrations
+    pub fn connect(url: &str, pool_size: usize) -> Result<Self, StoreError> {
+        let config = url
+            .parse::<tokio_postgres::Config>()
+            .map_err(|e| StoreError::Database(format!("invalid DATABASE_URL: {}", e)))?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("postgres")
+            .enable_all()
+            .build()
+            .map_err(|e| StoreError::Database(format!("failed to start runtime: {}", e)))?;
+        // every connection runs a query when it's checked out, so a broken one is replaced
+        let manager = Manager::from_config(
+            config,
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Verified,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .max_size(pool_size.max(1))
+            .build()
+            .map_err(|e| StoreError::Database(format!("failed to create pool: {}", e)))?;
+        let store = Self {
+            runtime: StoreRuntime(Some(runtime)),
+            pool,
+        };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    /// Run a query on the store's runtime and wait for its result
+    fn run<T, F>(&self, future: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: Future<Output = Result<T, StoreError>> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.runtime.get().spawn(async move {
+            let result = future.await;
+            let _ = sender.send(result);
+        });
+        receiver
+            .recv()
+            .map_err(|_| StoreError::Database("query was cancelled".to_string()))?
+    }
+
+    /// Apply every migration not yet recorded in `schema_migrations`
+    pub fn migrate(&self) -> Result<(), StoreError> {
+        let pool = self.pool.clone();
+        let applied = self.run(async move {
+            let mut conn = get(&pool).await?;
+            conn.begin().await?;
+            let client = conn.client();
+            // taken first, concurrent CREATE TABLE IF NOT EXISTS can still fail on the catalog's
+            // unique index
+            client
+                .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+                .await
+                .map_err(db_error)?;
+            client
+                .batch_execute(
+                    "SET LOCAL client_min_messages = warning;
+                    CREATE TABLE IF NOT EXISTS schema_migrations (
+                        version INTEGER PRIMARY KEY,
+                        name TEXT NOT NULL,
+                        applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                    )",
+                )
+                .await
+                .map_err(db_error)?;
+            let mut applied = Vec::new();
+            for (version, name, sql) in MIGRATIONS.iter() {
+                let done = client
+                    .query_opt(
+                        "SELECT 1 FROM schema_migrations WHERE version = $1",
+                        &[version],
+                    )
+                    .await
+                    .map_err(db_error)?
+                    .is_some();
+                if done {
+                    continue;
+                }
+                client.batch_execute(sql).await.map_err(db_error)?;
+                client
+                    .execute(
+                        "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                        &[version, name],
+                    )
+                    .await
+                    .map_err(db_error)?;
+                applied.push(format!("{:04}_{}", version, name));
+            }
+            conn.commit().await?;
+            Ok(applied)
+        })?;
+        for migration in applied.iter() {
+            info!("Applied migration {}", migration);
+        }
+        Ok(())
+    }
+
+    /// Read the raw records of a content type. A content type never written is empty.
+    pub fn read(&self, content_type: ContentType) -> Result<Records, StoreError> {
+        let pool = self.pool.clone();
+        self.run(async move {
+            let mut conn = get(&pool).await?;
+            select_records(conn.client(), content_type).await
+        })
+    }
+
+    /// Read and decode every record of a content type, keyed by content hash
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        content_type: ContentType,
+    ) -> Result<Vec<(u64, T)>, StoreError> {
+        self.read(content_type)?
+            .into_iter()
+            .map(|(key, value)| {
+                bincode::deserialize::<T>(&value)
+                    .map(|record| (key, record))
+                    .map_err(|e| StoreError::Decode(location(content_type).into(), e))
+            })
+            .collect()
+    }
+
+    /// Version stamp of a content type, `0` if it has never been written
+    pub fn version(&self, content_type: ContentType) -> Result<u64, StoreError> {
+        let pool = self.pool.clone();
+        self.run(async move {
+            let mut conn = get(&pool).await?;
+            select_version(conn.client(), content_type).await
+        })
+    }
+
+    /// Replace every record of a content type. Returns the new version.
+    pub fn write(&self, content_type: ContentType, records: &Records) -> Result<u64, StoreError> {
+        let records = records.clone();
+        let pool = self.pool.clone();
+        self.run(async move {
+            let mut conn = get(&pool).await?;
+            conn.begin().await?;
+            lock(conn.client(), content_type).await?;
+            let version = replace_records(conn.client(), content_type, &records).await?;
+            conn.commit().await?;
+            Ok(version)
+        })
+    }
+
+    /// Read, modify and write back the records of a content type in one transaction holding
+    /// its advisory lock, so concurrent writers can't drop each other's changes.
+    /// Returns the new version.
+    pub fn update<E, F>(&self, content_type: ContentType, modify: F) -> Result<u64, E>
+    where
+        E: From<StoreError>,
+        F: FnOnce(&mut Records) -> Result<(), E>,
+    {
+        let pool = self.pool.clone();
+        // the transaction stays open on this connection while `modify` runs on the caller
+        let (mut conn, mut records) = self.run(async move {
+            let mut conn = get(&pool).await?;
+            conn.begin().await?;
+            lock(conn.client(), content_type).await?;
+            let records = select_records(conn.client(), content_type).await?;
+            Ok((conn, records))
+        })?;
+        if let Err(e) = modify(&mut records) {
+            self.run(async move {
+                conn.rollback().await?;
+                Ok(())
+            })?;
+            return Err(e);
+        }
+        Ok(self.run(async move {
+            let version = replace_records(conn.client(), content_type, &records).await?;
+            conn.commit().await?;
+            Ok(version)
+        })?)
+    }
+
+    /// The records of a content type encoded like a cache file, with their version, read in
+    /// one transaction. `None` if the content type has never been written.
+    pub fn dump(&self, content_type: ContentType) -> Result<Option<(Vec<u8>, u64)>, StoreError> {
+        let pool = self.pool.clone();
+        let (records, version) = self.run(async move {
+            let mut conn = get(&pool).await?;
+            conn.begin().await?;
+            lock(conn.client(), content_type).await?;
+            let records = select_records(conn.client(), content_type).await?;
+            let version = select_version(conn.client(), content_type).await?;
+            conn.commit().await?;
+            Ok((records, version))
+        })?;
+        if version == 0 && records.is_empty() {
+            return Ok(None);
+        }
+        let bytes = bincode::serialize(&records)
+            .map_err(|e| StoreError::Encode(location(content_type).into(), e))?;
+        Ok(Some((bytes, version)))
+    }
+}
+
+/// Where a content type's records live, for messages
+pub fn location(content_type: ContentType) -> String {
+    format!("postgres:content_records/{}", content_type)
+}
+
+fn db_error(e: tokio_postgres::Error) -> StoreError {
+    StoreError::Database(e.to_string())
+}
+
+/// Serialize writers of one content type until the end of the transaction
+async fn lock(client: &Client, content_type: ContentType) -> Result<(), StoreError> {
+    client
+        .execute(
+            "SELECT pg_advisory_xact_lock(hashtext('content_records:' || $1))",
+            &[&content_type.name()],
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+async fn select_records(client: &Client, content_type: ContentType) -> Result<Records, StoreError> {
+    let rows = client
+        .query(
+            "SELECT key, value FROM content_records WHERE content_type = $1",
+            &[&content_type.name()],
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, Vec<u8>>(1)))
+        .collect())
+}
+
+async fn select_version(client: &Client, content_type: ContentType) -> Result<u64, StoreError> {
+    let row = client
+        .query_opt(
+            "SELECT version FROM content_versions WHERE content_type = $1",
+            &[&content_type.name()],
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(row.map_or(0, |row| row.get::<_, i64>(0) as u64))
+}
+
+/// Replace the rows of a content type and bump its version, inside the caller's transaction
+async fn replace_records(
+    client: &Client,
+    content_type: ContentType,
+    records: &Records,
+) -> Result<u64, StoreError> {
+    let name = content_type.name();
+    client
+        .execute(
+            "DELETE FROM content_records WHERE content_type = $1",
+            &[&name],
+        )
+        .await
+        .map_err(db_error)?;
+    let (keys, values): (Vec<i64>, Vec<Vec<u8>>) = records
+        .iter()
+        .map(|(key, value)| (*key as i64, value.clone()))
+        .unzip();
+    client
+        .execute(
+            "INSERT INTO content_records (content_type, key, value)
+             SELECT $1, key, value FROM UNNEST($2::BIGINT[], $3::BYTEA[]) AS r(key, value)",
+            &[&name, &keys, &values],
+        )
+        .await
+        .map_err(db_error)?;
+    let row = client
+        .query_one(
+            "INSERT INTO content_versions (content_type, version) VALUES ($1, 1)
+             ON CONFLICT (content_type)
+             DO UPDATE SET version = content_versions.version + 1, updated_at = now()
+             RETURNING version",
+            &[&name],
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(row.get::<_, i64>(0) as u64)
+}
+
+/// Owns the store's runtime. Dropped in the background, since the store itself may be
+/// dropped inside another runtime where blocking on shutdown isn't allowed.
+struct StoreRuntime(Option<Runtime>);
+
+impl StoreRuntime {
+    fn get(&self) -> &Runtime {
+        self.0
+            .as_ref()
+            .expect("store runtime is only taken on drop")
+    }
+}
+
+impl Drop for StoreRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// A connection checked out of the pool. Returned to it on drop unless a transaction is left
+/// open, the pool checks it still works before handing it out again.
+struct PooledClient {
+    client: Option<Object>,
+    in_transaction: bool,
+}
+
+async fn get(pool: &Pool) -> Result<PooledClient, StoreError> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|e| StoreError::Database(format!("no database connection: {}", e)))?;
+    Ok(PooledClient {
+        client: Some(client),
+        in_transaction: false,
+    })
+}
+
+impl PooledClient {
+    fn client(&mut self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("pooled client is only taken on drop")
+    }
+
+    async fn begin(&mut self) -> Result<(), StoreError> {
+        self.client()
+            .batch_execute("BEGIN")
+            .await
+            .map_err(db_error)?;
+        self.in_transaction = true;
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        self.client()
+            .batch_execute("COMMIT")
+            .await
+            .map_err(db_error)?;
+        self.in_transaction = false;
+        Ok(())
+    }
+
+    async fn rollback(&mut self) -> Result<(), StoreError> {
+        self.client()
+            .batch_execute("ROLLBACK")
+            .await
+            .map_err(db_error)?;
+        self.in_transaction = false;
+        Ok(())
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        // a connection left mid-transaction by an error is closed rather than reused
+        if let Some(client) = self.client.take() {
+            if self.in_transaction {
+                drop(Object::take(client));
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Raw contents of a cache file: content hash key to bincode encoded record
+pub type Records = HashMap<u64, Vec<u8>>;
+
+/// Every kind of content persisted as a `cache/<name>.bin` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentType {
+    Articles,
+    Calibrations,
+    Testimonials,
+    TestimonialImages,
+    CategoryImages,
+    ContentTypeImages,
+}
+
+impl ContentType {
+    pub const ALL: [ContentType; 6] = [
+        ContentType::Articles,
+        ContentType::Calibrations,
+        ContentType::Testimonials,
+        ContentType::TestimonialImages,
+        ContentType::CategoryImages,
+        ContentType::ContentTypeImages,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentType::Articles => "articles",
+            ContentType::Calibrations => "calibrations",
+            ContentType::Testimonials => "testimonials",
+            ContentType::TestimonialImages => "testimonial_images",
+            ContentType::CategoryImages => "category_images",
+            ContentType::ContentTypeImages => "content_type_images",
+        }
+    }
+
+    pub fn cache_file(&self) -> String {
+        format!("{}.bin", self.name())
+    }
+
+    /// Counter bumped on every write of the cache file
+    pub fn version_file(&self) -> String {
+        format!("{}.version", self.name())
+    }
+
+    /// Advisory lock held by writers for the whole read-modify-write of the cache file
+    pub fn lock_file(&self) -> String {
+        format!("{}.lock", self.name())
+    }
+
+    /// Image lists store [`ImageInfo`](crate::ImageInfo) records. Bare URL strings of caches
+    /// written before images were processed still decode, see [`ImageInfo::de`](crate::ImageInfo::de).
+    pub fn is_image_list(&self) -> bool {
+        matches!(
+            self,
+            ContentType::TestimonialImages
+                | ContentType::CategoryImages
+                | ContentType::ContentTypeImages
+        )
+    }
+}
+
+impl Display for ContentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContentType::ALL
+            .into_iter()
+            .find(|content_type| content_type.name() == s)
+            .ok_or_else(|| format!("{} is not a valid content type", s))
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// The cache file could not be opened, read or written
+    Io(PathBuf, std::io::Error),
+    /// The cache file was read but its contents could not be decoded
+    Decode(PathBuf, bincode::Error),
+    /// Records could not be encoded for writing
+    Encode(PathBuf, bincode::Error),
+    /// The database could not be reached or a query failed
+    Database(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            StoreError::Decode(path, e) => write!(f, "{}: failed to decode: {}", path.display(), e),
+            StoreError::Encode(path, e) => write!(f, "{}: failed to encode: {}", path.display(), e),
+            StoreError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// Exclusive advisory lock on one content type's cache, released on drop
+#[derive(Debug)]
+pub struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    /// Block until this process holds the lock file at `path`, creating it if needed
+    pub(crate) fn acquire(path: PathBuf) -> Result<Self, StoreError> {
+        let file = File::options()
+            .create(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| StoreError::Io(path.clone(), e))?;
+        file.lock_exclusive().map_err(|e| StoreError::Io(path, e))?;
+        Ok(StoreLock { file })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Content store backed by one bincode file per [`ContentType`] in a cache directory.
+///
+/// Writes never modify a cache file in place: records are written to a temp file in the same
+/// directory, synced and renamed over the cache file, so readers see either the old or the new
+/// contents. Writers serialize on a `<name>.lock` file and bump a `<name>.version` stamp after
+/// every write, which readers can poll to notice new content.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The `cache` directory under the current working directory
+    pub fn from_current_dir() -> std::io::Result<Self> {
+        Ok(Self::new(std::env::current_dir()?.join("cache")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, content_type: ContentType) -> PathBuf {
+        self.dir.join(content_type.cache_file())
+    }
+
+    /// Read the raw records of a cache file. An empty file is an empty cache.
+    pub fn read(&self, content_type: ContentType) -> Result<Records, StoreError> {
+        let path = self.path(content_type);
+        let buf = std::fs::read(&path).map_err(|e| StoreError::Io(path.clone(), e))?;
+        if buf.is_empty() {
+            return Ok(Records::new());
+        }
+        bincode::deserialize::<Records>(&buf).map_err(|e| StoreError::Decode(path, e))
+    }
+
+    /// Read and decode every record of a cache file, keyed by content hash
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        content_type: ContentType,
+    ) -> Result<Vec<(u64, T)>, StoreError> {
+        let path = self.path(content_type);
+        self.read(content_type)?
+            .into_iter()
+            .map(|(key, value)| {
+                bincode::deserialize::<T>(&value)
+                    .map(|record| (key, record))
+                    .map_err(|e| StoreError::Decode(path.clone(), e))
+            })
+            .collect()
+    }
+
+    /// Version stamp of a cache file, `0` if it has never been written through the store
+    pub fn version(&self, content_type: ContentType) -> Result<u64, StoreError> {
+        let path = self.dir.join(content_type.version_file());
+        match std::fs::read_to_string(&path) {
+            Ok(version) => version
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| StoreError::Io(path, std::io::Error::new(ErrorKind::InvalidData, e))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(StoreError::Io(path, e)),
+        }
+    }
+
+    /// Block until this process holds the write lock of a content type
+    pub fn lock(&self, content_type: ContentType) -> Result<StoreLock, StoreError> {
+        StoreLock::acquire(self.dir.join(content_type.lock_file()))
+    }
+
+    /// Replace every record of a cache file. Returns the new version.
+    pub fn write(&self, content_type: ContentType, records: &Records) -> Result<u64, StoreError> {
+        let _lock = self.lock(content_type)?;
+        self.write_locked(content_type, records)
+    }
+
+    /// Read, modify and write back a cache file while holding its lock, so concurrent writers
+    /// can't drop each other's changes. A missing cache file starts out empty.
+    /// Returns the new version.
+    pub fn update<E, F>(&self, content_type: ContentType, modify: F) -> Result<u64, E>
+    where
+        E: From<StoreError>,
+        F: FnOnce(&mut Records) -> Result<(), E>,
+    {
+        let _lock = self.lock(content_type)?;
+        let mut records = match self.read(content_type) {
+            Ok(records) => records,
+            Err(StoreError::Io(_, e)) if e.kind() == ErrorKind::NotFound => Records::new(),
+            Err(e) => return Err(e.into()),
+        };
+        modify(&mut records)?;
+        Ok(self.write_locked(content_type, &records)?)
+    }
+
+    /// The cache file's contents with its version, read under the write lock so the two match.
+    /// `None` if the cache file doesn't exist.
+    pub fn dump(&self, content_type: ContentType) -> Result<Option<(Vec<u8>, u64)>, StoreError> {
+        let _lock = self.lock(content_type)?;
+        let path = self.path(content_type);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(StoreError::Io(path, e)),
+        };
+        Ok(Some((bytes, self.version(content_type)?)))
+    }
+
+    fn write_locked(
+        &self,
+        content_type: ContentType,
+        records: &Records,
+    ) -> Result<u64, StoreError> {
+        let path = self.path(content_type);
+        let buf = bincode::serialize(records).map_err(|e| StoreError::Encode(path.clone(), e))?;
+        self.replace(&path, &buf)?;
+
+        // bumped after the rename, so a reader that sees the new version also sees the new records
+        let version = self.version(content_type)? + 1;
+        self.replace(
+            &self.dir.join(content_type.version_file()),
+            version.to_string().as_bytes(),
+        )?;
+        Ok(version)
+    }
+
+    /// Atomically replace `path` with `contents` via a synced temp file and rename
+    pub(crate) fn replace(&self, path: &Path, contents: &[u8]) -> Result<(), StoreError> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let tmp_path = self
+            .dir
+            .join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+        let result = File::create(&tmp_path)
+            .and_then(|mut tmp| {
+                tmp.write_all(contents)?;
+                tmp.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .and_then(|_| File::open(&self.dir)?.sync_all());
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result.map_err(|e| StoreError::Io(path.to_path_buf(), e))
+    }
+}
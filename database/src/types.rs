@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 // ==================== Article ====================
 
 #[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Article {
     pub title: String,
     pub tags: Vec<String>,
@@ -22,8 +24,7 @@ pub struct DbArticle {
 
 impl Article {
     pub fn de(article: &[u8]) -> Result<Article, Error> {
-        let article =
-            bincode::deserialize::<Article>(article).expect("Failed to deserialize article");
+        let article = bincode::deserialize::<Article>(article)?;
 
         Ok(Article {
             title: article.title,
@@ -37,7 +38,7 @@ impl Article {
 
     pub fn ser(&self) -> Result<DbArticle, Error> {
         let key = MessageHasher::new().hash_article(&self.data);
-        let value = bincode::serialize(&self).expect("Failed to serialize article");
+        let value = bincode::serialize(&self)?;
 
         Ok(DbArticle { key, value })
     }
@@ -46,6 +47,8 @@ impl Article {
 // ==================== Calibration ====================
 
 #[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Calibration {
     pub title: String,
     pub calibration: u32,
@@ -62,8 +65,7 @@ pub struct DbCalibration {
 
 impl Calibration {
     pub fn de(calibration: &[u8]) -> Result<Calibration, Error> {
-        let calibration = bincode::deserialize::<Calibration>(calibration)
-            .expect("Failed to deserialize calibration");
+        let calibration = bincode::deserialize::<Calibration>(calibration)?;
 
         Ok(Calibration {
             title: calibration.title,
@@ -76,7 +78,7 @@ impl Calibration {
 
     pub fn ser(&self) -> Result<DbCalibration, Error> {
         let key = MessageHasher::new().hash_calibration(&self.title, self.calibration);
-        let value = bincode::serialize(&self).expect("Failed to serialize calibration");
+        let value = bincode::serialize(&self)?;
 
         Ok(DbCalibration { key, value })
     }
@@ -85,6 +87,8 @@ impl Calibration {
 // ==================== Testimonial ====================
 
 #[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Testimonial {
     pub image_url: String,
     pub testimonial: String,
@@ -98,8 +102,7 @@ pub struct DbTestimonial {
 
 impl Testimonial {
     pub fn de(testimonial: &[u8]) -> Result<Testimonial, Error> {
-        let testimonial = bincode::deserialize::<Testimonial>(testimonial)
-            .expect("Failed to deserialize testimonial");
+        let testimonial = bincode::deserialize::<Testimonial>(testimonial)?;
 
         Ok(Testimonial {
             image_url: testimonial.image_url,
@@ -109,8 +112,77 @@ impl Testimonial {
 
     pub fn ser(&self) -> Result<DbTestimonial, Error> {
         let key = MessageHasher::new().hash_testimonial(&self.image_url, &self.testimonial);
-        let value = bincode::serialize(&self).expect("Failed to serialize testimonial");
+        let value = bincode::serialize(&self)?;
 
         Ok(DbTestimonial { key, value })
     }
 }
+
+// ==================== Image ====================
+
+/// A processed image: its original URL plus what the frontend needs to lazy-load it
+#[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImageInfo {
+    pub url: String,
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Placeholder to render while the image loads, see https://blurha.sh
+    #[serde(default)]
+    pub blurhash: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<ImageVariant>,
+    /// Resized copies, narrowest first
+    #[serde(default)]
+    pub variants: Vec<ImageVariant>,
+}
+
+#[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImageVariant {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    /// File format, e.g. "webp"
+    pub format: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
+pub struct DbImage {
+    pub key: u64,
+    pub value: Vec<u8>,
+}
+
+impl ImageInfo {
+    /// An image nothing is known about but its URL
+    pub fn from_url(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            width: None,
+            height: None,
+            blurhash: None,
+            thumbnail: None,
+            variants: Vec::new(),
+        }
+    }
+
+    /// Decode an image record. Caches written before images were processed hold bare URL strings.
+    pub fn de(image: &[u8]) -> Result<ImageInfo, Error> {
+        match bincode::deserialize::<ImageInfo>(image) {
+            Ok(image) => Ok(image),
+            Err(_) => Ok(ImageInfo::from_url(bincode::deserialize::<String>(image)?)),
+        }
+    }
+
+    /// Keyed by URL like the bare URL records, so reprocessing an image replaces its record
+    pub fn ser(&self) -> Result<DbImage, Error> {
+        let key = MessageHasher::new().hash_string(&self.url);
+        let value = bincode::serialize(&self)?;
+
+        Ok(DbImage { key, value })
+    }
+}
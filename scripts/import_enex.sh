@@ -11,7 +11,7 @@ usage() {
 
 usage: $0 [OPTIONS] [ARGS]
 
-Converts Evernotes .enex file to .md file.
+Converts an Evernote .enex file to article .md files, images and articles.json entries.
 
 ARGS:
   --input               - Input file path .enex
@@ -44,4 +44,6 @@ fi
 
 
 WORKDIR="$(git rev-parse --show-toplevel)"
-evernote2md "$HOME"/LIFE/C-Archive/Evernote_enex/"$input" "$WORKDIR"/data/articles
\ No newline at end of file
+cargo run -r -p admin -- import-enex \
+  -f "$HOME"/LIFE/C-Archive/Evernote_enex/"$input" \
+  -o "$WORKDIR"/data/articles
\ No newline at end of file
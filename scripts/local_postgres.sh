@@ -0,0 +1,12 @@
+#!/bin/bash
+
+# Start a throwaway Postgres for CONTENT_STORE=postgres on localhost:5432, e.g.
+# DATABASE_URL=postgres://postgres@localhost:5432/consciousness_archive
+
+docker run -d --rm \
+  --name ca-postgres \
+  -p 5432:5432 \
+  -e POSTGRES_HOST_AUTH_METHOD=trust \
+  -e POSTGRES_DB=consciousness_archive \
+  postgres:15 \
+  "$@"
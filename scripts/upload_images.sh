@@ -0,0 +1,10 @@
+#!/bin/bash
+
+set -e
+
+for images in testimonial_images category_images content_type_images; do
+  cargo run -r -p admin -- assets upload \
+    -t "$images" \
+    "$HOME"/LIFE/DivinityCode/images/"$images" \
+    "$@"
+done
@@ -2,6 +2,7 @@
 
 WORKDIR="$(git rev-parse --show-toplevel)"
 
-cargo run -r -p admin -- \
+cargo run -r -p admin -- upsert \
   -t articles \
-  -f "$WORKDIR"/data/articles/articles.json
\ No newline at end of file
+  -f "$WORKDIR"/data/articles/articles.json \
+  "$@"
\ No newline at end of file
@@ -2,18 +2,10 @@
 
 WORKDIR="$(git rev-parse --show-toplevel)"
 
-cargo run -r -p admin -- \
+cargo run -r -p admin -- upsert \
   -t calibrations \
-  -f "$WORKDIR"/data/calibrations/movies.json
-
-cargo run -r -p admin -- \
-  -t calibrations \
-  -f "$WORKDIR"/data/calibrations/sports.json
-
-cargo run -r -p admin -- \
-  -t calibrations \
-  -f "$WORKDIR"/data/calibrations/books.json
-
-cargo run -r -p admin -- \
-  -t calibrations \
-  -f "$WORKDIR"/data/calibrations/people.json
\ No newline at end of file
+  -f "$WORKDIR"/data/calibrations/movies.json \
+  -f "$WORKDIR"/data/calibrations/sports.json \
+  -f "$WORKDIR"/data/calibrations/books.json \
+  -f "$WORKDIR"/data/calibrations/people.json \
+  "$@"
\ No newline at end of file
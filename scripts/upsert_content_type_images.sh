@@ -1,5 +1,6 @@
 #!/bin/bash
 
-cargo run -r -p admin -- \
+cargo run -r -p admin -- upsert \
   -t content_type_images \
-  -f "$HOME"/LIFE/DivinityCode/images/content_type_images
\ No newline at end of file
+  -f "$HOME"/LIFE/DivinityCode/images/content_type_images \
+  "$@"
\ No newline at end of file
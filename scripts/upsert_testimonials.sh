@@ -2,6 +2,7 @@
 
 WORKDIR="$(git rev-parse --show-toplevel)"
 
-cargo run -r -p admin -- \
+cargo run -r -p admin -- upsert \
   -t testimonials \
-  -f "$WORKDIR"/data/testimonials/testimonials.json
\ No newline at end of file
+  -f "$WORKDIR"/data/testimonials/testimonials.json \
+  "$@"
\ No newline at end of file
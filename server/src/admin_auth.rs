@@ -0,0 +1,274 @@
+use crate::errors::ServiceError;
+use crate::handler::blocking;
+use crate::identity::IdentityProvider;
+use crate::oauth::validate_claims;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error as ActixError, FromRequest, HttpMessage, HttpRequest};
+use actix_web_httpauth::extractors::bearer::{BearerAuth, Config};
+use actix_web_httpauth::extractors::AuthenticationError;
+use database::{AdminKeyStore, AdminRole, KEY_PREFIX};
+use futures::future::{ready, Either, MapOk, Ready};
+use futures::TryFutureExt;
+use log::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Permission an `/admin` route requires, declared on the route with `wrap = "Scope::..."`
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+pub enum Scope {
+    #[serde(rename = "assets:read")]
+    AssetsRead,
+    #[serde(rename = "assets:write")]
+    AssetsWrite,
+    #[serde(rename = "billing:read")]
+    BillingRead,
+    #[serde(rename = "billing:write")]
+    BillingWrite,
+    #[serde(rename = "keys:manage")]
+    KeysManage,
+    #[serde(rename = "audit:read")]
+    AuditRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 6] = [
+        Scope::AssetsRead,
+        Scope::AssetsWrite,
+        Scope::BillingRead,
+        Scope::BillingWrite,
+        Scope::KeysManage,
+        Scope::AuditRead,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::AssetsRead => "assets:read",
+            Scope::AssetsWrite => "assets:write",
+            Scope::BillingRead => "billing:read",
+            Scope::BillingWrite => "billing:write",
+            Scope::KeysManage => "keys:manage",
+            Scope::AuditRead => "audit:read",
+        }
+    }
+
+    /// Scopes granted by `role`
+    pub fn of_role(role: AdminRole) -> &'static [Scope] {
+        match role {
+            AdminRole::Support => &[Scope::AssetsRead, Scope::BillingRead],
+            AdminRole::Billing => &[Scope::BillingRead, Scope::BillingWrite],
+            AdminRole::ContentEditor => &[Scope::AssetsRead, Scope::AssetsWrite],
+            AdminRole::Owner => &Scope::ALL,
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.name() == s)
+            .ok_or_else(|| format!("{} is not a valid admin scope", s))
+    }
+}
+
+/// The admin a request was authenticated as, stored in the request extensions by
+/// [`admin_validator`]
+#[derive(Debug, Clone)]
+pub struct Admin {
+    /// `key:<id>` for an admin API key, `jwt:<sub>` for a token
+    pub actor: String,
+    pub scopes: BTreeSet<Scope>,
+}
+
+impl Admin {
+    fn from_key(store: &AdminKeyStore, token: &str) -> Result<Self, ServiceError> {
+        let key = store.verify(token)?.ok_or_else(|| {
+            ServiceError::Unauthorized("Unknown or revoked admin key".to_string())
+        })?;
+        Ok(Admin {
+            actor: format!("key:{}", key.id),
+            scopes: Scope::of_role(key.role).iter().copied().collect(),
+        })
+    }
+
+    /// Scopes of the roles in the `roles_claim` claim (`roles` by default), and of the
+    /// `permissions` claim Auth0 RBAC adds to access tokens
+    fn from_claims(claims: &Value, roles_claim: &str) -> Result<Self, ServiceError> {
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ServiceError::Unauthorized("Token has no subject".to_string()))?;
+        let names = |claim: &str| -> Vec<String> {
+            claims
+                .get(claim)
+                .and_then(Value::as_array)
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let mut scopes = BTreeSet::new();
+        for role in names(roles_claim) {
+            match role.parse::<AdminRole>() {
+                Ok(role) => scopes.extend(Scope::of_role(role)),
+                Err(e) => debug!("Ignoring role of {}: {}", subject, e),
+            }
+        }
+        for permission in names("permissions") {
+            match permission.parse::<Scope>() {
+                Ok(scope) => {
+                    scopes.insert(scope);
+                }
+                Err(e) => debug!("Ignoring permission of {}: {}", subject, e),
+            }
+        }
+        if scopes.is_empty() {
+            return Err(ServiceError::Forbidden(
+                "Token grants no admin role".to_string(),
+            ));
+        }
+        Ok(Admin {
+            actor: format!("jwt:{}", subject),
+            scopes,
+        })
+    }
+
+    pub fn has(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// Only available behind [`admin_validator`], anywhere else the request is unauthorized
+impl FromRequest for Admin {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Admin>()
+                .cloned()
+                .ok_or_else(|| ServiceError::Unauthorized("Not authenticated".to_string())),
+        )
+    }
+}
+
+/// Accepts an admin API key (`ca_admin_...`) or a JWT with admin roles, and stores the
+/// [`Admin`] in the request extensions. Routes check its scopes with [`Scope`].
+pub async fn admin_validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
+    let config = req.app_data::<Config>().cloned().unwrap_or_default();
+    let token = credentials.token().to_string();
+    let admin = if token.starts_with(KEY_PREFIX) {
+        match req.app_data::<web::Data<AdminKeyStore>>() {
+            Some(store) => {
+                let store = store.clone();
+                blocking(move || Admin::from_key(&store, &token)).await
+            }
+            None => Err(ServiceError::Internal(
+                "Admin key store is not configured".to_string(),
+            )),
+        }
+    } else {
+        match req.app_data::<web::Data<IdentityProvider>>() {
+            Some(provider) => {
+                let provider = provider.clone();
+                validate_claims(&provider, &token)
+                    .await
+                    .and_then(|claims| Admin::from_claims(&claims, &provider.config().roles_claim))
+            }
+            None => Err(ServiceError::Internal(
+                "Identity provider is not configured".to_string(),
+            )),
+        }
+    };
+    match admin {
+        Ok(admin) => {
+            debug!("Admin {} authenticated", admin.actor);
+            req.extensions_mut().insert(admin);
+            Ok(req)
+        }
+        Err(ServiceError::Unauthorized(e)) => {
+            info!("Admin authentication failed: {}", e);
+            Err((AuthenticationError::from(config).into(), req))
+        }
+        Err(e) => Err((e.into(), req)),
+    }
+}
+
+/// Rejects requests whose [`Admin`] lacks this scope with `403`. The rejection is a response
+/// rather than an error, so outer middleware such as the audit log still sees the request.
+impl<S, B> Transform<S, ServiceRequest> for Scope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = ActixError;
+    type Transform = ScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ScopeMiddleware {
+            service,
+            scope: *self,
+        }))
+    }
+}
+
+pub struct ScopeMiddleware<S> {
+    service: S,
+    scope: Scope,
+}
+
+type Allowed<B> = fn(ServiceResponse<B>) -> ServiceResponse<EitherBody<B>>;
+
+impl<S, B> Service<ServiceRequest> for ScopeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = ActixError;
+    type Future = Either<MapOk<S::Future, Allowed<B>>, Ready<Result<Self::Response, Self::Error>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed = req
+            .extensions()
+            .get::<Admin>()
+            .map(|admin| admin.has(self.scope));
+        let error = match allowed {
+            Some(true) => {
+                let allowed: Allowed<B> = ServiceResponse::map_into_left_body;
+                return Either::Left(self.service.call(req).map_ok(allowed));
+            }
+            Some(false) => ServiceError::Forbidden(format!("Requires the {} scope", self.scope)),
+            None => ServiceError::Unauthorized("Not authenticated".to_string()),
+        };
+        Either::Right(ready(Ok(req.error_response(error).map_into_right_body())))
+    }
+}
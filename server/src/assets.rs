@@ -0,0 +1,88 @@
+use crate::config::{ObjectStorageConfig, StorageBackend};
+use crate::errors::ServiceError;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use storage::{ObjectInfo, ObjectStore, StorageConfig};
+use tokio::sync::OnceCell;
+use schemars::JsonSchema;
+
+/// Uploads larger than this are rejected
+pub const MAX_ASSET_SIZE: usize = 20 * 1024 * 1024;
+
+/// Object storage for assets. The config is read at startup, the backend is only connected
+/// to on first use, so the server runs without storage credentials until assets are managed.
+pub struct AssetStore {
+    config: StorageConfig,
+    store: OnceCell<Arc<dyn ObjectStore>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Asset {
+    pub key: String,
+    pub url: String,
+    pub size: u64,
+    pub md5: Option<String>,
+    pub content_type: Option<String>,
+    pub updated: Option<String>,
+}
+
+impl AssetStore {
+    /// The object store selected by `storage`, relative to the working directory for `local`
+    pub fn from_config(storage: &ObjectStorageConfig) -> Result<Self, String> {
+        let config = match storage.backend {
+            StorageBackend::Gcs => StorageConfig::gcs(
+                storage.bucket.clone(),
+                storage.emulator_host.clone(),
+                storage.public_url.clone(),
+            ),
+            StorageBackend::Local => {
+                StorageConfig::local(&storage.dir, storage.public_url.clone())
+                    .map_err(|e| format!("Failed to resolve storage directory: {}", e))?
+            }
+        };
+        Ok(Self {
+            config,
+            store: OnceCell::new(),
+        })
+    }
+
+    async fn store(&self) -> Result<&Arc<dyn ObjectStore>, ServiceError> {
+        self.store
+            .get_or_try_init(|| self.config.open())
+            .await
+            .map_err(ServiceError::from)
+    }
+
+    fn asset(&self, object: ObjectInfo) -> Asset {
+        Asset {
+            url: self.config.public_url(&object.key),
+            key: object.key,
+            size: object.size,
+            md5: object.md5,
+            content_type: object.content_type,
+            updated: object.updated.map(|updated| updated.to_rfc3339()),
+        }
+    }
+
+    pub async fn list(&self, prefix: &str) -> Result<Vec<Asset>, ServiceError> {
+        let objects = self.store().await?.list(prefix).await?;
+        Ok(objects
+            .into_iter()
+            .map(|object| self.asset(object))
+            .collect())
+    }
+
+    pub async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<Asset, ServiceError> {
+        let object = self.store().await?.put(key, data, content_type).await?;
+        Ok(self.asset(object))
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), ServiceError> {
+        Ok(self.store().await?.delete(key).await?)
+    }
+}
@@ -0,0 +1,333 @@
+use crate::admin_auth::Admin;
+use crate::errors::ServiceError;
+use crate::handler::blocking;
+use crate::oauth::Identity;
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error as ActixError, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Actor of a request that wasn't authenticated, e.g. one with a bad admin key
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Entries returned by a query when `limit` is omitted
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// One audited request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    /// `key:<id>` or `jwt:<sub>` for admins, `user:<sub>` for users, `anonymous` if the request
+    /// failed authentication
+    pub actor: String,
+    /// Method and route, e.g. `DELETE /admin/keys/{id}`
+    pub action: String,
+    /// Path parameter of the route, e.g. the asset key, or the user's email for user actions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Query parameters, and what the handler recorded from the request body
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub params: Map<String, Value>,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Outcome {
+    pub status: u16,
+    /// Error code of a failed request, see `ErrorBody`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    fn from_response<B>(res: &ServiceResponse<B>) -> Self {
+        let req = res.request();
+        let extensions = req.extensions();
+        let identity = extensions.get::<Identity>();
+        let actor = match (extensions.get::<Admin>(), identity) {
+            (Some(admin), _) => admin.actor.clone(),
+            (None, Some(identity)) => format!("user:{}", identity.subject),
+            (None, None) => ANONYMOUS.to_string(),
+        };
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let target = req
+            .match_info()
+            .iter()
+            .next()
+            .map(|(_, value)| value.to_string())
+            .or_else(|| identity.and_then(|identity| identity.email.clone()));
+        let mut params = web::Query::<BTreeMap<String, String>>::from_query(req.query_string())
+            .map(|query| {
+                query
+                    .into_inner()
+                    .into_iter()
+                    .map(|(name, value)| (name, Value::String(value)))
+                    .collect::<Map<String, Value>>()
+            })
+            .unwrap_or_default();
+        if let Some(AuditParams(recorded)) = extensions.get::<AuditParams>() {
+            params.extend(recorded.clone());
+        }
+        AuditEntry {
+            time: Utc::now(),
+            actor,
+            action: format!("{} {}", req.method(), route),
+            target,
+            params,
+            outcome: Outcome {
+                status: res.status().as_u16(),
+                error: res.response().error().map(error_code),
+            },
+        }
+    }
+
+    /// A request that failed before it had a response, when all that's left of it is the
+    /// `action` recorded on the way in
+    fn from_error(action: String, e: &ActixError) -> Self {
+        AuditEntry {
+            time: Utc::now(),
+            actor: ANONYMOUS.to_string(),
+            action,
+            target: None,
+            params: Map::new(),
+            outcome: Outcome {
+                status: e.as_response_error().status_code().as_u16(),
+                error: Some(error_code(e)),
+            },
+        }
+    }
+}
+
+/// The `ErrorBody` code of a [`ServiceError`], otherwise the status, e.g. `unauthorized` for
+/// a missing bearer token
+fn error_code(e: &ActixError) -> String {
+    match e.as_error::<ServiceError>() {
+        Some(e) => e.code().to_string(),
+        None => e
+            .as_response_error()
+            .status_code()
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace(' ', "_"),
+    }
+}
+
+/// Parameters a handler adds to its request's audit entry, e.g. from the request body
+#[derive(Debug, Clone, Default)]
+struct AuditParams(Map<String, Value>);
+
+/// Add `name` to the audit entry of `req`
+pub fn record_param(req: &HttpRequest, name: &str, value: impl Into<Value>) {
+    let mut extensions = req.extensions_mut();
+    if extensions.get::<AuditParams>().is_none() {
+        extensions.insert(AuditParams::default());
+    }
+    if let Some(AuditParams(params)) = extensions.get_mut::<AuditParams>() {
+        params.insert(name.to_string(), value.into());
+    }
+}
+
+/// Filters of `/admin/audit`, every one set must match
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct AuditQuery {
+    /// Exact actor, e.g. `key:5433c158f426`
+    pub actor: Option<String>,
+    /// Part of the action, e.g. `/admin/keys` or `DELETE`
+    pub action: Option<String>,
+    /// Exact target
+    pub target: Option<String>,
+    /// Entries at or after this time, RFC 3339
+    pub since: Option<DateTime<Utc>>,
+    /// Entries before this time, RFC 3339
+    pub until: Option<DateTime<Utc>>,
+    /// Only successful (`true`) or failed (`false`) requests
+    pub success: Option<bool>,
+    /// Most recent entries returned, 100 by default and at most 1000. Export returns all.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor
+            .as_ref()
+            .map_or(true, |actor| &entry.actor == actor)
+            && self
+                .action
+                .as_ref()
+                .map_or(true, |action| entry.action.contains(action.as_str()))
+            && self
+                .target
+                .as_ref()
+                .map_or(true, |target| entry.target.as_ref() == Some(target))
+            && self.since.map_or(true, |since| entry.time >= since)
+            && self.until.map_or(true, |until| entry.time < until)
+            && self
+                .success
+                .map_or(true, |success| (entry.outcome.status < 400) == success)
+    }
+}
+
+/// Append-only log of admin and billing actions, one JSON entry per line in
+/// `cache.audit_log_file` (`cache/audit.jsonl` by default).
+///
+/// Entries are only ever appended, nothing in the server rewrites or truncates the file.
+pub struct AuditLog {
+    path: PathBuf,
+    /// Serializes appends so entries never interleave
+    append: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            append: Mutex::new(()),
+        }
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> Result<(), ServiceError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let _append = self
+            .append
+            .lock()
+            .map_err(|_| ServiceError::Internal("Audit log lock poisoned".to_string()))?;
+        File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|e| {
+                ServiceError::Internal(format!(
+                    "Failed to append to {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+    }
+
+    /// Every entry matching `query`, oldest first
+    pub fn entries(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, ServiceError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(ServiceError::Internal(format!(
+                    "Failed to open {}: {}",
+                    self.path.display(),
+                    e
+                )))
+            }
+        };
+        let mut entries = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| {
+                ServiceError::Internal(format!("Failed to read {}: {}", self.path.display(), e))
+            })?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<AuditEntry>(&line) {
+                Ok(entry) if query.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Skipping {} line {}: {}",
+                    self.path.display(),
+                    number + 1,
+                    e
+                ),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The most recent entries matching `query`, newest first
+    pub fn recent(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, ServiceError> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit > MAX_LIMIT {
+            return Err(ServiceError::BadRequest(format!(
+                "limit must be at most {}",
+                MAX_LIMIT
+            )));
+        }
+        let mut entries = self.entries(query)?;
+        entries.reverse();
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+/// Records every request through it in the [`AuditLog`] once it has a response or failed,
+/// declared on a scope with `.wrap(Audit)` or a route with `wrap = "Audit"`. Wrapped outside
+/// auth, requests that fail it are recorded as `anonymous`.
+#[derive(Debug, Clone, Copy)]
+pub struct Audit;
+
+impl<S, B> Transform<S, ServiceRequest> for Audit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Transform = AuditMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuditMiddleware { service }))
+    }
+}
+
+pub struct AuditMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let log = req.app_data::<web::Data<AuditLog>>().cloned();
+        // the route isn't matched yet, an error leaves only the path
+        let action = format!("{} {}", req.method(), req.path());
+        let response = self.service.call(req);
+        Box::pin(async move {
+            let result = response.await;
+            let log = match log {
+                Some(log) => log,
+                None => return result,
+            };
+            let entry = match &result {
+                Ok(res) => AuditEntry::from_response(res),
+                Err(e) => AuditEntry::from_error(action, e),
+            };
+            let action = entry.action.clone();
+            if let Err(e) = blocking(move || log.append(&entry)).await {
+                error!("Failed to audit {}: {}", action, e);
+            }
+            result
+        })
+    }
+}
@@ -0,0 +1,793 @@
+use crate::rate_limit::{Limits, Rate, RouteClass};
+use log::LevelFilter;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use url::Url;
+
+/// Read from the working directory when `CONFIG_FILE` isn't set, if it exists
+pub const DEFAULT_CONFIG_FILE: &str = "server.toml";
+pub const DEFAULT_PORT: u16 = 3333;
+pub const DEFAULT_SQUARE_API_URL: &str = "https://connect.squareupsandbox.com/";
+pub const DEFAULT_SQUARE_API_VERSION: &str = "2023-10-18";
+const DEFAULT_ORIGINS: [&str; 2] = ["http://localhost:3000", "https://consciousnessarchive.com"];
+const DEFAULT_CORS_MAX_AGE: usize = 3600;
+const DEFAULT_JWKS_TTL_SECS: u64 = 600;
+const MIN_JWKS_TTL_SECS: u64 = 2;
+const DEFAULT_POOL_SIZE: usize = database::postgres::DEFAULT_POOL_SIZE;
+/// Audience of the test provider's tokens unless `auth.audience` is set
+pub const TEST_AUDIENCE: &str = "consciousness-archive";
+/// Location of the in-memory payments backend unless `square.location_id` is set
+pub const IN_MEMORY_LOCATION_ID: &str = "LOCALSQUARE";
+
+/// Every server setting, read by [`Config::load`] from a TOML file with one table per section.
+/// Each setting can be overridden by the env var named in its doc, e.g. `PORT` for `server.port`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub cors: CorsConfig,
+    pub cache: CacheConfig,
+    pub content: ContentConfig,
+    pub storage: ObjectStorageConfig,
+    pub square: SquareConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// `PORT`, 3333 by default
+    pub port: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// `CORS_ALLOWED_ORIGINS`, comma separated, e.g. `https://consciousnessarchive.com`
+    pub allowed_origins: Vec<String>,
+    /// `CORS_MAX_AGE`, seconds browsers may cache a preflight response
+    pub max_age: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// `CACHE_DIR`, the content caches of the `file` content store, `cache` by default
+    pub dir: PathBuf,
+    /// `ADMIN_KEYS_FILE`, `admin_keys.json` in `dir` by default
+    pub admin_keys_file: PathBuf,
+    /// `AUDIT_LOG_FILE`, `audit.jsonl` in `dir` by default
+    pub audit_log_file: PathBuf,
+}
+
+/// Where the content caches are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentBackend {
+    /// The `*.bin` files in `cache.dir`
+    File,
+    /// Tables in the Postgres database at `content.database_url`
+    Postgres,
+}
+
+impl FromStr for ContentBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(ContentBackend::File),
+            "postgres" => Ok(ContentBackend::Postgres),
+            _ => Err(format!("{} is not file or postgres", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentConfig {
+    /// `CONTENT_STORE`, `file` by default
+    pub store: ContentBackend,
+    /// `DATABASE_URL`, the Postgres connection string, required for `postgres`
+    pub database_url: String,
+    /// `DATABASE_POOL_SIZE`, the most Postgres connections at once, 8 by default
+    pub pool_size: usize,
+}
+
+/// Which object store assets live in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// A GCS bucket, or the bucket of a GCS emulator
+    Gcs,
+    /// The files in `storage.dir`
+    Local,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gcs" => Ok(StorageBackend::Gcs),
+            "local" => Ok(StorageBackend::Local),
+            _ => Err(format!("{} is not gcs or local", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectStorageConfig {
+    /// `STORAGE_BACKEND`, `gcs` by default
+    pub backend: StorageBackend,
+    /// `STORAGE_BUCKET`, `consciousness-archive` by default
+    pub bucket: String,
+    /// `STORAGE_EMULATOR_HOST`, a GCS emulator to use instead of Google, e.g.
+    /// `http://localhost:4443`
+    pub emulator_host: Option<String>,
+    /// `STORAGE_DIR`, the files of the `local` backend, `assets` by default
+    pub dir: PathBuf,
+    /// `STORAGE_PUBLIC_URL`, the URL objects are served from, the bucket's public URL or a
+    /// `file://` URL of `dir` by default
+    pub public_url: Option<String>,
+}
+
+/// Where billing calls go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentBackend {
+    /// The Square API at `square.api_url`
+    Square,
+    /// An in-memory fake of Square that starts empty on every restart. Only for tests and
+    /// offline work.
+    InMemory,
+}
+
+impl FromStr for PaymentBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(PaymentBackend::Square),
+            "memory" => Ok(PaymentBackend::InMemory),
+            _ => Err(format!("{} is not square or memory", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SquareConfig {
+    /// `PAYMENT_BACKEND`, `square` by default
+    pub backend: PaymentBackend,
+    /// `SQUARE_API_URL`, the sandbox by default, always ends with `/`
+    pub api_url: String,
+    /// `SQUARE_ACCESS_TOKEN`, required for `square`
+    pub access_token: String,
+    /// `SQUARE_API_VERSION`
+    pub api_version: String,
+    /// `SQUARE_APP_ID`
+    pub app_id: String,
+    /// `SQUARE_LOCATION_ID`, required for `square`, `LOCALSQUARE` for `memory` by default
+    pub location_id: String,
+    /// `SQUARE_SUBSCRIPTION_CATALOG_ID`, empty until `/admin/upsert_subscription_catalog` created
+    /// the plan
+    pub subscription_catalog_id: String,
+    /// `SQUARE_SUBSCRIPTION_PRICE`, in cents, required
+    pub subscription_price: u64,
+    /// `SQUARE_SUBSCRIPTION_NAME`, required
+    pub subscription_name: String,
+    /// `SQUARE_REDIRECT_URL`, where checkout returns to, required
+    pub redirect_url: String,
+}
+
+/// Who issues the tokens `/api` and `/admin` accept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthProvider {
+    /// Auth0, or any issuer publishing its signing keys at `<issuer>.well-known/jwks.json`
+    Auth0,
+    /// The built-in test issuer under `/test_idp`, which mints a token for anyone who asks.
+    /// Only for tests and offline work.
+    Test,
+}
+
+impl FromStr for AuthProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auth0" => Ok(AuthProvider::Auth0),
+            "test" => Ok(AuthProvider::Test),
+            _ => Err(format!("{} is not auth0 or test", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// `AUTH_PROVIDER`, `auth0` by default
+    pub provider: AuthProvider,
+    /// `AUTH0_ENDPOINT`, the `iss` of every token, ending with `/`. Required for `auth0`, the
+    /// test provider's `/test_idp/` URL on localhost by default.
+    pub issuer: String,
+    /// `AUTH0_AUDIENCE`, which every token's `aud` must list. Required for `auth0`,
+    /// `consciousness-archive` for the test provider by default.
+    pub audience: String,
+    /// `JWKS_FILE`, signing keys read from disk instead of `<issuer>.well-known/jwks.json`
+    pub jwks_file: Option<PathBuf>,
+    /// `JWKS_TTL_SECS`, 10 minutes by default, at least 2 seconds
+    pub jwks_ttl: Duration,
+    /// `AUTH0_EMAIL_CLAIM`, `email` by default
+    pub email_claim: String,
+    /// `AUTH0_TRUST_EMAIL_CLAIM`, take the email claim without `email_verified: true`. Only for
+    /// a custom claim the issuer sets to verified emails alone, `false` by default.
+    pub trust_email_claim: bool,
+    /// `AUTH0_ROLES_CLAIM`, the admin roles of a token, `roles` by default
+    pub roles_claim: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// `RATE_LIMIT_<CLASS>_IP` and `RATE_LIMIT_<CLASS>_USER` for `<class>_ip` and `<class>_user`,
+    /// e.g. `square_user = "10/60"` or `off`. Every class has its defaults unless set.
+    pub limits: HashMap<RouteClass, Limits>,
+    /// `RATE_LIMIT_TRUST_PROXY`, take the client IP from the last `X-Forwarded-For` hop as
+    /// appended by a reverse proxy such as Heroku's router, `false` by default
+    pub trust_proxy: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    /// `LOG_LEVEL`, `DEBUG` by default
+    pub level: LevelFilter,
+    /// `LOG_FILE`, `server.log` by default
+    pub file: PathBuf,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Debug,
+            file: PathBuf::from("server.log"),
+        }
+    }
+}
+
+impl Config {
+    /// Read `CONFIG_FILE`, or `server.toml` if it exists, and apply the env overrides.
+    /// Returns every missing or invalid setting, not just the first.
+    pub fn load() -> Result<Self, Vec<String>> {
+        let mut settings = match env("CONFIG_FILE") {
+            Some(path) => Settings::read(Path::new(&path), true),
+            None => Settings::read(Path::new(DEFAULT_CONFIG_FILE), false),
+        };
+        let config = Config::from_settings(&mut settings);
+        settings.finish().map(|_| config)
+    }
+
+    fn from_settings(s: &mut Settings) -> Self {
+        let server = ServerConfig {
+            port: s
+                .parse("server.port", "PORT", "a port")
+                .unwrap_or(DEFAULT_PORT),
+        };
+
+        let allowed_origins = s
+            .list("cors.allowed_origins", "CORS_ALLOWED_ORIGINS")
+            .unwrap_or_else(|| DEFAULT_ORIGINS.iter().map(|o| o.to_string()).collect())
+            .into_iter()
+            .filter_map(|origin| {
+                s.check(
+                    "cors.allowed_origins",
+                    "CORS_ALLOWED_ORIGINS",
+                    parse_origin(&origin),
+                )
+            })
+            .collect();
+        let cors = CorsConfig {
+            allowed_origins,
+            max_age: s
+                .parse("cors.max_age", "CORS_MAX_AGE", "a number of seconds")
+                .unwrap_or(DEFAULT_CORS_MAX_AGE),
+        };
+
+        let dir = s
+            .string("cache.dir", "CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("cache"));
+        let cache = CacheConfig {
+            admin_keys_file: s
+                .string("cache.admin_keys_file", "ADMIN_KEYS_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| dir.join("admin_keys.json")),
+            audit_log_file: s
+                .string("cache.audit_log_file", "AUDIT_LOG_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| dir.join("audit.jsonl")),
+            dir,
+        };
+
+        let store = s
+            .parse("content.store", "CONTENT_STORE", "file or postgres")
+            .unwrap_or(ContentBackend::File);
+        let content = ContentConfig {
+            store,
+            database_url: match store {
+                ContentBackend::File => s
+                    .string("content.database_url", "DATABASE_URL")
+                    .unwrap_or_default(),
+                ContentBackend::Postgres => {
+                    s.required_string("content.database_url", "DATABASE_URL")
+                }
+            },
+            pool_size: s
+                .parse(
+                    "content.pool_size",
+                    "DATABASE_POOL_SIZE",
+                    "a number of connections",
+                )
+                .and_then(|size| {
+                    s.check(
+                        "content.pool_size",
+                        "DATABASE_POOL_SIZE",
+                        parse_pool_size(size),
+                    )
+                })
+                .unwrap_or(DEFAULT_POOL_SIZE),
+        };
+
+        let storage = ObjectStorageConfig {
+            backend: s
+                .parse("storage.backend", "STORAGE_BACKEND", "gcs or local")
+                .unwrap_or(StorageBackend::Gcs),
+            bucket: s
+                .string("storage.bucket", "STORAGE_BUCKET")
+                .unwrap_or_else(|| storage::DEFAULT_BUCKET.to_string()),
+            emulator_host: s
+                .string("storage.emulator_host", "STORAGE_EMULATOR_HOST")
+                .and_then(|host| {
+                    s.check(
+                        "storage.emulator_host",
+                        "STORAGE_EMULATOR_HOST",
+                        parse_url(&host).map(|_| host.clone()),
+                    )
+                }),
+            dir: s
+                .string("storage.dir", "STORAGE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(storage::DEFAULT_LOCAL_DIR)),
+            public_url: s
+                .string("storage.public_url", "STORAGE_PUBLIC_URL")
+                .and_then(|url| {
+                    s.check(
+                        "storage.public_url",
+                        "STORAGE_PUBLIC_URL",
+                        parse_public_url(&url),
+                    )
+                }),
+        };
+
+        let backend = s
+            .parse("square.backend", "PAYMENT_BACKEND", "square or memory")
+            .unwrap_or(PaymentBackend::Square);
+        let (access_token, location_id) = match backend {
+            PaymentBackend::Square => (
+                s.required_string("square.access_token", "SQUARE_ACCESS_TOKEN"),
+                s.required_string("square.location_id", "SQUARE_LOCATION_ID"),
+            ),
+            PaymentBackend::InMemory => (
+                s.string("square.access_token", "SQUARE_ACCESS_TOKEN")
+                    .unwrap_or_default(),
+                s.string("square.location_id", "SQUARE_LOCATION_ID")
+                    .unwrap_or_else(|| IN_MEMORY_LOCATION_ID.to_string()),
+            ),
+        };
+        let api_url = s
+            .string("square.api_url", "SQUARE_API_URL")
+            .unwrap_or_else(|| DEFAULT_SQUARE_API_URL.to_string());
+        let square = SquareConfig {
+            backend,
+            api_url: s
+                .check("square.api_url", "SQUARE_API_URL", parse_base_url(&api_url))
+                .unwrap_or(api_url),
+            access_token,
+            api_version: s
+                .string("square.api_version", "SQUARE_API_VERSION")
+                .unwrap_or_else(|| DEFAULT_SQUARE_API_VERSION.to_string()),
+            app_id: s
+                .string("square.app_id", "SQUARE_APP_ID")
+                .unwrap_or_default(),
+            location_id,
+            subscription_catalog_id: s
+                .string(
+                    "square.subscription_catalog_id",
+                    "SQUARE_SUBSCRIPTION_CATALOG_ID",
+                )
+                .unwrap_or_default(),
+            subscription_price: s
+                .required(
+                    "square.subscription_price",
+                    "SQUARE_SUBSCRIPTION_PRICE",
+                    "a price in cents",
+                )
+                .unwrap_or_default(),
+            subscription_name: s
+                .required_string("square.subscription_name", "SQUARE_SUBSCRIPTION_NAME"),
+            redirect_url: {
+                let url = s.required_string("square.redirect_url", "SQUARE_REDIRECT_URL");
+                if !url.is_empty() {
+                    s.check(
+                        "square.redirect_url",
+                        "SQUARE_REDIRECT_URL",
+                        parse_url(&url),
+                    );
+                }
+                url
+            },
+        };
+
+        let provider = s
+            .parse("auth.provider", "AUTH_PROVIDER", "auth0 or test")
+            .unwrap_or(AuthProvider::Auth0);
+        let (issuer, audience) = match provider {
+            AuthProvider::Auth0 => (
+                s.required_string("auth.issuer", "AUTH0_ENDPOINT"),
+                s.required_string("auth.audience", "AUTH0_AUDIENCE"),
+            ),
+            AuthProvider::Test => (
+                s.string("auth.issuer", "AUTH0_ENDPOINT")
+                    .unwrap_or_else(|| format!("http://localhost:{}/test_idp/", server.port)),
+                s.string("auth.audience", "AUTH0_AUDIENCE")
+                    .unwrap_or_else(|| TEST_AUDIENCE.to_string()),
+            ),
+        };
+        if !issuer.is_empty() {
+            s.check("auth.issuer", "AUTH0_ENDPOINT", parse_issuer(&issuer));
+        }
+        let jwks_file = s.string("auth.jwks_file", "JWKS_FILE").map(PathBuf::from);
+        if provider == AuthProvider::Test && jwks_file.is_some() {
+            s.error(
+                "auth.jwks_file",
+                "JWKS_FILE",
+                "the test provider signs with its own keys",
+            );
+        }
+        let auth = AuthConfig {
+            provider,
+            issuer,
+            audience,
+            jwks_file,
+            jwks_ttl: Duration::from_secs(
+                s.parse("auth.jwks_ttl_secs", "JWKS_TTL_SECS", "a number of seconds")
+                    .and_then(|ttl| {
+                        s.check("auth.jwks_ttl_secs", "JWKS_TTL_SECS", parse_jwks_ttl(ttl))
+                    })
+                    .unwrap_or(DEFAULT_JWKS_TTL_SECS),
+            ),
+            email_claim: s
+                .string("auth.email_claim", "AUTH0_EMAIL_CLAIM")
+                .unwrap_or_else(|| "email".to_string()),
+            trust_email_claim: s
+                .parse(
+                    "auth.trust_email_claim",
+                    "AUTH0_TRUST_EMAIL_CLAIM",
+                    "true or false",
+                )
+                .unwrap_or(false),
+            roles_claim: s
+                .string("auth.roles_claim", "AUTH0_ROLES_CLAIM")
+                .unwrap_or_else(|| "roles".to_string()),
+        };
+
+        let mut limits = HashMap::new();
+        for class in RouteClass::ALL {
+            let mut class_limits = class.default_limits();
+            for (kind, limit) in [
+                ("ip", &mut class_limits.ip),
+                ("user", &mut class_limits.user),
+            ] {
+                let key = format!("rate_limit.{}_{}", class.name(), kind);
+                let env_var = format!("RATE_LIMIT_{}", key["rate_limit.".len()..].to_uppercase());
+                if let Some(rate) = s
+                    .string(&key, &env_var)
+                    .and_then(|rate| s.check(&key, &env_var, Rate::parse(&rate)))
+                {
+                    *limit = rate;
+                }
+            }
+            limits.insert(class, class_limits);
+        }
+        let rate_limit = RateLimitConfig {
+            limits,
+            trust_proxy: s
+                .parse::<String>(
+                    "rate_limit.trust_proxy",
+                    "RATE_LIMIT_TRUST_PROXY",
+                    "true or false",
+                )
+                .and_then(|value| {
+                    s.check(
+                        "rate_limit.trust_proxy",
+                        "RATE_LIMIT_TRUST_PROXY",
+                        parse_flag(&value),
+                    )
+                })
+                .unwrap_or(false),
+        };
+
+        let default_logging = LoggingConfig::default();
+        let logging = LoggingConfig {
+            level: s
+                .parse(
+                    "logging.level",
+                    "LOG_LEVEL",
+                    "one of OFF, ERROR, WARN, INFO, DEBUG or TRACE",
+                )
+                .unwrap_or(default_logging.level),
+            file: s
+                .string("logging.file", "LOG_FILE")
+                .map(PathBuf::from)
+                .unwrap_or(default_logging.file),
+        };
+
+        Config {
+            server,
+            cors,
+            cache,
+            content,
+            storage,
+            square,
+            auth,
+            rate_limit,
+            logging,
+        }
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// `scheme://host[:port]`, as browsers send it in `Origin`
+fn parse_origin(origin: &str) -> Result<String, String> {
+    let url = Url::parse(origin).map_err(|e| format!("{} is not an origin: {}", origin, e))?;
+    if !matches!(url.scheme(), "http" | "https")
+        || url.host().is_none()
+        || url.path() != "/"
+        || url.query().is_some()
+    {
+        return Err(format!(
+            "{} is not an origin, e.g. https://consciousnessarchive.com",
+            origin
+        ));
+    }
+    Ok(url.origin().ascii_serialization())
+}
+
+fn parse_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("{} is not a URL: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("{} is not an http or https URL", url));
+    }
+    Ok(parsed)
+}
+
+/// A URL that paths are appended to, with a trailing `/`
+fn parse_base_url(url: &str) -> Result<String, String> {
+    parse_url(url)?;
+    Ok(match url.ends_with('/') {
+        true => url.to_string(),
+        false => format!("{}/", url),
+    })
+}
+
+/// Where objects are served from: a URL like [`parse_base_url`], or `file://` for local storage
+fn parse_public_url(url: &str) -> Result<String, String> {
+    match url.starts_with("file://") {
+        true => Ok(url.to_string()),
+        false => parse_base_url(url),
+    }
+}
+
+/// `true` or `false`, or `1` and `0`
+fn parse_flag(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("{} is not true or false", value)),
+    }
+}
+
+/// Keys are refreshed every half TTL, which must be at least a second
+fn parse_jwks_ttl(secs: u64) -> Result<u64, String> {
+    match secs >= MIN_JWKS_TTL_SECS {
+        true => Ok(secs),
+        false => Err(format!("must be at least {} seconds", MIN_JWKS_TTL_SECS)),
+    }
+}
+
+fn parse_pool_size(size: usize) -> Result<usize, String> {
+    match size {
+        0 => Err("must be at least 1".to_string()),
+        size => Ok(size),
+    }
+}
+
+/// Tokens carry the issuer verbatim, Auth0's ends with `/`
+fn parse_issuer(issuer: &str) -> Result<(), String> {
+    parse_url(issuer)?;
+    match issuer.ends_with('/') {
+        true => Ok(()),
+        false => Err(format!("{} must end with /", issuer)),
+    }
+}
+
+/// Where a setting's value came from
+enum Raw {
+    Env(String),
+    File(toml::Value),
+}
+
+/// The settings of the config file flattened to `section.key`, read as env overrides allow,
+/// collecting every error along the way
+struct Settings {
+    file: Option<PathBuf>,
+    values: BTreeMap<String, toml::Value>,
+    errors: Vec<String>,
+}
+
+impl Settings {
+    /// `required` fails if there is no file at `path`, otherwise env vars and defaults are used
+    fn read(path: &Path, required: bool) -> Self {
+        let mut settings = Settings {
+            file: None,
+            values: BTreeMap::new(),
+            errors: Vec::new(),
+        };
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return settings,
+            Err(e) => {
+                settings
+                    .errors
+                    .push(format!("Failed to read {}: {}", path.display(), e));
+                return settings;
+            }
+        };
+        settings.file = Some(path.to_path_buf());
+        let table = match contents.parse::<toml::Value>() {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => toml::value::Table::new(),
+            Err(e) => {
+                settings
+                    .errors
+                    .push(format!("Invalid TOML in {}: {}", path.display(), e));
+                return settings;
+            }
+        };
+        for (section, value) in table {
+            match value {
+                toml::Value::Table(keys) => {
+                    for (key, value) in keys {
+                        settings
+                            .values
+                            .insert(format!("{}.{}", section, key), value);
+                    }
+                }
+                _ => settings.errors.push(format!(
+                    "{} in {} must be a [{}] table",
+                    section,
+                    path.display(),
+                    section
+                )),
+            }
+        }
+        settings
+    }
+
+    /// `env` if set, otherwise `key` of the file
+    fn raw(&mut self, key: &str, env_var: &str) -> Option<Raw> {
+        let value = self.values.remove(key);
+        match env(env_var) {
+            Some(value) => Some(Raw::Env(value)),
+            None => value.map(Raw::File),
+        }
+    }
+
+    fn error(&mut self, key: &str, env_var: &str, message: impl Display) {
+        self.errors
+            .push(format!("{} ({}): {}", key, env_var, message));
+    }
+
+    /// `result`'s value, recording its error
+    fn check<T>(&mut self, key: &str, env_var: &str, result: Result<T, String>) -> Option<T> {
+        result.map_err(|e| self.error(key, env_var, e)).ok()
+    }
+
+    fn string(&mut self, key: &str, env_var: &str) -> Option<String> {
+        match self.raw(key, env_var)? {
+            Raw::Env(value) => Some(value),
+            Raw::File(toml::Value::String(value)) if value.is_empty() => None,
+            Raw::File(toml::Value::String(value)) => Some(value),
+            Raw::File(value) => {
+                self.error(key, env_var, format!("{} is not a string", value));
+                None
+            }
+        }
+    }
+
+    fn required_string(&mut self, key: &str, env_var: &str) -> String {
+        let errors = self.errors.len();
+        let value = self.string(key, env_var);
+        if value.is_none() && self.errors.len() == errors {
+            self.error(key, env_var, "is required");
+        }
+        value.unwrap_or_default()
+    }
+
+    /// A string, number or boolean parsed as `T`, described as `expected` when it isn't one
+    fn parse<T: FromStr>(&mut self, key: &str, env_var: &str, expected: &str) -> Option<T> {
+        let value = match self.raw(key, env_var)? {
+            Raw::Env(value) | Raw::File(toml::Value::String(value)) => value,
+            Raw::File(value) => value.to_string(),
+        };
+        match value.trim().parse::<T>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.error(key, env_var, format!("{} is not {}", value, expected));
+                None
+            }
+        }
+    }
+
+    fn required<T: FromStr>(&mut self, key: &str, env_var: &str, expected: &str) -> Option<T> {
+        let errors = self.errors.len();
+        let value = self.parse(key, env_var, expected);
+        if value.is_none() && self.errors.len() == errors {
+            self.error(key, env_var, "is required");
+        }
+        value
+    }
+
+    /// An array of strings in the file, comma separated in the env var
+    fn list(&mut self, key: &str, env_var: &str) -> Option<Vec<String>> {
+        match self.raw(key, env_var)? {
+            Raw::Env(value) => Some(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            Raw::File(toml::Value::Array(items)) => {
+                let strings = items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(str::to_string))
+                    .collect::<Vec<String>>();
+                if strings.len() != items.len() {
+                    self.error(key, env_var, "must be an array of strings");
+                    return None;
+                }
+                Some(strings)
+            }
+            Raw::File(value) => {
+                self.error(
+                    key,
+                    env_var,
+                    format!("{} is not an array of strings", value),
+                );
+                None
+            }
+        }
+    }
+
+    /// Every error, including settings in the file that nothing reads
+    fn finish(mut self) -> Result<(), Vec<String>> {
+        if let Some(file) = &self.file {
+            for key in self.values.keys() {
+                self.errors
+                    .push(format!("Unknown setting {} in {}", key, file.display()));
+            }
+        }
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self.errors),
+        }
+    }
+}
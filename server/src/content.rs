@@ -0,0 +1,147 @@
+use crate::config::{Config, ContentBackend};
+use crate::errors::ServiceError;
+use database::{ContentStore, ContentType, ImageInfo, Records, StoreConfig};
+use lazy_static::lazy_static;
+use log::*;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+lazy_static! {
+    /// Opened once by [`init`], before the server starts accepting requests
+    static ref STORE: RwLock<Option<Arc<ContentStore>>> = RwLock::new(None);
+
+    /// Raw records of each cache file, kept until `admin` writes a new version
+    static ref CONTENT_CACHE: RwLock<HashMap<ContentType, CachedRecords>> =
+        RwLock::new(HashMap::new());
+}
+
+/// What identifies one version of a cache. The version stamp is bumped by every store write,
+/// the file metadata of a file store catches caches replaced by other means (e.g. a git checkout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    version: u64,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+struct CachedRecords {
+    stamp: Stamp,
+    records: Arc<Records>,
+}
+
+/// Open the content store selected by `config.content`, the files in `config.cache.dir` by default
+pub fn init(config: &Config) -> Result<(), String> {
+    let store = match config.content.store {
+        ContentBackend::File => StoreConfig::File {
+            dir: std::env::current_dir()
+                .map_err(|e| format!("Failed to resolve working directory: {}", e))?
+                .join(&config.cache.dir),
+        },
+        ContentBackend::Postgres => StoreConfig::Postgres {
+            url: config.content.database_url.clone(),
+            pool_size: config.content.pool_size,
+        },
+    }
+    .open()
+    .map_err(|e| format!("Failed to open content store: {}", e))?;
+    *STORE
+        .write()
+        .map_err(|_| "Content store lock poisoned".to_string())? = Some(Arc::new(store));
+    Ok(())
+}
+
+fn store() -> Result<Arc<ContentStore>, ServiceError> {
+    STORE
+        .read()
+        .map_err(|_| ServiceError::Internal("Content store lock poisoned".to_string()))?
+        .clone()
+        .ok_or_else(|| ServiceError::CacheUnavailable("Content store is not open".to_string()))
+}
+
+fn stamp(store: &ContentStore, content_type: ContentType) -> Result<Stamp, ServiceError> {
+    let version = store.version(content_type)?;
+    let (modified, len) = match store {
+        ContentStore::File(files) => {
+            let path = files.path(content_type);
+            let metadata = std::fs::metadata(&path).map_err(|e| {
+                ServiceError::CacheUnavailable(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            (metadata.modified().ok(), metadata.len())
+        }
+        // every write goes through the store and bumps the version
+        ContentStore::Postgres(_) => (None, 0),
+    };
+    Ok(Stamp {
+        version,
+        modified,
+        len,
+    })
+}
+
+/// Raw records of a content cache, re-read from disk only when a new version was written
+pub fn records(content_type: ContentType) -> Result<Arc<Records>, ServiceError> {
+    let store = store()?;
+    let stamp = stamp(&store, content_type)?;
+    if let Some(cached) = CONTENT_CACHE
+        .read()
+        .map_err(|_| ServiceError::Internal("Content cache lock poisoned".to_string()))?
+        .get(&content_type)
+    {
+        if cached.stamp == stamp {
+            return Ok(cached.records.clone());
+        }
+    }
+
+    // cache files are replaced by rename, so this reads either the old or the new records
+    let records = Arc::new(store.read(content_type)?);
+    info!(
+        "Loaded {} {} records, version {}",
+        records.len(),
+        content_type,
+        stamp.version
+    );
+    CONTENT_CACHE
+        .write()
+        .map_err(|_| ServiceError::Internal("Content cache lock poisoned".to_string()))?
+        .insert(
+            content_type,
+            CachedRecords {
+                stamp,
+                records: records.clone(),
+            },
+        );
+    Ok(records)
+}
+
+/// Decode every record of a content cache
+pub fn load<T: DeserializeOwned>(content_type: ContentType) -> Result<Vec<T>, ServiceError> {
+    records(content_type)?
+        .values()
+        .map(|record| {
+            bincode::deserialize::<T>(record).map_err(|e| {
+                ServiceError::CacheCorrupt(format!(
+                    "Failed to decode record in {}: {}",
+                    content_type, e
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Decode every record of an image list cache, whether written with its metadata or,
+/// by older versions of `admin`, as a bare URL
+pub fn images(content_type: ContentType) -> Result<Vec<ImageInfo>, ServiceError> {
+    records(content_type)?
+        .values()
+        .map(|record| {
+            ImageInfo::de(record).map_err(|e| {
+                ServiceError::CacheCorrupt(format!(
+                    "Failed to decode image in {}: {}",
+                    content_type, e
+                ))
+            })
+        })
+        .collect()
+}
@@ -1,29 +1,207 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use crate::square::SquareErrorResponse;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{error::ResponseError, http::StatusCode, HttpResponse};
+use async_graphql::ErrorExtensions;
+use database::StoreError;
 use derive_more::Display;
+use log::*;
+use serde::{Deserialize, Serialize};
+use storage::StorageError;
+use schemars::JsonSchema;
 
-#[allow(dead_code)]
+/// Every failure the server reports to a client.
+///
+/// Each variant maps to a stable machine-readable [`ServiceError::code`] and an HTTP status,
+/// and is rendered as an [`ErrorBody`].
 #[derive(Debug, Display)]
 pub enum ServiceError {
-  #[display(fmt = "Internal Server Error")]
-  InternalServerError,
+  #[display(fmt = "Internal Server Error: {}", _0)]
+  Internal(String),
 
   #[display(fmt = "BadRequest: {}", _0)]
   BadRequest(String),
 
+  #[display(fmt = "Unauthorized: {}", _0)]
+  Unauthorized(String),
+
+  /// Authenticated, but not allowed to do this
+  #[display(fmt = "Forbidden: {}", _0)]
+  Forbidden(String),
+
+  /// Over a rate limit, may retry after this many seconds
+  #[display(fmt = "RateLimited: retry after {}s", _0)]
+  RateLimited(u64),
+
+  #[display(fmt = "NotFound: {}", _0)]
+  NotFound(String),
+
+  /// A content cache could not be opened or read
+  #[display(fmt = "CacheUnavailable: {}", _0)]
+  CacheUnavailable(String),
+
+  /// A content cache was read but could not be decoded
+  #[display(fmt = "CacheCorrupt: {}", _0)]
+  CacheCorrupt(String),
+
+  /// Square could not be reached
+  #[display(fmt = "UpstreamUnavailable: {}", _0)]
+  UpstreamUnavailable(String),
+
+  /// Square answered with an error or a response we could not parse
+  #[display(fmt = "UpstreamError: {}", _0)]
+  UpstreamError(String),
+
+  /// The object storage backend could not be reached or failed
+  #[display(fmt = "StorageUnavailable: {}", _0)]
+  StorageUnavailable(String),
+
   #[display(fmt = "JWKSFetchError")]
   JWKSFetchError,
 }
 
-impl ResponseError for ServiceError {
-  fn error_response(&self) -> HttpResponse {
+/// JSON shape of every error response, e.g.
+/// `{ "error": { "code": "cache_unavailable", "message": "..." } }`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorBody {
+  pub error: ErrorDetail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorDetail {
+  pub code: String,
+  pub message: String,
+}
+
+impl ServiceError {
+  /// Stable identifier clients can match on. Never change an existing code.
+  pub fn code(&self) -> &'static str {
     match self {
-      ServiceError::InternalServerError => {
-        HttpResponse::InternalServerError().json("Internal Server Error, Please try later")
+      ServiceError::Internal(_) => "internal_error",
+      ServiceError::BadRequest(_) => "bad_request",
+      ServiceError::Unauthorized(_) => "unauthorized",
+      ServiceError::Forbidden(_) => "forbidden",
+      ServiceError::RateLimited(_) => "rate_limited",
+      ServiceError::NotFound(_) => "not_found",
+      ServiceError::CacheUnavailable(_) => "cache_unavailable",
+      ServiceError::CacheCorrupt(_) => "cache_corrupt",
+      ServiceError::UpstreamUnavailable(_) => "upstream_unavailable",
+      ServiceError::UpstreamError(_) => "upstream_error",
+      ServiceError::StorageUnavailable(_) => "storage_unavailable",
+      ServiceError::JWKSFetchError => "jwks_fetch_failed",
+    }
+  }
+
+  /// Message safe to show a client. Server side details are only logged.
+  pub fn message(&self) -> String {
+    match self {
+      ServiceError::Internal(_) => "Internal Server Error, Please try later".to_string(),
+      ServiceError::BadRequest(message) => message.clone(),
+      ServiceError::Unauthorized(message) => message.clone(),
+      ServiceError::Forbidden(message) => message.clone(),
+      ServiceError::RateLimited(secs) => format!("Too many requests, retry in {} seconds", secs),
+      ServiceError::NotFound(message) => message.clone(),
+      ServiceError::CacheUnavailable(_) | ServiceError::CacheCorrupt(_) => {
+        "Content is temporarily unavailable".to_string()
       }
-      ServiceError::BadRequest(ref message) => HttpResponse::BadRequest().json(message),
-      ServiceError::JWKSFetchError => {
-        HttpResponse::InternalServerError().json("Could not fetch JWKS")
+      ServiceError::UpstreamUnavailable(_) => "Payment provider is unreachable".to_string(),
+      ServiceError::UpstreamError(_) => "Payment provider request failed".to_string(),
+      ServiceError::StorageUnavailable(_) => "Asset storage is unavailable".to_string(),
+      ServiceError::JWKSFetchError => "Could not fetch JWKS".to_string(),
+    }
+  }
+
+  pub fn body(&self) -> ErrorBody {
+    ErrorBody {
+      error: ErrorDetail {
+        code: self.code().to_string(),
+        message: self.message(),
+      },
+    }
+  }
+}
+
+impl ResponseError for ServiceError {
+  fn status_code(&self) -> StatusCode {
+    match self {
+      ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+      ServiceError::BadRequest(_) => StatusCode::BAD_REQUEST,
+      ServiceError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+      ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+      ServiceError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+      ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+      ServiceError::CacheUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+      ServiceError::CacheCorrupt(_) => StatusCode::INTERNAL_SERVER_ERROR,
+      ServiceError::UpstreamUnavailable(_) => StatusCode::BAD_GATEWAY,
+      ServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
+      ServiceError::StorageUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+      ServiceError::JWKSFetchError => StatusCode::SERVICE_UNAVAILABLE,
+    }
+  }
+
+  fn error_response(&self) -> HttpResponse {
+    let status = self.status_code();
+    if status.is_server_error() {
+      error!("{}", self);
+    } else {
+      debug!("{}", self);
+    }
+    let mut response = HttpResponse::build(status);
+    if let ServiceError::RateLimited(secs) = self {
+      response.insert_header((RETRY_AFTER, secs.to_string()));
+    }
+    response.json(self.body())
+  }
+}
+
+/// GraphQL errors carry the same client safe message, with the code under `extensions.code`
+impl ErrorExtensions for ServiceError {
+  fn extend(&self) -> async_graphql::Error {
+    if self.status_code().is_server_error() {
+      error!("{}", self);
+    } else {
+      debug!("{}", self);
+    }
+    async_graphql::Error::new(self.message()).extend_with(|_, extensions| {
+      extensions.set("code", self.code());
+    })
+  }
+}
+
+impl From<SquareErrorResponse> for ServiceError {
+  fn from(response: SquareErrorResponse) -> Self {
+    let codes = response
+      .errors
+      .iter()
+      .map(|e| format!("{}/{}: {}", e.category, e.code, e.detail))
+      .collect::<Vec<String>>()
+      .join(", ");
+    ServiceError::UpstreamError(codes)
+  }
+}
+
+impl From<serde_json::Error> for ServiceError {
+  fn from(e: serde_json::Error) -> Self {
+    ServiceError::Internal(format!("JSON serialization failed: {}", e))
+  }
+}
+
+impl From<StoreError> for ServiceError {
+  fn from(e: StoreError) -> Self {
+    match e {
+      StoreError::Io(..) | StoreError::Database(_) => ServiceError::CacheUnavailable(e.to_string()),
+      StoreError::Decode(..) | StoreError::Encode(..) => ServiceError::CacheCorrupt(e.to_string()),
+    }
+  }
+}
+
+impl From<StorageError> for ServiceError {
+  fn from(e: StorageError) -> Self {
+    match e {
+      StorageError::NotFound(key) => ServiceError::NotFound(format!("No asset named {}", key)),
+      StorageError::InvalidKey(_) => ServiceError::BadRequest(e.to_string()),
+      StorageError::Io(..) | StorageError::Backend(_) | StorageError::Config(_) => {
+        ServiceError::StorageUnavailable(e.to_string())
       }
     }
   }
-}
\ No newline at end of file
+}
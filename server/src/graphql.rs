@@ -0,0 +1,256 @@
+use crate::content;
+use crate::handler::{blocking, ServerHandler};
+use crate::oauth::Identity;
+use crate::square::{
+    CoachingSessions, CustomerInfo, PaymentProvider, SubscriptionInfo, UserEmailRequest,
+    UserSubscriptionInfo,
+};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, InputObject, Object, OutputType, Result, ResultExt,
+    Schema, SimpleObject,
+};
+use database::{Article, Calibration, ContentType, ImageInfo, Testimonial};
+use std::sync::Arc;
+
+/// Page size when `limit` is omitted
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+/// Content is only a few levels deep, anything deeper is a mistake or abuse
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 500;
+
+pub type ApiSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// `payments` answers the account fields
+pub fn schema(payments: Arc<dyn PaymentProvider>) -> ApiSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(payments)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// One page of a list
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "ArticlePage", params(Article)))]
+#[graphql(concrete(name = "CalibrationPage", params(Calibration)))]
+#[graphql(concrete(name = "TestimonialPage", params(Testimonial)))]
+#[graphql(concrete(name = "ImagePage", params(ImageInfo)))]
+pub struct Page<T: OutputType> {
+    /// Items matching the filter across all pages
+    total: usize,
+    offset: usize,
+    items: Vec<T>,
+}
+
+fn paginate<T: OutputType>(items: Vec<T>, offset: usize, limit: usize) -> Result<Page<T>> {
+    if limit > MAX_LIMIT {
+        return Err(format!("limit must be at most {}", MAX_LIMIT).into());
+    }
+    let total = items.len();
+    let items = items.into_iter().skip(offset).take(limit).collect();
+    Ok(Page {
+        total,
+        offset,
+        items,
+    })
+}
+
+fn contains_ignore_case(text: &str, search: &str) -> bool {
+    text.to_lowercase().contains(&search.to_lowercase())
+}
+
+#[derive(InputObject, Default)]
+pub struct ArticleFilter {
+    /// Only articles with this tag
+    tag: Option<String>,
+    premium: Option<bool>,
+    /// Case insensitive match anywhere in the title
+    search: Option<String>,
+}
+
+impl ArticleFilter {
+    fn matches(&self, article: &Article) -> bool {
+        self.tag
+            .as_ref()
+            .map_or(true, |tag| article.tags.contains(tag))
+            && self
+                .premium
+                .map_or(true, |premium| article.premium == premium)
+            && self
+                .search
+                .as_deref()
+                .map_or(true, |search| contains_ignore_case(&article.title, search))
+    }
+}
+
+#[derive(InputObject, Default)]
+pub struct CalibrationFilter {
+    /// Only calibrations with this tag
+    tag: Option<String>,
+    /// Case insensitive match anywhere in the title
+    search: Option<String>,
+    min_calibration: Option<u32>,
+    max_calibration: Option<u32>,
+}
+
+impl CalibrationFilter {
+    fn matches(&self, calibration: &Calibration) -> bool {
+        self.tag
+            .as_ref()
+            .map_or(true, |tag| calibration.tags.contains(tag))
+            && self.search.as_deref().map_or(true, |search| {
+                contains_ignore_case(&calibration.title, search)
+            })
+            && self
+                .min_calibration
+                .map_or(true, |min| calibration.calibration >= min)
+            && self
+                .max_calibration
+                .map_or(true, |max| calibration.calibration <= max)
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+/// An image list, e.g. `TESTIMONIAL` for `testimonial_images`
+pub enum ImageList {
+    Testimonial,
+    Category,
+    ContentType,
+}
+
+impl From<ImageList> for ContentType {
+    fn from(list: ImageList) -> Self {
+        match list {
+            ImageList::Testimonial => ContentType::TestimonialImages,
+            ImageList::Category => ContentType::CategoryImages,
+            ImageList::ContentType => ContentType::ContentTypeImages,
+        }
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Articles ordered by index
+    async fn articles(
+        &self,
+        filter: Option<ArticleFilter>,
+        #[graphql(default)] offset: usize,
+        #[graphql(default_with = "DEFAULT_LIMIT")] limit: usize,
+    ) -> Result<Page<Article>> {
+        let filter = filter.unwrap_or_default();
+        let mut articles = blocking(ServerHandler::handle_articles).await.extend()?;
+        articles.retain(|article| filter.matches(article));
+        articles.sort_by(|a, b| a.index.cmp(&b.index).then_with(|| a.title.cmp(&b.title)));
+        paginate(articles, offset, limit)
+    }
+
+    async fn article(&self, title: String) -> Result<Option<Article>> {
+        let articles = blocking(ServerHandler::handle_articles).await.extend()?;
+        Ok(articles.into_iter().find(|article| article.title == title))
+    }
+
+    /// Calibrations ordered by title
+    async fn calibrations(
+        &self,
+        filter: Option<CalibrationFilter>,
+        #[graphql(default)] offset: usize,
+        #[graphql(default_with = "DEFAULT_LIMIT")] limit: usize,
+    ) -> Result<Page<Calibration>> {
+        let filter = filter.unwrap_or_default();
+        let mut calibrations = blocking(ServerHandler::handle_calibrations)
+            .await
+            .extend()?;
+        calibrations.retain(|calibration| filter.matches(calibration));
+        calibrations.sort_by(|a, b| a.title.cmp(&b.title));
+        paginate(calibrations, offset, limit)
+    }
+
+    /// Testimonials ordered by image URL
+    async fn testimonials(
+        &self,
+        #[graphql(default)] offset: usize,
+        #[graphql(default_with = "DEFAULT_LIMIT")] limit: usize,
+    ) -> Result<Page<Testimonial>> {
+        let mut testimonials = blocking(ServerHandler::handle_testimonials)
+            .await
+            .extend()?;
+        testimonials.sort_by(|a, b| a.image_url.cmp(&b.image_url));
+        paginate(testimonials, offset, limit)
+    }
+
+    /// Images of a list ordered by URL, with their dimensions, blurhash and variants
+    async fn images(
+        &self,
+        list: ImageList,
+        #[graphql(default)] offset: usize,
+        #[graphql(default_with = "DEFAULT_LIMIT")] limit: usize,
+    ) -> Result<Page<ImageInfo>> {
+        let mut images = blocking(move || content::images(list.into()))
+            .await
+            .extend()?;
+        images.sort_by(|a, b| a.url.cmp(&b.url));
+        paginate(images, offset, limit)
+    }
+
+    /// The account of the signed in user
+    async fn account(&self, ctx: &Context<'_>) -> Result<Account> {
+        let email = ctx.data::<Identity>()?.email_request().extend()?.email;
+        Ok(Account { email })
+    }
+}
+
+/// A user's Square customer, subscription and coaching sessions, each looked up only if selected
+pub struct Account {
+    email: String,
+}
+
+impl Account {
+    fn request(&self) -> UserEmailRequest {
+        UserEmailRequest {
+            email: self.email.clone(),
+        }
+    }
+}
+
+fn payments<'a>(ctx: &Context<'a>) -> Result<&'a Arc<dyn PaymentProvider>> {
+    ctx.data::<Arc<dyn PaymentProvider>>()
+}
+
+#[Object]
+impl Account {
+    async fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// Null if the email has no Square customer
+    async fn customer(&self, ctx: &Context<'_>) -> Result<Option<CustomerInfo>> {
+        payments(ctx)?
+            .get_customer_info(self.request())
+            .await
+            .extend()
+    }
+
+    /// The subscription plan on offer
+    async fn plan(&self, ctx: &Context<'_>) -> Result<Option<SubscriptionInfo>> {
+        payments(ctx)?.get_subscription_info().await.extend()
+    }
+
+    /// Null if the user has never subscribed
+    async fn subscription(&self, ctx: &Context<'_>) -> Result<Option<UserSubscriptionInfo>> {
+        payments(ctx)?
+            .get_user_subscription_info(self.request())
+            .await
+            .extend()
+    }
+
+    /// Coaching packages bought, newest first
+    async fn coaching_sessions(&self, ctx: &Context<'_>) -> Result<Vec<CoachingSessions>> {
+        payments(ctx)?
+            .get_coaching_sessions(self.request())
+            .await
+            .extend()
+    }
+}
@@ -1,20 +1,18 @@
 use crate::square::{
-    CanceledSubscriptionInfo, CheckoutInfo, SquareClient, SquareResponse, UserEmailRequest,
-    UserProfile,
+    CanceledSubscriptionInfo, CheckoutInfo, PaymentProvider, UserEmailRequest, UserProfile,
 };
-use actix_web::{web, Result};
-use database::{Article, Calibration, Testimonial};
-use futures::StreamExt;
+use crate::content;
+use crate::errors::{ErrorDetail, ServiceError};
+use crate::oauth::Identity;
+use database::{Article, Calibration, ContentType, ImageInfo, Testimonial};
+use futures::Future;
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use tokio::sync::MutexGuard;
-
-const MAX_SIZE: usize = 262_144; // max payload size is 256k
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct LoadState {
     pub content_type_images: Vec<String>,
     pub category_images: Vec<String>,
@@ -26,74 +24,228 @@ pub struct LoadState {
     pub user_profile: UserProfile,
 }
 
+/// A part of [`LoadState`] a client can ask for alone, e.g. `?include=articles,calibrations`.
+/// Cancel subscription is the only endpoint that isn't loaded up front.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    ContentTypeImages,
+    CategoryImages,
+    Articles,
+    Calibrations,
+    Testimonials,
+    TestimonialImages,
+    SubscribeCheckout,
+    UserProfile,
+}
+
+impl Section {
+    pub const ALL: [Section; 8] = [
+        Section::ContentTypeImages,
+        Section::CategoryImages,
+        Section::Articles,
+        Section::Calibrations,
+        Section::Testimonials,
+        Section::TestimonialImages,
+        Section::SubscribeCheckout,
+        Section::UserProfile,
+    ];
+
+    /// Field name in [`LoadState`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::ContentTypeImages => "content_type_images",
+            Section::CategoryImages => "category_images",
+            Section::Articles => "articles",
+            Section::Calibrations => "calibrations",
+            Section::Testimonials => "testimonials",
+            Section::TestimonialImages => "testimonial_images",
+            Section::SubscribeCheckout => "subscribe_checkout",
+            Section::UserProfile => "user_profile",
+        }
+    }
+}
+
+impl FromStr for Section {
+    type Err = ServiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Section::ALL
+            .into_iter()
+            .find(|section| section.name() == s)
+            .ok_or_else(|| ServiceError::BadRequest(format!("Unknown load_state section {}", s)))
+    }
+}
+
+/// Query of `load_state`
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct LoadStateQuery {
+    /// Comma separated sections to load, every section as a [`LoadState`] if omitted
+    pub include: Option<String>,
+}
+
+impl LoadStateQuery {
+    /// The requested sections, `None` for the full [`LoadState`]
+    pub fn sections(&self) -> Result<Option<Vec<Section>>, ServiceError> {
+        self.include
+            .as_deref()
+            .map(|include| {
+                include
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|section| !section.is_empty())
+                    .map(str::parse::<Section>)
+                    .collect()
+            })
+            .transpose()
+    }
+}
+
+/// The requested sections of [`LoadState`]. A section that failed to load is left out
+/// and its error is reported under its name in `errors`.
+#[derive(Serialize, Deserialize, Debug, Default, JsonSchema)]
+pub struct PartialLoadState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type_images: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_images: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub articles: Option<Vec<Article>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibrations: Option<Vec<Calibration>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub testimonials: Option<Vec<Testimonial>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub testimonial_images: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscribe_checkout: Option<CheckoutInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_profile: Option<UserProfile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<Section, ErrorDetail>,
+}
+
+/// Result of each section that was loaded, `None` for those not requested
+struct Sections {
+    content_type_images: Option<Result<Vec<String>, ServiceError>>,
+    category_images: Option<Result<Vec<String>, ServiceError>>,
+    articles: Option<Result<Vec<Article>, ServiceError>>,
+    calibrations: Option<Result<Vec<Calibration>, ServiceError>>,
+    testimonials: Option<Result<Vec<Testimonial>, ServiceError>>,
+    testimonial_images: Option<Result<Vec<String>, ServiceError>>,
+    subscribe_checkout: Option<Result<CheckoutInfo, ServiceError>>,
+    user_profile: Option<Result<UserProfile, ServiceError>>,
+}
+
+impl Sections {
+    fn into_partial(self) -> PartialLoadState {
+        let mut errors = BTreeMap::new();
+        PartialLoadState {
+            content_type_images: partial(
+                Section::ContentTypeImages,
+                self.content_type_images,
+                &mut errors,
+            ),
+            category_images: partial(Section::CategoryImages, self.category_images, &mut errors),
+            articles: partial(Section::Articles, self.articles, &mut errors),
+            calibrations: partial(Section::Calibrations, self.calibrations, &mut errors),
+            testimonials: partial(Section::Testimonials, self.testimonials, &mut errors),
+            testimonial_images: partial(
+                Section::TestimonialImages,
+                self.testimonial_images,
+                &mut errors,
+            ),
+            subscribe_checkout: partial(
+                Section::SubscribeCheckout,
+                self.subscribe_checkout,
+                &mut errors,
+            ),
+            user_profile: partial(Section::UserProfile, self.user_profile, &mut errors),
+            errors,
+        }
+    }
+
+    /// Fails with the error of the first section that failed, as `load_state` always has
+    fn into_load_state(self) -> Result<LoadState, ServiceError> {
+        Ok(LoadState {
+            content_type_images: required(Section::ContentTypeImages, self.content_type_images)?,
+            category_images: required(Section::CategoryImages, self.category_images)?,
+            articles: required(Section::Articles, self.articles)?,
+            calibrations: required(Section::Calibrations, self.calibrations)?,
+            testimonials: required(Section::Testimonials, self.testimonials)?,
+            testimonial_images: required(Section::TestimonialImages, self.testimonial_images)?,
+            subscribe_checkout: required(Section::SubscribeCheckout, self.subscribe_checkout)?,
+            user_profile: required(Section::UserProfile, self.user_profile)?,
+        })
+    }
+}
+
+fn partial<T>(
+    section: Section,
+    result: Option<Result<T, ServiceError>>,
+    errors: &mut BTreeMap<Section, ErrorDetail>,
+) -> Option<T> {
+    match result? {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!("Failed to load {} section: {}", section.name(), e);
+            errors.insert(section, e.body().error);
+            None
+        }
+    }
+}
+
+fn required<T>(
+    section: Section,
+    result: Option<Result<T, ServiceError>>,
+) -> Result<T, ServiceError> {
+    let value = result.unwrap_or_else(|| {
+        Err(ServiceError::Internal(format!("{} section was not loaded", section.name())))
+    })?;
+    debug!("Fetched {}", section.name());
+    Ok(value)
+}
+
+/// Await `future` only if its section was requested
+async fn load_if<T>(wanted: bool, future: impl Future<Output = T>) -> Option<T> {
+    if wanted {
+        Some(future.await)
+    } else {
+        None
+    }
+}
+
+/// Read content off the async runtime, the content store may block on disk or Postgres
+pub async fn blocking<T, F>(load: F) -> Result<T, ServiceError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ServiceError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(load)
+        .await
+        .map_err(|e| ServiceError::Internal(format!("Content task failed: {}", e)))?
+}
+
 pub struct ServerHandler<'a> {
-    pub client: MutexGuard<'a, SquareClient>,
+    pub payments: &'a dyn PaymentProvider,
 }
 
 impl<'a> ServerHandler<'a> {
-    pub fn new(client: MutexGuard<'a, SquareClient>) -> Self {
-        ServerHandler { client }
+    pub fn new(payments: &'a dyn PaymentProvider) -> Self {
+        ServerHandler { payments }
     }
 
     /// Open all to all users
-    pub fn handle_content_type_images() -> Result<Vec<String>> {
-        let cache_path = std::env::current_dir()
-          .unwrap()
-          .to_str()
-          .unwrap()
-          .to_string()
-          + "/cache/content_type_images.bin";
-
-        let mut cache_file = File::open(&cache_path).expect("Failed to open content type images \
-        cache");
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file
-          .read_to_end(&mut cache_buf)
-          .expect("Failed to read content type images cache");
-
-        let mut db_images = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-          .expect("Failed to read content type images cache");
-        let mut images = Vec::new();
-
-        for (_, db_image) in db_images.drain() {
-            let image = bincode::deserialize::<String>(&db_image)
-              .expect("Failed to deserialize content type image");
-            images.push(image);
-        }
-        Ok(images)
+    pub fn handle_content_type_images() -> Result<Vec<String>, ServiceError> {
+        Self::handle_image_urls(ContentType::ContentTypeImages)
     }
 
     /// Open all to all users
-    pub fn handle_category_images() -> Result<Vec<String>> {
-        let cache_path = std::env::current_dir()
-          .unwrap()
-          .to_str()
-          .unwrap()
-          .to_string()
-          + "/cache/category_images.bin";
-
-        let mut cache_file = File::open(&cache_path).expect("Failed to open category images \
-        cache");
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file
-          .read_to_end(&mut cache_buf)
-          .expect("Failed to read category images cache");
-
-        let mut db_images = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-          .expect("Failed to read category images cache");
-        let mut images = Vec::new();
-
-        for (_, db_image) in db_images.drain() {
-            let image = bincode::deserialize::<String>(&db_image)
-              .expect("Failed to deserialize category image");
-            images.push(image);
-        }
-        Ok(images)
+    pub fn handle_category_images() -> Result<Vec<String>, ServiceError> {
+        Self::handle_image_urls(ContentType::CategoryImages)
     }
 
-    pub fn handle_free_articles() -> Result<Vec<Article>> {
+    pub fn handle_free_articles() -> Result<Vec<Article>, ServiceError> {
         let articles = Self::handle_articles()?;
         Ok(articles.into_iter().map(|mut article| {
             if article.premium {
@@ -103,157 +255,72 @@ impl<'a> ServerHandler<'a> {
         }).collect::<Vec<Article>>())
     }
 
-    pub fn handle_articles() -> Result<Vec<Article>> {
-        let cache_path = std::env::current_dir()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/cache/articles.bin";
-
-        let mut cache_file = File::open(&cache_path).expect("Failed to open articles cache");
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file
-            .read_to_end(&mut cache_buf)
-            .expect("Failed to read articles cache");
-
-        let mut db_articles = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-            .expect("Failed to read articles cache");
-        let mut articles = Vec::new();
-        // for each DbArticle in the hashmap, deserialize into Article and collect to vector
-        for (_, db_article) in db_articles.drain() {
-            let article = bincode::deserialize::<Article>(&db_article)
-                .expect("Failed to deserialize article");
-            articles.push(article);
-        }
-        Ok(articles)
+    pub fn handle_articles() -> Result<Vec<Article>, ServiceError> {
+        content::load::<Article>(ContentType::Articles)
     }
 
     /// Open all to all users
-    pub fn handle_calibrations() -> Result<Vec<Calibration>> {
-        let cache_path = std::env::current_dir()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/cache/calibrations.bin";
-
-        let mut cache_file = File::open(&cache_path).expect("Failed to open calibrations cache");
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file
-            .read_to_end(&mut cache_buf)
-            .expect("Failed to read calibrations cache");
-
-        let mut db_calibrations = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-            .expect("Failed to read calibrations cache");
-        let mut calibrations = Vec::new();
-        // for each DbCalibration in the hashmap, deserialize into Calibration and collect to vector
-        for (_, db_calibration) in db_calibrations.drain() {
-            let calibration = bincode::deserialize::<Calibration>(&db_calibration)
-                .expect("Failed to deserialize calibration");
-            calibrations.push(calibration);
-        }
-        Ok(calibrations)
+    pub fn handle_calibrations() -> Result<Vec<Calibration>, ServiceError> {
+        content::load::<Calibration>(ContentType::Calibrations)
     }
 
     /// Open all to all users
-    pub fn handle_testimonials() -> Result<Vec<Testimonial>> {
-        let cache_path = std::env::current_dir()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/cache/testimonials.bin";
-
-        let mut cache_file = File::open(&cache_path).expect("Failed to open testimonials cache");
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file
-            .read_to_end(&mut cache_buf)
-            .expect("Failed to read testimonials cache");
-
-        let mut db_testimonials = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-            .expect("Failed to read testimonials cache");
-        let mut testimonials = Vec::new();
-        // for each DbCalibration in the hashmap, deserialize into Calibration and collect to vector
-        for (_, db_testimonial) in db_testimonials.drain() {
-            let testimonial = bincode::deserialize::<Testimonial>(&db_testimonial)
-                .expect("Failed to deserialize testimonial");
-            testimonials.push(testimonial);
-        }
-        Ok(testimonials)
+    pub fn handle_testimonials() -> Result<Vec<Testimonial>, ServiceError> {
+        content::load::<Testimonial>(ContentType::Testimonials)
     }
 
     /// Open all to all users
-    pub fn handle_testimonial_images() -> Result<Vec<String>> {
-        let cache_path = std::env::current_dir()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-            + "/cache/testimonial_images.bin";
-
-        let mut cache_file = File::open(&cache_path)?;
-        // Read the contents into a Vec<u8>
-        let mut cache_buf = Vec::new();
-        cache_file.read_to_end(&mut cache_buf)?;
-
-        let mut db_images = bincode::deserialize::<HashMap<u64, Vec<u8>>>(&cache_buf)
-            .expect("Failed to read testimonial images cache");
-        let mut images = Vec::new();
-        // for each DbArticle in the hashmap, deserialize into Article and collect to vector
-        for (_, db_image) in db_images.drain() {
-            let image = bincode::deserialize::<String>(&db_image)
-                .expect("Failed to deserialize testimonial image");
-            images.push(image);
-        }
-        Ok(images)
+    pub fn handle_testimonial_images() -> Result<Vec<String>, ServiceError> {
+        Self::handle_image_urls(ContentType::TestimonialImages)
     }
 
-    /// Restricted to authenticated request
-    pub async fn handle_subscribe(&self, mut payload: web::Payload) -> Result<CheckoutInfo> {
-        let mut body = web::BytesMut::new();
-        while let Some(chunk) = payload.next().await {
-            let chunk = chunk?;
-            if (body.len() + chunk.len()) > MAX_SIZE {
-                return Err(actix_web::error::ErrorBadRequest(
-                    "POST request bytes overflow",
-                ));
-            }
-            body.extend_from_slice(&chunk);
+    /// Open all to all users. Dimensions, blurhash, thumbnail and WebP variants of an image
+    /// list, e.g. `testimonial_images`
+    pub fn handle_image_info(content_type: &str) -> Result<Vec<ImageInfo>, ServiceError> {
+        match content_type.parse::<ContentType>() {
+            Ok(content_type) if content_type.is_image_list() => content::images(content_type),
+            _ => Err(ServiceError::NotFound(format!(
+                "No image list named {}",
+                content_type
+            ))),
         }
+    }
 
-        let buyer_email = serde_json::from_slice::<UserEmailRequest>(&body)?;
-        debug!("Checkout user email: {:?}", &buyer_email);
-        let res = self.client.subscribe_checkout(Some(buyer_email)).await?;
-
-        if let SquareResponse::Success(subscribe) = res {
-            debug!("Subscription checkout: {:?}", &subscribe);
-            Ok(subscribe)
-        } else {
-            error!("Failed to subscribe: {:?}", &res);
-            Err(actix_web::error::ErrorBadRequest("Failed to subscribe"))
-        }
+    /// The original URLs of an image list, as clients have always received them
+    fn handle_image_urls(content_type: ContentType) -> Result<Vec<String>, ServiceError> {
+        Ok(content::images(content_type)?
+            .into_iter()
+            .map(|image| image.url)
+            .collect())
     }
 
-    /// Open to all users
-    pub async fn handle_user_profile(&self, mut payload: web::Payload) -> Result<UserProfile> {
-        let mut body = web::BytesMut::new();
-        while let Some(chunk) = payload.next().await {
-            let chunk = chunk?;
-            if (body.len() + chunk.len()) > MAX_SIZE {
-                return Err(actix_web::error::ErrorBadRequest(
-                    "POST request bytes overflow",
-                ));
+    /// Restricted to authenticated request
+    pub async fn handle_subscribe(
+        &self,
+        identity: &Identity,
+    ) -> Result<CheckoutInfo, ServiceError> {
+        let buyer_email = identity.email_request()?;
+        debug!("Checkout user email: {:?}", &buyer_email);
+        match self.payments.subscribe_checkout(Some(buyer_email)).await {
+            Ok(subscribe) => {
+                debug!("Subscription checkout: {:?}", &subscribe);
+                Ok(subscribe)
+            }
+            Err(err) => {
+                error!("Failed to subscribe: {:?}", &err);
+                Err(err)
             }
-            body.extend_from_slice(&chunk);
         }
+    }
 
-        let buyer_email = serde_json::from_slice::<UserEmailRequest>(&body)?;
+    /// Open to all users
+    pub async fn handle_user_profile(
+        &self,
+        identity: &Identity,
+    ) -> Result<UserProfile, ServiceError> {
+        let buyer_email = identity.email_request()?;
         debug!("User subscription request email: {:?}", &buyer_email);
-        let info = self.client.get_user_profile(buyer_email).await?;
+        let info = self.payments.get_user_profile(buyer_email).await?;
         debug!("Get user subscription info: {:?}", &info);
         Ok(info)
     }
@@ -261,123 +328,71 @@ impl<'a> ServerHandler<'a> {
     /// Restricted to authenticated request
     pub async fn handle_cancel_subscription(
         &self,
-        mut payload: web::Payload,
-    ) -> Result<CanceledSubscriptionInfo> {
-        let mut body = web::BytesMut::new();
-        while let Some(chunk) = payload.next().await {
-            let chunk = chunk?;
-            if (body.len() + chunk.len()) > MAX_SIZE {
-                return Err(actix_web::error::ErrorBadRequest(
-                    "POST request bytes overflow",
-                ));
-            }
-            body.extend_from_slice(&chunk);
-        }
-
-        let buyer_email = serde_json::from_slice::<UserEmailRequest>(&body)?;
-        let info = self.client.cancel_subscription(buyer_email).await?;
-        if let SquareResponse::Success(info) = info {
-            Ok(info)
-        } else {
-            error!("Failed to cancel subscription: {:?}", &info);
-            Err(actix_web::error::ErrorBadRequest(
-                "Failed to cancel subscription",
-            ))
-        }
+        identity: &Identity,
+    ) -> Result<CanceledSubscriptionInfo, ServiceError> {
+        let buyer_email = identity.email_request()?;
+        self.payments
+            .cancel_subscription(buyer_email)
+            .await
+            .map_err(|err| {
+                error!("Failed to cancel subscription: {:?}", &err);
+                err
+            })
     }
 
-    pub async fn load_free_state(&self) -> Result<LoadState> {
-        let content_type_images = Self::handle_content_type_images()?;
-        debug!("Fetched content type images");
-        let category_images = Self::handle_category_images()?;
-        debug!("Fetched category images");
-        let articles = Self::handle_free_articles()?;
-        debug!("Fetched articles");
-        let calibrations = Self::handle_calibrations()?;
-        debug!("Fetched calibrations");
-        let testimonials = Self::handle_testimonials()?;
-        debug!("Fetched testimonials");
-        let testimonial_images = Self::handle_testimonial_images()?;
-        debug!("Fetched testimonial images");
-        let subscribe_checkout = match self.client.subscribe_checkout(None)
-          .await? {
-            SquareResponse::Success(subscribe) => subscribe,
-            SquareResponse::Error(err) => {
-                error!(
-                    "Failed to fetch subscribe checkout in state dump: {:?}",
-                    &err
-                );
-                return Err(actix_web::error::ErrorBadRequest(
-                    "Failed to fetch subscribe checkout in
-                    state dump",
-                ));
-            }
-        };
-        debug!("Fetched subscribe checkout");
+    /// Every section, premium articles have no body and the user profile is empty
+    pub async fn load_free_state(&self) -> Result<LoadState, ServiceError> {
+        self.load_sections(None, &Section::ALL)
+            .await
+            .into_load_state()
+    }
 
-        Ok(LoadState {
-            content_type_images,
-            category_images,
-            articles,
-            calibrations,
-            testimonials,
-            testimonial_images,
-            subscribe_checkout,
-            user_profile: Default::default(),
-        })
+    /// Only the requested sections, as in [`Self::load_free_state`]
+    pub async fn load_free_sections(&self, sections: &[Section]) -> PartialLoadState {
+        self.load_sections(None, sections).await.into_partial()
     }
 
     /// Filtered responses if not subscribed
     pub async fn load_state(
         &self,
-        mut payload: web::Payload,
-    ) -> Result<LoadState> {
-        let mut body = web::BytesMut::new();
-        while let Some(chunk) = payload.next().await {
-            let chunk = chunk?;
-            if (body.len() + chunk.len()) > MAX_SIZE {
-                return Err(actix_web::error::ErrorBadRequest(
-                    "POST request bytes overflow",
-                ));
-            }
-            body.extend_from_slice(&chunk);
-        }
-        let user_email = serde_json::from_slice::<UserEmailRequest>(&body)?;
+        identity: &Identity,
+    ) -> Result<LoadState, ServiceError> {
+        let user_email = identity.email_request()?;
         let email = user_email.email.clone();
+        let state = self
+            .load_sections(Some(user_email), &Section::ALL)
+            .await
+            .into_load_state()?;
+        debug!("Loaded state for {}", email);
+        Ok(state)
+    }
 
-        let content_type_images = Self::handle_content_type_images()?;
-        debug!("Fetched content type images");
-        let category_images = Self::handle_category_images()?;
-        debug!("Fetched category images");
-        let articles = Self::handle_articles()?;
-        debug!("Fetched articles");
-        let calibrations = Self::handle_calibrations()?;
-        debug!("Fetched calibrations");
-        let testimonials = Self::handle_testimonials()?;
-        debug!("Fetched testimonials");
-        let testimonial_images = Self::handle_testimonial_images()?;
-        debug!("Fetched testimonial images");
-        let subscribe_checkout = match self.client.subscribe_checkout(Some(user_email.clone()))
-          .await? {
-            SquareResponse::Success(subscribe) => subscribe,
-            SquareResponse::Error(err) => {
-                error!(
-                    "Failed to fetch subscribe checkout in state dump: {:?}",
-                    &err
-                );
-                return Err(actix_web::error::ErrorBadRequest(
-                    "Failed to fetch subscribe checkout in
-                    state dump",
-                ));
-            }
-        };
-        debug!("Fetched subscribe checkout");
-        let user_profile = self.client.get_user_profile(user_email).await?;
-        debug!("Fetched user profile");
-        // cancel subscription is the only endpoint that isn't loaded up front
+    /// Only the requested sections, as in [`Self::load_state`]
+    pub async fn load_state_sections(
+        &self,
+        identity: &Identity,
+        sections: &[Section],
+    ) -> Result<PartialLoadState, ServiceError> {
+        let user_email = identity.email_request()?;
+        let email = user_email.email.clone();
+        let state = self.load_sections(Some(user_email), sections).await.into_partial();
+        debug!("Loaded {:?} for {}", sections, email);
+        Ok(state)
+    }
 
-        debug!("Loaded state for {}", email);
-        Ok(LoadState {
+    /// Load the requested sections concurrently, skipping the others.
+    /// Without a user, premium articles have no body and the user profile is empty.
+    async fn load_sections(
+        &self,
+        user_email: Option<UserEmailRequest>,
+        sections: &[Section],
+    ) -> Sections {
+        let wants = |section: Section| sections.contains(&section);
+        let articles: fn() -> Result<Vec<Article>, ServiceError> = match user_email {
+            Some(_) => Self::handle_articles,
+            None => Self::handle_free_articles,
+        };
+        let (
             content_type_images,
             category_images,
             articles,
@@ -386,6 +401,52 @@ impl<'a> ServerHandler<'a> {
             testimonial_images,
             subscribe_checkout,
             user_profile,
-        })
+        ) = futures::join!(
+            load_if(wants(Section::ContentTypeImages), blocking(Self::handle_content_type_images)),
+            load_if(wants(Section::CategoryImages), blocking(Self::handle_category_images)),
+            load_if(wants(Section::Articles), blocking(articles)),
+            load_if(wants(Section::Calibrations), blocking(Self::handle_calibrations)),
+            load_if(wants(Section::Testimonials), blocking(Self::handle_testimonials)),
+            load_if(wants(Section::TestimonialImages), blocking(Self::handle_testimonial_images)),
+            load_if(wants(Section::SubscribeCheckout), self.subscribe_checkout(user_email.clone())),
+            load_if(wants(Section::UserProfile), self.user_profile(user_email.clone())),
+        );
+
+        Sections {
+            content_type_images,
+            category_images,
+            articles,
+            calibrations,
+            testimonials,
+            testimonial_images,
+            subscribe_checkout,
+            user_profile,
+        }
+    }
+
+    async fn subscribe_checkout(
+        &self,
+        user_email: Option<UserEmailRequest>,
+    ) -> Result<CheckoutInfo, ServiceError> {
+        self.payments
+            .subscribe_checkout(user_email)
+            .await
+            .map_err(|err| {
+                error!(
+                    "Failed to fetch subscribe checkout in state dump: {:?}",
+                    &err
+                );
+                err
+            })
+    }
+
+    async fn user_profile(
+        &self,
+        user_email: Option<UserEmailRequest>,
+    ) -> Result<UserProfile, ServiceError> {
+        match user_email {
+            Some(user_email) => self.payments.get_user_profile(user_email).await,
+            None => Ok(Default::default()),
+        }
     }
 }
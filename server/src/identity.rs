@@ -0,0 +1,74 @@
+use crate::config::{AuthConfig, AuthProvider};
+use crate::errors::ServiceError;
+use crate::jwks::JwksCache;
+use crate::test_issuer::TestIssuer;
+use alcoholic_jwt::JWK;
+use log::*;
+use std::sync::Arc;
+
+/// Who issues the tokens `/api` and `/admin` accept, selected by `auth.provider`, and where their
+/// signing keys come from. Tokens are validated the same way whichever it is.
+pub enum IdentityProvider {
+    /// Keys fetched from the issuer's JWKS, or read from `auth.jwks_file`
+    Auth0 {
+        auth: AuthConfig,
+        jwks: Arc<JwksCache>,
+    },
+    /// Keys of the built-in [`TestIssuer`], which also mints the tokens
+    Test {
+        auth: AuthConfig,
+        issuer: TestIssuer,
+    },
+}
+
+impl IdentityProvider {
+    pub fn from_config(auth: &AuthConfig) -> Result<Self, String> {
+        match auth.provider {
+            AuthProvider::Auth0 => Ok(IdentityProvider::Auth0 {
+                auth: auth.clone(),
+                jwks: Arc::new(JwksCache::from_config(auth)),
+            }),
+            AuthProvider::Test => {
+                warn!(
+                    "Test identity provider enabled, anyone can mint tokens for {} at /test_idp/token",
+                    auth.issuer
+                );
+                Ok(IdentityProvider::Test {
+                    auth: auth.clone(),
+                    issuer: TestIssuer::new(auth)?,
+                })
+            }
+        }
+    }
+
+    pub fn config(&self) -> &AuthConfig {
+        match self {
+            IdentityProvider::Auth0 { auth, .. } | IdentityProvider::Test { auth, .. } => auth,
+        }
+    }
+
+    /// `None` unless `auth.provider` is `test`
+    pub fn test_issuer(&self) -> Option<&TestIssuer> {
+        match self {
+            IdentityProvider::Test { issuer, .. } => Some(issuer),
+            IdentityProvider::Auth0 { .. } => None,
+        }
+    }
+
+    /// Keep the keys fresh in the background, see [`JwksCache::spawn_refresh`]
+    pub fn spawn_refresh(&self) {
+        if let IdentityProvider::Auth0 { jwks, .. } = self {
+            jwks.clone().spawn_refresh();
+        }
+    }
+
+    /// The key a token with this `kid` was signed with
+    pub async fn key(&self, kid: &str) -> Result<JWK, ServiceError> {
+        match self {
+            IdentityProvider::Auth0 { jwks, .. } => jwks.key(kid).await,
+            IdentityProvider::Test { issuer, .. } => issuer.key(kid).ok_or_else(|| {
+                ServiceError::Unauthorized("Token signed by an unknown key".to_string())
+            }),
+        }
+    }
+}
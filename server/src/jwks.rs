@@ -0,0 +1,163 @@
+use crate::config::AuthConfig;
+use crate::errors::ServiceError;
+use alcoholic_jwt::{JWK, JWKS};
+use derive_more::Display;
+use log::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+/// Least time between fetches when a token names a key we don't have, or a fetch failed
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the signing keys of the identity provider are read from
+#[derive(Debug, Clone, Display)]
+pub enum JwksSource {
+    /// e.g. `https://<tenant>.auth0.com/.well-known/jwks.json`
+    #[display(fmt = "{}", _0)]
+    Url(String),
+    /// A JWKS file on disk, for tests and offline work
+    #[display(fmt = "{}", "_0.display()")]
+    File(PathBuf),
+}
+
+struct Keys {
+    jwks: JWKS,
+    fetched_at: Instant,
+}
+
+/// Signing keys shared by every request.
+///
+/// Keys are refreshed in the background every half TTL, and refetched when a token names a key
+/// that isn't in the set, e.g. after the provider rotates keys. If a refresh fails the last keys
+/// keep being used, so requests only fail if keys were never fetched.
+pub struct JwksCache {
+    source: JwksSource,
+    ttl: Duration,
+    http: reqwest::Client,
+    keys: RwLock<Option<Keys>>,
+    /// Held while fetching so concurrent misses wait on one fetch, holds when it was last tried
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl JwksCache {
+    pub fn new(source: JwksSource, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl,
+            http: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            keys: RwLock::new(None),
+            last_fetch: Mutex::new(None),
+        }
+    }
+
+    /// `jwks_file` if set, otherwise `.well-known/jwks.json` of the issuer
+    pub fn from_config(auth: &AuthConfig) -> Self {
+        let source = match &auth.jwks_file {
+            Some(path) => JwksSource::File(path.clone()),
+            None => JwksSource::Url(format!("{}.well-known/jwks.json", auth.issuer)),
+        };
+        info!(
+            "Signing keys from {}, refreshed every {:?}",
+            source, auth.jwks_ttl
+        );
+        Self::new(source, auth.jwks_ttl)
+    }
+
+    /// The key a token with this `kid` was signed with
+    pub async fn key(&self, kid: &str) -> Result<JWK, ServiceError> {
+        if let Some(key) = self.cached(kid, false).await {
+            return Ok(key);
+        }
+
+        // stale keys, or a key we haven't seen
+        if let Err(e) = self.refresh(MIN_REFETCH_INTERVAL).await {
+            warn!("Failed to refresh JWKS, using cached keys if any: {}", e);
+        }
+        if let Some(key) = self.cached(kid, true).await {
+            return Ok(key);
+        }
+        if self.keys.read().await.is_none() {
+            return Err(ServiceError::JWKSFetchError);
+        }
+        Err(ServiceError::Unauthorized(
+            "Token signed by an unknown key".to_string(),
+        ))
+    }
+
+    /// Refresh the keys every half TTL, starting now, so requests rarely wait on a fetch
+    pub fn spawn_refresh(self: Arc<Self>) {
+        tokio::spawn(async move {
+            // interval panics on zero, config rejects such a TTL but `new` takes any
+            let mut interval = tokio::time::interval((self.ttl / 2).max(Duration::from_secs(1)));
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.refresh(Duration::ZERO).await {
+                    warn!("Failed to refresh JWKS, keeping the cached keys: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn cached(&self, kid: &str, allow_stale: bool) -> Option<JWK> {
+        let keys = self.keys.read().await;
+        let keys = keys.as_ref()?;
+        if !allow_stale && keys.fetched_at.elapsed() > self.ttl {
+            return None;
+        }
+        keys.jwks.find(kid).cloned()
+    }
+
+    /// Fetch the keys, unless a fetch was tried within `min_interval`
+    async fn refresh(&self, min_interval: Duration) -> Result<(), ServiceError> {
+        let mut last_fetch = self.last_fetch.lock().await;
+        if let Some(at) = *last_fetch {
+            if at.elapsed() < min_interval {
+                return Ok(());
+            }
+        }
+        *last_fetch = Some(Instant::now());
+
+        let jwks = self.fetch().await?;
+        *self.keys.write().await = Some(Keys {
+            jwks,
+            fetched_at: Instant::now(),
+        });
+        debug!("Refreshed JWKS");
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<JWKS, ServiceError> {
+        let source = &self.source;
+        let jwks = match source {
+            JwksSource::Url(url) => self.fetch_url(url).await,
+            JwksSource::File(path) => tokio::fs::read(path)
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    serde_json::from_slice::<JWKS>(&bytes).map_err(|e| e.to_string())
+                }),
+        };
+        jwks.map_err(|e| {
+            error!("Failed to fetch JWKS from {}: {}", source, e);
+            ServiceError::JWKSFetchError
+        })
+    }
+
+    async fn fetch_url(&self, url: &str) -> Result<JWKS, String> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<JWKS>()
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
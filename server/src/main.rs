@@ -1,7 +1,19 @@
+mod admin_auth;
+mod assets;
+mod audit;
+mod config;
+mod content;
 mod errors;
+mod graphql;
 mod handler;
+mod identity;
+mod jwks;
 mod oauth;
+mod openapi;
+mod rate_limit;
 mod square;
+mod sync;
+mod test_issuer;
 
 use handler::*;
 use oauth::*;
@@ -11,64 +23,118 @@ use square::*;
 // extern crate lazy_static;
 
 use actix_cors::Cors;
-use actix_web::{get, post, web, App, Error, HttpResponse, HttpServer, Result};
+use admin_auth::{admin_validator, Admin, Scope};
+use actix_web::{
+    delete, get, post, put, web, App, Error, HttpRequest, HttpResponse, HttpServer, Result,
+};
+use assets::{AssetStore, MAX_ASSET_SIZE};
+use audit::{record_param, Audit, AuditLog, AuditQuery};
+use config::{Config, LoggingConfig};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use database::{Article, Calibration, Testimonial};
+use database::{AdminKey, AdminKeyStore, AdminRole};
 use dotenv::dotenv;
-use futures::StreamExt;
-use google_cloud_storage::client::{Client, ClientConfig};
-use google_cloud_storage::http::objects::list::ListObjectsRequest;
-use lazy_static::lazy_static;
+use errors::ServiceError;
+use graphql::ApiSchema;
+use identity::IdentityProvider;
 use log::*;
+use rate_limit::{RateLimiter, RouteClass};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use simplelog::{
     ColorChoice, CombinedLogger, Config as SimpleLogConfig, ConfigBuilder, TermLogger,
     TerminalMode, WriteLogger,
 };
-use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
-use std::str::FromStr;
-use tokio::sync::Mutex;
-
-const MAX_SIZE: usize = 262_144; // max payload size is 256k
-const GCLOUD_BUCKET: &str = "consciousness-archive";
-const GCLOUD_STORAGE_PREFIX: &str = "https://storage.googleapis.com/consciousness-archive/";
-
-lazy_static! {
-    static ref SQUARE_CLIENT: Mutex<SquareClient> = Mutex::new(SquareClient::new());
-}
+use storage::content_type_for;
+use test_issuer::TokenRequest;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    init_logger(&PathBuf::from("server.log".to_string()))?;
 
-    info!("Starting Server...");
+    // every invalid setting is reported at once, in the log file too if logging is configured
+    let config = Config::load();
+    let logging = match &config {
+        Ok(config) => config.logging.clone(),
+        Err(_) => LoggingConfig::default(),
+    };
+    init_logger(&logging)?;
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3333".to_string());
-    let bind_address = format!("0.0.0.0:{}", port);
+    info!("Starting Server...");
 
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:3000")
-            .allowed_origin("https://consciousnessarchive.com")
-            .allowed_origin("https://drew.ngrok-free.app")
+    let config = match config {
+        Ok(config) => config,
+        Err(errors) => {
+            for e in errors.iter() {
+                error!("{}", e);
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} invalid setting(s)", errors.len()),
+            ));
+        }
+    };
+
+    let bind_address = format!("0.0.0.0:{}", config.server.port);
+
+    content::init(&config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+    let asset_store = web::Data::new(
+        AssetStore::from_config(&config.storage)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+    );
+    let (payments, in_memory_square) = payment_provider(&config.square);
+    let payments: web::Data<dyn PaymentProvider> = web::Data::from(payments);
+    let in_memory_square = in_memory_square.map(web::Data::from);
+    let schema = web::Data::new(graphql::schema(payments.clone().into_inner()));
+    let admin_keys = web::Data::new(AdminKeyStore::new(&config.cache.admin_keys_file));
+    let audit_log = web::Data::new(AuditLog::new(&config.cache.audit_log_file));
+    let rate_limiter = web::Data::new(RateLimiter::from_config(&config.rate_limit));
+    let identity_provider = web::Data::new(
+        IdentityProvider::from_config(&config.auth)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+    );
+    identity_provider.spawn_refresh();
+    let test_idp = identity_provider.test_issuer().is_some();
+
+    info!("Allowing CORS from {}", config.cors.allowed_origins.join(", "));
+    let cors_config = config.cors.clone();
+
+    HttpServer::new(move || {
+        let cors = cors_config
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
             .allow_any_method()
             .allow_any_header()
-            .max_age(3600);
+            .max_age(cors_config.max_age);
         let auth = HttpAuthentication::bearer(validator);
         let admin_auth = HttpAuthentication::bearer(admin_validator);
 
         App::new()
             .wrap(cors)
+            .app_data(asset_store.clone())
+            .app_data(schema.clone())
+            .app_data(payments.clone())
+            .app_data(identity_provider.clone())
+            .app_data(admin_keys.clone())
+            .app_data(audit_log.clone())
+            .app_data(rate_limiter.clone())
             .service(
               web::scope("/api/public")
+                    .wrap(RouteClass::Public)
                     .service(load_free_state)
+                    .service(sync_free_state)
             )
             .service(
                 web::scope("/api")
+                    // the last wrap runs first: IPs are limited before auth, even when it fails,
+                    // and users after it by identity
+                    .wrap(RouteClass::Api.per_user())
                     .wrap(auth)
+                    .wrap(RouteClass::Api.per_ip())
                     .service(articles)
                     .service(calibrations)
                     .service(cancel_subscription)
@@ -78,11 +144,32 @@ async fn main() -> std::io::Result<()> {
                     .service(subscribe)
                     .service(testimonials)
                     .service(testimonial_images)
-                    .service(load_state),
+                    .service(image_info)
+                    .service(load_state)
+                    .service(sync_state)
+                    .service(graphql_query)
+                    .service(graphql_schema),
             )
             .service(
                 web::scope("/admin")
+                    // like /api, IPs are limited before auth and admins after it
+                    .wrap(RouteClass::Admin.per_user())
                     .wrap(admin_auth)
+                    .wrap(RouteClass::Admin.per_ip())
+                    // outermost, so rate limited and unauthenticated requests are audited too
+                    .wrap(Audit)
+                    .app_data(web::PayloadConfig::new(MAX_ASSET_SIZE))
+                    .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                        ServiceError::BadRequest(format!("Invalid request body: {}", e)).into()
+                    }))
+                    .service(list_keys)
+                    .service(create_key)
+                    .service(revoke_key)
+                    .service(audit_entries)
+                    .service(export_audit)
+                    .service(list_assets)
+                    .service(upload_asset)
+                    .service(delete_asset)
                     .service(catalogs)
                     .service(customers)
                     .service(email_list)
@@ -91,16 +178,35 @@ async fn main() -> std::io::Result<()> {
                     .service(subscriptions)
                     .service(upsert_subscription_catalog),
             )
+            .configure(|cfg| {
+                if let Some(square) = &in_memory_square {
+                    cfg.service(
+                        web::scope("/test_square")
+                            .wrap(RouteClass::Public)
+                            .app_data(square.clone())
+                            .service(test_square_checkout)
+                            .service(test_square_coaching),
+                    );
+                }
+                if test_idp {
+                    cfg.service(
+                        web::scope("/test_idp")
+                            .wrap(RouteClass::Public)
+                            .service(test_idp_jwks)
+                            .service(test_idp_token),
+                    );
+                }
+            })
             .service(test)
+            .service(openapi_json)
     })
     .bind(bind_address)?
     .run()
     .await
 }
 
-pub fn init_logger(log_file: &PathBuf) -> std::io::Result<()> {
-    let log_level = std::env::var("LOG_LEVEL").unwrap_or_else(|_| "DEBUG".to_string());
-    let level_filter = LevelFilter::from_str(log_level.as_str()).unwrap();
+pub fn init_logger(logging: &LoggingConfig) -> std::io::Result<()> {
+    let level_filter = logging.level;
     CombinedLogger::init(vec![
         TermLogger::new(
             level_filter,
@@ -111,7 +217,7 @@ pub fn init_logger(log_file: &PathBuf) -> std::io::Result<()> {
         WriteLogger::new(
             level_filter,
             ConfigBuilder::new().set_time_format_rfc3339().build(),
-            File::create(log_file)?,
+            File::create(&logging.file)?,
         ),
     ])
     .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to initialize logger"))
@@ -122,152 +228,391 @@ async fn test() -> Result<HttpResponse, Error> {
     Ok(HttpResponse::Ok().body("Welcome to Consciousness Archive! We hope you brought cookies."))
 }
 
+/// OpenAPI 3 document of every route
+#[get("/openapi.json")]
+async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(openapi::DOCUMENT.as_str())
+}
+
 // ================================== API ================================== //
 
-#[post("/load_state")]
-async fn load_state(payload: web::Payload) -> Result<HttpResponse, Error> {
+/// `?include=articles,calibrations` loads only those sections, reporting errors per section
+#[post("/load_state", wrap = "RouteClass::Square")]
+async fn load_state(
+    payments: web::Data<dyn PaymentProvider>,
+    query: web::Query<LoadStateQuery>,
+    identity: Identity,
+) -> Result<HttpResponse, Error> {
     debug!("Loading state...");
-    let client = SQUARE_CLIENT.lock().await;
-    let handler = ServerHandler::new(client);
-    let res = handler.load_state(payload).await?;
-    Ok(HttpResponse::Ok().json(res))
+    let sections = query.sections()?;
+    let handler = ServerHandler::new(payments.get_ref());
+    match sections {
+        Some(sections) => {
+            let res = handler.load_state_sections(&identity, &sections).await?;
+            Ok(HttpResponse::Ok().json(res))
+        }
+        None => {
+            let res = handler.load_state(&identity).await?;
+            Ok(HttpResponse::Ok().json(res))
+        }
+    }
 }
 
 /// Not protected behind auth
-#[get("/load_state")]
-async fn load_free_state() -> Result<HttpResponse, Error> {
+#[get("/load_state", wrap = "RouteClass::Square")]
+async fn load_free_state(
+    payments: web::Data<dyn PaymentProvider>,
+    query: web::Query<LoadStateQuery>,
+) -> Result<HttpResponse, Error> {
     debug!("Loading free state...");
-    let client = SQUARE_CLIENT.lock().await;
-    let handler = ServerHandler::new(client);
-    let res = handler.load_free_state().await?;
+    let sections = query.sections()?;
+    let handler = ServerHandler::new(payments.get_ref());
+    match sections {
+        Some(sections) => {
+            let res = handler.load_free_sections(&sections).await;
+            Ok(HttpResponse::Ok().json(res))
+        }
+        None => {
+            let res = handler.load_free_state().await?;
+            Ok(HttpResponse::Ok().json(res))
+        }
+    }
+}
+
+/// Only what changed since the `since` token of the previous sync
+#[get("/sync")]
+async fn sync_state(query: web::Query<sync::SyncQuery>) -> Result<HttpResponse, Error> {
+    let res = blocking(move || sync::sync(query.since.as_deref(), false)).await?;
     Ok(HttpResponse::Ok().json(res))
 }
 
+/// Not protected behind auth, premium articles have no body
+#[get("/sync")]
+async fn sync_free_state(query: web::Query<sync::SyncQuery>) -> Result<HttpResponse, Error> {
+    let res = blocking(move || sync::sync(query.since.as_deref(), true)).await?;
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// Content and the user's account in one query, see `/api/graphql/schema` for what can be selected
+#[post("/graphql", wrap = "RouteClass::Square")]
+async fn graphql_query(
+    schema: web::Data<ApiSchema>,
+    identity: Identity,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    schema.execute(request.into_inner().data(identity)).await.into()
+}
+
+/// The GraphQL schema in SDL, for generating clients
+#[get("/graphql/schema")]
+async fn graphql_schema(schema: web::Data<ApiSchema>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(schema.sdl())
+}
+
 #[get("/content_type_images")]
 async fn content_type_images() -> Result<HttpResponse, Error> {
-    let images = ServerHandler::handle_content_type_images()?;
+    let images = blocking(ServerHandler::handle_content_type_images).await?;
     Ok(HttpResponse::Ok().json(images))
 }
 
 #[get("/category_images")]
 async fn category_images() -> Result<HttpResponse, Error> {
-    let images = ServerHandler::handle_category_images()?;
+    let images = blocking(ServerHandler::handle_category_images).await?;
     Ok(HttpResponse::Ok().json(images))
 }
 
 #[get("/articles")]
 async fn articles() -> Result<HttpResponse, Error> {
-    let articles = ServerHandler::handle_articles()?;
+    let articles = blocking(ServerHandler::handle_articles).await?;
     Ok(HttpResponse::Ok().json(articles))
 }
 
 #[get("/calibrations")]
 async fn calibrations() -> Result<HttpResponse, Error> {
-    let calibrations = ServerHandler::handle_calibrations()?;
+    let calibrations = blocking(ServerHandler::handle_calibrations).await?;
     Ok(HttpResponse::Ok().json(calibrations))
 }
 
 #[get("/testimonials")]
 async fn testimonials() -> Result<HttpResponse, Error> {
-    let testimonials = ServerHandler::handle_testimonials()?;
+    let testimonials = blocking(ServerHandler::handle_testimonials).await?;
     Ok(HttpResponse::Ok().json(testimonials))
 }
 
 #[get("/testimonial_images")]
 async fn testimonial_images() -> Result<HttpResponse, Error> {
-    let images = ServerHandler::handle_testimonial_images()?;
+    let images = blocking(ServerHandler::handle_testimonial_images).await?;
     Ok(HttpResponse::Ok().json(images))
 }
 
-#[post("/subscribe")]
-async fn subscribe(payload: web::Payload) -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let handler = ServerHandler::new(client);
-    let res = handler.handle_subscribe(payload).await?;
+#[get("/image_info/{content_type}")]
+async fn image_info(content_type: web::Path<String>) -> Result<HttpResponse, Error> {
+    let images = blocking(move || ServerHandler::handle_image_info(&content_type)).await?;
+    Ok(HttpResponse::Ok().json(images))
+}
+
+#[post("/subscribe", wrap = "Audit", wrap = "RouteClass::Square")]
+async fn subscribe(
+    payments: web::Data<dyn PaymentProvider>,
+    identity: Identity,
+) -> Result<HttpResponse, Error> {
+    let handler = ServerHandler::new(payments.get_ref());
+    let res = handler.handle_subscribe(&identity).await?;
     Ok(HttpResponse::Ok().json(res))
 }
 
-#[post("/user_profile")]
-async fn user_profile(payload: web::Payload) -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let handler = ServerHandler::new(client);
-    let info = handler.handle_user_profile(payload).await?;
+#[post("/user_profile", wrap = "RouteClass::Square")]
+async fn user_profile(
+    payments: web::Data<dyn PaymentProvider>,
+    identity: Identity,
+) -> Result<HttpResponse, Error> {
+    let handler = ServerHandler::new(payments.get_ref());
+    let info = handler.handle_user_profile(&identity).await?;
     Ok(HttpResponse::Ok().json(info))
 }
 
-#[post("/cancel_subscription")]
-async fn cancel_subscription(payload: web::Payload) -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let handler = ServerHandler::new(client);
-    let info = handler.handle_cancel_subscription(payload).await?;
+#[post("/cancel_subscription", wrap = "Audit", wrap = "RouteClass::Square")]
+async fn cancel_subscription(
+    payments: web::Data<dyn PaymentProvider>,
+    identity: Identity,
+) -> Result<HttpResponse, Error> {
+    let handler = ServerHandler::new(payments.get_ref());
+    let info = handler.handle_cancel_subscription(&identity).await?;
     Ok(HttpResponse::Ok().json(info))
 }
 
+// ============================ TEST IDENTITY PROVIDER ============================ //
+
+fn test_issuer(provider: &IdentityProvider) -> Result<&test_issuer::TestIssuer, ServiceError> {
+    provider
+        .test_issuer()
+        .ok_or_else(|| ServiceError::NotFound("The test identity provider is disabled".to_string()))
+}
+
+/// Signing keys of the test identity provider, only served with `auth.provider = "test"`
+#[get("/.well-known/jwks.json")]
+async fn test_idp_jwks(provider: web::Data<IdentityProvider>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(test_issuer(&provider)?.jwks()))
+}
+
+/// A token for whoever is asked for, only served with `auth.provider = "test"`
+#[post("/token")]
+async fn test_idp_token(
+    provider: web::Data<IdentityProvider>,
+    request: web::Json<TokenRequest>,
+) -> Result<HttpResponse, Error> {
+    let token = test_issuer(&provider)?.mint(&request)?;
+    Ok(HttpResponse::Ok().json(token))
+}
+
+// ============================ TEST PAYMENTS ============================ //
+
+/// The buyer paying a checkout link, only served with `square.backend = "memory"`
+#[post("/checkout")]
+async fn test_square_checkout(
+    square: web::Data<InMemorySquare>,
+    payment: web::Json<CheckoutPayment>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(square.complete_checkout(&payment)?))
+}
+
+/// A coaching package sold to an existing customer, only served with `square.backend = "memory"`
+#[post("/coaching")]
+async fn test_square_coaching(
+    square: web::Data<InMemorySquare>,
+    purchase: web::Json<CoachingPurchase>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(square.buy_coaching_package(&purchase)?))
+}
+
 // ================================== ADMIN ================================== //
 
-#[get("/upsert_subscription_catalog")]
-async fn upsert_subscription_catalog() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let catalog = client.upsert_subscription_catalog().await?;
-    Ok(HttpResponse::Ok().json(catalog))
+#[get("/upsert_subscription_catalog", wrap = "Scope::BillingWrite")]
+async fn upsert_subscription_catalog(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let plan = payments.upsert_subscription_plan().await?;
+    Ok(HttpResponse::Ok().json(plan))
 }
 
-#[get("/subscriptions")]
-async fn subscriptions() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.list_subscriptions().await?;
+#[get("/subscriptions", wrap = "Scope::BillingRead")]
+async fn subscriptions(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let list = payments.subscriptions().await?;
     Ok(HttpResponse::Ok().json(list))
 }
 
-#[get("/email_list")]
-async fn email_list() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.email_list().await?;
+#[get("/email_list", wrap = "Scope::BillingRead")]
+async fn email_list(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let list = payments.email_list().await?;
     Ok(HttpResponse::Ok().json(list))
 }
 
-#[get("/customers")]
-async fn customers() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.list_customers().await?;
+#[get("/customers", wrap = "Scope::BillingRead")]
+async fn customers(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let list = payments.customers().await?;
     Ok(HttpResponse::Ok().json(list))
 }
 
-#[get("/orders")]
-async fn orders() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.list_orders().await?;
-    match list {
-        SquareResponse::Success(orders) => {
-            info!("Orders: {:?}", &orders.orders.len());
-            Ok(HttpResponse::Ok().json(orders))
-        }
-        SquareResponse::Error(e) => Err(actix_web::error::ErrorBadRequest(format!(
-            "Failed to get Square orders: {:?}",
-            e
-        ))),
-    }
+#[get("/orders", wrap = "Scope::BillingRead")]
+async fn orders(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let orders = payments.orders().await.map_err(|e| {
+        error!("Failed to get orders: {:?}", &e);
+        e
+    })?;
+    info!("Orders: {:?}", &orders.len());
+    Ok(HttpResponse::Ok().json(orders))
 }
 
-#[get("/invoices")]
-async fn invoices() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.list_invoices().await?;
-    match list {
-        SquareResponse::Success(invoices) => {
-            info!("Invoices: {:?}", &invoices.invoices.len());
-            Ok(HttpResponse::Ok().json(invoices))
-        }
-        SquareResponse::Error(e) => Err(actix_web::error::ErrorBadRequest(format!(
-            "Failed to get Square invoices: {:?}",
-            e
-        ))),
-    }
+#[get("/invoices", wrap = "Scope::BillingRead")]
+async fn invoices(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let invoices = payments.invoices().await.map_err(|e| {
+        error!("Failed to get invoices: {:?}", &e);
+        e
+    })?;
+    info!("Invoices: {:?}", &invoices.len());
+    Ok(HttpResponse::Ok().json(invoices))
 }
 
-#[get("/catalogs")]
-async fn catalogs() -> Result<HttpResponse, Error> {
-    let client = SQUARE_CLIENT.lock().await;
-    let list = client.list_catalogs().await?;
+#[get("/catalogs", wrap = "Scope::BillingRead")]
+async fn catalogs(payments: web::Data<dyn PaymentProvider>) -> Result<HttpResponse, Error> {
+    let list = payments.plans().await?;
     Ok(HttpResponse::Ok().json(list))
 }
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct AssetsQuery {
+    /// Only list keys starting with this, e.g. images/testimonial_images/
+    #[serde(default)]
+    prefix: String,
+}
+
+#[get("/assets", wrap = "Scope::AssetsRead")]
+async fn list_assets(
+    assets: web::Data<AssetStore>,
+    query: web::Query<AssetsQuery>,
+) -> Result<HttpResponse, Error> {
+    let list = assets.list(&query.prefix).await?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Create or overwrite the asset at `key` with the request body
+#[put("/assets/{key:.*}", wrap = "Scope::AssetsWrite")]
+async fn upload_asset(
+    assets: web::Data<AssetStore>,
+    key: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let content_type = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| content_type_for(&key));
+    record_param(&req, "content_type", content_type.as_str());
+    record_param(&req, "size", body.len());
+    let asset = assets.put(&key, body.to_vec(), &content_type).await?;
+    info!("Uploaded asset {}", asset.key);
+    Ok(HttpResponse::Ok().json(asset))
+}
+
+#[delete("/assets/{key:.*}", wrap = "Scope::AssetsWrite")]
+async fn delete_asset(
+    assets: web::Data<AssetStore>,
+    key: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    assets.delete(&key).await?;
+    info!("Deleted asset {}", key);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Body of `POST /admin/keys`
+#[derive(Deserialize, Debug, JsonSchema)]
+struct CreateKeyRequest {
+    /// Who or what the key is for, e.g. "support laptop"
+    name: String,
+    role: AdminRole,
+}
+
+/// A new admin key, the only time `key` itself is returned
+#[derive(Serialize, Debug, JsonSchema)]
+struct CreatedKey {
+    #[serde(flatten)]
+    info: AdminKey,
+    key: String,
+}
+
+/// Every admin key ever issued, revoked ones included
+#[get("/keys", wrap = "Scope::KeysManage")]
+async fn list_keys(keys: web::Data<AdminKeyStore>) -> Result<HttpResponse, Error> {
+    let list = blocking(move || Ok(keys.list()?)).await?;
+    Ok(HttpResponse::Ok().json(list))
+}
+
+#[post("/keys", wrap = "Scope::KeysManage")]
+async fn create_key(
+    keys: web::Data<AdminKeyStore>,
+    admin: Admin,
+    req: HttpRequest,
+    body: web::Json<CreateKeyRequest>,
+) -> Result<HttpResponse, Error> {
+    let request = body.into_inner();
+    record_param(&req, "name", request.name.as_str());
+    record_param(&req, "role", request.role.name());
+    let (info, key) = blocking(move || Ok(keys.create(&request.name, request.role)?)).await?;
+    info!("{} created {} admin key {} for {}", admin.actor, info.role, info.id, info.name);
+    record_param(&req, "id", info.id.as_str());
+    Ok(HttpResponse::Created().json(CreatedKey { info, key }))
+}
+
+/// Revoked keys stop working on the next request
+#[delete("/keys/{id}", wrap = "Scope::KeysManage")]
+async fn revoke_key(
+    keys: web::Data<AdminKeyStore>,
+    admin: Admin,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let key = blocking(move || {
+        keys.revoke(&id)?
+            .ok_or_else(|| ServiceError::NotFound(format!("No admin key {}", id)))
+    })
+    .await?;
+    info!("{} revoked admin key {}", admin.actor, key.id);
+    Ok(HttpResponse::Ok().json(key))
+}
+
+/// Most recent audit entries matching the filters, newest first
+#[get("/audit", wrap = "Scope::AuditRead")]
+async fn audit_entries(
+    log: web::Data<AuditLog>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+    let entries = blocking(move || log.recent(&query)).await?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Every audit entry matching the filters as JSON lines, oldest first
+#[get("/audit/export", wrap = "Scope::AuditRead")]
+async fn export_audit(
+    log: web::Data<AuditLog>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+    let entries = blocking(move || log.entries(&query)).await?;
+    let mut body = String::new();
+    for entry in entries.iter() {
+        body.push_str(&serde_json::to_string(entry).map_err(ServiceError::from)?);
+        body.push('\n');
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            "attachment; filename=\"audit.jsonl\"",
+        ))
+        .body(body))
+}
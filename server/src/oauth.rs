@@ -1,83 +1,319 @@
+use crate::config::AuthConfig;
 use crate::errors::ServiceError;
-use actix_web::{dev::ServiceRequest, Error as ActixError};
+use crate::identity::IdentityProvider;
+use crate::square::UserEmailRequest;
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::{web, Error as ActixError, FromRequest, HttpMessage, HttpRequest, ResponseError};
 use actix_web_httpauth::extractors::bearer::{BearerAuth, Config};
 use actix_web_httpauth::extractors::AuthenticationError;
-use alcoholic_jwt::{token_kid, validate, Validation, JWKS};
+use alcoholic_jwt::{token_kid, validate, Validation};
+use futures::future::{ready, Ready};
 use log::*;
-use serde::{Deserialize, Serialize};
-use std::error::Error;
+use serde_json::Value;
 
 // Auth0 Rust example
 // https://auth0.com/blog/build-an-api-in-rust-with-jwt-authentication-using-actix-web/#Getting-Started
 
-/// Credentials is a key that should match environment variable ADMIN_BEARER_TOKEN
-pub async fn admin_validator(
-    req: ServiceRequest,
-    credentials: BearerAuth,
-) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
-    let admin_bearer_token =
-        std::env::var("ADMIN_BEARER_TOKEN").expect("ADMIN_BEARER_TOKEN must be set");
-    if credentials.token() != admin_bearer_token {
-        info!("Token validation failed");
-        let config = req.app_data::<Config>().cloned().unwrap_or_default();
-        return Err((AuthenticationError::from(config).into(), req));
-    } else {
-        Ok(req)
-    }
-}
-
+/// Validates the bearer JWT and stores its [`Identity`] in the request extensions
 pub async fn validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (ActixError, ServiceRequest)> {
     debug!("req: {:?}", req);
-    debug!("credentials: {:?}", credentials);
     let config = req.app_data::<Config>().cloned().unwrap_or_default();
-    match validate_token(credentials.token()).await {
-        Ok(res) => {
-            if res {
-                debug!("Token validated");
-                Ok(req)
-            } else {
-                info!("Token validation failed");
-                Err((AuthenticationError::from(config).into(), req))
-            }
+    let provider = match req.app_data::<web::Data<IdentityProvider>>() {
+        Some(provider) => provider.clone(),
+        None => {
+            let e = ServiceError::Internal("Identity provider is not configured".to_string());
+            return Err((e.into(), req));
         }
-        Err(_) => {
-            error!("Token validation errored");
+    };
+    match validate_token(&provider, credentials.token()).await {
+        Ok(identity) => {
+            debug!("Token validated for {}", identity.subject);
+            req.extensions_mut().insert(identity);
+            Ok(req)
+        }
+        // keys couldn't be fetched, the token may well be valid
+        Err(e) if e.status_code().is_server_error() => Err((e.into(), req)),
+        Err(e) => {
+            info!("Token validation failed: {}", e);
             Err((AuthenticationError::from(config).into(), req))
         }
     }
 }
 
-async fn fetch_jwks(uri: &str) -> Result<JWKS, Box<dyn Error>> {
-    let res = reqwest::get(uri).await?;
-    let val = res.json::<JWKS>().await?;
-    Ok(val)
+/// The user a request was authenticated as, taken from the claims of its verified token.
+/// User scoped handlers act only on this, never on an email sent in the request.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// `sub` claim, e.g. `auth0|64f1...`
+    pub subject: String,
+    /// Email claim named by `auth.email_claim`, `None` if missing or not verified
+    pub email: Option<String>,
+}
+
+impl Identity {
+    /// The email is only taken if `email_verified` is `true`, or `auth.trust_email_claim` says
+    /// the issuer only ever sets the claim to verified emails
+    fn from_claims(claims: &Value, auth: &AuthConfig) -> Result<Self, ServiceError> {
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ServiceError::Unauthorized("Token has no subject".to_string()))?
+            .to_string();
+        let verified = auth.trust_email_claim
+            || claims.get("email_verified").and_then(Value::as_bool) == Some(true);
+        let email = claims
+            .get(&auth.email_claim)
+            .and_then(Value::as_str)
+            .filter(|_| verified)
+            .map(str::to_string);
+        Ok(Identity { subject, email })
+    }
+
+    /// The verified email, which Square customers are looked up by
+    pub fn email_request(&self) -> Result<UserEmailRequest, ServiceError> {
+        match &self.email {
+            Some(email) => Ok(UserEmailRequest {
+                email: email.clone(),
+            }),
+            None => Err(ServiceError::Unauthorized(
+                "Token has no verified email".to_string(),
+            )),
+        }
+    }
+}
+
+/// Only available behind [`validator`], anywhere else the request is unauthorized
+impl FromRequest for Identity {
+    type Error = ServiceError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Identity>()
+                .cloned()
+                .ok_or_else(|| ServiceError::Unauthorized("Not authenticated".to_string())),
+        )
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    company: String,
-    exp: usize,
+/// Verifies `token` and returns the [`Identity`] it was issued to
+pub async fn validate_token(
+    provider: &IdentityProvider,
+    token: &str,
+) -> Result<Identity, ServiceError> {
+    Identity::from_claims(&validate_claims(provider, token).await?, provider.config())
 }
 
-pub async fn validate_token(token: &str) -> Result<bool, ServiceError> {
-    let authority = std::env::var("AUTH0_ENDPOINT").expect("AUTH0_ENDPOINT must be set");
-    let jwks = fetch_jwks(&format!(
-        "{}{}",
-        authority.as_str(),
-        ".well-known/jwks.json"
-    ))
-    .await
-    .expect("failed to fetch jwks");
-    let validations = vec![Validation::Issuer(authority), Validation::SubjectPresent];
+/// Verifies the signature, issuer, audience and expiry of `token` and returns its claims
+pub async fn validate_claims(
+    provider: &IdentityProvider,
+    token: &str,
+) -> Result<Value, ServiceError> {
+    let auth = provider.config();
+    let validations = vec![
+        Validation::Issuer(auth.issuer.clone()),
+        Validation::Audience(auth.audience.clone()),
+        Validation::SubjectPresent,
+        Validation::NotExpired,
+    ];
     let kid = match token_kid(token) {
-        Ok(res) => res.expect("failed to decode kid"),
-        Err(_) => return Err(ServiceError::JWKSFetchError),
+        Ok(Some(kid)) => kid,
+        Ok(None) => return Err(ServiceError::Unauthorized("Token has no kid".to_string())),
+        Err(_) => return Err(ServiceError::Unauthorized("Malformed token".to_string())),
     };
-    let jwk = jwks.find(&kid).expect("Specified key not found in set");
-    let res = validate(token, jwk, validations);
-    Ok(res.is_ok())
+    let jwk = provider.key(&kid).await?;
+    let jwt = validate(token, &jwk, validations)
+        .map_err(|e| ServiceError::Unauthorized(format!("Invalid token: {:?}", e)))?;
+    Ok(jwt.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AuthProvider, PaymentBackend, SquareConfig};
+    use crate::square::{payment_provider, PaymentProvider};
+    use crate::test_issuer::{TestIssuer, TokenRequest};
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, read_body, read_body_json, TestRequest};
+    use actix_web::App;
+    use actix_web_httpauth::middleware::HttpAuthentication;
+    use serde_json::json;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn auth(trust_email_claim: bool) -> AuthConfig {
+        AuthConfig {
+            provider: AuthProvider::Test,
+            issuer: "http://localhost:3333/test_idp/".to_string(),
+            audience: "consciousness-archive".to_string(),
+            jwks_file: None,
+            jwks_ttl: Duration::from_secs(600),
+            email_claim: "email".to_string(),
+            trust_email_claim,
+            roles_claim: "roles".to_string(),
+        }
+    }
+
+    fn email(claims: Value, auth: &AuthConfig) -> Option<String> {
+        Identity::from_claims(&claims, auth).unwrap().email
+    }
+
+    #[test]
+    fn email_requires_email_verified() {
+        let auth = auth(false);
+        let verified = json!({ "sub": "a", "email": "a@example.com", "email_verified": true });
+        assert_eq!(email(verified, &auth).as_deref(), Some("a@example.com"));
+        let unverified = json!({ "sub": "a", "email": "a@example.com", "email_verified": false });
+        assert_eq!(email(unverified, &auth), None);
+        let missing = json!({ "sub": "a", "email": "a@example.com" });
+        assert_eq!(email(missing, &auth), None);
+        let not_a_bool = json!({ "sub": "a", "email": "a@example.com", "email_verified": "true" });
+        assert_eq!(email(not_a_bool, &auth), None);
+    }
+
+    #[test]
+    fn trusted_email_claim_needs_no_email_verified() {
+        let claims = json!({ "sub": "a", "email": "a@example.com" });
+        assert_eq!(email(claims, &auth(true)).as_deref(), Some("a@example.com"));
+    }
+
+    fn token_request(expires_in: Option<i64>, audience: Option<&str>) -> TokenRequest {
+        TokenRequest {
+            sub: "test|alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            email_verified: None,
+            roles: Vec::new(),
+            permissions: Vec::new(),
+            expires_in,
+            audience: audience.map(str::to_string),
+        }
+    }
+
+    fn mint(issuer: &TestIssuer, request: &TokenRequest) -> String {
+        issuer.mint(request).unwrap().access_token
+    }
+
+    /// Tokens of `provider`'s issuer are accepted, expired ones, tokens for another audience and
+    /// tokens signed by another issuer's key are not
+    async fn check_validation(provider: &IdentityProvider, issuer: &TestIssuer) {
+        let identity = validate_token(provider, &mint(issuer, &token_request(None, None)))
+            .await
+            .unwrap();
+        assert_eq!(identity.subject, "test|alice");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+
+        let expired = mint(issuer, &token_request(Some(-3600), None));
+        let wrong_audience = mint(issuer, &token_request(None, Some("someone-else")));
+        let unknown_kid = mint(
+            &TestIssuer::new(provider.config()).unwrap(),
+            &token_request(None, None),
+        );
+        for token in [expired, wrong_audience, unknown_kid] {
+            match validate_token(provider, &token).await {
+                Err(ServiceError::Unauthorized(_)) => {}
+                result => panic!("expected Unauthorized, got {:?}", result),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_test_issuer_tokens() {
+        let provider = IdentityProvider::from_config(&auth(false)).unwrap();
+        let issuer = provider.test_issuer().unwrap();
+        check_validation(&provider, issuer).await;
+    }
+
+    #[tokio::test]
+    async fn validates_tokens_against_jwks() {
+        let issuer = TestIssuer::new(&auth(false)).unwrap();
+        let jwks_file = std::env::temp_dir().join(format!("jwks-{}.json", Uuid::new_v4()));
+        std::fs::write(&jwks_file, issuer.jwks().to_string()).unwrap();
+        let provider = IdentityProvider::from_config(&AuthConfig {
+            provider: AuthProvider::Auth0,
+            jwks_file: Some(jwks_file.clone()),
+            ..auth(false)
+        })
+        .unwrap();
+        check_validation(&provider, &issuer).await;
+        std::fs::remove_file(jwks_file).unwrap();
+    }
+
+    /// `POST /api/user_profile` through the auth middleware, as main.rs wires `/api`
+    async fn user_profile(provider: &web::Data<IdentityProvider>, token: &str) -> ServiceResponse {
+        let (payments, _) = payment_provider(&SquareConfig {
+            backend: PaymentBackend::InMemory,
+            api_url: String::new(),
+            access_token: String::new(),
+            api_version: String::new(),
+            app_id: String::new(),
+            location_id: "LOCALSQUARE".to_string(),
+            subscription_catalog_id: String::new(),
+            subscription_price: 1100,
+            subscription_name: "Premium".to_string(),
+            redirect_url: "http://localhost:3000/".to_string(),
+        });
+        let payments: web::Data<dyn PaymentProvider> = web::Data::from(payments);
+        let app = init_service(
+            App::new()
+                .app_data(provider.clone())
+                .app_data(payments)
+                .service(
+                    web::scope("/api")
+                        .wrap(HttpAuthentication::bearer(validator))
+                        .service(crate::user_profile),
+                ),
+        )
+        .await;
+        let request = TestRequest::post()
+            .uri("/api/user_profile")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        call_service(&app, request).await
+    }
+
+    #[actix_web::test]
+    async fn authenticates_api_routes() {
+        let provider = web::Data::new(IdentityProvider::from_config(&auth(false)).unwrap());
+        let issuer = provider.test_issuer().unwrap();
+
+        let response = user_profile(&provider, &mint(issuer, &token_request(None, None))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let profile: Value = read_body_json(response).await;
+        assert_eq!(
+            profile,
+            json!({
+                "customer": null,
+                "subscription_info": { "title": "Premium", "cost": 11.0 },
+                "user_subscription": null,
+            })
+        );
+
+        let unverified = TokenRequest {
+            email_verified: Some(false),
+            ..token_request(None, None)
+        };
+        // a valid token gets past the middleware, the handler needs a verified email
+        let response = user_profile(&provider, &mint(issuer, &unverified)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let error: Value = read_body_json(response).await;
+        assert_eq!(
+            error,
+            json!({
+                "error": { "code": "unauthorized", "message": "Token has no verified email" }
+            })
+        );
+
+        let expired = mint(issuer, &token_request(Some(-3600), None));
+        for token in [expired.as_str(), "not-a-token"] {
+            let response = user_profile(&provider, token).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert!(response.headers().contains_key("www-authenticate"));
+            assert!(read_body(response).await.is_empty());
+        }
+    }
 }
@@ -0,0 +1,556 @@
+use crate::admin_auth::Scope;
+use crate::assets::Asset;
+use crate::audit::{AuditEntry, AuditQuery};
+use crate::errors::ErrorBody;
+use crate::handler::{LoadState, LoadStateQuery, PartialLoadState};
+use crate::square::billing::{Customer, Invoice, Order, Plan, Subscription};
+use crate::square::{CanceledSubscriptionInfo, CheckoutInfo, CustomerEmailInfo, UserProfile};
+use crate::sync::{SyncQuery, SyncResponse};
+use crate::{AssetsQuery, CreateKeyRequest, CreatedKey};
+use database::{AdminKey, Article, Calibration, ImageInfo, Testimonial};
+use lazy_static::lazy_static;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+lazy_static! {
+    /// The document as served, generated on first request
+    pub static ref DOCUMENT: String = serde_json::to_string_pretty(&document()).unwrap_or_default();
+}
+
+/// Who may call a route
+#[derive(Clone, Copy)]
+enum Auth {
+    Public,
+    /// JWT issued by the identity provider
+    User,
+    /// Admin API key or JWT granting the scope
+    Admin(Scope),
+}
+
+/// One operation of the document, built up from the Rust types it reads and writes
+struct Route {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    tag: &'static str,
+    auth: Auth,
+    parameters: Vec<Value>,
+    request: Option<Value>,
+    /// Status and response object of the successful response
+    response: (&'static str, Value),
+}
+
+impl Route {
+    fn new(method: &'static str, path: &'static str, summary: &'static str, auth: Auth) -> Self {
+        let tag = match auth {
+            Auth::Public => "public",
+            Auth::User => "api",
+            Auth::Admin(_) => "admin",
+        };
+        Route {
+            method,
+            path,
+            summary,
+            tag,
+            auth,
+            parameters: Vec::new(),
+            request: None,
+            response: ("204", json!({ "description": "No content" })),
+        }
+    }
+
+    fn get(path: &'static str, summary: &'static str, auth: Auth) -> Self {
+        Self::new("get", path, summary, auth)
+    }
+
+    fn post(path: &'static str, summary: &'static str, auth: Auth) -> Self {
+        Self::new("post", path, summary, auth)
+    }
+
+    /// Every field of `T` as a query parameter
+    fn query<T: JsonSchema>(mut self, gen: &mut SchemaGenerator) -> Self {
+        let schema = gen.root_schema_for::<T>().schema;
+        if let Some(object) = schema.object {
+            for (name, property) in object.properties.into_iter() {
+                let required = object.required.contains(&name);
+                let description = property
+                    .clone()
+                    .into_object()
+                    .metadata
+                    .and_then(|metadata| metadata.description);
+                self.parameters.push(json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required,
+                    "description": description,
+                    "schema": property,
+                }));
+            }
+        }
+        self
+    }
+
+    fn path_param(mut self, name: &str, description: &str) -> Self {
+        self.parameters.push(json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "description": description,
+            "schema": { "type": "string" },
+        }));
+        self
+    }
+
+    fn json_body<T: JsonSchema>(mut self, gen: &mut SchemaGenerator) -> Self {
+        self.request = Some(json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema::<T>(gen) } },
+        }));
+        self
+    }
+
+    fn request(mut self, request: Value) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    fn binary_body(mut self) -> Self {
+        self.request = Some(json!({
+            "required": true,
+            "content": {
+                "application/octet-stream": { "schema": { "type": "string", "format": "binary" } }
+            },
+        }));
+        self
+    }
+
+    fn json<T: JsonSchema>(self, gen: &mut SchemaGenerator) -> Self {
+        let schema = schema::<T>(gen);
+        self.json_schema(schema)
+    }
+
+    /// Status of the successful response, when not `200`
+    fn status(mut self, status: &'static str) -> Self {
+        self.response.0 = status;
+        self
+    }
+
+    fn json_schema(mut self, schema: Value) -> Self {
+        self.response = (
+            "200",
+            json!({
+                "description": "OK",
+                "content": { "application/json": { "schema": schema } },
+            }),
+        );
+        self
+    }
+
+    fn text(mut self, description: &str) -> Self {
+        self.response = (
+            "200",
+            json!({
+                "description": description,
+                "content": { "text/plain": { "schema": { "type": "string" } } },
+            }),
+        );
+        self
+    }
+
+    /// JSON lines of `T`, one per line
+    fn json_lines<T: JsonSchema>(mut self, gen: &mut SchemaGenerator) -> Self {
+        self.response = (
+            "200",
+            json!({
+                "description": "One JSON object per line",
+                "content": { "application/x-ndjson": { "schema": schema::<T>(gen) } },
+            }),
+        );
+        self
+    }
+
+    fn operation(self) -> Value {
+        let mut responses = Map::new();
+        responses.insert(self.response.0.to_string(), self.response.1);
+        if !matches!(self.auth, Auth::Public) {
+            responses.insert(
+                "401".to_string(),
+                json!({ "description": "Missing or invalid bearer token" }),
+            );
+        }
+        if self.path.starts_with("/api") || self.path.starts_with("/admin") {
+            responses.insert(
+                "429".to_string(),
+                json!({
+                    "description": "Rate limited",
+                    "headers": {
+                        "Retry-After": {
+                            "description": "Seconds until the request may be retried",
+                            "schema": { "type": "integer" },
+                        }
+                    },
+                }),
+            );
+        }
+        responses.insert(
+            "default".to_string(),
+            json!({
+                "description": "Error",
+                "content": {
+                    "application/json": { "schema": { "$ref": "#/components/schemas/ErrorBody" } }
+                },
+            }),
+        );
+
+        let mut operation = Map::new();
+        operation.insert("summary".to_string(), json!(self.summary));
+        operation.insert("tags".to_string(), json!([self.tag]));
+        if let Auth::Admin(scope) = self.auth {
+            operation.insert(
+                "description".to_string(),
+                json!(format!("Requires the `{}` scope", scope)),
+            );
+            operation.insert("x-required-scope".to_string(), json!(scope));
+        }
+        let security = match self.auth {
+            Auth::Public => None,
+            Auth::User => Some("user"),
+            Auth::Admin(_) => Some("admin"),
+        };
+        if let Some(scheme) = security {
+            operation.insert("security".to_string(), json!([{ scheme: [] }]));
+        }
+        if !self.parameters.is_empty() {
+            operation.insert("parameters".to_string(), json!(self.parameters));
+        }
+        if let Some(request) = self.request {
+            operation.insert("requestBody".to_string(), request);
+        }
+        operation.insert("responses".to_string(), Value::Object(responses));
+        Value::Object(operation)
+    }
+}
+
+/// Reference to the schema of `T`, adding it and everything it uses to the components
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    schema_value(gen.subschema_for::<T>())
+}
+
+fn schema_value(schema: Schema) -> Value {
+    serde_json::to_value(schema).unwrap_or_default()
+}
+
+/// Every route the server serves, see [`document`]
+fn routes(gen: &mut SchemaGenerator) -> Vec<Route> {
+    use Auth::*;
+    use Scope::*;
+
+    let load_state = json!({
+        "oneOf": [schema::<LoadState>(gen), schema::<PartialLoadState>(gen)],
+        "description": "LoadState, or PartialLoadState when `include` is set",
+    });
+    let graphql_request = json!({
+        "type": "object",
+        "required": ["query"],
+        "properties": {
+            "query": { "type": "string" },
+            "operationName": { "type": "string", "nullable": true },
+            "variables": { "type": "object", "nullable": true },
+        },
+    });
+    let graphql_response = json!({
+        "type": "object",
+        "properties": {
+            "data": { "type": "object", "nullable": true },
+            "errors": { "type": "array", "items": { "type": "object" } },
+        },
+    });
+
+    vec![
+        Route::get("/", "Health check", Public).text("Welcome message"),
+        // public
+        Route::get(
+            "/api/public/load_state",
+            "Every section of the free state",
+            Public,
+        )
+        .query::<LoadStateQuery>(gen)
+        .json_schema(load_state.clone()),
+        Route::get(
+            "/api/public/sync",
+            "Content changed since a sync token, premium articles have no body",
+            Public,
+        )
+        .query::<SyncQuery>(gen)
+        .json::<SyncResponse>(gen),
+        Route::get("/openapi.json", "This document", Public)
+            .json_schema(json!({ "type": "object" })),
+        // api
+        Route::post("/api/load_state", "Every section of the user's state", User)
+            .query::<LoadStateQuery>(gen)
+            .json_schema(load_state),
+        Route::get("/api/sync", "Content changed since a sync token", User)
+            .query::<SyncQuery>(gen)
+            .json::<SyncResponse>(gen),
+        Route::post(
+            "/api/graphql",
+            "GraphQL query over content and the user's account",
+            User,
+        )
+        .request(json!({
+            "required": true,
+            "content": { "application/json": { "schema": graphql_request } },
+        }))
+        .json_schema(graphql_response),
+        Route::get("/api/graphql/schema", "GraphQL schema in SDL", User).text("GraphQL SDL"),
+        Route::get("/api/articles", "Every article", User).json::<Vec<Article>>(gen),
+        Route::get("/api/calibrations", "Every calibration", User).json::<Vec<Calibration>>(gen),
+        Route::get("/api/testimonials", "Every testimonial", User).json::<Vec<Testimonial>>(gen),
+        Route::get("/api/content_type_images", "Content type image URLs", User)
+            .json::<Vec<String>>(gen),
+        Route::get("/api/category_images", "Category image URLs", User).json::<Vec<String>>(gen),
+        Route::get("/api/testimonial_images", "Testimonial image URLs", User)
+            .json::<Vec<String>>(gen),
+        Route::get(
+            "/api/image_info/{content_type}",
+            "Dimensions, blurhash and variants of an image list",
+            User,
+        )
+        .path_param("content_type", "Image list, e.g. testimonial_images")
+        .json::<Vec<ImageInfo>>(gen),
+        Route::post(
+            "/api/subscribe",
+            "Create a subscription checkout link",
+            User,
+        )
+        .json::<CheckoutInfo>(gen),
+        Route::post(
+            "/api/user_profile",
+            "Square customer and subscription of the user",
+            User,
+        )
+        .json::<UserProfile>(gen),
+        Route::post(
+            "/api/cancel_subscription",
+            "Cancel the user's subscription",
+            User,
+        )
+        .json::<CanceledSubscriptionInfo>(gen),
+        // admin
+        Route::get("/admin/assets", "List stored assets", Admin(AssetsRead))
+            .query::<AssetsQuery>(gen)
+            .json::<Vec<Asset>>(gen),
+        Route::new(
+            "put",
+            "/admin/assets/{key}",
+            "Create or overwrite an asset with the request body",
+            Admin(AssetsWrite),
+        )
+        .path_param("key", "Object key, may contain slashes")
+        .binary_body()
+        .json::<Asset>(gen),
+        Route::new(
+            "delete",
+            "/admin/assets/{key}",
+            "Delete an asset",
+            Admin(AssetsWrite),
+        )
+        .path_param("key", "Object key, may contain slashes"),
+        Route::get("/admin/catalogs", "Subscription plans", Admin(BillingRead))
+            .json::<Vec<Plan>>(gen),
+        Route::get(
+            "/admin/customers",
+            "The 10 newest customers",
+            Admin(BillingRead),
+        )
+        .json::<Vec<Customer>>(gen),
+        Route::get(
+            "/admin/email_list",
+            "Name and email of every customer",
+            Admin(BillingRead),
+        )
+        .json::<Vec<CustomerEmailInfo>>(gen),
+        Route::get("/admin/invoices", "Invoices", Admin(BillingRead)).json::<Vec<Invoice>>(gen),
+        Route::get(
+            "/admin/orders",
+            "Orders of the 10 newest customers",
+            Admin(BillingRead),
+        )
+        .json::<Vec<Order>>(gen),
+        Route::get(
+            "/admin/subscriptions",
+            "Every subscription",
+            Admin(BillingRead),
+        )
+        .json::<Vec<Subscription>>(gen),
+        Route::get(
+            "/admin/upsert_subscription_catalog",
+            "Create or update the subscription plan",
+            Admin(BillingWrite),
+        )
+        .json::<Plan>(gen),
+        Route::get(
+            "/admin/keys",
+            "Every admin key ever issued",
+            Admin(KeysManage),
+        )
+        .json::<Vec<AdminKey>>(gen),
+        Route::post("/admin/keys", "Issue an admin key", Admin(KeysManage))
+            .json_body::<CreateKeyRequest>(gen)
+            .json::<CreatedKey>(gen)
+            .status("201"),
+        Route::new(
+            "delete",
+            "/admin/keys/{id}",
+            "Revoke an admin key",
+            Admin(KeysManage),
+        )
+        .path_param("id", "Key id")
+        .json::<AdminKey>(gen),
+        Route::get(
+            "/admin/audit",
+            "Most recent audit entries",
+            Admin(AuditRead),
+        )
+        .query::<AuditQuery>(gen)
+        .json::<Vec<AuditEntry>>(gen),
+        Route::get(
+            "/admin/audit/export",
+            "Every matching audit entry as JSON lines",
+            Admin(AuditRead),
+        )
+        .query::<AuditQuery>(gen)
+        .json_lines::<AuditEntry>(gen),
+    ]
+}
+
+/// OpenAPI 3 document of every route, with schemas generated from the types they read and write
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let routes = routes(&mut gen);
+    // the error body is referenced by every route
+    let _ = schema::<ErrorBody>(&mut gen);
+
+    let mut paths = Map::new();
+    for route in routes.into_iter() {
+        let item = paths
+            .entry(route.path.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(item) = item {
+            item.insert(route.method.to_string(), route.operation());
+        }
+    }
+    let schemas = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, schema_value(schema)))
+        .collect::<Map<String, Value>>();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Consciousness Archive",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "tags": [
+            { "name": "public", "description": "No authentication" },
+            { "name": "api", "description": "Requires a user's JWT" },
+            { "name": "admin", "description": "Requires an admin API key or JWT with the route's scope" },
+        ],
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "user": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+                "admin": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::{BTreeSet, HashMap};
+
+    const MAIN: &str = include_str!("main.rs");
+
+    /// Index just past the parenthesis closing the one at `open`
+    fn closing_paren(source: &str, open: usize) -> usize {
+        let mut depth = 0;
+        for (i, c) in source[open..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 1 => return open + i + 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+        source.len()
+    }
+
+    /// `method path` of every handler registered on the app in main.rs, from the
+    /// `#[get("...")]` style attribute of each handler and the scope it is a service of.
+    /// The `/test_*` scopes only exist for offline work and aren't documented.
+    fn registered_routes() -> BTreeSet<String> {
+        let attribute =
+            Regex::new(r#"#\[(get|post|put|delete)\("([^"]*)"[^\]]*\]\s*(?:pub )?async fn (\w+)"#)
+                .unwrap();
+        let handlers = attribute
+            .captures_iter(MAIN)
+            .map(|c| (c[3].to_string(), (c[1].to_string(), c[2].to_string())))
+            .collect::<HashMap<String, (String, String)>>();
+        // `{key:.*}` matches like the OpenAPI `{key}`
+        let param = Regex::new(r"\{(\w+):[^}]*\}").unwrap();
+        let service = Regex::new(r"\.service\((\w+)\)").unwrap();
+
+        let app_start = MAIN.find("App::new()").expect("main.rs builds an App");
+        let app_end = app_start + MAIN[app_start..].find(".bind(").unwrap();
+        let app = &MAIN[app_start..app_end];
+        let mut scopes = vec![(String::new(), app.to_string())];
+        for (start, _) in app.match_indices("web::scope(\"") {
+            let prefix_start = start + "web::scope(\"".len();
+            let prefix = &app[prefix_start..prefix_start + app[prefix_start..].find('"').unwrap()];
+            let open = app[..start].rfind('(').unwrap();
+            let scope = app[open..closing_paren(app, open)].to_string();
+            scopes[0].1 = scopes[0].1.replacen(&scope, "", 1);
+            scopes.push((prefix.to_string(), scope));
+        }
+
+        let mut routes = BTreeSet::new();
+        for (prefix, source) in scopes.iter() {
+            if prefix.starts_with("/test_") {
+                continue;
+            }
+            for c in service.captures_iter(source) {
+                let (method, path) = handlers
+                    .get(&c[1])
+                    .unwrap_or_else(|| panic!("no route attribute on handler {}", &c[1]));
+                let path = param.replace_all(path, "{$1}");
+                routes.insert(format!("{} {}{}", method, prefix, path));
+            }
+        }
+        routes
+    }
+
+    fn documented_routes() -> BTreeSet<String> {
+        let mut routes = BTreeSet::new();
+        for (path, item) in document()["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                routes.insert(format!("{} {}", method, path));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn documents_every_registered_route() {
+        let registered = registered_routes();
+        assert!(registered.contains("get /api/sync"));
+        assert!(registered.contains("put /admin/assets/{key}"));
+        assert_eq!(documented_routes(), registered);
+    }
+}
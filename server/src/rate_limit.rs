@@ -0,0 +1,395 @@
+use crate::admin_auth::Admin;
+use crate::config::RateLimitConfig;
+use crate::errors::ServiceError;
+use crate::oauth::Identity;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error as ActixError, HttpMessage};
+use futures::future::{ready, Either, MapOk, Ready};
+use futures::TryFutureExt;
+use log::*;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often buckets that have refilled completely are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Group of routes sharing limits, declared on a scope or route with `.wrap(RouteClass::...)`
+/// or `wrap = "RouteClass::..."`. A request counts against every class it passes through.
+///
+/// Around auth middleware, wrap [`RouteClass::per_user`] inside it and [`RouteClass::per_ip`]
+/// outside, so requests that fail authentication are still limited by IP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    /// `/api/public`
+    Public,
+    /// `/api`
+    Api,
+    /// Routes that call Square, limited tighter since every call spends Square quota
+    Square,
+    /// `/admin`
+    Admin,
+}
+
+impl RouteClass {
+    pub const ALL: [RouteClass; 4] = [
+        RouteClass::Public,
+        RouteClass::Api,
+        RouteClass::Square,
+        RouteClass::Admin,
+    ];
+
+    /// Only the per IP limit of this class
+    pub fn per_ip(self) -> RateLimit {
+        RateLimit {
+            class: self,
+            ip: true,
+            user: false,
+        }
+    }
+
+    /// Only the per user limit of this class, which needs the user authenticated first
+    pub fn per_user(self) -> RateLimit {
+        RateLimit {
+            class: self,
+            ip: false,
+            user: true,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteClass::Public => "public",
+            RouteClass::Api => "api",
+            RouteClass::Square => "square",
+            RouteClass::Admin => "admin",
+        }
+    }
+
+    /// Per IP and per user limits when not configured
+    pub fn default_limits(&self) -> Limits {
+        let per_minute = |requests| Some(Rate::new(requests, Duration::from_secs(60)));
+        match self {
+            RouteClass::Public => Limits {
+                ip: per_minute(120),
+                user: None,
+            },
+            RouteClass::Api => Limits {
+                ip: per_minute(600),
+                user: per_minute(120),
+            },
+            RouteClass::Square => Limits {
+                ip: per_minute(60),
+                user: per_minute(10),
+            },
+            RouteClass::Admin => Limits {
+                ip: per_minute(120),
+                user: per_minute(120),
+            },
+        }
+    }
+}
+
+impl Display for RouteClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Token bucket refill rate: `requests` per `per`, with bursts of up to `requests`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    pub fn new(requests: u32, per: Duration) -> Self {
+        Self { requests, per }
+    }
+
+    /// `<requests>/<seconds>`, e.g. `10/60`, or `off` for no limit
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        if value == "off" {
+            return Ok(None);
+        }
+        let invalid = || format!("{} is not <requests>/<seconds> or off", value);
+        let (requests, seconds) = value.split_once('/').ok_or_else(invalid)?;
+        let requests = requests.trim().parse::<u32>().map_err(|_| invalid())?;
+        let seconds = seconds.trim().parse::<u64>().map_err(|_| invalid())?;
+        if requests == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(Some(Rate::new(requests, Duration::from_secs(seconds))))
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        self.requests as f64 / self.per.as_secs_f64()
+    }
+}
+
+impl Display for Rate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}s", self.requests, self.per.as_secs())
+    }
+}
+
+/// Limits of one [`RouteClass`], `None` for no limit
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub ip: Option<Rate>,
+    /// Authenticated users and admins, by JWT subject or admin key
+    pub user: Option<Rate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Ip(IpAddr),
+    User(String),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Tokens after refilling at `rate` since the last update, at most a full burst
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.tokens_per_sec()).min(rate.requests as f64);
+        self.updated = now;
+    }
+}
+
+struct Buckets {
+    buckets: HashMap<(RouteClass, Client), Bucket>,
+    last_sweep: Instant,
+}
+
+/// Token buckets of every client, shared by every request.
+///
+/// Each request takes a token from the bucket of its IP and, once authenticated, of its user,
+/// for each [`RouteClass`] it belongs to. If any of them is empty nothing is taken and the
+/// request is rejected with `429` and a `Retry-After` of when the emptiest one has a token again.
+pub struct RateLimiter {
+    limits: HashMap<RouteClass, Limits>,
+    /// Use the last `X-Forwarded-For` hop as the client IP, as appended by a reverse proxy
+    trust_proxy: bool,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    fn new(limits: HashMap<RouteClass, Limits>, trust_proxy: bool) -> Self {
+        Self {
+            limits,
+            trust_proxy,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// The limits of `rate_limit`, the defaults of each class unless configured
+    pub fn from_config(rate_limit: &RateLimitConfig) -> Self {
+        for class in RouteClass::ALL {
+            let limits = rate_limit.limits[&class];
+            let show = |rate: Option<Rate>| rate.map_or("off".to_string(), |rate| rate.to_string());
+            info!(
+                "Rate limit of {} routes: {} per IP, {} per user",
+                class,
+                show(limits.ip),
+                show(limits.user)
+            );
+        }
+        Self::new(rate_limit.limits.clone(), rate_limit.trust_proxy)
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_proxy
+            .then(|| req.headers().get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        forwarded.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+    }
+
+    /// Take a token for `limit.class` from each of the request's buckets `limit` applies, or
+    /// the time until that is possible
+    fn check(&self, limit: RateLimit, req: &ServiceRequest) -> Result<(), Duration> {
+        let class = limit.class;
+        let limits = match self.limits.get(&class) {
+            Some(limits) => *limits,
+            None => return Ok(()),
+        };
+        let user = {
+            let extensions = req.extensions();
+            extensions
+                .get::<Identity>()
+                .map(|identity| identity.subject.clone())
+                .or_else(|| extensions.get::<Admin>().map(|admin| admin.actor.clone()))
+        };
+        let mut keys = Vec::with_capacity(2);
+        if let (true, Some(rate), Some(ip)) = (limit.ip, limits.ip, self.client_ip(req)) {
+            keys.push((Client::Ip(ip), rate));
+        }
+        if let (true, Some(rate), Some(user)) = (limit.user, limits.user, user) {
+            keys.push((Client::User(user), rate));
+        }
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            // a panic mid update leaves at worst one stale bucket
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if now.saturating_duration_since(buckets.last_sweep) >= SWEEP_INTERVAL {
+            self.sweep(&mut buckets, now);
+        }
+        let mut wait = Duration::ZERO;
+        for (client, rate) in keys.iter() {
+            let bucket = buckets
+                .buckets
+                .entry((class, client.clone()))
+                .or_insert_with(|| Bucket {
+                    tokens: rate.requests as f64,
+                    updated: now,
+                });
+            bucket.refill(*rate, now);
+            if bucket.tokens < 1.0 {
+                let secs = (1.0 - bucket.tokens) / rate.tokens_per_sec();
+                wait = wait.max(Duration::from_secs_f64(secs));
+            }
+        }
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+        for (client, _) in keys.iter() {
+            if let Some(bucket) = buckets.buckets.get_mut(&(class, client.clone())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the buckets that are full again, a new one starts full anyway
+    fn sweep(&self, buckets: &mut Buckets, now: Instant) {
+        let limits = &self.limits;
+        buckets.buckets.retain(|(class, client), bucket| {
+            let rate = limits.get(class).and_then(|limits| match client {
+                Client::Ip(_) => limits.ip,
+                Client::User(_) => limits.user,
+            });
+            match rate {
+                Some(rate) => {
+                    bucket.refill(rate, now);
+                    bucket.tokens < rate.requests as f64
+                }
+                None => false,
+            }
+        });
+        buckets.last_sweep = now;
+    }
+}
+
+/// Which limits of a class a middleware applies, see [`RouteClass::per_ip`]
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    class: RouteClass,
+    ip: bool,
+    user: bool,
+}
+
+/// Rejects requests over the per IP or per user limits of this class with `429`
+impl<S, B> Transform<S, ServiceRequest> for RouteClass
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = ActixError;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let limit = RateLimit {
+            class: *self,
+            ip: true,
+            user: true,
+        };
+        limit.new_transform(service)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = ActixError;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service,
+            limit: *self,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limit: RateLimit,
+}
+
+type Allowed<B> = fn(ServiceResponse<B>) -> ServiceResponse<EitherBody<B>>;
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = ActixError;
+    type Future = Either<MapOk<S::Future, Allowed<B>>, Ready<Result<Self::Response, Self::Error>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let checked = match req.app_data::<web::Data<RateLimiter>>() {
+            Some(limiter) => limiter.check(self.limit, &req),
+            None => Ok(()),
+        };
+        match checked {
+            Ok(()) => {
+                let allowed: Allowed<B> = ServiceResponse::map_into_left_body;
+                Either::Left(self.service.call(req).map_ok(allowed))
+            }
+            Err(wait) => {
+                let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                info!(
+                    "Rate limited {} request to {} for {}s",
+                    self.limit.class,
+                    req.path(),
+                    retry_after
+                );
+                // a response rather than an error, so middleware outside sees its status
+                let error = ServiceError::RateLimited(retry_after);
+                Either::Right(ready(Ok(req.error_response(error).map_into_right_body())))
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::SquareConfig;
+use crate::errors::ServiceError;
+use crate::*;
+use async_trait::async_trait;
+use log::*;
+
+/// The Square API calls the server makes, answered by Square itself or by a fake of it.
+///
+/// Implementations only make the calls, the required methods. Every implementation is a
+/// [`PaymentProvider`], which turns the Square objects into the server's own.
+#[async_trait]
+pub trait SquareApi: Send + Sync {
+    /// Plan, location and checkout settings
+    fn config(&self) -> &SquareConfig;
+
+    // ============================ Customers ============================ //
+
+    /// Customers with exactly this email address, ignoring case
+    async fn search_customers(
+        &self,
+        email: &str,
+    ) -> Result<SquareResponse<SearchCustomerResponse>, ServiceError>;
+
+    async fn create_customer(
+        &self,
+        request: &CustomerRequest,
+    ) -> Result<SquareResponse<CustomerResponse>, ServiceError>;
+
+    async fn update_customer(
+        &self,
+        customer_id: &str,
+        request: &CustomerRequest,
+    ) -> Result<SquareResponse<CustomerResponse>, ServiceError>;
+
+    /// The 10 newest customers. Provides customer name and card, but not email.
+    async fn list_customers(&self) -> Result<SquareResponse<CustomerListResponse>, ServiceError>;
+
+    // ============================ Catalog ============================ //
+
+    /// Create the subscription plan and its monthly variation at `subscription_price`.
+    ///
+    /// After creating a catalog, use `result.catalog_object.id`
+    ///
+    /// or use `result.catalog_object.subscription_plan_variation_data.subscription_plan_id`
+    ///
+    /// to set as the SQUARE_SUBSCRIPTION_CATALOG_ID in the env
+    async fn upsert_subscription_catalog(
+        &self,
+    ) -> Result<SquareResponse<SubscriptionPlanResponse>, ServiceError>;
+
+    /// Every subscription plan
+    async fn list_catalogs(&self) -> Result<SquareResponse<CatalogListResponse>, ServiceError>;
+
+    async fn list_locations(&self) -> Result<SquareResponse<LocationListResponse>, ServiceError>;
+
+    // ============================ Checkout ============================ //
+
+    async fn create_payment_link(
+        &self,
+        request: &CheckoutRequest,
+    ) -> Result<SquareResponse<CheckoutResponse>, ServiceError>;
+
+    // ============================ Subscriptions ============================ //
+
+    /// Subscriptions of this customer, or of everyone
+    async fn search_subscriptions(
+        &self,
+        customer_id: Option<&str>,
+    ) -> Result<SquareResponse<SubscriptionSearchResponse>, ServiceError>;
+
+    /// The subscription with its scheduled actions, e.g. a pending cancel
+    async fn retrieve_subscription(
+        &self,
+        subscription_id: &str,
+    ) -> Result<SquareResponse<SubscriptionResponse>, ServiceError>;
+
+    /// Cancel at the end of the paid period, `charged_through_date`
+    async fn cancel_subscription_by_id(
+        &self,
+        subscription_id: &str,
+    ) -> Result<SquareResponse<CancelSubscriptionResponse>, ServiceError>;
+
+    // ============================ Orders and invoices ============================ //
+
+    /// Orders of these customers at `location_id`
+    async fn search_orders(
+        &self,
+        customer_ids: Vec<String>,
+    ) -> Result<SquareResponse<SearchOrdersResponse>, ServiceError>;
+
+    /// Invoices at `location_id`
+    async fn list_invoices(&self) -> Result<SquareResponse<InvoiceListResponse>, ServiceError>;
+
+    // ============================ Cards ============================ //
+
+    /// Store a card on file for the customer of `request`
+    async fn create_card(
+        &self,
+        request: &CardRequest,
+    ) -> Result<SquareResponse<CardResponse>, ServiceError>;
+
+    /// The customer's enabled cards on file
+    async fn list_cards(
+        &self,
+        customer_id: &str,
+    ) -> Result<SquareResponse<CardListResponse>, ServiceError>;
+
+    /// Disabled cards can't be charged or enabled again
+    async fn disable_card(
+        &self,
+        card_id: &str,
+    ) -> Result<SquareResponse<CardResponse>, ServiceError>;
+
+    // ============================ Provided ============================ //
+
+    /// The first customer with this email, `None` if there is none or the search failed
+    async fn get_customer(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<CustomerResponse>, ServiceError> {
+        match self.search_customers(&request.email).await {
+            Ok(SquareResponse::Error(error)) => {
+                error!("Failed to get customer: {:?}", &error);
+                Ok(None)
+            }
+            Ok(SquareResponse::Success(res)) => Ok(res.customers.into_iter().next()),
+            Err(e) => {
+                error!("{:?}", &e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Update the customer with this email, or create them
+    async fn upsert_customer(
+        &self,
+        request: CustomerRequest,
+    ) -> Result<SquareResponse<CustomerResponse>, ServiceError> {
+        match self.search_customers(&request.email_address).await? {
+            SquareResponse::Error(error) => Ok(SquareResponse::Error(error)),
+            SquareResponse::Success(res) => match res.customers.into_iter().next() {
+                None => self.create_customer(&request).await,
+                Some(customer) => self.update_customer(&customer.id, &request).await,
+            },
+        }
+    }
+
+    /// The plan `subscription_catalog_id` names
+    async fn get_subscription_catalog(
+        &self,
+    ) -> Result<SquareResponse<CatalogResponseObject>, ServiceError> {
+        match self.list_catalogs().await? {
+            SquareResponse::Error(error) => Ok(SquareResponse::Error(error)),
+            SquareResponse::Success(catalog_list) => {
+                // catalog.id == catalog.catalog_object.subscription_plan_variation_data.subscription_plan_id
+                // which should match SQUARE_SUBSCRIPTION_CATALOG_ID in env
+                let catalog_id = &self.config().subscription_catalog_id;
+                let catalog = catalog_list
+                    .objects
+                    .into_iter()
+                    .find(|plan| &plan.id == catalog_id)
+                    .ok_or_else(|| {
+                        ServiceError::UpstreamError(format!(
+                            "Subscription catalog {} not found in Square",
+                            catalog_id
+                        ))
+                    })?;
+                Ok(SquareResponse::Success(catalog))
+            }
+        }
+    }
+
+    /// The location `location_id` names
+    async fn get_location(&self) -> Result<SquareResponse<LocationResponse>, ServiceError> {
+        match self.list_locations().await? {
+            SquareResponse::Error(error) => Ok(SquareResponse::Error(error)),
+            SquareResponse::Success(location_list) => {
+                let location_id = &self.config().location_id;
+                let location = location_list
+                    .locations
+                    .into_iter()
+                    .find(|location| &location.id == location_id)
+                    .ok_or_else(|| {
+                        ServiceError::UpstreamError(format!(
+                            "Location {} not found in Square",
+                            location_id
+                        ))
+                    })?;
+                Ok(SquareResponse::Success(location))
+            }
+        }
+    }
+
+    /// The first subscription of the customer with this email
+    async fn get_subscription(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<SubscriptionResponseObject>, ServiceError> {
+        let customer = match self.get_customer(request).await? {
+            Some(customer) => customer,
+            None => return Ok(None),
+        };
+        match self.search_subscriptions(Some(&customer.id)).await? {
+            SquareResponse::Error(_) => Ok(None),
+            SquareResponse::Success(list) => Ok(list.subscriptions.into_iter().next()),
+        }
+    }
+}
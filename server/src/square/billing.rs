@@ -0,0 +1,209 @@
+use crate::square::{
+    CardInfo, CatalogResponseObject, CustomerEmailInfo, CustomerResponse, Invoice as SquareInvoice,
+    LineItem, OrderObject, SubscriptionPlanResponseObject, SubscriptionResponseObject,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Billing records as the admin routes return them, whichever payment provider they came from.
+// Amounts are in cents.
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Customer {
+    pub id: String,
+    pub email_address: String,
+    pub given_name: String,
+    pub family_name: String,
+    pub created_at: String,
+    /// Cards on file
+    pub cards: Vec<CardInfo>,
+}
+
+/// A subscription plan with its monthly price
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Plan {
+    /// What `square.subscription_catalog_id` is set to
+    pub id: String,
+    pub name: String,
+    /// The variation checkout subscribes buyers to, `None` until one is created
+    pub variation_id: Option<String>,
+    pub price: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Subscription {
+    pub id: String,
+    pub customer_id: String,
+    /// The plan variation subscribed to
+    pub plan_variation_id: String,
+    /// e.g. `ACTIVE` or `CANCELED`
+    pub status: String,
+    pub start_date: String,
+    pub charged_through_date: Option<String>,
+    pub canceled_date: Option<String>,
+    /// When a pending cancel takes effect
+    pub cancel_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Order {
+    pub id: String,
+    pub customer_id: String,
+    /// e.g. `OPEN` or `COMPLETED`
+    pub state: String,
+    pub created_at: String,
+    pub items: Vec<OrderItem>,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OrderItem {
+    pub name: String,
+    /// e.g. a coaching package
+    pub variation_name: Option<String>,
+    pub quantity: u32,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Invoice {
+    pub id: String,
+    pub order_id: String,
+    /// `None` unless the invoice bills a subscription
+    pub subscription_id: Option<String>,
+    pub title: String,
+    /// e.g. `PAID` or `UNPAID`
+    pub status: String,
+    pub created_at: String,
+    pub recipient: CustomerEmailInfo,
+    /// Still to pay, `None` once paid
+    pub amount_due: Option<u64>,
+}
+
+impl From<CustomerResponse> for Customer {
+    fn from(customer: CustomerResponse) -> Self {
+        Customer {
+            id: customer.id,
+            email_address: customer.email_address,
+            given_name: customer.given_name,
+            family_name: customer.family_name,
+            created_at: customer.created_at,
+            cards: customer
+                .cards
+                .unwrap_or_default()
+                .into_iter()
+                .map(CardInfo::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<CatalogResponseObject> for Plan {
+    fn from(plan: CatalogResponseObject) -> Self {
+        let data = plan.subscription_plan_data;
+        let variation = data
+            .as_ref()
+            .and_then(|data| data.subscription_plan_variations.as_ref())
+            .and_then(|variations| variations.first());
+        Plan {
+            name: data
+                .as_ref()
+                .map(|data| data.name.clone())
+                .unwrap_or_default(),
+            variation_id: variation.map(|variation| variation.id.clone()),
+            price: variation.and_then(monthly_price),
+            id: plan.id,
+        }
+    }
+}
+
+impl From<SubscriptionPlanResponseObject> for Plan {
+    /// The plan of a variation, named after the variation
+    fn from(variation: SubscriptionPlanResponseObject) -> Self {
+        Plan {
+            price: monthly_price(&variation),
+            variation_id: Some(variation.id),
+            id: variation
+                .subscription_plan_variation_data
+                .subscription_plan_id,
+            name: variation.subscription_plan_variation_data.name,
+        }
+    }
+}
+
+fn monthly_price(variation: &SubscriptionPlanResponseObject) -> Option<u64> {
+    let pricing = &variation
+        .subscription_plan_variation_data
+        .phases
+        .first()?
+        .pricing;
+    pricing
+        .price_money
+        .as_ref()
+        .or(pricing.price.as_ref())
+        .map(|price| price.amount)
+}
+
+impl From<SubscriptionResponseObject> for Subscription {
+    fn from(subscription: SubscriptionResponseObject) -> Self {
+        let cancel_date = subscription
+            .actions
+            .iter()
+            .flatten()
+            .find(|action| action.type_ == "CANCEL")
+            .map(|action| action.effective_date.clone());
+        Subscription {
+            id: subscription.id,
+            customer_id: subscription.customer_id,
+            plan_variation_id: subscription.plan_variation_id,
+            status: subscription.status,
+            start_date: subscription.start_date,
+            charged_through_date: subscription.charged_through_date,
+            canceled_date: subscription.canceled_date,
+            cancel_date,
+        }
+    }
+}
+
+impl From<OrderObject> for Order {
+    fn from(order: OrderObject) -> Self {
+        Order {
+            id: order.id,
+            customer_id: order.customer_id,
+            state: order.state,
+            created_at: order.created_at,
+            items: order.line_items.into_iter().map(OrderItem::from).collect(),
+            total: order.total_money.amount,
+        }
+    }
+}
+
+impl From<LineItem> for OrderItem {
+    fn from(item: LineItem) -> Self {
+        OrderItem {
+            name: item.name,
+            variation_name: item.variation_name,
+            quantity: item.quantity.parse().unwrap_or(1),
+            total: item.total_money.amount,
+        }
+    }
+}
+
+impl From<SquareInvoice> for Invoice {
+    fn from(invoice: SquareInvoice) -> Self {
+        Invoice {
+            id: invoice.id,
+            order_id: invoice.order_id,
+            subscription_id: invoice.subscription_id,
+            title: invoice.title,
+            status: invoice.status,
+            created_at: invoice.created_at,
+            recipient: CustomerEmailInfo {
+                email_address: invoice.primary_recipient.email_address,
+                family_name: invoice.primary_recipient.family_name,
+                given_name: invoice.primary_recipient.given_name,
+            },
+            amount_due: invoice.next_payment_amount_money.map(|price| price.amount),
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use super::billing::{Customer, Order, Subscription};
+use super::*;
+use crate::config::SquareConfig;
+use crate::errors::ServiceError;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, MutexGuard};
+
+const CHECKOUT_URL: &str = "https://sandbox.square.link/u/";
+const CUSTOMERS_PAGE: usize = 10;
+
+/// [`SquareApi`] keeping a [`Merchant`] in memory, for tests and offline work.
+///
+/// It starts with the location and subscription plan of `config`, creating the plan if
+/// `subscription_catalog_id` is empty, and bills subscriptions as their months start. Buyers
+/// paying happens outside the API, so [`Self::complete_checkout`] and
+/// [`Self::buy_coaching_package`] stand in for them, served under `/test_square`.
+pub struct InMemorySquare {
+    config: SquareConfig,
+    merchant: Mutex<Merchant>,
+}
+
+/// A buyer paying a payment link, `POST /test_square/checkout`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckoutPayment {
+    /// The link's id or URL, e.g. the `url` `/api/subscribe` answers with
+    pub payment_link: String,
+    pub email: String,
+    #[serde(default)]
+    pub given_name: String,
+    #[serde(default)]
+    pub family_name: String,
+}
+
+/// A payment link paid by [`InMemorySquare::complete_checkout`]
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletedCheckout {
+    pub customer: Customer,
+    pub order_id: String,
+    /// `None` unless the link was for a subscription plan
+    pub subscription: Option<Subscription>,
+}
+
+/// A coaching package sale, `POST /test_square/coaching`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CoachingPurchase {
+    pub email: String,
+    pub package: CoachingPackage,
+    /// 1 by default
+    #[serde(default = "one")]
+    pub quantity: u32,
+    /// Cents per package
+    pub price: u64,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl InMemorySquare {
+    pub fn new(config: &SquareConfig) -> Self {
+        let mut config = config.clone();
+        let mut merchant = Merchant::new(&config.location_id);
+        let plan = merchant.create_plan(
+            Some(config.subscription_catalog_id.clone()).filter(|id| !id.is_empty()),
+            &config.subscription_name,
+            config.subscription_price,
+        );
+        config.subscription_catalog_id = plan.subscription_plan_variation_data.subscription_plan_id;
+        info!(
+            "In-memory subscription catalog ID: {}",
+            config.subscription_catalog_id
+        );
+        Self {
+            config,
+            merchant: Mutex::new(merchant),
+        }
+    }
+
+    /// The merchant, with the subscriptions due renewed
+    fn merchant(&self) -> MutexGuard<Merchant> {
+        let mut merchant = match self.merchant.lock() {
+            Ok(merchant) => merchant,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        merchant.renew_subscriptions(Utc::now().date_naive());
+        merchant
+    }
+
+    /// The buyer paying a payment link with a sandbox card. Finds or creates the customer with
+    /// the buyer's email, stores their card, completes the link's order and, for a subscription
+    /// plan, starts the subscription with its first invoice paid.
+    pub fn complete_checkout(
+        &self,
+        payment: &CheckoutPayment,
+    ) -> Result<CompletedCheckout, ServiceError> {
+        let buyer = CustomerRequest {
+            email_address: payment.email.clone(),
+            family_name: payment.family_name.clone(),
+            given_name: payment.given_name.clone(),
+            address: Address::default(),
+        };
+        let payment_link_id = &payment.payment_link;
+        let mut merchant = self.merchant();
+        let link = merchant.payment_link(payment_link_id).ok_or_else(|| {
+            ServiceError::NotFound(format!("No payment link {}", payment_link_id))
+        })?;
+        if merchant
+            .order(&link.order_id)
+            .map_or(true, |order| order.state != "OPEN")
+        {
+            return Err(ServiceError::BadRequest(format!(
+                "Payment link {} was already paid",
+                payment_link_id
+            )));
+        }
+
+        let paid = merchant.complete_checkout(payment_link_id, &buyer, CARD_NONCE)?;
+        Ok(CompletedCheckout {
+            customer: paid.customer.into(),
+            order_id: paid.order.id,
+            subscription: paid.subscription.map(Subscription::from),
+        })
+    }
+
+    /// A completed order of `quantity` coaching packages at `price` cents each, as a Square POS
+    /// or invoice sale would leave it
+    pub fn buy_coaching_package(&self, purchase: &CoachingPurchase) -> Result<Order, ServiceError> {
+        let mut merchant = self.merchant();
+        let customer = merchant.find_customer(&purchase.email).ok_or_else(|| {
+            ServiceError::NotFound(format!("No customer with email {}", purchase.email))
+        })?;
+        let order = merchant.record_sale(
+            &self.config.location_id,
+            &customer.id,
+            "Coaching",
+            Some(&purchase.package.name()),
+            purchase.price,
+            purchase.quantity as u64,
+        )?;
+        Ok(order.into())
+    }
+}
+
+#[async_trait]
+impl SquareApi for InMemorySquare {
+    fn config(&self) -> &SquareConfig {
+        &self.config
+    }
+
+    async fn search_customers(
+        &self,
+        email: &str,
+    ) -> Result<SquareResponse<SearchCustomerResponse>, ServiceError> {
+        let customers = self.merchant().search_customers(Some(email));
+        Ok(SquareResponse::Success(SearchCustomerResponse {
+            customers,
+        }))
+    }
+
+    async fn create_customer(
+        &self,
+        request: &CustomerRequest,
+    ) -> Result<SquareResponse<CustomerResponse>, ServiceError> {
+        Ok(self.merchant().create_customer(request).into())
+    }
+
+    async fn update_customer(
+        &self,
+        customer_id: &str,
+        request: &CustomerRequest,
+    ) -> Result<SquareResponse<CustomerResponse>, ServiceError> {
+        Ok(self.merchant().update_customer(customer_id, request).into())
+    }
+
+    async fn list_customers(&self) -> Result<SquareResponse<CustomerListResponse>, ServiceError> {
+        let mut customers = self.merchant().search_customers(None);
+        customers.reverse();
+        customers.truncate(CUSTOMERS_PAGE);
+        Ok(SquareResponse::Success(CustomerListResponse { customers }))
+    }
+
+    async fn upsert_subscription_catalog(
+        &self,
+    ) -> Result<SquareResponse<SubscriptionPlanResponse>, ServiceError> {
+        let variation = self.merchant().create_plan(
+            None,
+            &self.config.subscription_name,
+            self.config.subscription_price,
+        );
+        info!(
+            "Subscription catalog ID: {}",
+            &variation
+                .subscription_plan_variation_data
+                .subscription_plan_id
+        );
+        Ok(SquareResponse::Success(SubscriptionPlanResponse {
+            id_mappings: vec![IdMapping {
+                client_object_id: "#plan".to_string(),
+                object_id: variation.id.clone(),
+            }],
+            catalog_object: variation,
+        }))
+    }
+
+    async fn list_catalogs(&self) -> Result<SquareResponse<CatalogListResponse>, ServiceError> {
+        let objects = self
+            .merchant()
+            .catalog
+            .iter()
+            .filter(|object| object.type_ == "SUBSCRIPTION_PLAN")
+            .cloned()
+            .collect();
+        Ok(SquareResponse::Success(CatalogListResponse { objects }))
+    }
+
+    async fn list_locations(&self) -> Result<SquareResponse<LocationListResponse>, ServiceError> {
+        let locations = self.merchant().locations.clone();
+        Ok(SquareResponse::Success(LocationListResponse { locations }))
+    }
+
+    async fn create_payment_link(
+        &self,
+        request: &CheckoutRequest,
+    ) -> Result<SquareResponse<CheckoutResponse>, ServiceError> {
+        Ok(self
+            .merchant()
+            .create_payment_link(request, CHECKOUT_URL)
+            .into())
+    }
+
+    async fn search_subscriptions(
+        &self,
+        customer_id: Option<&str>,
+    ) -> Result<SquareResponse<SubscriptionSearchResponse>, ServiceError> {
+        let customer_ids = customer_id.map(|id| vec![id.to_string()]);
+        let subscriptions = self
+            .merchant()
+            .search_subscriptions(customer_ids.as_deref(), None);
+        Ok(SquareResponse::Success(SubscriptionSearchResponse {
+            subscriptions,
+        }))
+    }
+
+    async fn retrieve_subscription(
+        &self,
+        subscription_id: &str,
+    ) -> Result<SquareResponse<SubscriptionResponse>, ServiceError> {
+        match self.merchant().subscription(subscription_id) {
+            Some(sub) => Ok(SquareResponse::Success(SubscriptionResponse {
+                subscription: sub.clone(),
+            })),
+            None => Ok(SquareResponse::Error(SquareErrorResponse::not_found(
+                format!("Subscription {} not found", subscription_id),
+            ))),
+        }
+    }
+
+    async fn cancel_subscription_by_id(
+        &self,
+        subscription_id: &str,
+    ) -> Result<SquareResponse<CancelSubscriptionResponse>, ServiceError> {
+        Ok(self.merchant().cancel_subscription(subscription_id).into())
+    }
+
+    async fn search_orders(
+        &self,
+        customer_ids: Vec<String>,
+    ) -> Result<SquareResponse<SearchOrdersResponse>, ServiceError> {
+        let orders = self
+            .merchant()
+            .search_orders(&[self.config.location_id.clone()], Some(&customer_ids));
+        Ok(SquareResponse::Success(SearchOrdersResponse { orders }))
+    }
+
+    async fn list_invoices(&self) -> Result<SquareResponse<InvoiceListResponse>, ServiceError> {
+        let invoices = self
+            .merchant()
+            .invoices
+            .iter()
+            .filter(|invoice| invoice.location_id == self.config.location_id)
+            .cloned()
+            .collect();
+        Ok(SquareResponse::Success(InvoiceListResponse {
+            invoices,
+            cursor: None,
+        }))
+    }
+
+    async fn create_card(
+        &self,
+        request: &CardRequest,
+    ) -> Result<SquareResponse<CardResponse>, ServiceError> {
+        let card = self.merchant().create_card(request);
+        Ok(card.map(|card| CardResponse { card }).into())
+    }
+
+    async fn list_cards(
+        &self,
+        customer_id: &str,
+    ) -> Result<SquareResponse<CardListResponse>, ServiceError> {
+        let cards = self.merchant().list_cards(Some(customer_id), false);
+        Ok(SquareResponse::Success(CardListResponse { cards }))
+    }
+
+    async fn disable_card(
+        &self,
+        card_id: &str,
+    ) -> Result<SquareResponse<CardResponse>, ServiceError> {
+        let card = self.merchant().disable_card(card_id);
+        Ok(card.map(|card| CardResponse { card }).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PaymentBackend;
+
+    const EMAIL: &str = "alice@example.com";
+
+    fn square() -> InMemorySquare {
+        InMemorySquare::new(&SquareConfig {
+            backend: PaymentBackend::InMemory,
+            api_url: String::new(),
+            access_token: String::new(),
+            api_version: String::new(),
+            app_id: String::new(),
+            location_id: "LOCALSQUARE".to_string(),
+            subscription_catalog_id: String::new(),
+            subscription_price: 1100,
+            subscription_name: "Premium".to_string(),
+            redirect_url: "http://localhost:3000/".to_string(),
+        })
+    }
+
+    fn user() -> UserEmailRequest {
+        UserEmailRequest {
+            email: EMAIL.to_string(),
+        }
+    }
+
+    fn payment(payment_link: &str) -> CheckoutPayment {
+        CheckoutPayment {
+            payment_link: payment_link.to_string(),
+            email: EMAIL.to_string(),
+            given_name: "Alice".to_string(),
+            family_name: "Liddell".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_profile_cancel() {
+        let square = square();
+        let profile = square.get_user_profile(user()).await.unwrap();
+        assert!(profile.customer.is_none());
+        assert!(profile.user_subscription.is_none());
+        assert_eq!(profile.subscription_info.unwrap().cost, 11.0);
+
+        let checkout = square.subscribe_checkout(Some(user())).await.unwrap();
+        assert_eq!(checkout.amount, 11.0);
+        let completed = square.complete_checkout(&payment(&checkout.url)).unwrap();
+        let subscription = completed.subscription.unwrap();
+        assert_eq!(subscription.status, "ACTIVE");
+        assert_eq!(completed.customer.email_address, EMAIL);
+
+        let profile = square.get_user_profile(user()).await.unwrap();
+        let customer = profile.customer.unwrap();
+        assert_eq!(customer.given_name, "Alice");
+        assert_eq!(customer.cards.unwrap()[0].last_4, "1111");
+        let user_subscription = profile.user_subscription.unwrap();
+        assert_eq!(user_subscription.start_date, subscription.start_date);
+        assert_eq!(user_subscription.canceled_date, None);
+        let charged_through_date = user_subscription.charged_through_date.unwrap();
+
+        let canceled = square.cancel_subscription(user()).await.unwrap();
+        assert_eq!(
+            format!(
+                "{:04}-{:02}-{:02}",
+                canceled.charged_through_year,
+                canceled.charged_through_month,
+                canceled.charged_through_day
+            ),
+            charged_through_date
+        );
+        let profile = square.get_user_profile(user()).await.unwrap();
+        let user_subscription = profile.user_subscription.unwrap();
+        assert_eq!(
+            user_subscription.canceled_date,
+            Some(charged_through_date.clone())
+        );
+        let subscriptions = square.subscriptions().await.unwrap();
+        assert_eq!(subscriptions[0].status, "ACTIVE");
+        assert_eq!(subscriptions[0].cancel_date, Some(charged_through_date));
+
+        assert!(square.cancel_subscription(user()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn checkout_is_paid_once() {
+        let square = square();
+        let checkout = square.subscribe_checkout(Some(user())).await.unwrap();
+        square.complete_checkout(&payment(&checkout.url)).unwrap();
+        assert!(matches!(
+            square.complete_checkout(&payment(&checkout.url)),
+            Err(ServiceError::BadRequest(_))
+        ));
+        assert!(matches!(
+            square.complete_checkout(&payment("no-such-link")),
+            Err(ServiceError::NotFound(_))
+        ));
+        assert!(matches!(
+            square
+                .cancel_subscription(UserEmailRequest {
+                    email: "bob@example.com".to_string(),
+                })
+                .await,
+            Err(ServiceError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn coaching_sessions() {
+        let square = square();
+        let purchase = CoachingPurchase {
+            email: EMAIL.to_string(),
+            package: CoachingPackage::Three,
+            quantity: 2,
+            price: 30000,
+        };
+        assert!(square.buy_coaching_package(&purchase).is_err());
+
+        let checkout = square.subscribe_checkout(Some(user())).await.unwrap();
+        square.complete_checkout(&payment(&checkout.url)).unwrap();
+        let order = square.buy_coaching_package(&purchase).unwrap();
+        let sessions = square.get_coaching_sessions(user()).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].order_id, order.id);
+        assert_eq!(sessions[0].sessions, 6);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::config::{PaymentBackend, SquareConfig};
+use crate::errors::ServiceError;
+use crate::square::billing::{Customer, Invoice, Order, Plan, Subscription};
+use crate::*;
+use async_trait::async_trait;
+use log::*;
+use std::sync::Arc;
+
+/// Everything the server asks of its payment processor, `square.backend` picks which one.
+///
+/// Answers are the server's own types, whichever processor gave them. Every [`SquareApi`] is a
+/// provider, the Square objects never leave it.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Plan, location and checkout settings
+    fn config(&self) -> &SquareConfig;
+
+    // ============================ Users ============================ //
+
+    /// Name and cards of the customer with this email, `None` if there is none or the lookup
+    /// failed
+    async fn get_customer_info(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<CustomerInfo>, ServiceError>;
+
+    /// The plan `subscription_catalog_id` names
+    async fn get_subscription_info(&self) -> Result<Option<SubscriptionInfo>, ServiceError>;
+
+    /// The first subscription of the customer with this email
+    async fn get_user_subscription_info(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<UserSubscriptionInfo>, ServiceError>;
+
+    /// Coaching packages bought by the customer with this email, newest first
+    async fn get_coaching_sessions(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Vec<CoachingSessions>, ServiceError>;
+
+    /// A payment link for the subscription plan, with the buyer's email filled in if known
+    async fn subscribe_checkout(
+        &self,
+        user_email: Option<UserEmailRequest>,
+    ) -> Result<CheckoutInfo, ServiceError>;
+
+    /// Cancel the subscription of the customer with this email at the end of the paid period
+    async fn cancel_subscription(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<CanceledSubscriptionInfo, ServiceError>;
+
+    // ============================ Admin ============================ //
+
+    /// Create the subscription plan and its monthly variation at `subscription_price`.
+    /// Set `square.subscription_catalog_id` to the `id` of the plan.
+    async fn upsert_subscription_plan(&self) -> Result<Plan, ServiceError>;
+
+    /// Every subscription plan
+    async fn plans(&self) -> Result<Vec<Plan>, ServiceError>;
+
+    /// The 10 newest customers
+    async fn customers(&self) -> Result<Vec<Customer>, ServiceError>;
+
+    /// Every subscription, with the date a pending cancel takes effect
+    async fn subscriptions(&self) -> Result<Vec<Subscription>, ServiceError>;
+
+    /// Orders of the 10 newest customers
+    async fn orders(&self) -> Result<Vec<Order>, ServiceError>;
+
+    /// Invoices at `location_id`
+    async fn invoices(&self) -> Result<Vec<Invoice>, ServiceError>;
+
+    /// Name and email of every invoiced customer, once each
+    async fn email_list(&self) -> Result<Vec<CustomerEmailInfo>, ServiceError>;
+
+    // ============================ Provided ============================ //
+
+    async fn get_user_profile(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<UserProfile, ServiceError> {
+        let customer = match self.get_customer_info(request.clone()).await {
+            Ok(Some(customer)) => Some(customer),
+            Ok(None) => None,
+            Err(e) => {
+                error!("Failed to get customer info: {:?}", &e);
+                None
+            }
+        };
+        let sub_info = self.get_subscription_info().await?;
+        let user_sub_info = self.get_user_subscription_info(request).await?;
+        debug!("get_user_profile, customer?: {:?}", &customer.is_some());
+        debug!("get_user_profile, sub_info?: {:?}", &sub_info.is_some());
+        debug!(
+            "get_user_profile, user_sub_info?: {:?}",
+            &user_sub_info.is_some()
+        );
+        Ok(UserProfile {
+            customer,
+            subscription_info: sub_info,
+            user_subscription: user_sub_info,
+        })
+    }
+}
+
+/// A Square error answer as the server's error
+fn success<T>(response: SquareResponse<T>) -> Result<T, ServiceError> {
+    match response {
+        SquareResponse::Success(value) => Ok(value),
+        SquareResponse::Error(error) => Err(error.into()),
+    }
+}
+
+#[async_trait]
+impl<T: SquareApi> PaymentProvider for T {
+    fn config(&self) -> &SquareConfig {
+        SquareApi::config(self)
+    }
+
+    async fn get_customer_info(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<CustomerInfo>, ServiceError> {
+        let customer = self.get_customer(request).await?;
+        Ok(customer.map(|customer| CustomerInfo {
+            email_address: customer.email_address,
+            family_name: customer.family_name,
+            given_name: customer.given_name,
+            cards: customer
+                .cards
+                .map(|cards| cards.into_iter().map(CardInfo::from).collect()),
+        }))
+    }
+
+    async fn get_subscription_info(&self) -> Result<Option<SubscriptionInfo>, ServiceError> {
+        match self.get_subscription_catalog().await? {
+            SquareResponse::Error(_) => Ok(None),
+            SquareResponse::Success(catalog) => {
+                let plan = Plan::from(catalog);
+                Ok(plan.price.map(|price| SubscriptionInfo {
+                    title: plan.name,
+                    cost: price as f64 / 100.0,
+                }))
+            }
+        }
+    }
+
+    async fn get_user_subscription_info(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Option<UserSubscriptionInfo>, ServiceError> {
+        let subscription = self.get_subscription(request).await?;
+        Ok(subscription.map(|sub| UserSubscriptionInfo {
+            start_date: sub.start_date,
+            charged_through_date: sub.charged_through_date,
+            canceled_date: sub.canceled_date,
+        }))
+    }
+
+    async fn get_coaching_sessions(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<Vec<CoachingSessions>, ServiceError> {
+        let customer = match self.get_customer(request).await? {
+            Some(customer) => customer,
+            None => return Ok(Vec::new()),
+        };
+        let list = success(self.search_orders(vec![customer.id]).await?)?;
+
+        let mut sessions = list
+            .orders
+            .into_iter()
+            .map(Order::from)
+            .flat_map(|order| {
+                let Order {
+                    id,
+                    items,
+                    state,
+                    created_at,
+                    ..
+                } = order;
+                items
+                    .into_iter()
+                    .filter_map(|item| {
+                        let package = item
+                            .variation_name
+                            .as_deref()
+                            .and_then(CoachingPackage::from_name)?;
+                        Some(CoachingSessions {
+                            order_id: id.clone(),
+                            package: package.name(),
+                            sessions: package.sessions() * item.quantity,
+                            state: state.clone(),
+                            purchased_at: created_at.clone(),
+                        })
+                    })
+                    .collect::<Vec<CoachingSessions>>()
+            })
+            .collect::<Vec<CoachingSessions>>();
+        sessions.sort_by(|a, b| b.purchased_at.cmp(&a.purchased_at));
+        Ok(sessions)
+    }
+
+    async fn subscribe_checkout(
+        &self,
+        user_email: Option<UserEmailRequest>,
+    ) -> Result<CheckoutInfo, ServiceError> {
+        let subscription_plan_id = Plan::from(success(self.get_subscription_catalog().await?)?)
+            .variation_id
+            .ok_or_else(|| {
+                ServiceError::UpstreamError(
+                    "Subscription catalog has no plan variation".to_string(),
+                )
+            })?;
+        let location_id = success(self.get_location().await?)?.id;
+
+        let config = SquareApi::config(self);
+        let request = CheckoutRequest::new_subscription(SubscriptionCheckoutBuilder {
+            name: config.subscription_name.clone(),
+            price: config.subscription_price,
+            location_id,
+            subscription_plan_id,
+            redirect_url: config.redirect_url.clone(),
+            buyer_email: user_email.map(|request| request.email),
+        });
+        let checkout = success(self.create_payment_link(&request).await?)?;
+        let order = checkout.related_resources.orders.get(0).ok_or_else(|| {
+            ServiceError::UpstreamError("Checkout response has no order".to_string())
+        })?;
+        let checkout_info = CheckoutInfo {
+            url: checkout.payment_link.url,
+            amount: order.net_amount_due_money.amount as f64 / 100.0,
+        };
+        debug!("Square subscription checkout info: {:?}", &checkout_info);
+        Ok(checkout_info)
+    }
+
+    async fn cancel_subscription(
+        &self,
+        request: UserEmailRequest,
+    ) -> Result<CanceledSubscriptionInfo, ServiceError> {
+        let subscription_id = self
+            .get_subscription(request.clone())
+            .await?
+            .ok_or_else(|| {
+                ServiceError::NotFound(format!("No subscription found for {}", request.email))
+            })?
+            .id;
+        let object = success(self.cancel_subscription_by_id(&subscription_id).await?)?;
+
+        // yyyy-mm-dd
+        // break apart into year, month, day
+        let charged_through_date = object.subscription.charged_through_date;
+        let invalid_date = || {
+            ServiceError::UpstreamError(format!(
+                "Invalid charged_through_date from Square: {}",
+                charged_through_date
+            ))
+        };
+        let mut date_parts = charged_through_date.split('-');
+        let charged_through_year = date_parts
+            .next()
+            .and_then(|part| part.parse::<u16>().ok())
+            .ok_or_else(invalid_date)?;
+        let charged_through_month = date_parts
+            .next()
+            .and_then(|part| part.parse::<u8>().ok())
+            .ok_or_else(invalid_date)?;
+        let charged_through_day = date_parts
+            .next()
+            .and_then(|part| part.parse::<u8>().ok())
+            .ok_or_else(invalid_date)?;
+
+        Ok(CanceledSubscriptionInfo {
+            email: request.email,
+            charged_through_year,
+            charged_through_month,
+            charged_through_day,
+        })
+    }
+
+    async fn upsert_subscription_plan(&self) -> Result<Plan, ServiceError> {
+        let plan = success(self.upsert_subscription_catalog().await?)?;
+        Ok(Plan::from(plan.catalog_object))
+    }
+
+    async fn plans(&self) -> Result<Vec<Plan>, ServiceError> {
+        let list = success(self.list_catalogs().await?)?;
+        Ok(list.objects.into_iter().map(Plan::from).collect())
+    }
+
+    async fn customers(&self) -> Result<Vec<Customer>, ServiceError> {
+        let list = success(self.list_customers().await?)?;
+        Ok(list.customers.into_iter().map(Customer::from).collect())
+    }
+
+    async fn subscriptions(&self) -> Result<Vec<Subscription>, ServiceError> {
+        let list = success(self.search_subscriptions(None).await?)?;
+        let mut subs = Vec::<Subscription>::new();
+        for sub in list.subscriptions.into_iter() {
+            debug!("Subscription ID: {}", &sub.id);
+            // search results have no actions, e.g. a pending cancel
+            let sub = success(self.retrieve_subscription(&sub.id).await?)?;
+            subs.push(sub.subscription.into());
+        }
+        Ok(subs)
+    }
+
+    async fn orders(&self) -> Result<Vec<Order>, ServiceError> {
+        let customer_ids = success(self.list_customers().await?)?
+            .customers
+            .into_iter()
+            .map(|customer| customer.id)
+            .collect::<Vec<String>>();
+        let list = success(self.search_orders(customer_ids).await?)?;
+        Ok(list.orders.into_iter().map(Order::from).collect())
+    }
+
+    async fn invoices(&self) -> Result<Vec<Invoice>, ServiceError> {
+        let list = success(self.list_invoices().await?)?;
+        Ok(list.invoices.into_iter().map(Invoice::from).collect())
+    }
+
+    async fn email_list(&self) -> Result<Vec<CustomerEmailInfo>, ServiceError> {
+        let list = PaymentProvider::invoices(self).await?;
+        // filter out duplicate email_address
+        Ok(list.into_iter().fold(Vec::new(), |mut acc, invoice| {
+            if !acc
+                .iter()
+                .any(|e: &CustomerEmailInfo| e.email_address == invoice.recipient.email_address)
+            {
+                acc.push(invoice.recipient);
+            }
+            acc
+        }))
+    }
+}
+
+/// The provider `config.backend` selects, and the in-memory fake itself if it is that, for the
+/// `/test_square` routes paying its checkouts
+pub fn payment_provider(
+    config: &SquareConfig,
+) -> (Arc<dyn PaymentProvider>, Option<Arc<InMemorySquare>>) {
+    match config.backend {
+        PaymentBackend::Square => (Arc::new(SquareClient::new(config)), None),
+        PaymentBackend::InMemory => {
+            warn!("In-memory payments enabled, no Square calls are made and nothing is kept");
+            let square = Arc::new(InMemorySquare::new(config));
+            (square.clone(), Some(square))
+        }
+    }
+}
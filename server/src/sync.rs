@@ -0,0 +1,472 @@
+use crate::content;
+use crate::errors::ServiceError;
+use database::{Article, Calibration, ContentType, ImageInfo, Records, Testimonial};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock};
+use schemars::JsonSchema;
+
+/// Sync tokens are `v1.` followed by one hex digest per content type, in [`ContentType::ALL`] order
+const TOKEN_VERSION: &str = "v1";
+/// Free sync tokens start with `v1f.` instead, their premium articles came without a body
+const FREE_TOKEN_VERSION: &str = "v1f";
+/// Past states of each content type kept to diff against, older tokens get a reset
+const HISTORY_LEN: usize = 64;
+
+lazy_static! {
+    /// Recently served states of each content type, newest last
+    static ref HISTORY: RwLock<HashMap<ContentType, VecDeque<Arc<State>>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Query of `GET /api/sync`
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct SyncQuery {
+    /// Token of the last sync, omitted on first sync
+    pub since: Option<String>,
+}
+
+/// Records added, changed and deleted since the client's token, and the token to send next time
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct SyncResponse {
+    pub token: String,
+    pub articles: Changes<Article>,
+    pub calibrations: Changes<Calibration>,
+    pub testimonials: Changes<Testimonial>,
+    pub testimonial_images: Changes<ImageInfo>,
+    pub category_images: Changes<ImageInfo>,
+    pub content_type_images: Changes<ImageInfo>,
+}
+
+/// Changes to one content type. With `reset` the client's copy is unknown to the server, e.g.
+/// after a restart, and `added` holds every record: the client should drop what it has.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct Changes<T> {
+    pub reset: bool,
+    pub added: Vec<Entry<T>>,
+    pub changed: Vec<Entry<T>>,
+    pub deleted: Vec<String>,
+}
+
+/// A record with the id deletions refer to it by
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct Entry<T> {
+    pub id: String,
+    #[serde(flatten)]
+    pub record: T,
+}
+
+/// Content that can be synced: decoded from a cache record and identified by a stable id
+trait Syncable: Sized {
+    fn decode(bytes: &[u8]) -> Result<Self, String>;
+    /// The id of the record stored under `key`
+    fn id(&self, key: u64) -> String;
+}
+
+impl Syncable for Article {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(bytes).map_err(|e| e.to_string())
+    }
+
+    fn id(&self, _key: u64) -> String {
+        self.title.clone()
+    }
+}
+
+impl Syncable for Calibration {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(bytes).map_err(|e| e.to_string())
+    }
+
+    fn id(&self, _key: u64) -> String {
+        self.title.clone()
+    }
+}
+
+impl Syncable for Testimonial {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(bytes).map_err(|e| e.to_string())
+    }
+
+    /// Testimonials share images, so their key tells them apart. Editing one deletes it and
+    /// adds the new version.
+    fn id(&self, key: u64) -> String {
+        format!("{}#{:x}", self.image_url, key)
+    }
+}
+
+impl Syncable for ImageInfo {
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        ImageInfo::de(bytes).map_err(|e| e.to_string())
+    }
+
+    fn id(&self, _key: u64) -> String {
+        self.url.clone()
+    }
+}
+
+/// Digest of every record of a content type at one point in time
+struct State {
+    digest: u64,
+    /// Record id to digest of its encoded value
+    values: HashMap<String, u64>,
+}
+
+fn digest<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn token_version(free: bool) -> &'static str {
+    match free {
+        true => FREE_TOKEN_VERSION,
+        false => TOKEN_VERSION,
+    }
+}
+
+/// The digest of each content type in `token`. A token of the other sync, free or full, is
+/// unknown: the client's articles don't match what this sync sends, so it gets everything again.
+fn parse_token(token: &str, free: bool) -> Result<HashMap<ContentType, u64>, ServiceError> {
+    let invalid = || ServiceError::BadRequest(format!("Invalid sync token {}", token));
+    let mut parts = token.split('.');
+    match parts.next() {
+        Some(version) if version == token_version(free) => {}
+        Some(version) if version == token_version(!free) => return Ok(HashMap::new()),
+        _ => return Err(invalid()),
+    }
+    let digests = parts
+        .map(|part| u64::from_str_radix(part, 16).map_err(|_| invalid()))
+        .collect::<Result<Vec<u64>, ServiceError>>()?;
+    if digests.len() != ContentType::ALL.len() {
+        return Err(invalid());
+    }
+    Ok(ContentType::ALL.into_iter().zip(digests).collect())
+}
+
+fn remember(content_type: ContentType, state: Arc<State>) -> Result<(), ServiceError> {
+    let mut history = HISTORY
+        .write()
+        .map_err(|_| ServiceError::Internal("Sync history lock poisoned".to_string()))?;
+    let states = history.entry(content_type).or_default();
+    if states.back().map(|last| last.digest) != Some(state.digest) {
+        states.retain(|past| past.digest != state.digest);
+        states.push_back(state);
+        while states.len() > HISTORY_LEN {
+            states.pop_front();
+        }
+    }
+    Ok(())
+}
+
+fn recall(content_type: ContentType, digest: u64) -> Result<Option<Arc<State>>, ServiceError> {
+    let history = HISTORY
+        .read()
+        .map_err(|_| ServiceError::Internal("Sync history lock poisoned".to_string()))?;
+    Ok(history
+        .get(&content_type)
+        .and_then(|states| states.iter().find(|state| state.digest == digest))
+        .cloned())
+}
+
+/// Changes to the `records` of one content type since the state with digest `since`.
+/// Returns the digest of the current state along with the changes.
+fn changes<T: Syncable>(
+    content_type: ContentType,
+    records: &Records,
+    since: Option<u64>,
+) -> Result<(u64, Changes<T>), ServiceError> {
+    let mut keys = records.keys().copied().collect::<Vec<u64>>();
+    keys.sort();
+
+    let mut decoded = Vec::with_capacity(keys.len());
+    let mut counts = HashMap::<String, usize>::new();
+    for key in keys.into_iter() {
+        let record = T::decode(&records[&key]).map_err(|e| {
+            ServiceError::CacheCorrupt(format!(
+                "Failed to decode record in {}: {}",
+                content_type, e
+            ))
+        })?;
+        *counts.entry(record.id(key)).or_default() += 1;
+        decoded.push((key, record));
+    }
+    let mut entries = Vec::with_capacity(decoded.len());
+    let mut values = HashMap::with_capacity(decoded.len());
+    for (key, record) in decoded.into_iter() {
+        let mut id = record.id(key);
+        // every record sharing an id, e.g. two articles with one title, is told apart by key
+        if counts[&id] > 1 {
+            id = format!("{}#{:x}", id, key);
+        }
+        values.insert(id.clone(), digest(records[&key].as_slice()));
+        entries.push(Entry { id, record });
+    }
+    let mut sorted = values.iter().collect::<Vec<(&String, &u64)>>();
+    sorted.sort();
+    let state = Arc::new(State {
+        digest: digest(&sorted),
+        values,
+    });
+    remember(content_type, state.clone())?;
+
+    let previous = match since {
+        Some(since) => recall(content_type, since)?,
+        None => None,
+    };
+    let changes = match previous {
+        Some(previous) => {
+            let mut changes = Changes {
+                reset: false,
+                added: Vec::new(),
+                changed: Vec::new(),
+                deleted: Vec::new(),
+            };
+            for entry in entries.into_iter() {
+                match previous.values.get(&entry.id) {
+                    None => changes.added.push(entry),
+                    Some(value) if *value != state.values[&entry.id] => changes.changed.push(entry),
+                    Some(_) => {}
+                }
+            }
+            let current = state.values.keys().collect::<HashSet<&String>>();
+            changes.deleted = previous
+                .values
+                .keys()
+                .filter(|id| !current.contains(id))
+                .cloned()
+                .collect();
+            changes.deleted.sort();
+            changes
+        }
+        None => Changes {
+            reset: true,
+            added: entries,
+            changed: Vec::new(),
+            deleted: Vec::new(),
+        },
+    };
+    Ok((state.digest, changes))
+}
+
+/// Everything that changed since `since`, every record if `since` is `None`.
+/// With `free`, premium articles are sent without their body, as in the public `load_state`.
+pub fn sync(since: Option<&str>, free: bool) -> Result<SyncResponse, ServiceError> {
+    sync_records(content::records, since, free)
+}
+
+/// [`sync`] of the records `load` returns for each content type
+fn sync_records(
+    load: impl Fn(ContentType) -> Result<Arc<Records>, ServiceError>,
+    since: Option<&str>,
+    free: bool,
+) -> Result<SyncResponse, ServiceError> {
+    let since = match since {
+        Some(token) => parse_token(token, free)?,
+        None => HashMap::new(),
+    };
+    let changes_of = |content_type: ContentType| -> Result<_, ServiceError> {
+        Ok((load(content_type)?, since.get(&content_type).copied()))
+    };
+
+    let (records, since_articles) = changes_of(ContentType::Articles)?;
+    let (articles_digest, mut articles) =
+        changes::<Article>(ContentType::Articles, &records, since_articles)?;
+    if free {
+        for entry in articles.added.iter_mut().chain(articles.changed.iter_mut()) {
+            if entry.record.premium {
+                entry.record.data = String::new();
+            }
+        }
+    }
+    let (records, since_calibrations) = changes_of(ContentType::Calibrations)?;
+    let (calibrations_digest, calibrations) =
+        changes::<Calibration>(ContentType::Calibrations, &records, since_calibrations)?;
+    let (records, since_testimonials) = changes_of(ContentType::Testimonials)?;
+    let (testimonials_digest, testimonials) =
+        changes::<Testimonial>(ContentType::Testimonials, &records, since_testimonials)?;
+    let (records, since_images) = changes_of(ContentType::TestimonialImages)?;
+    let (testimonial_images_digest, testimonial_images) =
+        changes::<ImageInfo>(ContentType::TestimonialImages, &records, since_images)?;
+    let (records, since_images) = changes_of(ContentType::CategoryImages)?;
+    let (category_images_digest, category_images) =
+        changes::<ImageInfo>(ContentType::CategoryImages, &records, since_images)?;
+    let (records, since_images) = changes_of(ContentType::ContentTypeImages)?;
+    let (content_type_images_digest, content_type_images) =
+        changes::<ImageInfo>(ContentType::ContentTypeImages, &records, since_images)?;
+
+    // same order as ContentType::ALL
+    let digests = [
+        articles_digest,
+        calibrations_digest,
+        testimonials_digest,
+        testimonial_images_digest,
+        category_images_digest,
+        content_type_images_digest,
+    ];
+    let token = std::iter::once(token_version(free).to_string())
+        .chain(digests.iter().map(|digest| format!("{:x}", digest)))
+        .collect::<Vec<String>>()
+        .join(".");
+
+    Ok(SyncResponse {
+        token,
+        articles,
+        calibrations,
+        testimonials,
+        testimonial_images,
+        category_images,
+        content_type_images,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, data: &str, premium: bool) -> Article {
+        Article {
+            title: title.to_string(),
+            tags: vec![],
+            data: data.to_string(),
+            image_url: String::new(),
+            index: 0,
+            premium,
+        }
+    }
+
+    fn testimonial(image_url: &str, text: &str) -> Testimonial {
+        Testimonial {
+            image_url: image_url.to_string(),
+            testimonial: text.to_string(),
+        }
+    }
+
+    fn records<T: Serialize>(values: &[(u64, T)]) -> Arc<Records> {
+        Arc::new(
+            values
+                .iter()
+                .map(|(key, value)| (*key, bincode::serialize(value).unwrap()))
+                .collect(),
+        )
+    }
+
+    /// Syncs `articles` and `testimonials`, every other content type is empty
+    fn sync_with(
+        articles: &[(u64, Article)],
+        testimonials: &[(u64, Testimonial)],
+        since: Option<&str>,
+        free: bool,
+    ) -> SyncResponse {
+        let articles = records(articles);
+        let testimonials = records(testimonials);
+        sync_records(
+            |content_type| {
+                Ok(match content_type {
+                    ContentType::Articles => articles.clone(),
+                    ContentType::Testimonials => testimonials.clone(),
+                    _ => Arc::new(Records::new()),
+                })
+            },
+            since,
+            free,
+        )
+        .unwrap()
+    }
+
+    fn ids<T>(entries: &[Entry<T>]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn first_sync_sends_everything() {
+        let response = sync_with(&[(1, article("first sync", "a", false))], &[], None, false);
+        assert!(response.articles.reset);
+        assert_eq!(ids(&response.articles.added), vec!["first sync"]);
+        assert!(response.token.starts_with("v1."));
+    }
+
+    #[test]
+    fn sync_sends_added_changed_and_deleted() {
+        let before = [
+            (1, article("diff kept", "a", false)),
+            (2, article("diff changed", "b", false)),
+            (3, article("diff deleted", "c", false)),
+        ];
+        let token = sync_with(&before, &[], None, false).token;
+
+        let after = [
+            (1, article("diff kept", "a", false)),
+            (4, article("diff changed", "b2", false)),
+            (5, article("diff added", "d", false)),
+        ];
+        let response = sync_with(&after, &[], Some(&token), false);
+        assert!(!response.articles.reset);
+        assert_eq!(ids(&response.articles.added), vec!["diff added"]);
+        assert_eq!(ids(&response.articles.changed), vec!["diff changed"]);
+        assert_eq!(response.articles.changed[0].record.data, "b2");
+        assert_eq!(response.articles.deleted, vec!["diff deleted".to_string()]);
+
+        let response = sync_with(&after, &[], Some(&response.token), false);
+        assert!(!response.articles.reset);
+        assert!(response.articles.added.is_empty());
+        assert!(response.articles.changed.is_empty());
+        assert!(response.articles.deleted.is_empty());
+    }
+
+    #[test]
+    fn unknown_token_resets() {
+        let articles = [(1, article("unknown token", "a", false))];
+        let token = format!("v1{}", ".1234".repeat(ContentType::ALL.len()));
+        let response = sync_with(&articles, &[], Some(&token), false);
+        assert!(response.articles.reset);
+        assert_eq!(ids(&response.articles.added), vec!["unknown token"]);
+        assert!(sync_records(|_| Ok(Arc::new(Records::new())), Some("v2.1"), false).is_err());
+    }
+
+    #[test]
+    fn free_token_resets_full_sync() {
+        let articles = [(1, article("premium switch", "secret", true))];
+        let free = sync_with(&articles, &[], None, true);
+        assert!(free.token.starts_with("v1f."));
+        assert_eq!(free.articles.added[0].record.data, "");
+
+        let full = sync_with(&articles, &[], Some(&free.token), false);
+        assert!(full.articles.reset);
+        assert_eq!(full.articles.added[0].record.data, "secret");
+
+        let free = sync_with(&articles, &[], Some(&full.token), true);
+        assert!(free.articles.reset);
+        assert_eq!(free.articles.added[0].record.data, "");
+    }
+
+    #[test]
+    fn shared_ids_stay_stable() {
+        let testimonials = [
+            (1, testimonial("shared.jpg", "one")),
+            (2, testimonial("shared.jpg", "two")),
+        ];
+        let articles = [
+            (3, article("same title", "one", false)),
+            (4, article("same title", "two", false)),
+        ];
+        let first = sync_with(&articles, &testimonials, None, false);
+        assert_eq!(
+            ids(&first.testimonials.added),
+            vec!["shared.jpg#1", "shared.jpg#2"]
+        );
+        assert_eq!(
+            ids(&first.articles.added),
+            vec!["same title#3", "same title#4"]
+        );
+
+        let response = sync_with(&articles[1..], &testimonials[1..], Some(&first.token), false);
+        assert_eq!(response.testimonials.deleted, vec!["shared.jpg#1".to_string()]);
+        assert!(response.testimonials.added.is_empty());
+        assert!(response.testimonials.changed.is_empty());
+        assert!(response.articles.deleted.contains(&"same title#3".to_string()));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::AuthConfig;
+use crate::errors::ServiceError;
+use alcoholic_jwt::JWK;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::Signer;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+const KEY_BITS: u32 = 2048;
+const DEFAULT_EXPIRES_IN: i64 = 3600;
+
+/// Claims of a token to mint, `POST /test_idp/token`
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TokenRequest {
+    /// `sub`, e.g. `test|alice`
+    pub sub: String,
+    /// Claim named by `auth.email_claim`
+    pub email: Option<String>,
+    /// `true` by default if there is an `email`. Set to `false` and the server ignores it.
+    pub email_verified: Option<bool>,
+    /// Admin roles in the `auth.roles_claim` claim, e.g. `owner`
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Admin scopes in the `permissions` claim, e.g. `billing:read`
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Seconds until the token expires, 3600 by default. Negative for an expired token.
+    pub expires_in: Option<i64>,
+    /// `aud` instead of `auth.audience`, for a token the server rejects
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MintedToken {
+    pub access_token: String,
+    /// Always `Bearer`
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+/// Issuer of the test identity provider. Signs RS256 tokens with a key generated when the server
+/// starts, so its tokens stop working on restart, and serves the public half as a JWKS.
+pub struct TestIssuer {
+    auth: AuthConfig,
+    kid: String,
+    key: PKey<Private>,
+    jwk: JWK,
+    jwks: Value,
+}
+
+impl TestIssuer {
+    pub fn new(auth: &AuthConfig) -> Result<Self, String> {
+        let rsa = Rsa::generate(KEY_BITS)
+            .map_err(|e| format!("Failed to generate the test issuer key: {}", e))?;
+        let kid = format!("test-{}", &Uuid::new_v4().simple().to_string()[..12]);
+        let public = json!({
+            "kty": "RSA",
+            "alg": "RS256",
+            "use": "sig",
+            "kid": kid,
+            "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+            "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+        });
+        let jwk = serde_json::from_value::<JWK>(public.clone())
+            .map_err(|e| format!("Invalid test issuer JWK: {}", e))?;
+        let key = PKey::from_rsa(rsa)
+            .map_err(|e| format!("Failed to load the test issuer key: {}", e))?;
+        Ok(Self {
+            auth: auth.clone(),
+            kid,
+            key,
+            jwk,
+            jwks: json!({ "keys": [public] }),
+        })
+    }
+
+    /// The key set served at `/test_idp/.well-known/jwks.json`
+    pub fn jwks(&self) -> &Value {
+        &self.jwks
+    }
+
+    pub fn key(&self, kid: &str) -> Option<JWK> {
+        (kid == self.kid).then(|| self.jwk.clone())
+    }
+
+    /// A signed token with the claims of `request`, issued by `auth.issuer` for `auth.audience`
+    pub fn mint(&self, request: &TokenRequest) -> Result<MintedToken, ServiceError> {
+        if request.sub.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "sub must not be empty".to_string(),
+            ));
+        }
+        let now = Utc::now().timestamp();
+        let expires_in = request.expires_in.unwrap_or(DEFAULT_EXPIRES_IN);
+        let mut claims = Map::new();
+        claims.insert("iss".to_string(), json!(self.auth.issuer));
+        claims.insert("sub".to_string(), json!(request.sub));
+        claims.insert(
+            "aud".to_string(),
+            json!(request.audience.as_ref().unwrap_or(&self.auth.audience)),
+        );
+        claims.insert("iat".to_string(), json!(now));
+        claims.insert("exp".to_string(), json!(now + expires_in));
+        if let Some(email) = &request.email {
+            claims.insert(self.auth.email_claim.clone(), json!(email));
+        }
+        let verified = request
+            .email_verified
+            .or(request.email.as_ref().map(|_| true));
+        if let Some(verified) = verified {
+            claims.insert("email_verified".to_string(), json!(verified));
+        }
+        if !request.roles.is_empty() {
+            claims.insert(self.auth.roles_claim.clone(), json!(request.roles));
+        }
+        if !request.permissions.is_empty() {
+            claims.insert("permissions".to_string(), json!(request.permissions));
+        }
+
+        let header = json!({ "alg": "RS256", "typ": "JWT", "kid": self.kid });
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(Value::Object(claims).to_string())
+        );
+        let signature = Signer::new(MessageDigest::sha256(), &self.key)
+            .and_then(|mut signer| {
+                signer.update(signing_input.as_bytes())?;
+                signer.sign_to_vec()
+            })
+            .map_err(|e| ServiceError::Internal(format!("Failed to sign test token: {}", e)))?;
+        Ok(MintedToken {
+            access_token: format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)),
+            token_type: "Bearer".to_string(),
+            expires_in,
+        })
+    }
+}
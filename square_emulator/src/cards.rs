@@ -0,0 +1,66 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use square_model::CardRequest;
+
+/// `POST /v2/cards`, declining [`square_model::DECLINED_CARD_NONCE`]
+#[post("/cards")]
+pub async fn create_card(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let request = parse::<CardRequest>(&body)?;
+    let card = emulator
+        .store
+        .update(|merchant| merchant.create_card(&request))?;
+    Ok(HttpResponse::Ok().json(json!({ "card": card })))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListCardsQuery {
+    customer_id: Option<String>,
+    #[serde(default)]
+    include_disabled: bool,
+}
+
+/// `GET /v2/cards`, enabled cards unless `include_disabled`
+#[get("/cards")]
+pub async fn list_cards(
+    emulator: web::Data<Emulator>,
+    query: web::Query<ListCardsQuery>,
+) -> Result<HttpResponse, SquareError> {
+    let cards = emulator.store.read(|merchant| {
+        merchant.list_cards(query.customer_id.as_deref(), query.include_disabled)
+    })?;
+    Ok(HttpResponse::Ok().json(json!({ "cards": cards })))
+}
+
+/// `GET /v2/cards/{card_id}`
+#[get("/cards/{card_id}")]
+pub async fn retrieve_card(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let card_id = path.into_inner();
+    let card = emulator
+        .store
+        .read(|merchant| merchant.card(&card_id).cloned())?
+        .ok_or_else(|| SquareError::not_found(format!("Card {} not found", card_id)))?;
+    Ok(HttpResponse::Ok().json(json!({ "card": card })))
+}
+
+/// `POST /v2/cards/{card_id}/disable`
+#[post("/cards/{card_id}/disable")]
+pub async fn disable_card(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let card_id = path.into_inner();
+    let card = emulator
+        .store
+        .update(|merchant| merchant.disable_card(&card_id))?;
+    Ok(HttpResponse::Ok().json(json!({ "card": card })))
+}
@@ -0,0 +1,58 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use square_model::CatalogRequest;
+
+/// `POST /v2/catalog/object`, for subscription plans and their variations and items with theirs
+#[post("/catalog/object")]
+pub async fn upsert_catalog_object(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let request = parse::<CatalogRequest>(&body)?;
+    let upsert = emulator
+        .store
+        .update(|merchant| merchant.upsert_catalog_object(&request.object))?;
+    Ok(HttpResponse::Ok().json(upsert))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListCatalogQuery {
+    /// Comma separated, plans and items with their variations nested if missing
+    types: Option<String>,
+}
+
+/// `GET /v2/catalog/list`
+#[get("/catalog/list")]
+pub async fn list_catalog(
+    emulator: web::Data<Emulator>,
+    query: web::Query<ListCatalogQuery>,
+) -> Result<HttpResponse, SquareError> {
+    let types = query.types.as_ref().map(|types| {
+        types
+            .split(',')
+            .map(|type_| type_.trim().to_uppercase())
+            .collect::<Vec<String>>()
+    });
+    let objects = emulator
+        .store
+        .read(|merchant| merchant.list_catalog(types.as_deref()))?;
+    Ok(HttpResponse::Ok().json(json!({ "objects": objects })))
+}
+
+/// `GET /v2/catalog/object/{object_id}`
+#[get("/catalog/object/{object_id}")]
+pub async fn retrieve_catalog_object(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let object_id = path.into_inner();
+    let object = emulator
+        .store
+        .read(|merchant| merchant.catalog_object(&object_id))?
+        .ok_or_else(|| SquareError::not_found(format!("Catalog object {} not found", object_id)))?;
+    Ok(HttpResponse::Ok().json(json!({ "object": object })))
+}
@@ -0,0 +1,146 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::http::header;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use square_model::{CheckoutRequest, CustomerRequest, CARD_NONCE, DECLINED_CARD_NONCE};
+
+/// `POST /v2/online-checkout/payment-links`, for a `quick_pay` amount or an `order` of catalog
+/// item variations. The link's `url` is the emulator's checkout page.
+#[post("/online-checkout/payment-links")]
+pub async fn create_payment_link(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let request = parse::<CheckoutRequest>(&body)?;
+    let checkout_url = format!("{}pay/", emulator.public_url);
+    let response = emulator
+        .store
+        .update(|merchant| merchant.create_payment_link(&request, &checkout_url))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// `GET /v2/online-checkout/payment-links/{id}`
+#[get("/online-checkout/payment-links/{id}")]
+pub async fn retrieve_payment_link(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let id = path.into_inner();
+    let link = emulator
+        .store
+        .read(|merchant| merchant.payment_link(&id).cloned())?
+        .ok_or_else(|| SquareError::not_found(format!("Payment link {} not found", id)))?;
+    Ok(HttpResponse::Ok().json(json!({ "payment_link": link })))
+}
+
+/// The checkout page a payment link's `url` opens, a form standing in for Square's
+#[get("/pay/{id}")]
+pub async fn checkout_page(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let id = path.into_inner();
+    let (link, order) = emulator
+        .store
+        .read(|merchant| {
+            let link = merchant.payment_link(&id)?;
+            let order = merchant.order(&link.order_id)?;
+            Some((link.clone(), order.clone()))
+        })?
+        .ok_or_else(|| SquareError::not_found(format!("Payment link {} not found", id)))?;
+
+    let title = escape(
+        order
+            .line_items
+            .first()
+            .map_or("Checkout", |item| item.name.as_str()),
+    );
+    let amount = order.total_money.amount;
+    let form = if order.state == "OPEN" {
+        format!(
+            r#"<form method="post">
+<p><label>First name <input name="given_name"></label></p>
+<p><label>Last name <input name="family_name"></label></p>
+<p><label>Email <input name="email_address" type="email" value="{email}" required></label></p>
+<p><label>Card <select name="source_id">
+<option value="{ok}">Visa ending in 1111</option>
+<option value="{declined}">Declined card</option>
+</select></label></p>
+<p><button>Pay ${dollars}.{cents:02}</button></p>
+</form>"#,
+            email = escape(
+                link.pre_populated_data
+                    .and_then(|data| data.buyer_email)
+                    .as_deref()
+                    .unwrap_or_default()
+            ),
+            ok = CARD_NONCE,
+            declined = DECLINED_CARD_NONCE,
+            dollars = amount / 100,
+            cents = amount % 100,
+        )
+    } else {
+        "<p>This link has been paid.</p>".to_string()
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+             <body><h1>{title}</h1>\n{form}\n</body></html>\n",
+            title = title,
+            form = form,
+        )))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PayForm {
+    #[serde(default)]
+    given_name: String,
+    #[serde(default)]
+    family_name: String,
+    #[serde(default)]
+    email_address: String,
+    source_id: Option<String>,
+}
+
+/// The buyer submitting the checkout page, redirected to the link's `redirect_url` once paid
+#[post("/pay/{id}")]
+pub async fn pay(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+    form: web::Form<PayForm>,
+) -> Result<HttpResponse, SquareError> {
+    let id = path.into_inner();
+    let buyer = CustomerRequest {
+        email_address: form.email_address.clone(),
+        family_name: form.family_name.clone(),
+        given_name: form.given_name.clone(),
+        ..CustomerRequest::default()
+    };
+    let source_id = form.source_id.as_deref().unwrap_or(CARD_NONCE);
+    let paid = emulator
+        .store
+        .update(|merchant| merchant.complete_checkout(&id, &buyer, source_id))?;
+    let redirect_url = paid
+        .payment_link
+        .checkout_options
+        .and_then(|options| options.redirect_url);
+    match redirect_url {
+        Some(redirect_url) if !redirect_url.is_empty() => Ok(HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, redirect_url))
+            .finish()),
+        _ => Ok(HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body("<!DOCTYPE html>\n<html><body><p>Payment complete.</p></body></html>\n")),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
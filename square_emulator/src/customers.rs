@@ -0,0 +1,192 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{get, post, put, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use square_model::{
+    CreateCustomAttributeRequest, CustomerRequest, CustomerResponse, UpdateCustomerAttributeRequest,
+};
+
+/// `POST /v2/customers/search`, by `query.filter.email_address.exact` ignoring case
+#[post("/customers/search")]
+pub async fn search_customers(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let body = parse_body(&body)?;
+    let email = body
+        .pointer("/query/filter/email_address/exact")
+        .and_then(Value::as_str);
+    let limit = limit(body["limit"].as_u64(), 100, 100)?;
+    let mut customers = emulator
+        .store
+        .read(|merchant| merchant.search_customers(email))?;
+    customers.truncate(limit);
+    Ok(HttpResponse::Ok().json(json!({ "customers": customers })))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListCustomersQuery {
+    limit: Option<u64>,
+    /// An offset into the sorted customers
+    cursor: Option<String>,
+    /// `ASC` by default or `DESC`, creation order either way as `sort_field` is ignored
+    sort_order: Option<String>,
+}
+
+/// `GET /v2/customers`
+#[get("/customers")]
+pub async fn list_customers(
+    emulator: web::Data<Emulator>,
+    query: web::Query<ListCustomersQuery>,
+) -> Result<HttpResponse, SquareError> {
+    let limit = limit(query.limit, 100, 100)?;
+    let offset = offset(query.cursor.as_deref())?;
+    let mut sorted = emulator
+        .store
+        .read(|merchant| merchant.search_customers(None))?;
+    if query.sort_order.as_deref() == Some("DESC") {
+        sorted.reverse();
+    }
+    let more = sorted.len() > offset + limit;
+    let customers = sorted
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .collect::<Vec<CustomerResponse>>();
+    let mut response = json!({ "customers": customers });
+    if more {
+        response["cursor"] = json!((offset + limit).to_string());
+    }
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// `POST /v2/customers`
+#[post("/customers")]
+pub async fn create_customer(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let request = parse::<CustomerRequest>(&body)?;
+    let customer = emulator
+        .store
+        .update(|merchant| merchant.create_customer(&request))?;
+    Ok(HttpResponse::Ok().json(json!({ "customer": customer })))
+}
+
+/// `GET /v2/customers/{customer_id}`
+#[get("/customers/{customer_id}")]
+pub async fn retrieve_customer(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let customer_id = path.into_inner();
+    let customer = emulator
+        .store
+        .read(|merchant| merchant.customer(&customer_id))?
+        .ok_or_else(|| SquareError::not_found(format!("Customer {} not found", customer_id)))?;
+    Ok(HttpResponse::Ok().json(json!({ "customer": customer })))
+}
+
+/// `PUT /v2/customers/{customer_id}`, the names and email are replaced
+#[put("/customers/{customer_id}")]
+pub async fn update_customer(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let customer_id = path.into_inner();
+    let request = parse::<CustomerRequest>(&body)?;
+    let customer = emulator
+        .store
+        .update(|merchant| merchant.update_customer(&customer_id, &request))?;
+    Ok(HttpResponse::Ok().json(json!({ "customer": customer })))
+}
+
+/// `GET /v2/customers/custom-attribute-definitions`
+#[get("/customers/custom-attribute-definitions")]
+pub async fn list_custom_attribute_definitions(
+    emulator: web::Data<Emulator>,
+) -> Result<HttpResponse, SquareError> {
+    let definitions = emulator
+        .store
+        .read(|merchant| merchant.custom_attribute_definitions.clone())?;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attribute_definitions": definitions })))
+}
+
+/// `POST /v2/customers/custom-attribute-definitions`, keys are unique
+#[post("/customers/custom-attribute-definitions")]
+pub async fn create_custom_attribute_definition(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let request = parse::<CreateCustomAttributeRequest>(&body)?;
+    let definition = emulator.store.update(|merchant| {
+        merchant.create_custom_attribute_definition(&request.custom_attribute_definition)
+    })?;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attribute_definition": definition })))
+}
+
+/// `GET /v2/customers/custom-attribute-definitions/{key}`
+#[get("/customers/custom-attribute-definitions/{key}")]
+pub async fn retrieve_custom_attribute_definition(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let key = path.into_inner();
+    let definition = emulator
+        .store
+        .read(|merchant| merchant.custom_attribute_definition(&key).cloned())?
+        .ok_or_else(|| {
+            SquareError::not_found(format!("Custom attribute definition {} not found", key))
+        })?;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attribute_definition": definition })))
+}
+
+/// `GET /v2/customers/{customer_id}/custom-attributes`
+#[get("/customers/{customer_id}/custom-attributes")]
+pub async fn list_custom_attributes(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let customer_id = path.into_inner();
+    let attributes = emulator
+        .store
+        .read(|merchant| merchant.custom_attributes(&customer_id))??;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attributes": attributes })))
+}
+
+/// `GET /v2/customers/{customer_id}/custom-attributes/{key}`
+#[get("/customers/{customer_id}/custom-attributes/{key}")]
+pub async fn retrieve_custom_attribute(
+    emulator: web::Data<Emulator>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, SquareError> {
+    let (customer_id, key) = path.into_inner();
+    let attribute = emulator
+        .store
+        .read(|merchant| merchant.custom_attribute(&customer_id, &key).cloned())?
+        .ok_or_else(|| {
+            SquareError::not_found(format!(
+                "Custom attribute {} of customer {} not found",
+                key, customer_id
+            ))
+        })?;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attribute": attribute })))
+}
+
+/// `POST /v2/customers/{customer_id}/custom-attributes/{key}`, for a defined key
+#[post("/customers/{customer_id}/custom-attributes/{key}")]
+pub async fn upsert_custom_attribute(
+    emulator: web::Data<Emulator>,
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let (customer_id, key) = path.into_inner();
+    let request = parse::<UpdateCustomerAttributeRequest>(&body)?;
+    let attribute = emulator.store.update(|merchant| {
+        merchant.upsert_custom_attribute(&customer_id, &key, &request.custom_attribute.value)
+    })?;
+    Ok(HttpResponse::Ok().json(json!({ "custom_attribute": attribute })))
+}
@@ -0,0 +1,73 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use square_model::SquareErrorResponse;
+use std::fmt;
+
+/// An error as Square returns it, `{"errors": [{category, code, detail, field}]}` with a non-200
+/// status
+#[derive(Debug)]
+pub struct SquareError {
+    status: StatusCode,
+    response: SquareErrorResponse,
+}
+
+impl SquareError {
+    pub fn bad_request(code: &str, detail: impl Into<String>) -> Self {
+        SquareErrorResponse::bad_request(code, detail).into()
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        SquareErrorResponse::not_found(detail).into()
+    }
+
+    /// `field` of the request body or query is missing or empty
+    pub fn missing(field: &str) -> Self {
+        SquareErrorResponse::missing(field).into()
+    }
+
+    pub fn unauthorized() -> Self {
+        SquareErrorResponse::new(
+            "AUTHENTICATION_ERROR",
+            "UNAUTHORIZED",
+            "This request could not be authorized.",
+        )
+        .into()
+    }
+
+    pub fn internal(error: impl fmt::Display) -> Self {
+        SquareErrorResponse::new("API_ERROR", "INTERNAL_SERVER_ERROR", error.to_string()).into()
+    }
+}
+
+/// The status Square answers an error with, by its code
+impl From<SquareErrorResponse> for SquareError {
+    fn from(response: SquareErrorResponse) -> Self {
+        let status = match response.code() {
+            "NOT_FOUND" => StatusCode::NOT_FOUND,
+            "UNAUTHORIZED" => StatusCode::UNAUTHORIZED,
+            "CONFLICT" => StatusCode::CONFLICT,
+            "INTERNAL_SERVER_ERROR" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self { status, response }
+    }
+}
+
+impl fmt::Display for SquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for error in self.response.errors.iter() {
+            write!(f, "{} {}: {}", error.category, error.code, error.detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for SquareError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(&self.response)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use square_model::Invoice;
+
+#[derive(Deserialize, Debug)]
+pub struct ListInvoicesQuery {
+    location_id: Option<String>,
+    limit: Option<u64>,
+    /// An offset into the location's invoices
+    cursor: Option<String>,
+}
+
+/// `GET /v2/invoices`, oldest first
+#[get("/invoices")]
+pub async fn list_invoices(
+    emulator: web::Data<Emulator>,
+    query: web::Query<ListInvoicesQuery>,
+) -> Result<HttpResponse, SquareError> {
+    let location_id = query
+        .location_id
+        .clone()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| SquareError::missing("location_id"))?;
+    let limit = limit(query.limit, 100, 200)?;
+    let offset = offset(query.cursor.as_deref())?;
+    let invoices = emulator.store.read(|merchant| {
+        merchant
+            .invoices
+            .iter()
+            .filter(|invoice| invoice.location_id == location_id)
+            .cloned()
+            .collect::<Vec<Invoice>>()
+    })?;
+    let more = invoices.len() > offset + limit;
+    let page = invoices
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .collect::<Vec<Invoice>>();
+    let mut response = json!({ "invoices": page });
+    if more {
+        response["cursor"] = json!((offset + limit).to_string());
+    }
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,25 @@
+use crate::error::SquareError;
+use crate::Emulator;
+use actix_web::{get, web, HttpResponse};
+use serde_json::json;
+
+/// `GET /v2/locations`
+#[get("/locations")]
+pub async fn list_locations(emulator: web::Data<Emulator>) -> Result<HttpResponse, SquareError> {
+    let locations = emulator.store.read(|merchant| merchant.locations.clone())?;
+    Ok(HttpResponse::Ok().json(json!({ "locations": locations })))
+}
+
+/// `GET /v2/locations/{location_id}`
+#[get("/locations/{location_id}")]
+pub async fn retrieve_location(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let location_id = path.into_inner();
+    let location = emulator
+        .store
+        .read(|merchant| merchant.location(&location_id).cloned())?
+        .ok_or_else(|| SquareError::not_found(format!("Location {} not found", location_id)))?;
+    Ok(HttpResponse::Ok().json(json!({ "location": location })))
+}
@@ -0,0 +1,169 @@
+mod cards;
+mod catalog;
+mod checkout;
+mod customers;
+mod error;
+mod invoices;
+mod locations;
+mod orders;
+mod store;
+mod subscriptions;
+
+use actix_web::dev::{Service, ServiceRequest};
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpServer};
+use clap::Parser;
+use dotenv::dotenv;
+use error::SquareError;
+use futures::future::{self, Either};
+use log::*;
+use simplelog::{ColorChoice, Config as SimpleLogConfig, TermLogger, TerminalMode};
+use std::path::PathBuf;
+use store::Store;
+
+/// Emulates the Square API endpoints the server calls, so `SQUARE_API_URL` can point here
+/// instead of the sandbox. Payment links are paid on the emulator's own checkout page.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Address to listen on
+    #[clap(long, env = "SQUARE_EMULATOR_HOST", default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on
+    #[clap(long, env = "SQUARE_EMULATOR_PORT", default_value = "3555")]
+    port: u16,
+
+    /// JSON file customers, catalog, orders, subscriptions etc are kept in between runs
+    #[clap(
+        long,
+        env = "SQUARE_EMULATOR_STATE",
+        default_value = "cache/square_emulator.json"
+    )]
+    state: PathBuf,
+
+    /// Start over with an empty state instead of the saved one
+    #[clap(long)]
+    reset: bool,
+
+    /// Location the merchant always has, the server's `SQUARE_LOCATION_ID`
+    #[clap(long, env = "SQUARE_LOCATION_ID", default_value = "LOCALSQUARE")]
+    location_id: String,
+
+    /// Bearer token requests must send, the server's `SQUARE_ACCESS_TOKEN`. Any token is
+    /// accepted if unset.
+    #[clap(long, env = "SQUARE_ACCESS_TOKEN")]
+    access_token: Option<String>,
+
+    /// Base URL of the checkout pages payment links point at, `http://<host>:<port>/` by default
+    #[clap(long, env = "SQUARE_EMULATOR_URL")]
+    public_url: Option<String>,
+}
+
+pub struct Emulator {
+    pub store: Store,
+    pub access_token: Option<String>,
+    /// Always ends with `/`
+    pub public_url: String,
+}
+
+impl Emulator {
+    /// Like Square, every API request needs a bearer token
+    fn authorize(&self, req: &ServiceRequest) -> Result<(), SquareError> {
+        let token = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .filter(|token| !token.is_empty())
+            .ok_or_else(SquareError::unauthorized)?;
+        match &self.access_token {
+            Some(access_token) if access_token != token => Err(SquareError::unauthorized()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv().ok();
+    init_logger();
+    let args = Args::parse();
+
+    let store = Store::open(args.state, &args.location_id, args.reset)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:#}", e)))?;
+    let mut public_url = args
+        .public_url
+        .unwrap_or_else(|| format!("http://{}:{}/", args.host, args.port));
+    if !public_url.ends_with('/') {
+        public_url.push('/');
+    }
+    let emulator = web::Data::new(Emulator {
+        store,
+        access_token: args.access_token,
+        public_url,
+    });
+
+    info!(
+        "Square emulator listening on {}:{}, location {}",
+        args.host, args.port, args.location_id
+    );
+    HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .app_data(emulator.clone())
+            .service(
+                web::scope("/v2")
+                    .wrap_fn(|req, srv| {
+                        let authorized = req
+                            .app_data::<web::Data<Emulator>>()
+                            .map_or(Ok(()), |emulator| emulator.authorize(&req));
+                        match authorized {
+                            Ok(()) => Either::Left(srv.call(req)),
+                            Err(error) => Either::Right(future::ok(req.error_response(error))),
+                        }
+                    })
+                    .service(customers::search_customers)
+                    .service(customers::list_custom_attribute_definitions)
+                    .service(customers::create_custom_attribute_definition)
+                    .service(customers::retrieve_custom_attribute_definition)
+                    .service(customers::list_custom_attributes)
+                    .service(customers::retrieve_custom_attribute)
+                    .service(customers::upsert_custom_attribute)
+                    .service(customers::list_customers)
+                    .service(customers::create_customer)
+                    .service(customers::retrieve_customer)
+                    .service(customers::update_customer)
+                    .service(cards::create_card)
+                    .service(cards::list_cards)
+                    .service(cards::retrieve_card)
+                    .service(cards::disable_card)
+                    .service(catalog::upsert_catalog_object)
+                    .service(catalog::list_catalog)
+                    .service(catalog::retrieve_catalog_object)
+                    .service(locations::list_locations)
+                    .service(locations::retrieve_location)
+                    .service(checkout::create_payment_link)
+                    .service(checkout::retrieve_payment_link)
+                    .service(subscriptions::search_subscriptions)
+                    .service(subscriptions::retrieve_subscription)
+                    .service(subscriptions::cancel_subscription)
+                    .service(orders::search_orders)
+                    .service(invoices::list_invoices),
+            )
+            .service(checkout::checkout_page)
+            .service(checkout::pay)
+    })
+    .bind((args.host.as_str(), args.port))?
+    .run()
+    .await
+}
+
+fn init_logger() {
+    TermLogger::init(
+        LevelFilter::Info,
+        SimpleLogConfig::default(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    )
+    .expect("Failed to initialize logger");
+}
@@ -0,0 +1,33 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{post, web, HttpResponse};
+use serde_json::json;
+
+/// `POST /v2/orders/search`, newest first unless `query.sort.sort_order` is `ASC`
+#[post("/orders/search")]
+pub async fn search_orders(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let body = parse_body(&body)?;
+    let location_ids = strings(&body["location_ids"])
+        .filter(|ids| !ids.is_empty())
+        .ok_or_else(|| SquareError::missing("location_ids"))?;
+    let filter = &body["query"]["filter"];
+    let customer_ids = strings(&filter["customer_filter"]["customer_ids"]);
+    let states = strings(&filter["state_filter"]["states"]);
+    let ascending = body["query"]["sort"]["sort_order"] == "ASC";
+    let limit = limit(body["limit"].as_u64(), 500, 1000)?;
+    let mut orders = emulator
+        .store
+        .read(|merchant| merchant.search_orders(&location_ids, customer_ids.as_deref()))?;
+    if let Some(states) = states {
+        orders.retain(|order| states.contains(&order.state));
+    }
+    if ascending {
+        orders.reverse();
+    }
+    orders.truncate(limit);
+    Ok(HttpResponse::Ok().json(json!({ "orders": orders })))
+}
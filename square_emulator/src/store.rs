@@ -0,0 +1,140 @@
+use crate::error::SquareError;
+use anyhow::Context;
+use chrono::Utc;
+use log::*;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use square_model::{Merchant, SquareErrorResponse};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+/// The merchant, written to `path` after every change so it survives restarts
+pub struct Store {
+    path: PathBuf,
+    merchant: Mutex<Merchant>,
+}
+
+impl Store {
+    /// The merchant saved at `path`, or a new one if there is none or `reset`. Either way
+    /// `location_id` is one of its locations.
+    pub fn open(path: PathBuf, location_id: &str, reset: bool) -> anyhow::Result<Self> {
+        let mut merchant = if path.exists() && !reset {
+            let bytes = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            serde_json::from_slice::<Merchant>(&bytes)
+                .with_context(|| format!("Failed to parse {:?}", path))?
+        } else {
+            Merchant::new(location_id)
+        };
+        merchant.open_location(location_id);
+        info!(
+            "Square emulator state {:?}: {} customers, {} orders, {} subscriptions",
+            path,
+            merchant.customers.len(),
+            merchant.orders.len(),
+            merchant.subscriptions.len()
+        );
+        save(&path, &merchant)?;
+        Ok(Self {
+            path,
+            merchant: Mutex::new(merchant),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<Merchant> {
+        match self.merchant.lock() {
+            Ok(merchant) => merchant,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Runs `f` on the merchant with subscriptions billed up to today, saving it if `f`
+    /// succeeds and leaving it untouched if `f` fails
+    pub fn update<T>(
+        &self,
+        f: impl FnOnce(&mut Merchant) -> Result<T, SquareErrorResponse>,
+    ) -> Result<T, SquareError> {
+        let mut merchant = self.lock();
+        let before = merchant.clone();
+        merchant.renew_subscriptions(Utc::now().date_naive());
+        match f(&mut merchant) {
+            Ok(value) => {
+                save(&self.path, &merchant).map_err(SquareError::internal)?;
+                Ok(value)
+            }
+            Err(error) => {
+                *merchant = before;
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Runs `f` on the merchant with subscriptions billed up to today
+    pub fn read<T>(&self, f: impl FnOnce(&Merchant) -> T) -> Result<T, SquareError> {
+        let mut merchant = self.lock();
+        if merchant.renew_subscriptions(Utc::now().date_naive()) {
+            save(&self.path, &merchant).map_err(SquareError::internal)?;
+        }
+        Ok(f(&merchant))
+    }
+}
+
+/// Written to a temporary file first, so a crash never leaves half a state behind
+fn save(path: &PathBuf, merchant: &Merchant) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(merchant)?)
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {:?}", path))?;
+    Ok(())
+}
+
+/// The request body as the Square request `T`
+pub fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, SquareError> {
+    serde_json::from_slice(body).map_err(|e| {
+        SquareError::bad_request("BAD_REQUEST", format!("Invalid request body: {}", e))
+    })
+}
+
+/// The request body, `{}` if it's empty as a search without filters may be
+pub fn parse_body(body: &[u8]) -> Result<Value, SquareError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(json!({}));
+    }
+    parse(body)
+}
+
+/// The strings of a search filter's array, `None` if it doesn't filter
+pub fn strings(filter: &Value) -> Option<Vec<String>> {
+    filter.as_array().map(|values| {
+        values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// A `limit` query parameter or body field, `default` if missing, capped at `max`
+pub fn limit(limit: Option<u64>, default: usize, max: usize) -> Result<usize, SquareError> {
+    match limit {
+        None => Ok(default),
+        Some(0) => Err(SquareError::bad_request(
+            "INVALID_VALUE",
+            "limit must be at least 1",
+        )),
+        Some(limit) => Ok((limit as usize).min(max)),
+    }
+}
+
+/// A `cursor` query parameter, an offset into what's listed
+pub fn offset(cursor: Option<&str>) -> Result<usize, SquareError> {
+    match cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .map_err(|_| SquareError::bad_request("INVALID_CURSOR", "Invalid cursor")),
+        None => Ok(0),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::error::SquareError;
+use crate::store::*;
+use crate::Emulator;
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+/// `POST /v2/subscriptions/search`, by `query.filter` customer, location and source names
+#[post("/subscriptions/search")]
+pub async fn search_subscriptions(
+    emulator: web::Data<Emulator>,
+    body: web::Bytes,
+) -> Result<HttpResponse, SquareError> {
+    let body = parse_body(&body)?;
+    let filter = &body["query"]["filter"];
+    let customer_ids = strings(&filter["customer_ids"]);
+    let location_ids = strings(&filter["location_ids"]);
+    let source_names = strings(&filter["source_names"]);
+    let limit = limit(body["limit"].as_u64(), 200, 200)?;
+    let mut subscriptions = emulator.store.read(|merchant| {
+        merchant.search_subscriptions(customer_ids.as_deref(), location_ids.as_deref())
+    })?;
+    if let Some(source_names) = source_names {
+        subscriptions.retain(|sub| source_names.contains(&sub.source.name));
+    }
+    subscriptions.truncate(limit);
+    Ok(HttpResponse::Ok().json(json!({ "subscriptions": subscriptions })))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RetrieveSubscriptionQuery {
+    include: Option<String>,
+}
+
+/// `GET /v2/subscriptions/{subscription_id}`, with its actions if `include=actions`
+#[get("/subscriptions/{subscription_id}")]
+pub async fn retrieve_subscription(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+    query: web::Query<RetrieveSubscriptionQuery>,
+) -> Result<HttpResponse, SquareError> {
+    let subscription_id = path.into_inner();
+    let mut subscription = emulator
+        .store
+        .read(|merchant| merchant.subscription(&subscription_id).cloned())?
+        .ok_or_else(|| {
+            SquareError::not_found(format!("Subscription {} not found", subscription_id))
+        })?;
+    let include_actions = query
+        .include
+        .as_deref()
+        .map_or(false, |include| include.split(',').any(|i| i == "actions"));
+    if !include_actions {
+        subscription.actions = None;
+    }
+    Ok(HttpResponse::Ok().json(json!({ "subscription": subscription })))
+}
+
+/// `POST /v2/subscriptions/{subscription_id}/cancel`, active until its charged through date
+/// like Square
+#[post("/subscriptions/{subscription_id}/cancel")]
+pub async fn cancel_subscription(
+    emulator: web::Data<Emulator>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, SquareError> {
+    let subscription_id = path.into_inner();
+    let response = emulator
+        .store
+        .update(|merchant| merchant.cancel_subscription(&subscription_id))?;
+    Ok(HttpResponse::Ok().json(response))
+}
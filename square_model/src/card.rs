@@ -6,7 +6,7 @@ pub struct CardBuilder {
   pub customer_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardRequest {
   pub idempotency_key: String,
   /// card token (from seller account) or payment_id
@@ -30,7 +30,7 @@ impl CardRequest {
   }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardRequestObject {
   pub billing_address: Address,
   /// Pull from [`CustomerResponse`](crate::CustomerResponse) first and last name
@@ -40,12 +40,19 @@ pub struct CardRequestObject {
   pub reference_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardResponse {
   pub card: CardResponseObject
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CardListResponse {
+  /// Square leaves this out when the customer has no cards
+  #[serde(default)]
+  pub cards: Vec<CardResponseObject>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardResponseObject {
   pub id: String,
   pub billing_address: Address,
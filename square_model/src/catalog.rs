@@ -22,6 +22,7 @@ pub struct CoachingCatalogBuilder {
 // ======================= Subscription Plan Request =======================
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Phase {
     pub uid: Option<String>,
     pub cadence: String,
@@ -30,7 +31,8 @@ pub struct Phase {
     pub pricing: Pricing,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionPlanData {
     pub name: String,
     pub all_items: Option<bool>,
@@ -38,6 +40,7 @@ pub struct SubscriptionPlanData {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct ItemData {
     // request fields
     pub abbreviation: Option<String>,
@@ -60,6 +63,7 @@ pub struct ItemData {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Variation {
     // request fields
     pub id: String,
@@ -75,6 +79,7 @@ pub struct Variation {
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct ItemVariationData {
     // request fields,
     pub name: String,
@@ -92,19 +97,21 @@ pub struct ItemVariationData {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct LocationOverride {
     pub location_id: String,
     pub track_inventory: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionPlanVariationData {
     pub name: String,
     pub phases: Vec<Phase>,
     pub subscription_plan_id: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CatalogRequestObject {
     pub present_at_all_locations: Option<bool>,
     /// SUBSCRIPTION_PLAN or ITEM
@@ -116,7 +123,7 @@ pub struct CatalogRequestObject {
     pub item_data: Option<ItemData>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CatalogRequest {
     pub object: CatalogRequestObject,
     pub idempotency_key: String,
@@ -228,7 +235,7 @@ impl CatalogRequest {
 
 // ======================= Subscription Plan Response =======================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CatalogResponse {
     pub catalog_object: CatalogResponseObject,
     pub id_mappings: Vec<IdMapping>,
@@ -264,7 +271,8 @@ impl CatalogResponse {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CatalogResponseObject {
     #[serde(rename = "type")]
     pub type_: String,
@@ -278,19 +286,22 @@ pub struct CatalogResponseObject {
     pub item_data: Option<ItemData>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct IdMapping {
     pub client_object_id: String,
     pub object_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionPlanResponse {
     pub catalog_object: SubscriptionPlanResponseObject,
     pub id_mappings: Vec<IdMapping>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionPlanResponseObject {
     pub created_at: String,
     pub id: String,
@@ -303,12 +314,13 @@ pub struct SubscriptionPlanResponseObject {
     pub version: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionPlanListResponse {
     pub objects: Vec<SubscriptionPlanResponseObject>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CatalogListResponse {
     pub objects: Vec<CatalogResponseObject>,
 }
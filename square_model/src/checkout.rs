@@ -4,6 +4,7 @@ use crate::{Price, Source};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UserEmailRequest {
     pub email: String,
 }
@@ -55,13 +56,10 @@ impl CheckoutRequest {
                 },
                 location_id: request.location_id,
             }),
-            pre_populated_data: match request.buyer_email {
-                Some(email) => Some(PrePopulatedData {
-                    buyer_email: Some(email),
-                    ..Default::default()
-                }),
-                None => None,
-            },
+            pre_populated_data: request.buyer_email.map(|email| PrePopulatedData {
+                buyer_email: Some(email),
+                ..Default::default()
+            }),
             checkout_options: Some(CheckoutOptions {
                 subscription_plan_id: request.subscription_plan_id,
                 redirect_url: Some(request.redirect_url),
@@ -206,6 +204,7 @@ pub struct NetAmounts {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CheckoutInfo {
     pub url: String,
     pub amount: f64,
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use serde_repr::{Serialize_repr, Deserialize_repr};
+
+#[derive(Debug, Clone, Serialize_repr, Deserialize_repr, PartialEq)]
+#[repr(u8)]
+pub enum CoachingPackage {
+  Single,
+  Three,
+  Six,
+  Ten
+}
+
+impl CoachingPackage {
+  pub fn name(&self) -> String {
+    match self {
+      CoachingPackage::Single => "Single Session".to_string(),
+      CoachingPackage::Three => "Package of 3".to_string(),
+      CoachingPackage::Six => "Package of 6".to_string(),
+      CoachingPackage::Ten => "Package of 10".to_string()
+    }
+  }
+
+  /// Number of sessions the package is for
+  pub fn sessions(&self) -> u32 {
+    match self {
+      CoachingPackage::Single => 1,
+      CoachingPackage::Three => 3,
+      CoachingPackage::Six => 6,
+      CoachingPackage::Ten => 10
+    }
+  }
+
+  /// The package an order line item's catalog variation name refers to
+  pub fn from_name(name: &str) -> Option<Self> {
+    [
+      CoachingPackage::Single,
+      CoachingPackage::Three,
+      CoachingPackage::Six,
+      CoachingPackage::Ten
+    ]
+    .into_iter()
+    .find(|package| package.name() == name)
+  }
+}
+
+/// Coaching sessions bought in one order line item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+pub struct CoachingSessions {
+  pub order_id: String,
+  /// [`CoachingPackage::name`]
+  pub package: String,
+  /// Sessions bought, the package's sessions times the quantity ordered
+  pub sessions: u32,
+  /// Square order state, e.g. COMPLETED
+  pub state: String,
+  pub purchased_at: String,
+}
\ No newline at end of file
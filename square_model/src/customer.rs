@@ -1,10 +1,12 @@
-use crate::square::{SquareErrorResponse, SquareResponse};
+use crate::SquareResponse;
 use crate::Address;
 use serde::{Deserialize, Serialize};
 
 // ======================= Create Customer Request =======================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Square only needs one of the fields, missing ones are empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CustomerRequest {
     pub email_address: String,
     pub family_name: String,
@@ -13,6 +15,7 @@ pub struct CustomerRequest {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Preferences {
     pub email_unsubscribed: bool,
 }
@@ -20,6 +23,7 @@ pub struct Preferences {
 // ======================= Create Customer Response =======================
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CustomerResponse {
     pub created_at: String,
     pub creation_source: String,
@@ -34,6 +38,7 @@ pub struct CustomerResponse {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Card {
     pub id: String,
     pub card_brand: String,
@@ -46,29 +51,29 @@ pub struct Card {
 
 // ======================= Update Customer Response =======================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateCustomerResponse {
     pub customer: CustomerResponse,
 }
 
 // ======================= Search Customer Request =======================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchCustomerExact {
     exact: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchCustomerEmailAddress {
     email_address: SearchCustomerExact,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchCustomerFilter {
     filter: SearchCustomerEmailAddress,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchCustomerRequest {
     query: SearchCustomerFilter,
 }
@@ -89,7 +94,7 @@ impl SearchCustomerRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateCustomerAttributeRequest {
     pub idempotency_key: String,
     pub custom_attribute: CustomAttribute,
@@ -107,13 +112,13 @@ impl UpdateCustomerAttributeRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomAttribute {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateCustomAttributeRequest {
     pub idempotency_key: String,
     pub custom_attribute_definition: CustomAttributeDefinitionRequest,
@@ -133,7 +138,7 @@ impl CreateCustomAttributeRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomAttributeDefinitionRequest {
     pub key: String,
     pub schema: CustomAttributeSchemaObject,
@@ -154,12 +159,15 @@ pub struct SearchCustomerResponse {
     pub customers: Vec<CustomerResponse>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CustomerListResponse {
     pub customers: Vec<CustomerResponse>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CustomerInfo {
     pub email_address: String,
     pub family_name: String,
@@ -168,6 +176,8 @@ pub struct CustomerInfo {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CardInfo {
     pub card_brand: String,
     pub last_4: String,
@@ -176,6 +186,18 @@ pub struct CardInfo {
     pub cardholder_name: String,
 }
 
+impl From<Card> for CardInfo {
+    fn from(card: Card) -> Self {
+        CardInfo {
+            card_brand: card.card_brand,
+            last_4: card.last_4,
+            exp_month: card.exp_month,
+            exp_year: card.exp_year,
+            cardholder_name: card.cardholder_name,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomAttributeResponses {
     pub sessions: SquareResponse<CreateCustomAttributeResponse>,
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum SquareResponse<T> {
+    Success(T),
+    Error(SquareErrorResponse),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SquareErrorResponse {
+    pub errors: Vec<SquareError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SquareError {
+    pub category: String,
+    pub code: String,
+    #[serde(default)]
+    pub detail: String,
+    /// The request field at fault, if any
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub field: String,
+}
+
+impl SquareErrorResponse {
+    pub fn new(category: &str, code: &str, detail: impl Into<String>) -> Self {
+        Self {
+            errors: vec![SquareError {
+                category: category.to_string(),
+                code: code.to_string(),
+                detail: detail.into(),
+                field: String::new(),
+            }],
+        }
+    }
+
+    pub fn bad_request(code: &str, detail: impl Into<String>) -> Self {
+        Self::new("INVALID_REQUEST_ERROR", code, detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::bad_request("NOT_FOUND", detail)
+    }
+
+    /// `field` of the request is missing or empty
+    pub fn missing(field: &str) -> Self {
+        let mut response = Self::bad_request(
+            "MISSING_REQUIRED_PARAMETER",
+            format!("Field must be set: {}", field),
+        );
+        response.errors[0].field = field.to_string();
+        response
+    }
+
+    /// The code of the first error
+    pub fn code(&self) -> &str {
+        self.errors.first().map_or("", |error| error.code.as_str())
+    }
+
+    pub fn from_value(value: serde_json::Value) -> serde_json::Result<SquareErrorResponse> {
+        serde_json::from_value(value)
+    }
+}
+
+impl<T> From<Result<T, SquareErrorResponse>> for SquareResponse<T> {
+    fn from(result: Result<T, SquareErrorResponse>) -> Self {
+        match result {
+            Ok(value) => SquareResponse::Success(value),
+            Err(error) => SquareResponse::Error(error),
+        }
+    }
+}
@@ -2,12 +2,14 @@ use crate::Price;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct InvoiceListResponse {
     pub invoices: Vec<Invoice>,
     pub cursor: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Invoice {
     pub id: String,
     pub version: u64,
@@ -33,6 +35,7 @@ pub struct Invoice {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PaymentMethod {
     pub bank_account: bool,
     pub buy_now_pay_later: bool,
@@ -42,6 +45,7 @@ pub struct PaymentMethod {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PaymentRequest {
     pub automatic_payment_source: String,
     pub card_id: String,
@@ -54,6 +58,7 @@ pub struct PaymentRequest {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Recipient {
     pub customer_id: String,
     pub email_address: String,
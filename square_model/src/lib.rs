@@ -0,0 +1,60 @@
+pub mod card;
+pub mod catalog;
+pub mod customer;
+pub mod checkout;
+pub mod location;
+pub mod subscription;
+pub mod invoice;
+pub mod coaching_package;
+pub mod webhook;
+pub mod error;
+pub mod order;
+pub mod merchant;
+
+pub use card::*;
+pub use catalog::*;
+pub use customer::*;
+pub use checkout::*;
+pub use location::*;
+pub use subscription::*;
+pub use invoice::*;
+pub use coaching_package::*;
+pub use webhook::*;
+pub use error::*;
+pub use order::*;
+pub use merchant::*;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct Source {
+  pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct Price {
+  /// Amount is smallest denomination of currency, so cents for USD
+  /// Ex: 1295 for $12.95
+  pub amount: u64,
+  pub currency: String
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct Pricing {
+  /// STATIC
+  #[serde(rename = "type")]
+  pub type_: String,
+  pub price: Option<Price>,
+  pub price_money: Option<Price>
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CustomerEmailInfo {
+  pub email_address: String,
+  pub family_name: String,
+  pub given_name: String,
+}
\ No newline at end of file
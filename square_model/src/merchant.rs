@@ -0,0 +1,1606 @@
+use crate::*;
+use chrono::{Datelike, Months, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Sandbox nonce of a card Square declines, any other source is a Visa ending in 1111
+pub const DECLINED_CARD_NONCE: &str = "cnon:card-nonce-declined";
+/// Sandbox nonce of a card Square accepts
+pub const CARD_NONCE: &str = "cnon:card-nonce-ok";
+const SOURCE_NAME: &str = "Consciousness Archive";
+
+/// A Square merchant account, its objects kept as Square returns them, oldest first.
+///
+/// It answers like the Square sandbox: unknown ids are `NOT_FOUND` errors, cancelling keeps a
+/// subscription active until its charged through date and only enabled cards are listed. The
+/// server's in-memory payments keep one for as long as they run, the Square emulator saves its
+/// one to the state file after every change.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Merchant {
+    pub merchant_id: String,
+    pub locations: Vec<LocationResponse>,
+    /// Without `cards`, which come from `cards`
+    pub customers: Vec<CustomerResponse>,
+    pub custom_attribute_definitions: Vec<CustomAttributeDefinitionResponse>,
+    /// Custom attributes by customer id, then key
+    pub custom_attributes: BTreeMap<String, BTreeMap<String, CustomAttributeResponse>>,
+    pub cards: Vec<CardResponseObject>,
+    /// Subscription plans and items, their variations nested in them
+    pub catalog: Vec<CatalogResponseObject>,
+    pub payment_links: Vec<PaymentLink>,
+    pub orders: Vec<OrderObject>,
+    /// With their actions
+    pub subscriptions: Vec<SubscriptionResponseObject>,
+    pub invoices: Vec<Invoice>,
+}
+
+/// A catalog object of any type the merchant keeps
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum CatalogObject {
+    /// A subscription plan or item
+    Object(CatalogResponseObject),
+    PlanVariation(SubscriptionPlanResponseObject),
+    ItemVariation(Variation),
+}
+
+/// The answer to a catalog object upsert
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogUpsert {
+    pub catalog_object: CatalogObject,
+    /// The ids `#` temporary ids in the request were given
+    pub id_mappings: Vec<IdMapping>,
+}
+
+/// A payment link paid by [`Merchant::complete_checkout`]
+#[derive(Debug, Clone, Serialize)]
+pub struct PaidCheckout {
+    pub payment_link: PaymentLink,
+    /// The buyer, with their new card
+    pub customer: CustomerResponse,
+    pub order: OrderObject,
+    /// `None` unless the link was for a subscription plan
+    pub subscription: Option<SubscriptionResponseObject>,
+}
+
+impl Merchant {
+    /// A new merchant with the location `location_id`
+    pub fn new(location_id: &str) -> Self {
+        let mut merchant = Merchant {
+            merchant_id: new_id(),
+            ..Merchant::default()
+        };
+        merchant.open_location(location_id);
+        merchant
+    }
+
+    /// Adds the location `id` unless the merchant has it
+    pub fn open_location(&mut self, id: &str) {
+        if self.location(id).is_none() {
+            let location = location(id, &self.merchant_id);
+            self.locations.push(location);
+        }
+    }
+
+    pub fn location(&self, id: &str) -> Option<&LocationResponse> {
+        self.locations.iter().find(|location| location.id == id)
+    }
+
+    fn check_location(&self, id: &str) -> Result<(), SquareErrorResponse> {
+        match self.location(id) {
+            Some(_) => Ok(()),
+            None => Err(SquareErrorResponse::not_found(format!(
+                "Location {} not found",
+                id
+            ))),
+        }
+    }
+
+    // ============================ Customers ============================ //
+
+    /// The customer with their enabled cards, as Square still lists them on customers
+    pub fn with_cards(&self, customer: &CustomerResponse) -> CustomerResponse {
+        let cards = self
+            .cards
+            .iter()
+            .filter(|card| card.customer_id == customer.id && card.enabled)
+            .map(|card| customer::Card {
+                id: card.id.clone(),
+                card_brand: card.card_brand.clone(),
+                last_4: card.last_4.clone(),
+                exp_month: card.exp_month,
+                exp_year: card.exp_year,
+                cardholder_name: card.cardholder_name.clone(),
+                billing_address: card.billing_address.clone(),
+            })
+            .collect::<Vec<customer::Card>>();
+        CustomerResponse {
+            cards: Some(cards).filter(|cards| !cards.is_empty()),
+            ..customer.clone()
+        }
+    }
+
+    pub fn customer(&self, id: &str) -> Option<CustomerResponse> {
+        self.customers
+            .iter()
+            .find(|customer| customer.id == id)
+            .map(|customer| self.with_cards(customer))
+    }
+
+    /// Customers with exactly this email address ignoring case, or every customer
+    pub fn search_customers(&self, email: Option<&str>) -> Vec<CustomerResponse> {
+        self.customers
+            .iter()
+            .filter(|customer| {
+                email.map_or(true, |email| {
+                    customer.email_address.eq_ignore_ascii_case(email)
+                })
+            })
+            .map(|customer| self.with_cards(customer))
+            .collect()
+    }
+
+    pub fn find_customer(&self, email: &str) -> Option<CustomerResponse> {
+        self.search_customers(Some(email)).into_iter().next()
+    }
+
+    pub fn create_customer(
+        &mut self,
+        request: &CustomerRequest,
+    ) -> Result<CustomerResponse, SquareErrorResponse> {
+        if request.email_address.is_empty()
+            && request.given_name.is_empty()
+            && request.family_name.is_empty()
+        {
+            return Err(SquareErrorResponse::bad_request(
+                "MISSING_REQUIRED_PARAMETER",
+                "At least one of email_address, given_name or family_name is required",
+            ));
+        }
+        let now = now();
+        let customer = CustomerResponse {
+            created_at: now.clone(),
+            creation_source: "THIRD_PARTY".to_string(),
+            email_address: request.email_address.clone(),
+            family_name: request.family_name.clone(),
+            given_name: request.given_name.clone(),
+            id: new_id(),
+            preferences: Preferences {
+                email_unsubscribed: false,
+            },
+            updated_at: now,
+            version: 0,
+            cards: None,
+        };
+        self.customers.push(customer.clone());
+        Ok(customer)
+    }
+
+    pub fn update_customer(
+        &mut self,
+        id: &str,
+        request: &CustomerRequest,
+    ) -> Result<CustomerResponse, SquareErrorResponse> {
+        let customer = self
+            .customers
+            .iter_mut()
+            .find(|customer| customer.id == id)
+            .ok_or_else(|| SquareErrorResponse::not_found(format!("Customer {} not found", id)))?;
+        customer.email_address = request.email_address.clone();
+        customer.family_name = request.family_name.clone();
+        customer.given_name = request.given_name.clone();
+        customer.updated_at = now();
+        customer.version += 1;
+        let customer = customer.clone();
+        Ok(self.with_cards(&customer))
+    }
+
+    pub fn custom_attribute_definition(
+        &self,
+        key: &str,
+    ) -> Option<&CustomAttributeDefinitionResponse> {
+        self.custom_attribute_definitions
+            .iter()
+            .find(|definition| definition.key == key)
+    }
+
+    /// A hidden custom attribute every customer can have, keys are unique
+    pub fn create_custom_attribute_definition(
+        &mut self,
+        request: &CustomAttributeDefinitionRequest,
+    ) -> Result<CustomAttributeDefinitionResponse, SquareErrorResponse> {
+        if request.key.is_empty() {
+            return Err(SquareErrorResponse::missing(
+                "custom_attribute_definition.key",
+            ));
+        }
+        if self.custom_attribute_definition(&request.key).is_some() {
+            return Err(SquareErrorResponse::bad_request(
+                "CONFLICT",
+                format!("Custom attribute definition {} already exists", request.key),
+            ));
+        }
+        let now = now();
+        let definition = CustomAttributeDefinitionResponse {
+            key: request.key.clone(),
+            version: 1,
+            updated_at: now.clone(),
+            schema: request.schema.clone(),
+            created_at: now,
+            visibility: "VISIBILITY_HIDDEN".to_string(),
+        };
+        self.custom_attribute_definitions.push(definition.clone());
+        Ok(definition)
+    }
+
+    pub fn custom_attributes(
+        &self,
+        customer_id: &str,
+    ) -> Result<Vec<CustomAttributeResponse>, SquareErrorResponse> {
+        if self.customer(customer_id).is_none() {
+            return Err(SquareErrorResponse::not_found(format!(
+                "Customer {} not found",
+                customer_id
+            )));
+        }
+        Ok(self
+            .custom_attributes
+            .get(customer_id)
+            .map(|attributes| attributes.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    pub fn custom_attribute(
+        &self,
+        customer_id: &str,
+        key: &str,
+    ) -> Option<&CustomAttributeResponse> {
+        self.custom_attributes
+            .get(customer_id)
+            .and_then(|attributes| attributes.get(key))
+    }
+
+    /// Sets the customer's custom attribute `key`, which must be defined
+    pub fn upsert_custom_attribute(
+        &mut self,
+        customer_id: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<CustomAttributeResponse, SquareErrorResponse> {
+        if self.customer(customer_id).is_none() {
+            return Err(SquareErrorResponse::not_found(format!(
+                "Customer {} not found",
+                customer_id
+            )));
+        }
+        let visibility = self
+            .custom_attribute_definition(key)
+            .ok_or_else(|| {
+                SquareErrorResponse::not_found(format!(
+                    "Custom attribute definition {} not found",
+                    key
+                ))
+            })?
+            .visibility
+            .clone();
+        let now = now();
+        let attribute = self
+            .custom_attributes
+            .entry(customer_id.to_string())
+            .or_default()
+            .entry(key.to_string())
+            .or_insert_with(|| CustomAttributeResponse {
+                key: key.to_string(),
+                version: 0,
+                updated_at: now.clone(),
+                value: String::new(),
+                created_at: now.clone(),
+                visibility: visibility.clone(),
+            });
+        attribute.value = value.to_string();
+        attribute.visibility = visibility;
+        attribute.updated_at = now;
+        attribute.version += 1;
+        Ok(attribute.clone())
+    }
+
+    // ============================ Cards ============================ //
+
+    pub fn card(&self, id: &str) -> Option<&CardResponseObject> {
+        self.cards.iter().find(|card| card.id == id)
+    }
+
+    /// Cards of `customer_id`, or of every customer, the disabled ones only if
+    /// `include_disabled`
+    pub fn list_cards(
+        &self,
+        customer_id: Option<&str>,
+        include_disabled: bool,
+    ) -> Vec<CardResponseObject> {
+        self.cards
+            .iter()
+            .filter(|card| {
+                customer_id.map_or(true, |id| card.customer_id == id)
+                    && (include_disabled || card.enabled)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// A card on file for the customer, stored from the request's `source_id`
+    pub fn create_card(
+        &mut self,
+        request: &CardRequest,
+    ) -> Result<CardResponseObject, SquareErrorResponse> {
+        if request.source_id.is_empty() {
+            return Err(SquareErrorResponse::missing("source_id"));
+        }
+        if request.card.customer_id.is_empty() {
+            return Err(SquareErrorResponse::missing("card.customer_id"));
+        }
+        if self.customer(&request.card.customer_id).is_none() {
+            return Err(SquareErrorResponse::not_found(format!(
+                "Customer {} not found",
+                request.card.customer_id
+            )));
+        }
+        if request.source_id == DECLINED_CARD_NONCE {
+            return Err(SquareErrorResponse::new(
+                "PAYMENT_METHOD_ERROR",
+                "CARD_DECLINED",
+                "Authorization error: 'CARD_DECLINED'",
+            ));
+        }
+        let card = CardResponseObject {
+            id: format!("ccof:{}", new_id()),
+            billing_address: request.card.billing_address.clone(),
+            fingerprint: Some(format!("sq-1-{}", new_id())),
+            bin: "411111".to_string(),
+            card_brand: "VISA".to_string(),
+            card_type: "CREDIT".to_string(),
+            cardholder_name: request.card.cardholder_name.clone(),
+            customer_id: request.card.customer_id.clone(),
+            enabled: true,
+            exp_month: 11,
+            exp_year: today().year() as u16 + 3,
+            last_4: "1111".to_string(),
+            merchant_id: Some(self.merchant_id.clone()),
+            prepaid_type: "NOT_PREPAID".to_string(),
+            reference_id: request.card.reference_id.clone(),
+            version: 1,
+        };
+        self.cards.push(card.clone());
+        Ok(card)
+    }
+
+    pub fn disable_card(&mut self, id: &str) -> Result<CardResponseObject, SquareErrorResponse> {
+        let card = self
+            .cards
+            .iter_mut()
+            .find(|card| card.id == id)
+            .ok_or_else(|| SquareErrorResponse::not_found(format!("Card {} not found", id)))?;
+        if card.enabled {
+            card.enabled = false;
+            card.version += 1;
+        }
+        Ok(card.clone())
+    }
+
+    // ============================ Catalog ============================ //
+
+    /// A plan or item, or a variation nested in one
+    pub fn catalog_object(&self, id: &str) -> Option<CatalogObject> {
+        self.catalog.iter().find_map(|object| {
+            if object.id == id {
+                return Some(CatalogObject::Object(object.clone()));
+            }
+            if let Some(variation) = plan_variations(object).find(|variation| variation.id == id) {
+                return Some(CatalogObject::PlanVariation(variation.clone()));
+            }
+            item_variations(object)
+                .find(|variation| variation.id == id)
+                .map(|variation| CatalogObject::ItemVariation(variation.clone()))
+        })
+    }
+
+    /// Plans and items whose type is one of `types`, with the variations of those types nested
+    /// in them, or every plan and item
+    pub fn list_catalog(&self, types: Option<&[String]>) -> Vec<CatalogObject> {
+        let listed = |type_: &str| types.map_or(true, |types| types.iter().any(|t| t == type_));
+        let mut objects = Vec::new();
+        for object in self.catalog.iter() {
+            if listed(&object.type_) {
+                objects.push(CatalogObject::Object(object.clone()));
+            }
+            if types.is_some() {
+                objects.extend(
+                    plan_variations(object)
+                        .filter(|variation| listed(&variation.type_))
+                        .cloned()
+                        .map(CatalogObject::PlanVariation),
+                );
+                objects.extend(
+                    item_variations(object)
+                        .filter(|variation| listed(&variation.type_))
+                        .cloned()
+                        .map(CatalogObject::ItemVariation),
+                );
+            }
+        }
+        objects
+    }
+
+    pub fn plan_variation(&self, id: &str) -> Option<&SubscriptionPlanResponseObject> {
+        self.catalog
+            .iter()
+            .flat_map(plan_variations)
+            .find(|variation| variation.id == id)
+    }
+
+    /// The item variation `id` and the name of its item
+    fn item_variation(&self, id: &str) -> Option<(&Variation, &str)> {
+        self.catalog.iter().find_map(|item| {
+            let variation = item_variations(item).find(|variation| variation.id == id)?;
+            let name = item.item_data.as_ref()?.name.as_deref().unwrap_or_default();
+            Some((variation, name))
+        })
+    }
+
+    /// A plan and its monthly variation at `price` cents, `id` or a new one for the plan
+    pub fn create_plan(
+        &mut self,
+        id: Option<String>,
+        name: &str,
+        price: u64,
+    ) -> SubscriptionPlanResponseObject {
+        let now = now();
+        let plan_id = id.unwrap_or_else(new_id);
+        let variation = SubscriptionPlanResponseObject {
+            created_at: now.clone(),
+            id: new_id(),
+            is_deleted: false,
+            present_at_all_locations: true,
+            subscription_plan_variation_data: SubscriptionPlanVariationData {
+                name: name.to_string(),
+                phases: vec![Phase {
+                    uid: Some(new_id()),
+                    cadence: "MONTHLY".to_string(),
+                    ordinal: Some(0),
+                    periods: None,
+                    pricing: Pricing {
+                        type_: "STATIC".to_string(),
+                        price: None,
+                        price_money: Some(usd(price)),
+                    },
+                }],
+                subscription_plan_id: plan_id.clone(),
+            },
+            type_: "SUBSCRIPTION_PLAN_VARIATION".to_string(),
+            updated_at: now.clone(),
+            version: 1,
+        };
+        self.catalog.retain(|object| object.id != plan_id);
+        self.catalog.push(CatalogResponseObject {
+            type_: "SUBSCRIPTION_PLAN".to_string(),
+            id: plan_id,
+            updated_at: now.clone(),
+            created_at: now,
+            version: 1,
+            present_at_all_locations: true,
+            is_deleted: false,
+            subscription_plan_data: Some(SubscriptionPlanData {
+                name: name.to_string(),
+                all_items: Some(true),
+                subscription_plan_variations: Some(vec![variation.clone()]),
+            }),
+            item_data: None,
+        });
+        variation
+    }
+
+    /// Creates the object if its id is a `#` temporary id, or replaces the existing one. A plan
+    /// keeps its variations, which are upserted on their own and added to the plan they name.
+    /// An item's variations are upserted with it, one without variations keeps the ones it has.
+    pub fn upsert_catalog_object(
+        &mut self,
+        object: &CatalogRequestObject,
+    ) -> Result<CatalogUpsert, SquareErrorResponse> {
+        let mut id_mappings = Vec::new();
+        let now = now();
+        let version = Utc::now().timestamp_millis() as u64;
+        let present_at_all_locations = object.present_at_all_locations.unwrap_or(true);
+        let catalog_object = match object.type_.as_str() {
+            "SUBSCRIPTION_PLAN" => {
+                let data = object
+                    .subscription_plan_data
+                    .as_ref()
+                    .filter(|data| !data.name.is_empty())
+                    .ok_or_else(|| SquareErrorResponse::missing("subscription_plan_data.name"))?;
+                let (id, existing) = self.upsert_target(object, &mut id_mappings)?;
+                let variations = existing
+                    .as_ref()
+                    .and_then(|existing| existing.subscription_plan_data.as_ref())
+                    .and_then(|data| data.subscription_plan_variations.clone());
+                let plan = CatalogResponseObject {
+                    type_: object.type_.clone(),
+                    id,
+                    updated_at: now.clone(),
+                    created_at: existing.map_or(now, |existing| existing.created_at),
+                    version,
+                    present_at_all_locations,
+                    is_deleted: false,
+                    subscription_plan_data: Some(SubscriptionPlanData {
+                        subscription_plan_variations: variations,
+                        ..data.clone()
+                    }),
+                    item_data: None,
+                };
+                replace_or_push(&mut self.catalog, plan.clone(), |a, b| a.id == b.id);
+                CatalogObject::Object(plan)
+            }
+            "SUBSCRIPTION_PLAN_VARIATION" => {
+                let mut data = object
+                    .subscription_plan_variation_data
+                    .clone()
+                    .filter(|data| !data.name.is_empty())
+                    .ok_or_else(|| {
+                        SquareErrorResponse::missing("subscription_plan_variation_data.name")
+                    })?;
+                if data.phases.is_empty() {
+                    return Err(SquareErrorResponse::missing(
+                        "subscription_plan_variation_data.phases",
+                    ));
+                }
+                for (ordinal, phase) in data.phases.iter_mut().enumerate() {
+                    phase.uid.get_or_insert_with(new_id);
+                    phase.ordinal.get_or_insert(ordinal as u32);
+                }
+                let (id, created_at) = match new_object_id(&object.id, &mut id_mappings)? {
+                    Some(id) => (id, now.clone()),
+                    None => {
+                        let existing = self.plan_variation(&object.id).ok_or_else(|| {
+                            SquareErrorResponse::not_found(format!(
+                                "Catalog object {} not found",
+                                object.id
+                            ))
+                        })?;
+                        (existing.id.clone(), existing.created_at.clone())
+                    }
+                };
+                let plan = self
+                    .catalog
+                    .iter_mut()
+                    .find(|plan| {
+                        plan.id == data.subscription_plan_id && plan.type_ == "SUBSCRIPTION_PLAN"
+                    })
+                    .ok_or_else(|| {
+                        SquareErrorResponse::not_found(format!(
+                            "Catalog object {} not found",
+                            data.subscription_plan_id
+                        ))
+                    })?;
+                let variation = SubscriptionPlanResponseObject {
+                    created_at,
+                    id,
+                    is_deleted: false,
+                    present_at_all_locations,
+                    subscription_plan_variation_data: data,
+                    type_: object.type_.clone(),
+                    updated_at: now.clone(),
+                    version,
+                };
+                plan.version = version;
+                plan.updated_at = now;
+                if let Some(plan_data) = &mut plan.subscription_plan_data {
+                    let variations = plan_data
+                        .subscription_plan_variations
+                        .get_or_insert_with(Vec::new);
+                    replace_or_push(variations, variation.clone(), |a, b| a.id == b.id);
+                }
+                CatalogObject::PlanVariation(variation)
+            }
+            "ITEM" => {
+                let data = object
+                    .item_data
+                    .as_ref()
+                    .filter(|data| data.name.as_deref().map_or(false, |name| !name.is_empty()))
+                    .ok_or_else(|| SquareErrorResponse::missing("item_data.name"))?;
+                let (id, existing) = self.upsert_target(object, &mut id_mappings)?;
+                let existing_variations = existing
+                    .as_ref()
+                    .map(|existing| {
+                        item_variations(existing)
+                            .cloned()
+                            .collect::<Vec<Variation>>()
+                    })
+                    .unwrap_or_default();
+                let variations = match &data.variations {
+                    Some(requested) => requested
+                        .iter()
+                        .map(|variation| {
+                            let mut variation = variation.clone();
+                            if variation.type_ != "ITEM_VARIATION" {
+                                return Err(SquareErrorResponse::bad_request(
+                                    "INVALID_VALUE",
+                                    format!("ITEM can't have a {} variation", variation.type_),
+                                ));
+                            }
+                            let created_at = match new_object_id(&variation.id, &mut id_mappings)? {
+                                Some(new_id) => {
+                                    variation.id = new_id;
+                                    now.clone()
+                                }
+                                None => existing_variations
+                                    .iter()
+                                    .find(|existing| existing.id == variation.id)
+                                    .and_then(|existing| existing.created_at.clone())
+                                    .ok_or_else(|| {
+                                        SquareErrorResponse::not_found(format!(
+                                            "Catalog object {} not found",
+                                            variation.id
+                                        ))
+                                    })?,
+                            };
+                            let data = &mut variation.item_variation_data;
+                            data.item_id = id.clone();
+                            if data.pricing_type.is_empty() {
+                                data.pricing_type = "FIXED_PRICING".to_string();
+                            }
+                            variation.created_at = Some(created_at);
+                            variation.updated_at = Some(now.clone());
+                            variation.version = Some(version);
+                            variation.present_at_all_locations.get_or_insert(true);
+                            variation.is_deleted = Some(false);
+                            Ok(variation)
+                        })
+                        .collect::<Result<Vec<Variation>, SquareErrorResponse>>()?,
+                    None => existing_variations,
+                };
+                let item = CatalogResponseObject {
+                    type_: object.type_.clone(),
+                    id,
+                    updated_at: now.clone(),
+                    created_at: existing.map_or(now, |existing| existing.created_at),
+                    version,
+                    present_at_all_locations,
+                    is_deleted: false,
+                    subscription_plan_data: None,
+                    item_data: Some(ItemData {
+                        variations: Some(variations),
+                        ..data.clone()
+                    }),
+                };
+                replace_or_push(&mut self.catalog, item.clone(), |a, b| a.id == b.id);
+                CatalogObject::Object(item)
+            }
+            type_ => {
+                return Err(SquareErrorResponse::bad_request(
+                    "INVALID_VALUE",
+                    format!("Unsupported catalog object type {:?}", type_),
+                ))
+            }
+        };
+        Ok(CatalogUpsert {
+            catalog_object,
+            id_mappings,
+        })
+    }
+
+    /// The id a plan or item upsert writes to and the object it replaces, none for a new one
+    fn upsert_target(
+        &self,
+        object: &CatalogRequestObject,
+        id_mappings: &mut Vec<IdMapping>,
+    ) -> Result<(String, Option<CatalogResponseObject>), SquareErrorResponse> {
+        if let Some(id) = new_object_id(&object.id, id_mappings)? {
+            return Ok((id, None));
+        }
+        self.catalog
+            .iter()
+            .find(|existing| existing.id == object.id && existing.type_ == object.type_)
+            .map(|existing| (existing.id.clone(), Some(existing.clone())))
+            .ok_or_else(|| {
+                SquareErrorResponse::not_found(format!("Catalog object {} not found", object.id))
+            })
+    }
+
+    // ============================ Checkout ============================ //
+
+    /// A payment link for a `quick_pay` amount or an `order` of catalog item variations. Its
+    /// `url` is `checkout_url` followed by its id.
+    pub fn create_payment_link(
+        &mut self,
+        request: &CheckoutRequest,
+        checkout_url: &str,
+    ) -> Result<CheckoutResponse, SquareErrorResponse> {
+        let order = match (&request.quick_pay, &request.order) {
+            (Some(quick_pay), _) => {
+                if quick_pay.name.is_empty() {
+                    return Err(SquareErrorResponse::missing("quick_pay.name"));
+                }
+                self.check_location(&quick_pay.location_id)?;
+                let item = line_item(&quick_pay.name, None, None, quick_pay.price_money.amount, 1);
+                new_order(&quick_pay.location_id, None, vec![item])
+            }
+            (None, Some(order)) => {
+                self.check_location(&order.location_id)?;
+                let customer_id = Some(order.customer_id.as_str()).filter(|id| !id.is_empty());
+                if let Some(customer_id) = customer_id {
+                    if self.customer(customer_id).is_none() {
+                        return Err(SquareErrorResponse::not_found(format!(
+                            "Customer {} not found",
+                            customer_id
+                        )));
+                    }
+                }
+                if order.line_items.is_empty() {
+                    return Err(SquareErrorResponse::missing("order.line_items"));
+                }
+                let line_items = order
+                    .line_items
+                    .iter()
+                    .map(|item| self.catalog_line_item(item))
+                    .collect::<Result<Vec<LineItem>, SquareErrorResponse>>()?;
+                new_order(&order.location_id, customer_id, line_items)
+            }
+            (None, None) => return Err(SquareErrorResponse::missing("quick_pay")),
+        };
+        let subscription_plans = match &request.checkout_options {
+            Some(options) if !options.subscription_plan_id.is_empty() => {
+                let variation = self
+                    .plan_variation(&options.subscription_plan_id)
+                    .ok_or_else(|| {
+                        SquareErrorResponse::not_found(format!(
+                            "Subscription plan variation {} not found",
+                            options.subscription_plan_id
+                        ))
+                    })?;
+                Some(vec![variation.clone()])
+            }
+            _ => None,
+        };
+
+        let id = new_id();
+        let url = format!("{}{}", checkout_url, id);
+        let payment_link = PaymentLink {
+            checkout_options: request.checkout_options.clone(),
+            pre_populated_data: request.pre_populated_data.clone(),
+            created_at: now(),
+            long_url: format!("{}?src=sheet", url),
+            url,
+            id,
+            order_id: order.id.clone(),
+            version: 1,
+        };
+        let response = CheckoutResponse {
+            payment_link: payment_link.clone(),
+            related_resources: RelatedResources {
+                orders: vec![order_response(&order)],
+                subscription_plans,
+            },
+        };
+        self.orders.push(order);
+        self.payment_links.push(payment_link);
+        Ok(response)
+    }
+
+    /// A line item for a `catalog_object_id` item variation, named after its item
+    fn catalog_line_item(&self, item: &LineItemRequest) -> Result<LineItem, SquareErrorResponse> {
+        let quantity = item
+            .quantity
+            .parse::<u64>()
+            .ok()
+            .filter(|quantity| *quantity > 0)
+            .ok_or_else(|| {
+                SquareErrorResponse::bad_request(
+                    "INVALID_VALUE",
+                    "quantity must be a positive integer",
+                )
+            })?;
+        let (variation, item_name) =
+            self.item_variation(&item.catalog_object_id)
+                .ok_or_else(|| {
+                    SquareErrorResponse::not_found(format!(
+                        "Item variation {} not found",
+                        item.catalog_object_id
+                    ))
+                })?;
+        let data = &variation.item_variation_data;
+        Ok(line_item(
+            item_name,
+            Some(&data.name),
+            Some(&variation.id),
+            data.price_money.amount,
+            quantity,
+        ))
+    }
+
+    /// The link with this id or URL
+    pub fn payment_link(&self, id_or_url: &str) -> Option<&PaymentLink> {
+        self.payment_links
+            .iter()
+            .find(|link| link.id == id_or_url || link.url == id_or_url)
+    }
+
+    pub fn order(&self, id: &str) -> Option<&OrderObject> {
+        self.orders.iter().find(|order| order.id == id)
+    }
+
+    /// The buyer paying the link with this id or URL: the order's customer, or the one with the
+    /// buyer's email who is created if new, stores the card from `source_id` and pays the order,
+    /// starting the subscription with its first invoice paid for a plan's link
+    pub fn complete_checkout(
+        &mut self,
+        payment_link: &str,
+        buyer: &CustomerRequest,
+        source_id: &str,
+    ) -> Result<PaidCheckout, SquareErrorResponse> {
+        let link = self.payment_link(payment_link).cloned().ok_or_else(|| {
+            SquareErrorResponse::not_found(format!("Payment link {} not found", payment_link))
+        })?;
+        let order = self
+            .order(&link.order_id)
+            .filter(|order| order.state == "OPEN")
+            .ok_or_else(|| {
+                SquareErrorResponse::bad_request(
+                    "BAD_REQUEST",
+                    format!("Payment link {} was already paid", payment_link),
+                )
+            })?;
+
+        let customer = match Some(order.customer_id.as_str()).filter(|id| !id.is_empty()) {
+            Some(customer_id) => self.customer(customer_id).ok_or_else(|| {
+                SquareErrorResponse::not_found(format!("Customer {} not found", customer_id))
+            })?,
+            None => {
+                if buyer.email_address.is_empty() {
+                    return Err(SquareErrorResponse::missing("email_address"));
+                }
+                match self.find_customer(&buyer.email_address) {
+                    Some(customer) => customer,
+                    None => self.create_customer(buyer)?,
+                }
+            }
+        };
+        let cardholder_name = format!("{} {}", buyer.given_name, buyer.family_name);
+        let card = self.create_card(&CardRequest {
+            idempotency_key: new_id(),
+            source_id: source_id.to_string(),
+            card: CardRequestObject {
+                billing_address: buyer.address.clone(),
+                cardholder_name: cardholder_name.trim().to_string(),
+                customer_id: customer.id.clone(),
+                reference_id: buyer.email_address.clone(),
+            },
+        })?;
+        let order = self.pay_order(&link.order_id, &customer.id, &card, "KEYED")?;
+        let subscription = match link
+            .checkout_options
+            .as_ref()
+            .map(|options| options.subscription_plan_id.as_str())
+            .filter(|plan_id| !plan_id.is_empty())
+        {
+            Some(plan_variation_id) => {
+                Some(self.start_subscription(plan_variation_id, &order, &card)?)
+            }
+            None => None,
+        };
+        Ok(PaidCheckout {
+            payment_link: link,
+            customer: self.with_cards(&customer),
+            order,
+            subscription,
+        })
+    }
+
+    // ============================ Orders ============================ //
+
+    /// Completes the open order, paid in full by `customer_id` with `card`, `KEYED` at checkout
+    /// or `ON_FILE`
+    fn pay_order(
+        &mut self,
+        order_id: &str,
+        customer_id: &str,
+        card: &CardResponseObject,
+        entry_method: &str,
+    ) -> Result<OrderObject, SquareErrorResponse> {
+        let now = now();
+        let order = self
+            .orders
+            .iter_mut()
+            .find(|order| order.id == order_id)
+            .ok_or_else(|| {
+                SquareErrorResponse::not_found(format!("Order {} not found", order_id))
+            })?;
+        order.customer_id = customer_id.to_string();
+        order.state = "COMPLETED".to_string();
+        order.updated_at = now.clone();
+        order.version += 1;
+        order.tenders = vec![Tender {
+            id: new_id(),
+            location_id: order.location_id.clone(),
+            transaction_id: order.id.clone(),
+            created_at: now,
+            amount_money: order.total_money.clone(),
+            type_: "CARD".to_string(),
+            card_details: CardDetails {
+                status: "CAPTURED".to_string(),
+                card: order::Card {
+                    card_brand: card.card_brand.clone(),
+                    last_4: card.last_4.clone(),
+                    fingerprint: card.fingerprint.clone().unwrap_or_default(),
+                },
+                entry_method: entry_method.to_string(),
+            },
+            tip_money: usd(0),
+            payment_id: new_id(),
+        }];
+        order.net_amount_due_money = usd(0);
+        Ok(order.clone())
+    }
+
+    /// A completed order of `quantity` items at `price` cents each, as a Square POS or invoice
+    /// sale would leave it
+    pub fn record_sale(
+        &mut self,
+        location_id: &str,
+        customer_id: &str,
+        name: &str,
+        variation_name: Option<&str>,
+        price: u64,
+        quantity: u64,
+    ) -> Result<OrderObject, SquareErrorResponse> {
+        self.check_location(location_id)?;
+        let item = line_item(name, variation_name, None, price, quantity);
+        let mut order = new_order(location_id, Some(customer_id), vec![item]);
+        order.state = "COMPLETED".to_string();
+        order.net_amount_due_money = usd(0);
+        self.orders.push(order.clone());
+        Ok(order)
+    }
+
+    /// Orders at any of `location_ids`, of any of `customer_ids` if given, newest first
+    pub fn search_orders(
+        &self,
+        location_ids: &[String],
+        customer_ids: Option<&[String]>,
+    ) -> Vec<OrderObject> {
+        self.orders
+            .iter()
+            .rev()
+            .filter(|order| {
+                location_ids.contains(&order.location_id)
+                    && customer_ids.map_or(true, |ids| ids.contains(&order.customer_id))
+            })
+            .cloned()
+            .collect()
+    }
+
+    // ============================ Subscriptions ============================ //
+
+    pub fn subscription(&self, id: &str) -> Option<&SubscriptionResponseObject> {
+        self.subscriptions
+            .iter()
+            .find(|subscription| subscription.id == id)
+    }
+
+    /// Subscriptions of any of `customer_ids` at any of `location_ids`, as searches return them
+    /// without their actions
+    pub fn search_subscriptions(
+        &self,
+        customer_ids: Option<&[String]>,
+        location_ids: Option<&[String]>,
+    ) -> Vec<SubscriptionResponseObject> {
+        self.subscriptions
+            .iter()
+            .filter(|sub| {
+                customer_ids.map_or(true, |ids| ids.contains(&sub.customer_id))
+                    && location_ids.map_or(true, |ids| ids.contains(&sub.location_id))
+            })
+            .map(|sub| SubscriptionResponseObject {
+                actions: None,
+                ..sub.clone()
+            })
+            .collect()
+    }
+
+    /// An active subscription charged through a month from today, with its first invoice paid
+    /// by `order`
+    fn start_subscription(
+        &mut self,
+        plan_variation_id: &str,
+        order: &OrderObject,
+        card: &CardResponseObject,
+    ) -> Result<SubscriptionResponseObject, SquareErrorResponse> {
+        let plan_phase_uid = self
+            .plan_variation(plan_variation_id)
+            .ok_or_else(|| {
+                SquareErrorResponse::not_found(format!(
+                    "Subscription plan variation {} not found",
+                    plan_variation_id
+                ))
+            })?
+            .subscription_plan_variation_data
+            .phases
+            .first()
+            .and_then(|phase| phase.uid.clone())
+            .unwrap_or_default();
+        let today = today();
+        let order_template_id = new_id();
+        let mut subscription = SubscriptionResponseObject {
+            actions: None,
+            buyer_self_management_token: new_id(),
+            canceled_date: None,
+            card_id: Some(card.id.clone()),
+            charged_through_date: Some(add_months(today, 1).to_string()),
+            created_at: now(),
+            customer_id: order.customer_id.clone(),
+            id: new_id(),
+            invoice_ids: None,
+            location_id: order.location_id.clone(),
+            order_template_id: order_template_id.clone(),
+            phases: Some(vec![PlanPhaseResponse {
+                uid: new_id(),
+                ordinal: 0,
+                order_template_id,
+                plan_phase_uid,
+            }]),
+            plan_variation_id: plan_variation_id.to_string(),
+            source: Source {
+                name: SOURCE_NAME.to_string(),
+            },
+            start_date: today.to_string(),
+            status: "ACTIVE".to_string(),
+            timezone: "UTC".to_string(),
+            version: 1,
+            tax_percentage: None,
+            price_override_money: None,
+        };
+        let invoice = self.paid_invoice(&subscription, order, today);
+        subscription.invoice_ids = Some(vec![invoice.id]);
+        self.subscriptions.push(subscription.clone());
+        Ok(subscription)
+    }
+
+    /// Active until its charged through date, like Square
+    pub fn cancel_subscription(
+        &mut self,
+        id: &str,
+    ) -> Result<CancelSubscriptionResponse, SquareErrorResponse> {
+        let sub = self
+            .subscriptions
+            .iter_mut()
+            .find(|sub| sub.id == id)
+            .ok_or_else(|| {
+                SquareErrorResponse::not_found(format!("Subscription {} not found", id))
+            })?;
+        if sub.canceled_date.is_some() || sub.status != "ACTIVE" {
+            return Err(SquareErrorResponse::bad_request(
+                "BAD_REQUEST",
+                "Subscription already has a pending cancel or isn't active",
+            ));
+        }
+
+        let effective_date = sub
+            .charged_through_date
+            .clone()
+            .unwrap_or_else(|| today().to_string());
+        let action = CancelSubscriptionActions {
+            id: new_id(),
+            type_: "CANCEL".to_string(),
+            effective_date: effective_date.clone(),
+        };
+        sub.canceled_date = Some(effective_date.clone());
+        sub.version += 1;
+        sub.actions.get_or_insert_with(Vec::new).push(Action {
+            id: action.id.clone(),
+            type_: action.type_.clone(),
+            effective_date: action.effective_date.clone(),
+            new_plan_id: None,
+        });
+        let monthly_billing_anchor_date = parse_date(&sub.start_date)
+            .map(|date| date.day() as u8)
+            .unwrap_or(1);
+        Ok(CancelSubscriptionResponse {
+            subscription: CancelSubscriptionObject {
+                id: sub.id.clone(),
+                location_id: sub.location_id.clone(),
+                customer_id: sub.customer_id.clone(),
+                start_date: sub.start_date.clone(),
+                canceled_date: effective_date.clone(),
+                charged_through_date: effective_date,
+                status: sub.status.clone(),
+                invoice_ids: sub.invoice_ids.clone().unwrap_or_default(),
+                version: sub.version,
+                created_at: sub.created_at.clone(),
+                timezone: sub.timezone.clone(),
+                source: sub.source.clone(),
+                monthly_billing_anchor_date,
+                plan_variation_id: sub.plan_variation_id.clone(),
+            },
+            actions: vec![action],
+        })
+    }
+
+    /// Ends the active subscriptions whose cancel took effect by `today` and bills the others
+    /// for every month started since they were last charged, with the card they were started
+    /// with. True if any changed.
+    pub fn renew_subscriptions(&mut self, today: NaiveDate) -> bool {
+        let mut changed = false;
+        for i in 0..self.subscriptions.len() {
+            if self.subscriptions[i].status != "ACTIVE" {
+                continue;
+            }
+            let canceled_date = self.subscriptions[i]
+                .canceled_date
+                .as_deref()
+                .and_then(parse_date);
+            if canceled_date.map_or(false, |date| date <= today) {
+                let subscription = &mut self.subscriptions[i];
+                subscription.status = "CANCELED".to_string();
+                subscription.version += 1;
+                changed = true;
+                continue;
+            }
+            while let Some((order, card)) = self.renewal_order(&self.subscriptions[i], today) {
+                let subscription = self.subscriptions[i].clone();
+                let order_id = order.id.clone();
+                self.orders.push(order);
+                let order =
+                    match self.pay_order(&order_id, &subscription.customer_id, &card, "ON_FILE") {
+                        Ok(order) => order,
+                        Err(_) => break,
+                    };
+                let due_date = subscription
+                    .charged_through_date
+                    .as_deref()
+                    .and_then(parse_date)
+                    .unwrap_or(today);
+                let invoice = self.paid_invoice(&subscription, &order, due_date);
+
+                let subscription = &mut self.subscriptions[i];
+                let invoice_ids = subscription.invoice_ids.get_or_insert_with(Vec::new);
+                invoice_ids.push(invoice.id);
+                let months = invoice_ids.len() as u32;
+                // counted from the start date so the billing day doesn't drift after short months
+                let start_date = parse_date(&subscription.start_date).unwrap_or(due_date);
+                subscription.charged_through_date =
+                    Some(add_months(start_date, months).to_string());
+                subscription.version += 1;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The open order for the subscription's next month and the card paying it, if the month
+    /// started by `today`. None if the subscription ends before then or its card was disabled.
+    fn renewal_order(
+        &self,
+        subscription: &SubscriptionResponseObject,
+        today: NaiveDate,
+    ) -> Option<(OrderObject, CardResponseObject)> {
+        let charged_through = subscription
+            .charged_through_date
+            .as_deref()
+            .and_then(parse_date)?;
+        let canceled = subscription.canceled_date.as_deref().and_then(parse_date);
+        if charged_through > today || canceled.map_or(false, |date| date <= charged_through) {
+            return None;
+        }
+        let card = self.card(subscription.card_id.as_deref()?)?;
+        if !card.enabled {
+            return None;
+        }
+        let data = &self
+            .plan_variation(&subscription.plan_variation_id)?
+            .subscription_plan_variation_data;
+        let pricing = &data.phases.first()?.pricing;
+        let price = pricing
+            .price_money
+            .as_ref()
+            .or(pricing.price.as_ref())?
+            .amount;
+        let order = new_order(
+            &subscription.location_id,
+            Some(&subscription.customer_id),
+            vec![line_item(&data.name, None, None, price, 1)],
+        );
+        Some((order, card.clone()))
+    }
+
+    /// A paid invoice of the subscription for `order`, charged to the subscription's card
+    fn paid_invoice(
+        &mut self,
+        subscription: &SubscriptionResponseObject,
+        order: &OrderObject,
+        due_date: NaiveDate,
+    ) -> Invoice {
+        let now = now();
+        let customer = self.customer(&subscription.customer_id);
+        let invoice = Invoice {
+            id: format!("inv:0-{}", new_id()),
+            version: 2,
+            location_id: order.location_id.clone(),
+            order_id: order.id.clone(),
+            payment_requests: vec![PaymentRequest {
+                automatic_payment_source: "CARD_ON_FILE".to_string(),
+                card_id: subscription.card_id.clone().unwrap_or_default(),
+                computed_amount_money: order.total_money.clone(),
+                due_date: due_date.to_string(),
+                request_type: "BALANCE".to_string(),
+                tipping_enabled: false,
+                total_completed_amount_money: order.total_money.clone(),
+                uid: new_id(),
+            }],
+            invoice_number: format!("{:06}", self.invoices.len() + 1),
+            title: order
+                .line_items
+                .first()
+                .map(|item| item.name.clone())
+                .unwrap_or_default(),
+            description: None,
+            scheduled_at: None,
+            status: "PAID".to_string(),
+            timezone: "UTC".to_string(),
+            created_at: now.clone(),
+            updated_at: now,
+            primary_recipient: Recipient {
+                customer_id: subscription.customer_id.clone(),
+                email_address: customer
+                    .as_ref()
+                    .map(|customer| customer.email_address.clone())
+                    .unwrap_or_default(),
+                family_name: customer
+                    .as_ref()
+                    .map(|customer| customer.family_name.clone())
+                    .unwrap_or_default(),
+                given_name: customer
+                    .as_ref()
+                    .map(|customer| customer.given_name.clone())
+                    .unwrap_or_default(),
+                phone_number: String::new(),
+            },
+            accepted_payment_methods: PaymentMethod {
+                bank_account: false,
+                buy_now_pay_later: false,
+                card: true,
+                cash_app_pay: false,
+                square_gift_card: false,
+            },
+            delivery_method: "EMAIL".to_string(),
+            sale_or_service_date: None,
+            public_url: None,
+            store_payment_method_enabled: true,
+            subscription_id: Some(subscription.id.clone()),
+            next_payment_amount_money: None,
+        };
+        self.invoices.push(invoice.clone());
+        invoice
+    }
+}
+
+fn plan_variations(
+    object: &CatalogResponseObject,
+) -> impl Iterator<Item = &SubscriptionPlanResponseObject> {
+    object
+        .subscription_plan_data
+        .iter()
+        .flat_map(|data| data.subscription_plan_variations.iter().flatten())
+}
+
+fn item_variations(object: &CatalogResponseObject) -> impl Iterator<Item = &Variation> {
+    object
+        .item_data
+        .iter()
+        .flat_map(|data| data.variations.iter().flatten())
+}
+
+/// A new id for a `#` temporary id, mapped to it in `id_mappings`, none for an existing id
+fn new_object_id(
+    client_id: &str,
+    id_mappings: &mut Vec<IdMapping>,
+) -> Result<Option<String>, SquareErrorResponse> {
+    if client_id.is_empty() {
+        return Err(SquareErrorResponse::missing("object.id"));
+    }
+    if !client_id.starts_with('#') {
+        return Ok(None);
+    }
+    let id = new_id();
+    id_mappings.push(IdMapping {
+        client_object_id: client_id.to_string(),
+        object_id: id.clone(),
+    });
+    Ok(Some(id))
+}
+
+/// Replaces the object `same` as `object`, or adds it
+fn replace_or_push<T>(objects: &mut Vec<T>, object: T, same: impl Fn(&T, &T) -> bool) {
+    match objects.iter_mut().find(|existing| same(existing, &object)) {
+        Some(existing) => *existing = object,
+        None => objects.push(object),
+    }
+}
+
+/// A Square style id, 26 upper case characters
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..26].to_uppercase()
+}
+
+/// RFC 3339 in UTC with milliseconds, as Square's timestamps are
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// `months` after `date`, the end of the month if it's shorter
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    date.checked_add_months(Months::new(months)).unwrap_or(date)
+}
+
+fn usd(amount: u64) -> Price {
+    Price {
+        amount,
+        currency: "USD".to_string(),
+    }
+}
+
+fn location(id: &str, merchant_id: &str) -> LocationResponse {
+    LocationResponse {
+        id: id.to_string(),
+        name: SOURCE_NAME.to_string(),
+        address: Address::default(),
+        timezone: "UTC".to_string(),
+        capabilities: vec!["CREDIT_CARD_PROCESSING".to_string()],
+        status: "ACTIVE".to_string(),
+        created_at: now(),
+        merchant_id: merchant_id.to_string(),
+        country: "US".to_string(),
+        language_code: "en-US".to_string(),
+        currency: "USD".to_string(),
+        business_name: SOURCE_NAME.to_string(),
+        type_: "PHYSICAL".to_string(),
+        business_hours: BusinessHours {},
+        mcc: "7299".to_string(),
+    }
+}
+
+/// A line item of `quantity` at `price` cents each
+fn line_item(
+    name: &str,
+    variation_name: Option<&str>,
+    catalog_object_id: Option<&str>,
+    price: u64,
+    quantity: u64,
+) -> LineItem {
+    let total = usd(price * quantity);
+    LineItem {
+        uid: new_id(),
+        catalog_object_id: catalog_object_id.map(str::to_string),
+        catalog_version: None,
+        quantity: quantity.to_string(),
+        name: name.to_string(),
+        variation_name: variation_name.map(str::to_string),
+        base_price_money: usd(price),
+        gross_sales_money: total.clone(),
+        total_tax_money: usd(0),
+        total_service_charge_money: usd(0),
+        total_discount_money: usd(0),
+        total_money: total.clone(),
+        variation_total_price_money: total,
+        item_type: Some("ITEM".to_string()),
+        applied_discounts: Vec::new(),
+    }
+}
+
+/// An open order, with no customer until it's paid unless `customer_id`
+fn new_order(
+    location_id: &str,
+    customer_id: Option<&str>,
+    line_items: Vec<LineItem>,
+) -> OrderObject {
+    let now = now();
+    let total = usd(line_items.iter().map(|item| item.total_money.amount).sum());
+    OrderObject {
+        id: new_id(),
+        location_id: location_id.to_string(),
+        line_items,
+        fulfillments: Vec::new(),
+        discounts: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+        state: "OPEN".to_string(),
+        version: 1,
+        total_tax_money: usd(0),
+        total_discount_money: usd(0),
+        total_tip_money: usd(0),
+        total_money: total.clone(),
+        tenders: Vec::new(),
+        total_service_charge_money: usd(0),
+        net_amounts: order::NetAmounts {
+            total_money: total.clone(),
+            tax_money: usd(0),
+            discount_money: usd(0),
+            tip_money: usd(0),
+            service_charge_money: usd(0),
+        },
+        source: Source {
+            name: SOURCE_NAME.to_string(),
+        },
+        customer_id: customer_id.unwrap_or_default().to_string(),
+        net_amount_due_money: total,
+    }
+}
+
+/// The order as a payment link's related resources show it
+fn order_response(order: &OrderObject) -> OrderResponse {
+    OrderResponse {
+        created_at: order.created_at.clone(),
+        fulfillments: Vec::new(),
+        id: order.id.clone(),
+        line_items: order
+            .line_items
+            .iter()
+            .map(|item| LineItemResponse {
+                base_price_money: item.base_price_money.clone(),
+                gross_sales_money: item.gross_sales_money.clone(),
+                item_type: item.item_type.clone().unwrap_or_default(),
+                name: item.name.clone(),
+                quantity: item.quantity.clone(),
+                total_discount_money: item.total_discount_money.clone(),
+                total_money: item.total_money.clone(),
+                total_service_charge_money: item.total_service_charge_money.clone(),
+                total_tax_money: item.total_tax_money.clone(),
+                uid: item.uid.clone(),
+                variation_total_price_money: item.variation_total_price_money.clone(),
+                catalog_object_id: item.catalog_object_id.clone(),
+                variation_name: item.variation_name.clone(),
+            })
+            .collect(),
+        location_id: order.location_id.clone(),
+        net_amount_due_money: order.net_amount_due_money.clone(),
+        net_amounts: checkout::NetAmounts {
+            discount_money: order.net_amounts.discount_money.clone(),
+            service_charge_money: order.net_amounts.service_charge_money.clone(),
+            tax_money: order.net_amounts.tax_money.clone(),
+            tip_money: order.net_amounts.tip_money.clone(),
+            total_money: order.net_amounts.total_money.clone(),
+        },
+        source: order.source.clone(),
+        state: order.state.clone(),
+        total_discount_money: order.total_discount_money.clone(),
+        total_money: order.total_money.clone(),
+        total_service_charge_money: order.total_service_charge_money.clone(),
+        total_tax_money: order.total_tax_money.clone(),
+        total_tip_money: order.total_tip_money.clone(),
+        updated_at: order.updated_at.clone(),
+        version: order.version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCATION_ID: &str = "LOCALSQUARE";
+
+    /// A merchant with a $11 plan and `email` subscribed to it today
+    fn subscribed(email: &str) -> (Merchant, SubscriptionResponseObject) {
+        let mut merchant = Merchant::new(LOCATION_ID);
+        let variation = merchant.create_plan(None, "Premium", 1100);
+        let checkout = merchant
+            .create_payment_link(
+                &CheckoutRequest::new_subscription(SubscriptionCheckoutBuilder {
+                    name: "Premium".to_string(),
+                    price: 1100,
+                    location_id: LOCATION_ID.to_string(),
+                    subscription_plan_id: variation.id,
+                    redirect_url: String::new(),
+                    buyer_email: Some(email.to_string()),
+                }),
+                "http://localhost/pay/",
+            )
+            .unwrap();
+        let buyer = CustomerRequest {
+            email_address: email.to_string(),
+            ..CustomerRequest::default()
+        };
+        let paid = merchant
+            .complete_checkout(&checkout.payment_link.url, &buyer, CARD_NONCE)
+            .unwrap();
+        (merchant, paid.subscription.unwrap())
+    }
+
+    #[test]
+    fn bills_each_month_until_canceled() {
+        let (mut merchant, subscription) = subscribed("alice@example.com");
+        assert_eq!(merchant.invoices.len(), 1);
+        assert!(!merchant.renew_subscriptions(today()));
+
+        let charged_through = parse_date(subscription.charged_through_date.as_deref().unwrap());
+        assert!(merchant.renew_subscriptions(add_months(charged_through.unwrap(), 1)));
+        let renewed = merchant.subscription(&subscription.id).unwrap().clone();
+        assert_eq!(merchant.invoices.len(), 3);
+        assert_eq!(renewed.invoice_ids.unwrap().len(), 3);
+        assert_eq!(
+            renewed.charged_through_date,
+            Some(add_months(today(), 3).to_string())
+        );
+        assert!(merchant
+            .orders
+            .iter()
+            .all(|order| order.state == "COMPLETED"));
+
+        let canceled = merchant.cancel_subscription(&subscription.id).unwrap();
+        let end = parse_date(&canceled.subscription.canceled_date).unwrap();
+        assert!(merchant.renew_subscriptions(end));
+        let ended = merchant.subscription(&subscription.id).unwrap();
+        assert_eq!(ended.status, "CANCELED");
+        assert_eq!(merchant.invoices.len(), 3);
+    }
+
+    #[test]
+    fn declined_card_leaves_the_link_unpaid() {
+        let (mut merchant, _) = subscribed("alice@example.com");
+        let link = merchant.payment_links[0].clone();
+        let buyer = CustomerRequest {
+            email_address: "bob@example.com".to_string(),
+            ..CustomerRequest::default()
+        };
+        let error = merchant
+            .complete_checkout(&link.id, &buyer, DECLINED_CARD_NONCE)
+            .unwrap_err();
+        assert_eq!(error.code(), "BAD_REQUEST");
+
+        let mut merchant = Merchant::new(LOCATION_ID);
+        let variation = merchant.create_plan(None, "Premium", 1100);
+        let request = CheckoutRequest::new_subscription(SubscriptionCheckoutBuilder {
+            name: "Premium".to_string(),
+            price: 1100,
+            location_id: LOCATION_ID.to_string(),
+            subscription_plan_id: variation.id,
+            redirect_url: String::new(),
+            buyer_email: None,
+        });
+        let link = merchant
+            .create_payment_link(&request, "http://localhost/pay/")
+            .unwrap()
+            .payment_link;
+        let error = merchant
+            .complete_checkout(&link.id, &buyer, DECLINED_CARD_NONCE)
+            .unwrap_err();
+        assert_eq!(error.code(), "CARD_DECLINED");
+        assert_eq!(merchant.order(&link.order_id).unwrap().state, "OPEN");
+    }
+
+    #[test]
+    fn survives_a_round_trip_through_json() {
+        let (merchant, subscription) = subscribed("alice@example.com");
+        let json = serde_json::to_string(&merchant).unwrap();
+        let restored = serde_json::from_str::<Merchant>(&json).unwrap();
+        assert_eq!(restored.merchant_id, merchant.merchant_id);
+        assert_eq!(
+            restored.subscription(&subscription.id).unwrap().card_id,
+            subscription.card_id
+        );
+        assert_eq!(
+            restored
+                .find_customer("ALICE@example.com")
+                .unwrap()
+                .cards
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+    }
+}
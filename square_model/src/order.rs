@@ -1,4 +1,4 @@
-use crate::square::{Price, Source};
+use crate::{Price, Source};
 use serde::{Deserialize, Serialize};
 
 pub struct SearchOrdersRequestBuilder {
@@ -6,23 +6,23 @@ pub struct SearchOrdersRequestBuilder {
     pub customer_ids: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchOrdersRequest {
     pub location_ids: Vec<String>,
     pub query: Option<SearchOrdersQuery>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchOrdersQuery {
     pub filter: SearchOrdersFilter,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchOrdersFilter {
     pub customer_filter: CustomerFilter,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomerFilter {
     pub customer_ids: Vec<String>,
 }
@@ -48,12 +48,14 @@ impl SearchOrdersRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SearchOrdersResponse {
     pub orders: Vec<OrderObject>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct OrderObject {
     pub id: String,
     pub location_id: String,
@@ -76,7 +78,8 @@ pub struct OrderObject {
     pub net_amount_due_money: Price,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Tender {
     pub id: String,
     pub location_id: String,
@@ -90,21 +93,25 @@ pub struct Tender {
     pub payment_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CardDetails {
     pub status: String,
     pub card: Card,
     pub entry_method: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "openapi", schemars(rename = "OrderCard"))]
 pub struct Card {
     pub card_brand: String,
     pub last_4: String,
     pub fingerprint: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Fulfillment {
     pub uid: String,
     #[serde(rename = "type")]
@@ -112,7 +119,8 @@ pub struct Fulfillment {
     pub state: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct LineItem {
     pub uid: String,
     pub catalog_object_id: Option<String>,
@@ -131,14 +139,16 @@ pub struct LineItem {
     pub applied_discounts: Vec<AppliedDiscount>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct AppliedDiscount {
     pub uid: String,
     pub discount_uid: String,
     pub applied_money: Price,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Discount {
     pub uid: String,
     pub name: String,
@@ -149,7 +159,8 @@ pub struct Discount {
     pub scope: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct NetAmounts {
     pub total_money: Price,
     pub tax_money: Price,
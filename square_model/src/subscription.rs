@@ -1,10 +1,10 @@
-use crate::square::CustomerInfo;
+use crate::CustomerInfo;
 use crate::{Price, Source};
 use serde::{Deserialize, Serialize};
 
 // ==================== Subscription Request ====================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionRequest {
     pub idempotency_key: String,
     pub customer_id: String,
@@ -25,7 +25,7 @@ impl SubscriptionRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlanPhaseRequest {
     pub ordinal: u64,
     pub order_template_id: String,
@@ -33,12 +33,14 @@ pub struct PlanPhaseRequest {
 
 // ==================== Subscription Response ====================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionResponse {
     pub subscription: SubscriptionResponseObject,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionResponseObject {
     pub actions: Option<Vec<Action>>,
     pub buyer_self_management_token: String,
@@ -63,6 +65,7 @@ pub struct SubscriptionResponseObject {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct Action {
     pub id: String,
     #[serde(rename = "type")]
@@ -72,6 +75,7 @@ pub struct Action {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PlanPhaseResponse {
     pub uid: String,
     pub ordinal: u64,
@@ -86,7 +90,7 @@ pub struct SubscriptionSearchResponse {
     pub subscriptions: Vec<SubscriptionResponseObject>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchSubscriptionsRequest {
     pub query: SearchSubscriptionQuery,
 }
@@ -107,23 +111,27 @@ impl SearchSubscriptionsRequest {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchSubscriptionQuery {
     pub filter: SearchSubscriptionFilter,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchSubscriptionFilter {
     pub customer_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct SubscriptionInfo {
     pub title: String,
     pub cost: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UserSubscriptionInfo {
     pub start_date: String,
     pub charged_through_date: Option<String>,
@@ -131,6 +139,7 @@ pub struct UserSubscriptionInfo {
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UserProfile {
     pub customer: Option<CustomerInfo>,
     pub subscription_info: Option<SubscriptionInfo>,
@@ -170,6 +179,7 @@ pub struct CancelSubscriptionActions {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct CanceledSubscriptionInfo {
     pub email: String,
     pub charged_through_year: u16,
@@ -0,0 +1,101 @@
+use crate::gcs::{GcsStore, GCS_ENDPOINT};
+use crate::local::LocalStore;
+use crate::object_store::{ObjectStore, StorageError};
+use log::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const DEFAULT_BUCKET: &str = "consciousness-archive";
+pub const DEFAULT_LOCAL_DIR: &str = "assets";
+
+/// Which object store assets live in: a GCS bucket, [`DEFAULT_BUCKET`] unless configured, or
+/// the files in a local directory, [`DEFAULT_LOCAL_DIR`] unless configured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageConfig {
+    Gcs {
+        bucket: String,
+        emulator: Option<String>,
+        public_url: String,
+    },
+    Local {
+        dir: PathBuf,
+        public_url: String,
+    },
+}
+
+impl StorageConfig {
+    /// GCS `bucket`, or the bucket of the GCS `emulator` at e.g. `http://localhost:4443`.
+    /// `public_url` defaults to the bucket's public URL or `<emulator>/<bucket>/`.
+    pub fn gcs(bucket: String, emulator: Option<String>, public_url: Option<String>) -> Self {
+        let emulator = emulator.map(|host| host.trim_end_matches('/').to_string());
+        let public_url = public_url.unwrap_or_else(|| {
+            format!(
+                "{}/{}/",
+                emulator.as_deref().unwrap_or(GCS_ENDPOINT),
+                bucket
+            )
+        });
+        StorageConfig::Gcs {
+            bucket,
+            emulator,
+            public_url: with_trailing_slash(public_url),
+        }
+    }
+
+    /// Files in `dir`, relative to the working directory.
+    /// `public_url` defaults to a `file://` URL of the directory.
+    pub fn local(
+        dir: impl Into<PathBuf>,
+        public_url: Option<String>,
+    ) -> Result<Self, StorageError> {
+        let dir = dir.into();
+        let dir = if dir.is_absolute() {
+            dir
+        } else {
+            std::env::current_dir()
+                .map_err(|e| StorageError::Io(dir.clone(), e))?
+                .join(dir)
+        };
+        let public_url = public_url.unwrap_or_else(|| format!("file://{}/", dir.display()));
+        Ok(StorageConfig::Local {
+            dir,
+            public_url: with_trailing_slash(public_url),
+        })
+    }
+
+    /// URL an object is served from, e.g.
+    /// `https://storage.googleapis.com/consciousness-archive/images/articles/hack.png`
+    pub fn public_url(&self, key: &str) -> String {
+        match self {
+            StorageConfig::Gcs { public_url, .. } | StorageConfig::Local { public_url, .. } => {
+                format!("{}{}", public_url, key)
+            }
+        }
+    }
+
+    pub async fn open(&self) -> Result<Arc<dyn ObjectStore>, StorageError> {
+        let store: Arc<dyn ObjectStore> = match self {
+            StorageConfig::Gcs {
+                bucket,
+                emulator: Some(emulator),
+                public_url,
+            } => Arc::new(GcsStore::emulator(emulator, bucket, public_url)),
+            StorageConfig::Gcs {
+                bucket,
+                emulator: None,
+                public_url,
+            } => Arc::new(GcsStore::new(bucket, public_url).await?),
+            StorageConfig::Local { dir, public_url } => Arc::new(LocalStore::new(dir, public_url)),
+        };
+        info!("Using object storage at {}", store.location());
+        Ok(store)
+    }
+}
+
+fn with_trailing_slash(url: String) -> String {
+    if url.ends_with('/') {
+        url
+    } else {
+        format!("{}/", url)
+    }
+}
@@ -0,0 +1,210 @@
+use crate::object_store::{validate_key, ObjectInfo, ObjectStore, StorageError};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::{TimeZone, Utc};
+use google_cloud_storage::client::{Client, ClientConfig};
+use google_cloud_storage::http::objects::delete::DeleteObjectRequest;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::list::ListObjectsRequest;
+use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
+use google_cloud_storage::http::objects::Object;
+use google_cloud_storage::http::Error as GcsError;
+use google_cloud_token::{TokenSource, TokenSourceProvider};
+use std::sync::Arc;
+
+pub const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// Objects in a Google Cloud Storage bucket, or in a local emulator of the GCS JSON API
+/// such as `fake-gcs-server`
+pub struct GcsStore {
+    client: Client,
+    bucket: String,
+    public_url: String,
+}
+
+impl GcsStore {
+    /// Authenticates with the application default credentials, e.g. the service account key
+    /// in `GOOGLE_APPLICATION_CREDENTIALS`
+    pub async fn new(bucket: &str, public_url: &str) -> Result<Self, StorageError> {
+        let config = ClientConfig::default().with_auth().await.map_err(|e| {
+            StorageError::Config(format!("failed to authenticate with Google Cloud: {}", e))
+        })?;
+        Ok(Self {
+            client: Client::new(config),
+            bucket: bucket.to_string(),
+            public_url: public_url.to_string(),
+        })
+    }
+
+    /// Unauthenticated client for an emulator listening on `endpoint`, e.g. `http://localhost:4443`
+    pub fn emulator(endpoint: &str, bucket: &str, public_url: &str) -> Self {
+        let config = ClientConfig {
+            storage_endpoint: endpoint.trim_end_matches('/').to_string(),
+            token_source_provider: Box::new(EmulatorTokenSourceProvider),
+            ..Default::default()
+        };
+        Self {
+            client: Client::new(config),
+            bucket: bucket.to_string(),
+            public_url: public_url.to_string(),
+        }
+    }
+
+    fn error(&self, key: &str, e: GcsError) -> StorageError {
+        match e {
+            GcsError::Response(response) if response.code == 404 => {
+                StorageError::NotFound(key.to_string())
+            }
+            e => StorageError::Backend(format!("gs://{}/{}: {}", self.bucket, key, e)),
+        }
+    }
+}
+
+/// Emulators accept any credentials, the client still sends an `Authorization` header
+#[derive(Debug)]
+struct EmulatorTokenSourceProvider;
+
+#[derive(Debug)]
+struct EmulatorTokenSource;
+
+impl TokenSourceProvider for EmulatorTokenSourceProvider {
+    fn token_source(&self) -> Arc<dyn TokenSource> {
+        Arc::new(EmulatorTokenSource)
+    }
+}
+
+#[async_trait]
+impl TokenSource for EmulatorTokenSource {
+    async fn token(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Ok("Bearer emulator".to_string())
+    }
+}
+
+fn object_info(object: Object) -> ObjectInfo {
+    // GCS reports checksums base64 encoded
+    let md5 = object.md5_hash.as_ref().and_then(|md5| {
+        base64::engine::general_purpose::STANDARD
+            .decode(md5)
+            .ok()
+            .map(hex::encode)
+    });
+    let updated = object.updated.and_then(|updated| {
+        Utc.timestamp_opt(updated.unix_timestamp(), updated.nanosecond())
+            .single()
+    });
+    ObjectInfo {
+        key: object.name,
+        size: object.size.max(0) as u64,
+        md5,
+        content_type: object.content_type,
+        updated,
+    }
+}
+
+#[async_trait]
+impl ObjectStore for GcsStore {
+    fn location(&self) -> String {
+        format!("gs://{}", self.bucket)
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}{}", self.public_url, key)
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<ObjectInfo, StorageError> {
+        validate_key(key)?;
+        let media = Media {
+            name: key.to_string().into(),
+            content_type: content_type.to_string().into(),
+            content_length: Some(data.len() as u64),
+        };
+        let request = UploadObjectRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+        self.client
+            .upload_object(&request, data, &UploadType::Simple(media))
+            .await
+            .map(object_info)
+            .map_err(|e| self.error(key, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        validate_key(key)?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+        self.client
+            .download_object(&request, &Range::default())
+            .await
+            .map_err(|e| self.error(key, e))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError> {
+        validate_key(key)?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+        match self.client.get_object(&request).await {
+            Ok(object) => Ok(Some(object_info(object))),
+            Err(e) => match self.error(key, e) {
+                StorageError::NotFound(_) => Ok(None),
+                e => Err(e),
+            },
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut objects = Vec::new();
+        let mut page_token = None;
+        loop {
+            let request = ListObjectsRequest {
+                bucket: self.bucket.clone(),
+                prefix: Some(prefix.to_string()).filter(|prefix| !prefix.is_empty()),
+                page_token,
+                ..Default::default()
+            };
+            let response = self
+                .client
+                .list_objects(&request)
+                .await
+                .map_err(|e| self.error(prefix, e))?;
+            objects.extend(
+                response
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(object_info),
+            );
+            match response.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        validate_key(key)?;
+        let request = DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            object: key.to_string(),
+            ..Default::default()
+        };
+        self.client
+            .delete_object(&request)
+            .await
+            .map_err(|e| self.error(key, e))
+    }
+}
@@ -0,0 +1,9 @@
+pub mod config;
+pub mod gcs;
+pub mod local;
+pub mod object_store;
+
+pub use config::*;
+pub use gcs::*;
+pub use local::*;
+pub use object_store::*;
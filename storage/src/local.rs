@@ -0,0 +1,183 @@
+use crate::object_store::{
+    content_type_for, md5_hex, validate_key, ObjectInfo, ObjectStore, StorageError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+
+/// Bytes read at a time when hashing a file
+const MD5_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Objects stored as files under a local directory, laid out like the bucket.
+/// Used to script and test asset management offline.
+pub struct LocalStore {
+    root: PathBuf,
+    public_url: String,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>, public_url: &str) -> Self {
+        Self {
+            root: root.into(),
+            public_url: public_url.to_string(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        validate_key(key)?;
+        Ok(key
+            .split('/')
+            .fold(self.root.clone(), |path, segment| path.join(segment)))
+    }
+
+    /// Info of the file at `path`, hashing it unless its `md5` is already known
+    async fn info(
+        &self,
+        key: &str,
+        path: &Path,
+        md5: Option<String>,
+    ) -> Result<ObjectInfo, StorageError> {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .map_err(|e| io_error(key, path, e))?;
+        let md5 = match md5 {
+            Some(md5) => md5,
+            None => file_md5(path).await.map_err(|e| io_error(key, path, e))?,
+        };
+        Ok(ObjectInfo {
+            key: key.to_string(),
+            size: metadata.len(),
+            md5: Some(md5),
+            content_type: Some(content_type_for(key)),
+            updated: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+}
+
+/// Hex MD5 of a file, read in chunks rather than whole
+async fn file_md5(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; MD5_CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(format!("{:x}", context.compute()));
+        }
+        context.consume(&buf[..read]);
+    }
+}
+
+fn io_error(key: &str, path: &Path, e: std::io::Error) -> StorageError {
+    if e.kind() == ErrorKind::NotFound {
+        StorageError::NotFound(key.to_string())
+    } else {
+        StorageError::Io(path.to_path_buf(), e)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}{}", self.public_url, key)
+    }
+
+    /// Files are written to a temp file next to the object and renamed over it
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        _content_type: &str,
+    ) -> Result<ObjectInfo, StorageError> {
+        let path = self.path(key)?;
+        let dir = path.parent().unwrap_or(&self.root).to_path_buf();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| StorageError::Io(dir.clone(), e))?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let tmp = dir.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+        tokio::fs::write(&tmp, &data)
+            .await
+            .map_err(|e| StorageError::Io(tmp.clone(), e))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| StorageError::Io(path.clone(), e))?;
+        self.info(key, &path, Some(md5_hex(&data))).await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let path = self.path(key)?;
+        tokio::fs::read(&path)
+            .await
+            .map_err(|e| io_error(key, &path, e))
+    }
+
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError> {
+        let path = self.path(key)?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+        match self.info(key, &path, None).await {
+            Ok(info) => Ok(Some(info)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut objects = Vec::new();
+        let mut dirs = vec![(self.root.clone(), String::new())];
+        while let Some((dir, dir_key)) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                // an empty store has no root directory yet
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(StorageError::Io(dir, e)),
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| StorageError::Io(dir.clone(), e))?
+            {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // temp files of unfinished writes
+                if name.starts_with('.') {
+                    continue;
+                }
+                let key = format!("{}{}", dir_key, name);
+                let path = entry.path();
+                if path.is_dir() {
+                    let dir_prefix = format!("{}/", key);
+                    // only descend into directories that can hold matching keys
+                    if dir_prefix.starts_with(prefix) || prefix.starts_with(&dir_prefix) {
+                        dirs.push((path, dir_prefix));
+                    }
+                } else if key.starts_with(prefix) {
+                    objects.push(self.info(&key, &path, None).await?);
+                }
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| io_error(key, &path, e))
+    }
+}
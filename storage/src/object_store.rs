@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// Metadata of one stored object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    /// Path of the object in the store, e.g. `images/testimonial_images/jane.png`
+    pub key: String,
+    pub size: u64,
+    /// Hex encoded MD5 of the contents, which GCS computes for every upload
+    pub md5: Option<String>,
+    pub content_type: Option<String>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// No object is stored under the key
+    NotFound(String),
+    /// The key is empty, absolute or escapes the store with `..`
+    InvalidKey(String),
+    /// A local file could not be read or written
+    Io(PathBuf, std::io::Error),
+    /// The storage service failed or rejected the request
+    Backend(String),
+    /// The storage settings are incomplete or inconsistent
+    Config(String),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NotFound(key) => write!(f, "no object named {}", key),
+            StorageError::InvalidKey(key) => write!(f, "invalid object key {:?}", key),
+            StorageError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            StorageError::Backend(e) => write!(f, "storage request failed: {}", e),
+            StorageError::Config(e) => write!(f, "invalid storage config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// Where assets such as images are uploaded and served from.
+///
+/// Keys are `/` separated paths relative to the root of the store, e.g.
+/// `images/category_images/love.png`, and [`ObjectStore::public_url`] is where clients
+/// download them from.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Where objects are stored, for logs, e.g. `gs://consciousness-archive`
+    fn location(&self) -> String;
+
+    /// URL an object is served from once uploaded
+    fn public_url(&self, key: &str) -> String;
+
+    /// Create or overwrite an object
+    async fn put(
+        &self,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<ObjectInfo, StorageError>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Metadata of an object, `None` if nothing is stored under `key`
+    async fn head(&self, key: &str) -> Result<Option<ObjectInfo>, StorageError>;
+
+    /// Every object whose key starts with `prefix`, sorted by key
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, StorageError>;
+
+    /// Delete an object, [`StorageError::NotFound`] if there is none
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Reject keys that are empty, absolute, or have empty, `.` or `..` segments
+pub fn validate_key(key: &str) -> Result<(), StorageError> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && !key.contains('\\')
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidKey(key.to_string()))
+    }
+}
+
+/// MIME type of a key from its extension, `application/octet-stream` if unknown
+pub fn content_type_for(key: &str) -> String {
+    mime_guess::from_path(key)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+pub fn md5_hex(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}